@@ -3,6 +3,17 @@ use cbindgen;
 use std::env;
 
 fn main() {
+    // both SIMD features can be enabled, but only the backend for the target architecture
+    // is compiled, so there is at most one backend in a build
+    println!("cargo:rustc-check-cfg=cfg(block_aligner_avx2, block_aligner_wasm)");
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    if env::var("CARGO_FEATURE_SIMD_AVX2").is_ok() && (arch == "x86" || arch == "x86_64") {
+        println!("cargo:rustc-cfg=block_aligner_avx2");
+    }
+    if env::var("CARGO_FEATURE_SIMD_WASM").is_ok() && arch == "wasm32" {
+        println!("cargo:rustc-cfg=block_aligner_wasm");
+    }
+
     if env::var("BLOCK_ALIGNER_C").is_ok() {
         let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         cbindgen::generate(&crate_dir)
@@ -0,0 +1,182 @@
+//! Automatic selection of the alignment algorithm and block sizes based on divergence.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+use crate::myers::*;
+
+use std::cmp;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+/// Max divergence where the edit alignment is used directly.
+const EDIT_DIVERGENCE: f32 = 0.01;
+/// Max number of words stored for the traceback of the edit alignment (128 MiB).
+const MAX_EDIT_WORDS: usize = 1 << 24;
+/// Max divergence where small blocks are used.
+const SMALL_DIVERGENCE: f32 = 0.1;
+const SMALL_SIZE: RangeInclusive<usize> = 32..=128;
+const MIN_LARGE_SIZE: usize = 256;
+const MAX_LARGE_SIZE: usize = 2048;
+/// K-mer size used by `auto_align` for estimating divergence.
+const AUTO_K: usize = 12;
+
+/// Algorithm chosen by `auto_align`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AutoMode {
+    /// Edit alignment for near-identical sequences.
+    EditDistance,
+    /// Block aligner with a small range of block sizes.
+    SmallBlock(RangeInclusive<usize>),
+    /// Block aligner with a large range of block sizes, for divergent sequences.
+    LargeBlock(RangeInclusive<usize>)
+}
+
+/// Result of `auto_align`.
+pub struct AutoAlignment {
+    /// Result of the global alignment.
+    pub res: AlignResult,
+    /// CIGAR string of the global alignment.
+    pub cigar: Cigar,
+    /// Estimated divergence, which is the fraction of edits relative to the longer string,
+    /// or the k-mer based estimate if the sequences are clearly divergent.
+    pub divergence: f32,
+    /// Algorithm that was used.
+    pub mode: AutoMode
+}
+
+/// Estimate the divergence between two strings from the fraction of shared k-mers.
+///
+/// This is the Mash distance `-ln(2j / (1 + j)) / k`, where `j` is the Jaccard index of the
+/// sets of k-mers. It approximates the fraction of substitutions per base, and it is much
+/// cheaper than alignment since it does not depend on the product of the lengths. Strings
+/// without any shared k-mers (or that are too short to have any k-mers) have a divergence of 1.
+pub fn kmer_divergence(a: &[u8], b: &[u8], k: usize) -> f32 {
+    assert!(k > 0, "K-mer size must be positive!");
+    if a.eq_ignore_ascii_case(b) {
+        return 0.0;
+    }
+    if a.len() < k || b.len() < k {
+        return 1.0;
+    }
+
+    let kmers = |s: &[u8]| s.windows(k).map(|w| w.to_ascii_uppercase()).collect::<HashSet<_>>();
+    let (a, b) = (kmers(a), kmers(b));
+    let shared = a.intersection(&b).count();
+    if shared == 0 {
+        return 1.0;
+    }
+    let j = (shared as f64) / ((a.len() + b.len() - shared) as f64);
+    ((-(2.0 * j / (1.0 + j)).ln() / (k as f64)) as f32).clamp(0.0, 1.0)
+}
+
+/// Max number of edits for the edit alignment.
+fn edit_cutoff(query_len: usize, reference_len: usize) -> usize {
+    let len = cmp::max(cmp::max(query_len, reference_len), 1);
+    ((len as f32) * EDIT_DIVERGENCE).ceil() as usize
+}
+
+/// Choose an algorithm and block sizes based on the estimated divergence.
+///
+/// Near-identical sequences use the edit alignment, somewhat similar sequences use small
+/// blocks, and divergent sequences use large blocks, where the max block size grows with
+/// the difference in length between the sequences (an estimate of the largest gap).
+/// Near-identical sequences that are so long that the traceback of the edit alignment
+/// would use too much memory also use small blocks.
+pub fn auto_mode(query_len: usize, reference_len: usize, divergence: f32) -> AutoMode {
+    let k = edit_cutoff(query_len, reference_len);
+    if divergence <= EDIT_DIVERGENCE && 2 * band_words(query_len, k) * (reference_len + 1) <= MAX_EDIT_WORDS {
+        AutoMode::EditDistance
+    } else if divergence <= SMALL_DIVERGENCE {
+        AutoMode::SmallBlock(SMALL_SIZE)
+    } else {
+        let gap = query_len.abs_diff(reference_len);
+        let max_size = cmp::min(cmp::max(2 * gap, MIN_LARGE_SIZE).next_power_of_two(), MAX_LARGE_SIZE);
+        AutoMode::LargeBlock(*SMALL_SIZE.start()..=max_size)
+    }
+}
+
+/// Globally align two strings, automatically choosing the algorithm and block sizes.
+///
+/// The divergence is first estimated from shared k-mers with `kmer_divergence`. If the
+/// sequences look similar, then it is refined with the bit-parallel edit distance, which is
+/// cheap compared to alignment with a scoring matrix. See `auto_mode` for how the algorithm
+/// is chosen.
+pub fn auto_align<M: 'static + Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps) -> AutoAlignment {
+    let len = cmp::max(cmp::max(query.len(), reference.len()), 1);
+    let kmer_div = kmer_divergence(query, reference, AUTO_K);
+    let divergence = if kmer_div > SMALL_DIVERGENCE {
+        kmer_div
+    } else {
+        (edit_distance::<M>(query, reference) as f32) / (len as f32)
+    };
+    let mode = auto_mode(query.len(), reference.len(), divergence);
+
+    let size = match &mode {
+        AutoMode::EditDistance => {
+            let k = edit_cutoff(query.len(), reference.len());
+            let a = align_near_identical(query, reference, matrix, gaps, SMALL_SIZE, k);
+            return AutoAlignment { res: a.res, cigar: a.cigar, divergence, mode };
+        },
+        AutoMode::SmallBlock(size) | AutoMode::LargeBlock(size) => size.clone()
+    };
+
+    let q = PaddedBytes::from_bytes::<M>(query, *size.end());
+    let r = PaddedBytes::from_bytes::<M>(reference, *size.end());
+    let a = Block::<_, true, false>::align(&q, &r, matrix, gaps, size, 0);
+    let res = a.res();
+    AutoAlignment { res, cigar: a.trace().cigar(res.query_idx, res.reference_idx), divergence, mode }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_auto_align() {
+        let gaps = Gaps { open: -11, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+        let r = rand_str(1000, &AMINO_ACIDS, &mut rng);
+
+        let a = auto_align(&r, &r, &BLOSUM62, gaps);
+        assert_eq!(a.mode, AutoMode::EditDistance);
+        assert_eq!(a.divergence, 0.0);
+        assert_eq!(a.cigar.to_string(), "1000M");
+
+        let q = rand_mutate(&r, 50, &AMINO_ACIDS, &mut rng);
+        let a = auto_align(&q, &r, &BLOSUM62, gaps);
+        assert_eq!(a.mode, AutoMode::SmallBlock(32..=128));
+        let q_padded = PaddedBytes::from_bytes::<AAMatrix>(&q, 128);
+        let r_padded = PaddedBytes::from_bytes::<AAMatrix>(&r, 128);
+        let b = Block::<_, false, false>::align(&q_padded, &r_padded, &BLOSUM62, gaps, 32..=128, 0);
+        assert_eq!(a.res, b.res());
+
+        let q = rand_str(700, &AMINO_ACIDS, &mut rng);
+        let a = auto_align(&q, &r, &BLOSUM62, gaps);
+        assert_eq!(a.mode, AutoMode::LargeBlock(32..=1024));
+
+        assert_eq!(auto_mode(100, 100, 0.5), AutoMode::LargeBlock(32..=256));
+        assert_eq!(auto_mode(100000, 100000, 0.0), AutoMode::EditDistance);
+        assert_eq!(auto_mode(1000000, 1000000, 0.0), AutoMode::SmallBlock(32..=128));
+    }
+
+    #[test]
+    fn test_kmer_divergence() {
+        let mut rng = StdRng::seed_from_u64(1234);
+        let r = rand_str(10000, &NUC, &mut rng);
+        assert_eq!(kmer_divergence(&r, &r.to_ascii_lowercase(), 16), 0.0);
+
+        let mut q = r.clone();
+        for i in (0..q.len()).step_by(50) {
+            q[i] = if q[i] == b'A' { b'C' } else { b'A' };
+        }
+        let d = kmer_divergence(&q, &r, 16);
+        assert!((d - 0.02).abs() < 0.005);
+
+        let u = rand_str(10000, &NUC, &mut rng);
+        assert_eq!(kmer_divergence(&u, &r, 16), 1.0);
+        assert_eq!(kmer_divergence(b"ACGT", b"ACGA", 16), 1.0);
+    }
+}
@@ -3,6 +3,8 @@ use std::arch::x86::*;
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
+use crate::backend::SimdBackend;
+
 pub type Simd = __m256i;
 pub type HalfSimd = __m128i;
 pub type TraceType = i32;
@@ -13,6 +15,13 @@ pub const HALFSIMD_MUL: usize = 1;
 pub const ZERO: i16 = 1 << 14;
 pub const MIN: i16 = 0;
 
+/// Name of the instruction set.
+pub const NAME: &str = "avx2";
+
+/// Check whether the CPU that is running the code supports the instruction set.
+#[inline]
+pub fn is_supported() -> bool { is_x86_feature_detected!("avx2") }
+
 // Non-temporal store to avoid cluttering cache with traces
 #[target_feature(enable = "avx2")]
 #[inline]
@@ -326,6 +335,21 @@ pub unsafe fn halfsimd_lookup_bytes_i16(match_scores: HalfSimd, mismatch_scores:
     _mm256_cvtepi8_epi16(c)
 }
 
+#[target_feature(enable = "avx2")]
+#[inline]
+pub unsafe fn halfsimd_gather_i16(table: *const i8, v: HalfSimd) -> Simd {
+    // gather 32-bit words at each byte offset and only keep the lowest byte,
+    // so the table must be padded with at least 3 extra bytes
+    let idx1 = _mm256_cvtepu8_epi32(v);
+    let idx2 = _mm256_cvtepu8_epi32(_mm_srli_si128(v, 8));
+    let a = _mm256_i32gather_epi32(table as *const i32, idx1, 1);
+    let b = _mm256_i32gather_epi32(table as *const i32, idx2, 1);
+    let a = _mm256_srai_epi32(_mm256_slli_epi32(a, 24), 24);
+    let b = _mm256_srai_epi32(_mm256_slli_epi32(b, 24), 24);
+    // packing interleaves 128-bit lanes, so they must be reordered
+    _mm256_permute4x64_epi64(_mm256_packs_epi32(a, b), 0b11011000)
+}
+
 #[target_feature(enable = "avx2")]
 #[inline]
 pub unsafe fn halfsimd_load(ptr: *const HalfSimd) -> HalfSimd { _mm_load_si128(ptr) }
@@ -368,16 +392,23 @@ macro_rules! halfsimd_sr_i8 {
 #[target_feature(enable = "avx2")]
 #[allow(dead_code)]
 pub unsafe fn simd_dbg_i16(v: Simd) {
+    println!("{}", simd_fmt_i16(v));
+}
+
+#[target_feature(enable = "avx2")]
+#[allow(dead_code)]
+pub unsafe fn simd_fmt_i16(v: Simd) -> String {
     #[repr(align(32))]
     struct A([i16; L]);
 
     let mut a = A([0i16; L]);
     simd_store(a.0.as_mut_ptr() as *mut Simd, v);
 
+    let mut res = String::new();
     for i in (0..a.0.len()).rev() {
-        print!("{:6} ", a.0[i]);
+        res.push_str(&format!("{:6} ", a.0[i]));
     }
-    println!();
+    res
 }
 
 #[target_feature(enable = "avx2")]
@@ -417,6 +448,85 @@ pub unsafe fn halfsimd_assert_vec_eq(a: HalfSimd, b: [i8; L]) {
     assert_eq!(arr.0, b);
 }
 
+/// Marker type for the AVX2 backend.
+#[derive(Copy, Clone, Debug)]
+pub struct Avx2;
+
+// intrinsics need literal lane indexes and shift amounts, so const generic arguments are
+// matched against every possible value, and only the matching arm is compiled
+macro_rules! match_imm {
+    ($n:expr, $imm:ident in [$($v:literal),*] => $body:expr) => {
+        match $n {
+            $($v => { const $imm: usize = $v; $body })*
+            _ => unreachable!()
+        }
+    };
+}
+
+unsafe impl SimdBackend for Avx2 {
+    type Simd = Simd;
+    type HalfSimd = HalfSimd;
+    type PrefixScanConsts = PrefixScanConsts;
+
+    const NAME: &'static str = NAME;
+
+    #[inline]
+    fn is_supported() -> bool { is_supported() }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn with_features<R, F: FnOnce() -> R>(f: F) -> R { f() }
+
+    #[inline] unsafe fn store_trace(ptr: *mut TraceType, trace: TraceType) { store_trace(ptr, trace) }
+    #[inline] unsafe fn simd_adds_i16(a: Simd, b: Simd) -> Simd { simd_adds_i16(a, b) }
+    #[inline] unsafe fn simd_subs_i16(a: Simd, b: Simd) -> Simd { simd_subs_i16(a, b) }
+    #[inline] unsafe fn simd_max_i16(a: Simd, b: Simd) -> Simd { simd_max_i16(a, b) }
+    #[inline] unsafe fn simd_cmpeq_i16(a: Simd, b: Simd) -> Simd { simd_cmpeq_i16(a, b) }
+    #[inline] unsafe fn simd_blend_i8(a: Simd, b: Simd, mask: Simd) -> Simd { simd_blend_i8(a, b, mask) }
+    #[inline] unsafe fn simd_load(ptr: *const Simd) -> Simd { simd_load(ptr) }
+    #[inline] unsafe fn simd_store(ptr: *mut Simd, a: Simd) { simd_store(ptr, a) }
+    #[inline] unsafe fn simd_set1_i16(v: i16) -> Simd { simd_set1_i16(v) }
+
+    #[inline]
+    unsafe fn simd_extract_i16<const I: usize>(a: Simd) -> i16 {
+        match_imm!(I, J in [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15] => simd_extract_i16!(a, J))
+    }
+
+    #[inline]
+    unsafe fn simd_insert_i16<const I: usize>(a: Simd, v: i16) -> Simd {
+        match_imm!(I, J in [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15] => simd_insert_i16!(a, v, J))
+    }
+
+    #[inline] unsafe fn simd_movemask_i8(a: Simd) -> u32 { simd_movemask_i8(a) }
+
+    #[inline]
+    unsafe fn simd_sl_i16<const N: usize>(a: Simd, b: Simd) -> Simd {
+        match_imm!(N, M in [1, 2, 3, 4, 5, 6, 7, 8] => simd_sl_i16!(a, b, M))
+    }
+
+    #[inline]
+    unsafe fn simd_sr_i16<const N: usize>(a: Simd, b: Simd) -> Simd {
+        match_imm!(N, M in [1, 2, 3, 4, 5, 6, 7, 8] => simd_sr_i16!(a, b, M))
+    }
+
+    #[inline] unsafe fn simd_broadcasthi_i16(v: Simd) -> Simd { simd_broadcasthi_i16(v) }
+    #[inline] unsafe fn simd_slow_extract_i16(v: Simd, i: usize) -> i16 { simd_slow_extract_i16(v, i) }
+    #[inline] unsafe fn simd_hmax_i16(v: Simd) -> i16 { simd_hmax_i16(v) }
+    #[inline] unsafe fn simd_prefix_hadd_i16<const N: usize>(a: Simd) -> i16 { simd_prefix_hadd_i16!(a, N) }
+    #[inline] unsafe fn simd_hargmax_i16(v: Simd, max: i16) -> usize { simd_hargmax_i16(v, max) }
+    #[inline] unsafe fn get_gap_extend_all(gap: i16) -> Simd { get_gap_extend_all(gap) }
+    #[inline] unsafe fn get_prefix_scan_consts(gap: i16) -> PrefixScanConsts { get_prefix_scan_consts(gap) }
+    #[inline] unsafe fn simd_prefix_scan_i16(r_max: Simd, consts: PrefixScanConsts) -> Simd { simd_prefix_scan_i16(r_max, consts) }
+    #[inline] unsafe fn halfsimd_lookup2_i16(lut1: HalfSimd, lut2: HalfSimd, v: HalfSimd) -> Simd { halfsimd_lookup2_i16(lut1, lut2, v) }
+    #[inline] unsafe fn halfsimd_lookup1_i16(lut: HalfSimd, v: HalfSimd) -> Simd { halfsimd_lookup1_i16(lut, v) }
+    #[inline] unsafe fn halfsimd_lookup_bytes_i16(match_scores: HalfSimd, mismatch_scores: HalfSimd, a: HalfSimd, b: HalfSimd) -> Simd { halfsimd_lookup_bytes_i16(match_scores, mismatch_scores, a, b) }
+    #[inline] unsafe fn halfsimd_gather_i16(table: *const i8, v: HalfSimd) -> Simd { halfsimd_gather_i16(table, v) }
+    #[inline] unsafe fn halfsimd_load(ptr: *const HalfSimd) -> HalfSimd { halfsimd_load(ptr) }
+    #[inline] unsafe fn halfsimd_loadu(ptr: *const HalfSimd) -> HalfSimd { halfsimd_loadu(ptr) }
+    #[inline] unsafe fn halfsimd_set1_i8(v: i8) -> HalfSimd { halfsimd_set1_i8(v) }
+    #[inline] unsafe fn simd_fmt_i16(v: Simd) -> String { simd_fmt_i16(v) }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,186 @@
+//! Interface between the core algorithm and the SIMD instruction set backends.
+//!
+//! Each backend is a module, like `avx2` and `simd128`, with a zero-sized marker type that
+//! implements the `SimdBackend` trait. `Block` is generic over the backend, and it uses the
+//! backend that was selected with feature flags (`NativeBackend`) by default. A new backend
+//! (for example, NEON or AVX-512) can be added, including outside of this crate, by
+//! implementing `SimdBackend` for a new marker type, without touching the core algorithm.
+//!
+//! Each backend enables its own target features in `with_features`, so several backends can be
+//! compiled into one binary and selected at runtime with `is_supported`. The portable `scalar`
+//! backend is always compiled, as a fallback and as a reference for testing other backends.
+//!
+//! All backends in a build share the number of 16-bit lanes (`L`) and the trace format
+//! (`TraceType`) of the native backend, since the block sizes and the traceback depend on them.
+//! All scores are stored in 16-bit lanes, with `ZERO` as the biased zero score and
+//! `MIN` as the lowest score.
+
+#[cfg(block_aligner_avx2)]
+pub use crate::avx2::{TraceType, L, L_BYTES, ZERO, MIN};
+
+#[cfg(block_aligner_wasm)]
+pub use crate::simd128::{TraceType, L, L_BYTES, ZERO, MIN};
+
+/// Backend that was selected with feature flags.
+#[cfg(block_aligner_avx2)]
+pub type NativeBackend = crate::avx2::Avx2;
+
+/// Backend that was selected with feature flags.
+#[cfg(block_aligner_wasm)]
+pub type NativeBackend = crate::simd128::Simd128;
+
+/// SIMD functions that the core algorithm is built on.
+///
+/// `Simd` is a vector of `L` 16-bit lanes, and `HalfSimd` is a 16 byte vector that holds a
+/// lookup table or (in its first `L` bytes) a chunk of a string. Lane 0 is the lane that is
+/// first in memory. See the `avx2` backend for the exact semantics of each function.
+///
+/// The core algorithm runs inside `with_features` (its closures are `#[inline(always)]`), so a
+/// backend that needs target features should enable them there and mark its functions as
+/// `#[inline]`, so they are inlined into the core algorithm.
+///
+/// # Safety
+/// The core algorithm reads and writes buffers of `L` lanes through these functions, so `Simd`
+/// must have exactly `L` 16-bit lanes, `HalfSimd` must be 16 bytes, and `simd_movemask_i8`
+/// must set one bit for each of the `2 * L` bytes. All functions require the CPU to support
+/// the instruction set, which is checked with `is_supported` before aligning.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe trait SimdBackend: 'static {
+    type Simd: Copy;
+    type HalfSimd: Copy;
+    type PrefixScanConsts: Copy;
+
+    /// Name of the instruction set.
+    const NAME: &'static str;
+
+    /// Check whether the CPU that is running the code supports the instruction set.
+    fn is_supported() -> bool;
+
+    /// Call `f` from a function that is compiled with the target features of the backend.
+    unsafe fn with_features<R, F: FnOnce() -> R>(f: F) -> R;
+
+    /// Store a trace value, preferably without polluting the cache.
+    unsafe fn store_trace(ptr: *mut TraceType, trace: TraceType);
+    unsafe fn simd_adds_i16(a: Self::Simd, b: Self::Simd) -> Self::Simd;
+    unsafe fn simd_subs_i16(a: Self::Simd, b: Self::Simd) -> Self::Simd;
+    unsafe fn simd_max_i16(a: Self::Simd, b: Self::Simd) -> Self::Simd;
+    unsafe fn simd_cmpeq_i16(a: Self::Simd, b: Self::Simd) -> Self::Simd;
+    /// Select each byte from `b` if the highest bit of the byte in `mask` is set, and from
+    /// `a` otherwise.
+    unsafe fn simd_blend_i8(a: Self::Simd, b: Self::Simd, mask: Self::Simd) -> Self::Simd;
+    unsafe fn simd_load(ptr: *const Self::Simd) -> Self::Simd;
+    unsafe fn simd_store(ptr: *mut Self::Simd, a: Self::Simd);
+    unsafe fn simd_set1_i16(v: i16) -> Self::Simd;
+    /// Get the lane at index `I`.
+    unsafe fn simd_extract_i16<const I: usize>(a: Self::Simd) -> i16;
+    /// Replace the lane at index `I`.
+    unsafe fn simd_insert_i16<const I: usize>(a: Self::Simd, v: i16) -> Self::Simd;
+    /// Get a bitmask with the highest bit of each byte of the vector.
+    unsafe fn simd_movemask_i8(a: Self::Simd) -> u32;
+    /// Shift `a` by `N` lanes towards the last lane, shifting in the last `N` lanes of `b`.
+    unsafe fn simd_sl_i16<const N: usize>(a: Self::Simd, b: Self::Simd) -> Self::Simd;
+    /// Shift `b` by `N` lanes towards the first lane, shifting in the first `N` lanes of `a`.
+    unsafe fn simd_sr_i16<const N: usize>(a: Self::Simd, b: Self::Simd) -> Self::Simd;
+    /// Set every lane to the last lane.
+    unsafe fn simd_broadcasthi_i16(v: Self::Simd) -> Self::Simd;
+    unsafe fn simd_slow_extract_i16(v: Self::Simd, i: usize) -> i16;
+    unsafe fn simd_hmax_i16(v: Self::Simd) -> i16;
+    /// Sum of the first `N` lanes, after subtracting `ZERO` from each lane.
+    unsafe fn simd_prefix_hadd_i16<const N: usize>(a: Self::Simd) -> i16;
+    /// Index of the first lane that is equal to `max`.
+    unsafe fn simd_hargmax_i16(v: Self::Simd, max: i16) -> usize;
+    /// Get a vector where lane `i` is `gap * (i + 1)`.
+    unsafe fn get_gap_extend_all(gap: i16) -> Self::Simd;
+    unsafe fn get_prefix_scan_consts(gap: i16) -> Self::PrefixScanConsts;
+    /// Max of each lane and the lanes before it, plus the gap cost for each lane in between.
+    unsafe fn simd_prefix_scan_i16(r_max: Self::Simd, consts: Self::PrefixScanConsts) -> Self::Simd;
+    /// Look up each byte of `v` in a 32 entry table that is split in two halves.
+    unsafe fn halfsimd_lookup2_i16(lut1: Self::HalfSimd, lut2: Self::HalfSimd, v: Self::HalfSimd) -> Self::Simd;
+    /// Look up the low 4 bits of each byte of `v` in a 16 entry table.
+    unsafe fn halfsimd_lookup1_i16(lut: Self::HalfSimd, v: Self::HalfSimd) -> Self::Simd;
+    /// Select the match score for equal bytes of `a` and `b`, and the mismatch score otherwise.
+    unsafe fn halfsimd_lookup_bytes_i16(match_scores: Self::HalfSimd, mismatch_scores: Self::HalfSimd, a: Self::HalfSimd, b: Self::HalfSimd) -> Self::Simd;
+    /// Look up each byte of `v` in a 256 entry table.
+    unsafe fn halfsimd_gather_i16(table: *const i8, v: Self::HalfSimd) -> Self::Simd;
+    unsafe fn halfsimd_load(ptr: *const Self::HalfSimd) -> Self::HalfSimd;
+    unsafe fn halfsimd_loadu(ptr: *const Self::HalfSimd) -> Self::HalfSimd;
+    unsafe fn halfsimd_set1_i8(v: i8) -> Self::HalfSimd;
+
+    /// Format the lanes of a vector, from the last lane to the first, for debugging.
+    unsafe fn simd_fmt_i16(v: Self::Simd) -> String {
+        #[repr(C, align(32))]
+        struct A([i16; L]);
+
+        let mut a = A([0i16; L]);
+        Self::simd_store(a.0.as_mut_ptr() as *mut Self::Simd, v);
+        a.0.iter().rev().map(|x| format!("{:6} ", x)).collect()
+    }
+}
+
+/// Configuration of the SIMD backend that block aligner was compiled with.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BackendInfo {
+    /// Name of the instruction set, like `"avx2"` or `"simd128"`.
+    pub name: &'static str,
+    /// Number of 16-bit lanes in a SIMD vector.
+    pub lanes: usize,
+    /// Smallest block size that can be used for alignment.
+    ///
+    /// Smaller block sizes are rounded up to this size, so `PaddedBytes` should be padded
+    /// with at least this block size.
+    pub min_block_size: usize,
+    /// Whether the CPU that is running the code supports the instruction set.
+    pub supported: bool
+}
+
+/// Get the configuration of the SIMD backend that block aligner was compiled with.
+///
+/// This is useful for logging and for choosing block sizes at runtime instead of
+/// hardcoding them.
+pub fn backend_info() -> BackendInfo {
+    backend_info_for::<NativeBackend>()
+}
+
+/// Get the configuration of the SIMD backend `B`.
+pub fn backend_info_for<B: SimdBackend>() -> BackendInfo {
+    BackendInfo {
+        name: B::NAME,
+        lanes: L,
+        min_block_size: L,
+        supported: B::is_supported()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_backend() {
+        type B = NativeBackend;
+
+        unsafe {
+            B::with_features(|| {
+                let a = B::simd_set1_i16(3);
+                let b = B::simd_adds_i16(a, B::simd_set1_i16(ZERO));
+                assert_eq!(B::simd_hmax_i16(b), ZERO + 3);
+                assert_eq!(B::simd_hargmax_i16(b, ZERO + 3), 0);
+                let c = B::simd_insert_i16::<{ L - 1 }>(b, ZERO + 5);
+                assert_eq!(B::simd_extract_i16::<{ L - 1 }>(c), ZERO + 5);
+                assert_eq!(B::simd_extract_i16::<0>(B::simd_broadcasthi_i16(c)), ZERO + 5);
+                assert_eq!(B::simd_extract_i16::<1>(B::simd_sl_i16::<1>(c, a)), ZERO + 3);
+                assert_eq!(B::simd_extract_i16::<0>(B::simd_sl_i16::<1>(c, a)), 3);
+                assert_eq!(B::simd_extract_i16::<{ L - 2 }>(B::simd_sr_i16::<1>(a, c)), ZERO + 5);
+                assert_eq!(B::simd_extract_i16::<{ L - 1 }>(B::simd_sr_i16::<1>(a, c)), 3);
+                assert_eq!(B::simd_prefix_hadd_i16::<2>(c), 6);
+                assert_eq!(B::simd_movemask_i8(B::simd_cmpeq_i16(a, a)).count_ones() as usize, L * 2);
+            });
+        }
+
+        let info = backend_info();
+        assert_eq!(info.lanes, L);
+        assert!(info.min_block_size.is_power_of_two() && info.min_block_size >= info.lanes);
+        // the tests could not run otherwise
+        assert!(info.supported);
+    }
+}
@@ -0,0 +1,194 @@
+//! Accuracy benchmarking of block aligner against the scalar reference DP on simulated data.
+//!
+//! This reproduces the kind of accuracy numbers reported in the block aligner paper, but with
+//! your own scoring scheme, sequence lengths, identities, and block sizes. Random pairs of
+//! sequences with a known ground-truth alignment are generated, then each pair is globally
+//! aligned with both block aligner and the full DP in the `reference` module.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::simulate::*;
+use crate::reference;
+
+use rand::prelude::*;
+
+use std::ops::RangeInclusive;
+
+/// Benchmark that compares block aligner with the full DP on simulated sequence pairs.
+pub struct AccuracyBench<'a, M: 'static + Matrix> {
+    matrix: &'a M,
+    gaps: Gaps,
+    size: RangeInclusive<usize>,
+    len: usize,
+    pairs: usize,
+    indel_frac: f32,
+    alpha: &'a [u8],
+    seed: u64
+}
+
+impl<'a, M: 'static + Matrix> AccuracyBench<'a, M> {
+    /// Create a new benchmark with the scoring scheme and range of block sizes to evaluate.
+    ///
+    /// By default, 100 pairs of nucleotide sequences of length 1000 are simulated, with
+    /// 20% of the edits being indels.
+    pub fn new(matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>) -> Self {
+        Self {
+            matrix,
+            gaps,
+            size,
+            len: 1000,
+            pairs: 100,
+            indel_frac: 0.2,
+            alpha: &NUC,
+            seed: 1234
+        }
+    }
+
+    /// Set the length of the simulated reference sequences.
+    pub fn len(mut self, len: usize) -> Self {
+        self.len = len;
+        self
+    }
+
+    /// Set the number of simulated pairs for each identity level.
+    pub fn pairs(mut self, pairs: usize) -> Self {
+        assert!(pairs > 0, "Number of pairs must be positive!");
+        self.pairs = pairs;
+        self
+    }
+
+    /// Set the fraction of edits that are indels.
+    pub fn indel_frac(mut self, indel_frac: f32) -> Self {
+        self.indel_frac = indel_frac;
+        self
+    }
+
+    /// Set the alphabet used for simulating sequences, like `NUC` or `AMINO_ACIDS`.
+    pub fn alpha(mut self, alpha: &'a [u8]) -> Self {
+        assert!(alpha.len() >= 2, "Alphabet must have at least two characters!");
+        self.alpha = alpha;
+        self
+    }
+
+    /// Set the seed of the random number generator.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Run the benchmark for a single identity level.
+    pub fn run(&self, identity: f32) -> AccuracyReport {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut score_diffs = Vec::with_capacity(self.pairs);
+        let mut cigar_recall = 0.0;
+
+        for _i in 0..self.pairs {
+            let (q, r, truth) = rand_pair_identity(self.len, identity, self.indel_frac, self.alpha, &mut rng);
+            let q_padded = PaddedBytes::from_bytes::<M>(&q, *self.size.end());
+            let r_padded = PaddedBytes::from_bytes::<M>(&r, *self.size.end());
+            let a = Block::<_, true, false>::align(&q_padded, &r_padded, self.matrix, self.gaps, self.size.clone(), 0);
+            let res = a.res();
+            let optimal = reference::global(&q, &r, self.matrix, self.gaps);
+            score_diffs.push(optimal.score - res.score);
+
+            // fraction of the ground-truth aligned pairs that are recovered
+            let mut pairs = a.trace().cigar(res.query_idx, res.reference_idx)
+                .aligned_pairs(0, 0)
+                .filter_map(|p| match p { (Some(i), Some(j)) => Some((i, j)), _ => None })
+                .collect::<Vec<_>>();
+            pairs.sort_unstable();
+            let (mut total, mut found) = (0usize, 0usize);
+            for p in truth.aligned_pairs(0, 0) {
+                if let (Some(i), Some(j)) = p {
+                    total += 1;
+                    found += pairs.binary_search(&(i, j)).is_ok() as usize;
+                }
+            }
+            cigar_recall += if total == 0 { 1.0 } else { (found as f64) / (total as f64) };
+        }
+
+        score_diffs.sort_unstable();
+        AccuracyReport {
+            identity,
+            score_diffs,
+            cigar_recall: cigar_recall / (self.pairs as f64)
+        }
+    }
+
+    /// Run the benchmark for multiple identity levels.
+    pub fn sweep(&self, identities: &[f32]) -> Vec<AccuracyReport> {
+        identities.iter().map(|&identity| self.run(identity)).collect()
+    }
+}
+
+/// Accuracy of block aligner for a single identity level.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AccuracyReport {
+    /// Target identity of the simulated pairs.
+    pub identity: f32,
+    /// Sorted differences between the optimal score and the block aligner score.
+    ///
+    /// These are never negative, and they are zero when block aligner is exact.
+    pub score_diffs: Vec<i32>,
+    /// Mean fraction of the ground-truth aligned pairs (matches and mismatches) that are
+    /// also in the block aligner alignment.
+    pub cigar_recall: f64
+}
+
+impl AccuracyReport {
+    /// Number of simulated pairs.
+    pub fn pairs(&self) -> usize {
+        self.score_diffs.len()
+    }
+
+    /// Fraction of pairs where block aligner finds the optimal score.
+    pub fn frac_exact(&self) -> f64 {
+        (self.score_diffs.iter().filter(|&&d| d == 0).count() as f64) / (self.pairs() as f64)
+    }
+
+    /// Mean difference between the optimal score and the block aligner score.
+    pub fn mean_score_diff(&self) -> f64 {
+        (self.score_diffs.iter().map(|&d| d as i64).sum::<i64>() as f64) / (self.pairs() as f64)
+    }
+
+    /// Max difference between the optimal score and the block aligner score.
+    pub fn max_score_diff(&self) -> i32 {
+        *self.score_diffs.last().unwrap()
+    }
+
+    /// Histogram of score differences, as sorted pairs of (score difference, count).
+    pub fn score_diff_histogram(&self) -> Vec<(i32, usize)> {
+        let mut res: Vec<(i32, usize)> = Vec::new();
+        for &d in &self.score_diffs {
+            match res.last_mut() {
+                Some((prev, count)) if *prev == d => *count += 1,
+                _ => res.push((d, 1))
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accuracy_bench() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let bench = AccuracyBench::new(&NW1, gaps, 32..=256).len(200).pairs(10);
+        let reports = bench.sweep(&[1.0, 0.9]);
+
+        assert_eq!(reports[0].pairs(), 10);
+        assert_eq!(reports[0].frac_exact(), 1.0);
+        assert_eq!(reports[0].max_score_diff(), 0);
+        assert_eq!(reports[0].score_diff_histogram(), vec![(0, 10)]);
+        assert_eq!(reports[0].cigar_recall, 1.0);
+
+        // the block covers the entire DP matrix, so it is exact
+        let report = AccuracyBench::new(&NW1, gaps, 256..=256).len(200).pairs(10).run(0.8);
+        assert_eq!(report.mean_score_diff(), 0.0);
+        assert!(reports[1].score_diffs.iter().all(|&d| d >= 0));
+        assert!(reports[1].cigar_recall > 0.5);
+    }
+}
@@ -0,0 +1,344 @@
+//! Command-line tool for aligning sequences from FASTA/FASTQ files with block aligner.
+//!
+//! Build with `cargo build --release --features simd_avx2,cli`. The SIMD backend is only
+//! used if the CPU supports it, and the portable scalar backend is used otherwise.
+
+use block_aligner::backend::{NativeBackend, SimdBackend};
+use block_aligner::scalar::Scalar;
+use block_aligner::scan_block::*;
+use block_aligner::scores::*;
+use block_aligner::cigar::*;
+use block_aligner::fasta::*;
+
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::{env, fs, process};
+
+const USAGE: &str = "Usage: block-aligner-cli [options] <queries> <references>
+
+Aligns the i-th query with the i-th reference in the FASTA/FASTQ files, or the
+query and reference pairs listed in a pair file.
+
+Options:
+  --pairs <file>        Tab-separated query and reference IDs to align, one pair per line
+  --mode <mode>         global, glocal (query end-to-end), or xdrop [default: global]
+  --matrix <name>       nuc, blosum45, blosum50, blosum62, blosum80, blosum90, pam100,
+                        pam120, pam160, pam200, pam250, or bytes [default: nuc]
+  --match <score>       Match score for nuc and bytes matrices [default: 1]
+  --mismatch <score>    Mismatch score for nuc and bytes matrices [default: -1]
+  --gap-open <score>    Gap open score, including the first extension [default: -2]
+  --gap-extend <score>  Gap extend score [default: -1]
+  --min-size <size>     Minimum block size [default: 32]
+  --max-size <size>     Maximum block size [default: 256]
+  --x-drop <score>      X-drop threshold for xdrop mode [default: 50]
+  --format <format>     score, cigar, sam, or paf [default: cigar]
+  -h, --help            Print this message";
+
+#[derive(Copy, Clone, PartialEq)]
+enum Mode {
+    Global,
+    Glocal,
+    XDrop
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Score,
+    Cigar,
+    Sam,
+    Paf
+}
+
+struct Args {
+    queries: String,
+    references: String,
+    pairs: Option<String>,
+    mode: Mode,
+    matrix: String,
+    match_score: i8,
+    mismatch_score: i8,
+    gaps: Gaps,
+    size: RangeInclusive<usize>,
+    x_drop: i32,
+    format: Format
+}
+
+fn num<T: std::str::FromStr>(s: String) -> Result<T, String> {
+    s.parse().map_err(|_| format!("Invalid number: {}!", s))
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut files = Vec::new();
+    let mut pairs = None;
+    let mut mode = Mode::Global;
+    let mut matrix = "nuc".to_owned();
+    let (mut match_score, mut mismatch_score) = (1i8, -1i8);
+    let mut gaps = Gaps { open: -2, extend: -1 };
+    let (mut min_size, mut max_size) = (32usize, 256usize);
+    let mut x_drop = 50i32;
+    let mut format = Format::Cigar;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}!", arg));
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            "--pairs" => pairs = Some(value()?),
+            "--mode" => mode = match value()?.as_str() {
+                "global" => Mode::Global,
+                "glocal" => Mode::Glocal,
+                "xdrop" => Mode::XDrop,
+                m => return Err(format!("Unknown mode: {}!", m))
+            },
+            "--matrix" => matrix = value()?.to_ascii_lowercase(),
+            "--match" => match_score = num(value()?)?,
+            "--mismatch" => mismatch_score = num(value()?)?,
+            "--gap-open" => gaps.open = num(value()?)?,
+            "--gap-extend" => gaps.extend = num(value()?)?,
+            "--min-size" => min_size = num(value()?)?,
+            "--max-size" => max_size = num(value()?)?,
+            "--x-drop" => x_drop = num(value()?)?,
+            "--format" => format = match value()?.as_str() {
+                "score" => Format::Score,
+                "cigar" => Format::Cigar,
+                "sam" => Format::Sam,
+                "paf" => Format::Paf,
+                f => return Err(format!("Unknown format: {}!", f))
+            },
+            a if a.starts_with('-') => return Err(format!("Unknown option: {}!", a)),
+            _ => files.push(arg)
+        }
+    }
+
+    if files.len() != 2 {
+        return Err("Expected query and reference files!".to_owned());
+    }
+    for (name, size) in [("Minimum", min_size), ("Maximum", max_size)] {
+        if !size.is_power_of_two() || size < block_aligner::L {
+            return Err(format!("{} block size must be a power of two that is at least {}!", name, block_aligner::L));
+        }
+    }
+    if min_size > max_size {
+        return Err("Minimum block size must not be greater than the maximum block size!".to_owned());
+    }
+    let references = files.pop().unwrap();
+    let queries = files.pop().unwrap();
+
+    Ok(Args {
+        queries,
+        references,
+        pairs,
+        mode,
+        matrix,
+        match_score,
+        mismatch_score,
+        gaps,
+        size: min_size..=max_size,
+        x_drop,
+        format
+    })
+}
+
+/// Get the pairs of query and reference indexes to align.
+fn get_pairs(args: &Args, queries: &[Record], references: &[Record]) -> Result<Vec<(usize, usize)>, String> {
+    let path = match &args.pairs {
+        Some(path) => path,
+        None => {
+            if queries.len() != references.len() {
+                return Err("The number of queries and references must be the same without a pair file!".to_owned());
+            }
+            return Ok((0..queries.len()).map(|i| (i, i)).collect());
+        }
+    };
+
+    let q_idxs = queries.iter().enumerate().map(|(i, r)| (r.id.as_str(), i)).collect::<HashMap<_, _>>();
+    let r_idxs = references.iter().enumerate().map(|(i, r)| (r.id.as_str(), i)).collect::<HashMap<_, _>>();
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    contents
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let mut ids = l.split_whitespace();
+            let (q, r) = (ids.next().unwrap(), ids.next().ok_or_else(|| format!("Invalid pair: {}!", l))?);
+            let q = *q_idxs.get(q).ok_or_else(|| format!("Unknown query: {}!", q))?;
+            let r = *r_idxs.get(r).ok_or_else(|| format!("Unknown reference: {}!", r))?;
+            Ok((q, r))
+        })
+        .collect()
+}
+
+/// Count the number of matching residues in an alignment.
+fn count_matches(cigar: &Cigar, q: &[u8], r: &[u8], q_start: usize, r_start: usize) -> usize {
+    cigar
+        .aligned_pairs(q_start, r_start)
+        .filter(|&(i, j)| match (i, j) {
+            (Some(i), Some(j)) => q[i].eq_ignore_ascii_case(&r[j]),
+            _ => false
+        })
+        .count()
+}
+
+fn run<B: SimdBackend, M: 'static + Matrix>(args: &Args, matrix: &M, queries: &[Record], references: &[Record], pairs: &[(usize, usize)]) -> io::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let block_size = *args.size.end();
+
+    if args.format == Format::Sam {
+        writeln!(out, "@HD\tVN:1.6\tSO:unsorted")?;
+        for r in references {
+            writeln!(out, "@SQ\tSN:{}\tLN:{}", r.id, r.seq.len())?;
+        }
+        writeln!(out, "@PG\tID:block-aligner\tPN:block-aligner-cli")?;
+    }
+
+    for &(q_idx, r_idx) in pairs {
+        let (q, r) = (&queries[q_idx], &references[r_idx]);
+        let q_padded = PaddedBytes::from_bytes::<M>(&q.seq, block_size);
+        let r_padded = PaddedBytes::from_bytes::<M>(&r.seq, block_size);
+
+        if args.format == Format::Score {
+            let res = match args.mode {
+                Mode::Global => Block::<_, false, false, false, B>::align(&q_padded, &r_padded, matrix, args.gaps, args.size.clone(), 0).res(),
+                Mode::Glocal => Block::<_, false, false, false, B>::align_with(&q_padded, &r_padded, matrix, args.gaps, args.size.clone(), 0, &AlignOptions::new().glocal()).res(),
+                Mode::XDrop => Block::<_, false, true, false, B>::align(&q_padded, &r_padded, matrix, args.gaps, args.size.clone(), args.x_drop).res()
+            };
+            writeln!(out, "{}\t{}\t{}\t{}\t{}", q.id, r.id, res.score, res.query_idx, res.reference_idx)?;
+            continue;
+        }
+
+        // CIGAR string covering the whole query, and the query and reference positions where
+        // the traceback path starts
+        let (res, (q_start, r_start), cigar) = match args.mode {
+            Mode::Global => {
+                let a = Block::<_, true, false, false, B>::align(&q_padded, &r_padded, matrix, args.gaps, args.size.clone(), 0);
+                let res = a.res();
+                (res, a.trace().path_start(res.query_idx, res.reference_idx), a.trace().cigar(res.query_idx, res.reference_idx))
+            },
+            Mode::Glocal => {
+                let a = Block::<_, true, false, false, B>::align_with(&q_padded, &r_padded, matrix, args.gaps, args.size.clone(), 0, &AlignOptions::new().glocal());
+                let res = a.res();
+                (res, a.trace().path_start(res.query_idx, res.reference_idx), a.trace().cigar(res.query_idx, res.reference_idx))
+            },
+            Mode::XDrop => {
+                let a = Block::<_, true, true, false, B>::align(&q_padded, &r_padded, matrix, args.gaps, args.size.clone(), args.x_drop);
+                let res = a.res();
+                (res, a.trace().path_start(res.query_idx, res.reference_idx), a.trace().cigar_soft_clipped(res.query_idx, res.reference_idx))
+            }
+        };
+
+        match args.format {
+            Format::Cigar => {
+                writeln!(out, "{}\t{}\t{}\t{}", q.id, r.id, res.score, cigar)?;
+            },
+            Format::Sam => {
+                let qual = q.qual.as_ref().map(|qual| String::from_utf8_lossy(qual).into_owned()).unwrap_or_else(|| "*".to_owned());
+                writeln!(
+                    out,
+                    "{}\t0\t{}\t{}\t255\t{}\t*\t0\t0\t{}\t{}\tAS:i:{}",
+                    q.id,
+                    r.id,
+                    r_start + 1,
+                    cigar,
+                    String::from_utf8_lossy(&q.seq),
+                    qual,
+                    res.score
+                )?;
+            },
+            Format::Paf => {
+                // soft clips cover the unaligned query prefix, so the CIGAR string starts at 0
+                let q_end = res.query_idx;
+                let matches = count_matches(&cigar, &q.seq, &r.seq, 0, r_start);
+                let aligned = cigar
+                    .to_vec()
+                    .iter()
+                    .filter(|op_len| op_len.op != Operation::S)
+                    .map(|op_len| op_len.len)
+                    .sum::<usize>();
+                let cg = cigar
+                    .to_vec()
+                    .iter()
+                    .filter(|op_len| op_len.op != Operation::S)
+                    .map(|op_len| format!("{}{}", op_len.len, match op_len.op { Operation::I => 'I', Operation::D => 'D', Operation::N => 'N', _ => 'M' }))
+                    .collect::<String>();
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t+\t{}\t{}\t{}\t{}\t{}\t{}\t255\tAS:i:{}\tcg:Z:{}",
+                    q.id,
+                    q.seq.len(),
+                    q_start,
+                    q_end,
+                    r.id,
+                    r.seq.len(),
+                    r_start,
+                    res.reference_idx,
+                    matches,
+                    aligned,
+                    res.score,
+                    cg
+                )?;
+            },
+            Format::Score => unreachable!()
+        }
+    }
+
+    out.flush()
+}
+
+fn run_matrix<B: SimdBackend>(args: &Args, queries: &[Record], references: &[Record], pairs: &[(usize, usize)]) -> io::Result<()> {
+    match args.matrix.as_str() {
+        "nuc" => run::<B, _>(args, &NucMatrix::new_simple(args.match_score, args.mismatch_score), queries, references, pairs),
+        "bytes" => run::<B, _>(args, &FullMatrix::new_simple(args.match_score, args.mismatch_score), queries, references, pairs),
+        "blosum45" => run::<B, _>(args, &BLOSUM45, queries, references, pairs),
+        "blosum50" => run::<B, _>(args, &BLOSUM50, queries, references, pairs),
+        "blosum62" => run::<B, _>(args, &BLOSUM62, queries, references, pairs),
+        "blosum80" => run::<B, _>(args, &BLOSUM80, queries, references, pairs),
+        "blosum90" => run::<B, _>(args, &BLOSUM90, queries, references, pairs),
+        "pam100" => run::<B, _>(args, &PAM100, queries, references, pairs),
+        "pam120" => run::<B, _>(args, &PAM120, queries, references, pairs),
+        "pam160" => run::<B, _>(args, &PAM160, queries, references, pairs),
+        "pam200" => run::<B, _>(args, &PAM200, queries, references, pairs),
+        "pam250" => run::<B, _>(args, &PAM250, queries, references, pairs),
+        m => {
+            eprintln!("Unknown matrix: {}!\n\n{}", m, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let read = |path: &str| read_file(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let queries = read(&args.queries);
+    let references = read(&args.references);
+    let pairs = get_pairs(&args, &queries, &references).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    // use the native SIMD backend if the CPU supports it, and fall back to the portable backend
+    let res = if NativeBackend::is_supported() {
+        run_matrix::<NativeBackend>(&args, &queries, &references, &pairs)
+    } else {
+        run_matrix::<Scalar>(&args, &queries, &references, &pairs)
+    };
+
+    if let Err(e) = res {
+        // stop quietly when the output is closed early (for example, by `head`)
+        if e.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
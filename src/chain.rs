@@ -0,0 +1,383 @@
+//! Colinear chaining of multiple local hits (HSPs) between a query and a reference.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::ops::RangeInclusive;
+
+/// A high-scoring segment pair, which is a local alignment between the query range
+/// `query_start..query_end` and the reference range `reference_start..reference_end`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Hsp {
+    pub query_start: usize,
+    pub query_end: usize,
+    pub reference_start: usize,
+    pub reference_end: usize,
+    pub score: i32
+}
+
+/// A chain of compatible hits, in increasing order of their positions.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Chain {
+    /// Indexes of the hits in the chain.
+    pub hits: Vec<usize>,
+    /// Chaining score, which is the sum of the hit scores minus the gap costs between hits.
+    pub score: i32
+}
+
+/// Cost of the gap between two consecutive hits in a chain.
+///
+/// Hits on the same diagonal are free to chain. Otherwise, the difference between the
+/// query and reference distances is charged as an affine gap.
+#[inline]
+fn chain_gap_cost(prev: &Hsp, next: &Hsp, gaps: Gaps) -> i32 {
+    let dq = (next.query_start - prev.query_end) as i32;
+    let dr = (next.reference_start - prev.reference_end) as i32;
+    let diff = (dq - dr).abs();
+    if diff == 0 { 0 } else { (gaps.open as i32) + (gaps.extend as i32) * (diff - 1) }
+}
+
+/// Find the best chain of colinear, non-overlapping hits with chaining DP.
+///
+/// Each hit in the chain must end before the next hit starts in both the query and the
+/// reference. Returns `None` if there are no hits.
+pub fn chain_hits(hits: &[Hsp], gaps: Gaps) -> Option<Chain> {
+    if hits.is_empty() {
+        return None;
+    }
+
+    for h in hits {
+        assert!(h.query_start <= h.query_end && h.reference_start <= h.reference_end, "Hit ranges must not be reversed!");
+    }
+
+    let mut order = (0..hits.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| (hits[i].query_start, hits[i].reference_start));
+
+    // best chain score ending at each hit (in sorted order), and the previous hit
+    let mut f = vec![0i32; hits.len()];
+    let mut prev = vec![usize::MAX; hits.len()];
+
+    for j in 0..order.len() {
+        let h_j = &hits[order[j]];
+        f[j] = h_j.score;
+
+        for i in 0..j {
+            let h_i = &hits[order[i]];
+            if h_i.query_end > h_j.query_start || h_i.reference_end > h_j.reference_start {
+                continue;
+            }
+            let s = f[i] + chain_gap_cost(h_i, h_j, gaps) + h_j.score;
+            if s > f[j] {
+                f[j] = s;
+                prev[j] = i;
+            }
+        }
+    }
+
+    let mut j = (0..order.len()).max_by_key(|&j| f[j]).unwrap();
+    let score = f[j];
+    let mut res = vec![order[j]];
+    while prev[j] != usize::MAX {
+        j = prev[j];
+        res.push(order[j]);
+    }
+    res.reverse();
+
+    Some(Chain { hits: res, score })
+}
+
+/// Close the gaps between consecutive hits in a chain with global block alignment.
+///
+/// Returns the total score, which is the sum of the hit scores and the scores of the
+/// global alignments between hits, and the CIGAR string for the whole chain, which
+/// concatenates the CIGAR strings of the hits and the alignments between them.
+/// Hits with the same length in the query and the reference are assumed to be gapless,
+/// and other hits are globally aligned to get their CIGAR strings.
+/// `query` and `reference` are the unpadded strings.
+pub fn close_gaps<M: 'static + Matrix>(chain: &Chain, hits: &[Hsp], query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>) -> (i32, Cigar) {
+    let mut score = 0;
+    let mut cigar = unsafe { Cigar::new(0) };
+
+    for (k, &h) in chain.hits.iter().enumerate() {
+        let hit = &hits[h];
+
+        if k > 0 {
+            let p = &hits[chain.hits[k - 1]];
+            let q = &query[p.query_end..hit.query_start];
+            let r = &reference[p.reference_end..hit.reference_start];
+            let (s, c) = global_cigar(q, r, matrix, gaps, size.clone());
+            score += s;
+            cigar.append_cigar(&c);
+        }
+
+        score += hit.score;
+        let q = &query[hit.query_start..hit.query_end];
+        let r = &reference[hit.reference_start..hit.reference_end];
+        if q.len() == r.len() {
+            cigar.append_cigar(&op_cigar(Operation::M, q.len()));
+        } else {
+            cigar.append_cigar(&global_cigar(q, r, matrix, gaps, size.clone()).1);
+        }
+    }
+
+    (score, cigar)
+}
+
+/// CIGAR string with a single operation repeated `len` times.
+fn op_cigar(op: Operation, len: usize) -> Cigar {
+    let mut c = unsafe { Cigar::new(len) };
+    for _i in 0..len {
+        unsafe { c.add(op); }
+    }
+    c
+}
+
+/// Globally align two unpadded strings and return the score and the CIGAR string.
+///
+/// If one of the strings is empty, then the alignment is a single gap.
+fn global_cigar<M: 'static + Matrix>(q: &[u8], r: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>) -> (i32, Cigar) {
+    match (q.len(), r.len()) {
+        (0, 0) => (0, unsafe { Cigar::new(0) }),
+        (0, len) | (len, 0) => {
+            let op = if q.is_empty() { Operation::D } else { Operation::I };
+            ((gaps.open as i32) + (gaps.extend as i32) * ((len as i32) - 1), op_cigar(op, len))
+        },
+        _ => {
+            let q_padded = PaddedBytes::from_bytes::<M>(q, *size.end());
+            let r_padded = PaddedBytes::from_bytes::<M>(r, *size.end());
+            let a = Block::<_, true, false>::align(&q_padded, &r_padded, matrix, gaps, size, 0);
+            let res = a.res();
+            (res.score, a.trace().cigar(res.query_idx, res.reference_idx))
+        }
+    }
+}
+
+/// Globally align the query and the reference, with the constraint that the alignment
+/// must pass through each of the anchor cells.
+///
+/// An anchor `(i, j)` is a cell in the DP matrix, so the alignment must align `query[..i]`
+/// to `reference[..j]` (for example, at the boundaries of trusted exact matches).
+/// Anchors must be sorted and within bounds. The segments between anchors are aligned
+/// independently with global block alignment and their CIGAR strings are concatenated,
+/// so a gap that spans an anchor is charged the gap open cost on both sides.
+///
+/// Returns the total score and the CIGAR string for the whole alignment.
+/// `query` and `reference` are the unpadded strings.
+pub fn align_anchored<M: 'static + Matrix>(query: &[u8], reference: &[u8], anchors: &[(usize, usize)], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>) -> (i32, Cigar) {
+    let mut prev = (0, 0);
+    for &(i, j) in anchors {
+        assert!(i >= prev.0 && j >= prev.1, "Anchors must be sorted!");
+        prev = (i, j);
+    }
+    assert!(prev.0 <= query.len() && prev.1 <= reference.len(), "Anchors must be within bounds!");
+
+    let mut score = 0;
+    let mut cigar = unsafe { Cigar::new(0) };
+    let mut start = (0, 0);
+
+    for &end in anchors.iter().chain(std::iter::once(&(query.len(), reference.len()))) {
+        let q = &query[start.0..end.0];
+        let r = &reference[start.1..end.1];
+        start = end;
+
+        let (s, c) = global_cigar(q, r, matrix, gaps, size.clone());
+        score += s;
+        cigar.append_cigar(&c);
+    }
+
+    (score, cigar)
+}
+
+/// Extend an existing partial alignment from the cell where it ends.
+///
+/// `prefix` is a CIGAR string for an alignment that starts at the beginning of both strings
+/// and `prefix_score` is its score. If it ends at the cell `(i, j)`, then only `query[i..]`
+/// and `reference[j..]` are aligned with X-drop block alignment, so the prefix does not need
+/// to be recomputed when an alignment is extended incrementally. Soft and hard clips are
+/// only allowed at the start of the prefix, and leading soft clips count towards `i`.
+/// Skipped reference regions count towards `j`.
+///
+/// If the prefix ends with a gap, then the extension starts in the same gap state, so a gap
+/// that continues across the end of the prefix is only charged the gap open cost once.
+///
+/// Returns the end of the extended alignment in the full strings along with the total score,
+/// and the CIGAR string for the whole alignment.
+/// `query` and `reference` are the unpadded strings.
+#[allow(clippy::too_many_arguments)]
+pub fn extend_alignment<M: 'static + Matrix>(query: &[u8], reference: &[u8], prefix: &Cigar, prefix_score: i32, matrix: &M, gaps: Gaps, size: RangeInclusive<usize>, x_drop: i32) -> (AlignResult, Cigar) {
+    let prefix_ops = prefix.to_vec();
+    let (mut i, mut j) = (0, 0);
+    let mut aligned = false;
+    for op_len in &prefix_ops {
+        match op_len.op {
+            Operation::M => { i += op_len.len; j += op_len.len; },
+            Operation::I => i += op_len.len,
+            Operation::D | Operation::N => j += op_len.len,
+            Operation::S => {
+                assert!(!aligned, "Clips are only allowed at the start of the prefix alignment!");
+                i += op_len.len;
+            },
+            Operation::H => assert!(!aligned, "Clips are only allowed at the start of the prefix alignment!"),
+            Operation::Sentinel => unreachable!()
+        }
+        aligned |= op_len.op != Operation::S && op_len.op != Operation::H;
+    }
+    assert!(i <= query.len() && j <= reference.len(), "Prefix alignment must be within bounds!");
+
+    let mut cigar = unsafe { Cigar::new(0) };
+    cigar.append_cigar(prefix);
+    let res = AlignResult { score: prefix_score, query_idx: i, reference_idx: j };
+    if i == query.len() || j == reference.len() {
+        return (res, cigar);
+    }
+
+    let mut options = AlignOptions::new().start((i, j));
+    match prefix_ops.last() {
+        Some(last) if last.op == Operation::I || last.op == Operation::D => options = options.start_gap(last.op),
+        _ => ()
+    }
+    let q_padded = PaddedBytes::from_bytes::<M>(query, *size.end());
+    let r_padded = PaddedBytes::from_bytes::<M>(reference, *size.end());
+    let a = Block::<_, true, true>::align_with(&q_padded, &r_padded, matrix, gaps, size, x_drop, &options);
+    let ext_res = a.res();
+
+    // consecutive operations of the same type are merged when the CIGAR strings are joined
+    cigar.append_cigar(&a.trace().cigar(ext_res.query_idx, ext_res.reference_idx));
+    (AlignResult { score: prefix_score + ext_res.score, ..ext_res }, cigar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let hits = vec![
+            Hsp { query_start: 40, query_end: 50, reference_start: 45, reference_end: 55, score: 10 },
+            Hsp { query_start: 0, query_end: 20, reference_start: 0, reference_end: 20, score: 20 },
+            // overlaps with the first hit in the reference
+            Hsp { query_start: 22, query_end: 40, reference_start: 30, reference_end: 48, score: 15 },
+            Hsp { query_start: 25, query_end: 35, reference_start: 25, reference_end: 35, score: 8 }
+        ];
+
+        let chain = chain_hits(&hits, gaps).unwrap();
+        assert_eq!(chain.hits, vec![1, 3, 0]);
+        assert_eq!(chain.score, 20 + 8 + 10 - 6);
+
+        let chain = chain_hits(&hits[1..3], gaps).unwrap();
+        assert_eq!(chain.hits, vec![0, 1]);
+        assert_eq!(chain.score, 20 + 15 - 9);
+
+        assert!(chain_hits(&[], gaps).is_none());
+
+        let q = b"AAAAAAAACCTTTTTTTT";
+        let r = b"AAAAAAAACCGTTTTTTTT";
+        let hits = vec![
+            Hsp { query_start: 0, query_end: 8, reference_start: 0, reference_end: 8, score: 8 },
+            Hsp { query_start: 10, query_end: 18, reference_start: 11, reference_end: 19, score: 8 }
+        ];
+        let chain = chain_hits(&hits, gaps).unwrap();
+        assert_eq!(chain.score, 14);
+        let (score, cigar) = close_gaps(&chain, &hits, q, r, &NW1, gaps, 16..=16);
+        assert_eq!(score, 16);
+        assert_eq!(cigar.to_string(), "10M1D8M");
+
+        // gapped hit, with a gap-only fill-in after it
+        let hits = vec![
+            Hsp { query_start: 0, query_end: 18, reference_start: 0, reference_end: 19, score: 16 },
+            Hsp { query_start: 18, query_end: 18, reference_start: 19, reference_end: 19, score: 0 }
+        ];
+        let chain = Chain { hits: vec![0, 1], score: 16 };
+        let (score, cigar) = close_gaps(&chain, &hits, q, r, &NW1, gaps, 16..=16);
+        assert_eq!(score, 16);
+        assert_eq!(cigar.to_string(), "10M1D8M");
+    }
+
+    #[test]
+    fn test_anchored() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let q = b"AAAAAAAACCTTTTTTTT";
+        let r = b"AAAAAAAACCGTTTTTTTT";
+
+        let (score, cigar) = align_anchored(q, r, &[], &NW1, gaps, 16..=16);
+        assert_eq!(score, 16);
+
+        // force the C's to be aligned to the start of the CCG
+        let (score, cigar2) = align_anchored(q, r, &[(8, 8), (10, 10)], &NW1, gaps, 16..=16);
+        assert_eq!(score, 16);
+        assert_eq!(cigar2.to_string(), cigar.to_string());
+
+        // force a deletion before the C's, which causes a mismatch
+        let (score, cigar) = align_anchored(q, r, &[(8, 9)], &NW1, gaps, 16..=16);
+        assert_eq!(score, 8 - 2 + 9 - 1);
+        assert_eq!(cigar.to_string(), "8M1D10M");
+        assert!(cigar.aligned_pairs(0, 0).any(|p| p == (Some(8), Some(9))));
+
+        // anchors at the ends, repeated anchors, and gap-only segments
+        let (score, cigar) = align_anchored(q, r, &[(0, 0), (8, 8), (8, 9), (8, 9), (18, 19)], &NW1, gaps, 16..=16);
+        assert_eq!(score, 14);
+        assert_eq!(cigar.to_string(), "8M1D10M");
+        let (score, cigar) = align_anchored(q, r, &[(18, 0)], &NW1, gaps, 16..=16);
+        assert_eq!(score, (-2 - 17) + (-2 - 18));
+        assert_eq!(cigar.to_string(), "18I19D");
+    }
+
+    #[test]
+    fn test_extend_alignment() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let q = b"AAAAAAAACCTTTTTTTTGGGGGGGGGGGG";
+        let r = b"AAAAAAAACCGTTTTTTTTAAAAAAAAAAAA";
+
+        let (_, prefix) = align_anchored(&q[..8], &r[..8], &[], &NW1, gaps, 16..=16);
+        let (res, cigar) = extend_alignment(q, r, &prefix, 8, &NW1, gaps, 16..=16, 5);
+        assert_eq!(res, AlignResult { score: 16, query_idx: 18, reference_idx: 19 });
+        assert_eq!(cigar.to_string(), "10M1D8M");
+
+        // the deletion in the prefix is continued by the extension
+        let q = b"AAAAAAAATTTTTTTTTT";
+        let r = b"AAAAAAAAGGTTTTTTTTTT";
+        let (_, prefix) = align_anchored(&q[..8], &r[..9], &[], &NW1, gaps, 16..=16);
+        assert_eq!(prefix.to_string(), "8M1D");
+        let (res, cigar) = extend_alignment(q, r, &prefix, 8 - 2, &NW1, gaps, 16..=16, 5);
+        assert_eq!(cigar.to_string(), "8M2D10M");
+        assert_eq!(res, AlignResult { score: 8 - 3 + 10, query_idx: 18, reference_idx: 20 });
+
+        // nothing left to extend
+        let (res2, cigar2) = extend_alignment(q, r, &cigar, res.score, &NW1, gaps, 16..=16, 5);
+        assert_eq!(res2, res);
+        assert_eq!(cigar2.to_string(), "8M2D10M");
+
+        // opening the gap again would cost more than a mismatch, but extending it does not
+        let gaps = Gaps { open: -4, extend: -1 };
+        let (res, cigar) = extend_alignment(q, r, &prefix, 8 - 4, &NW1, gaps, 16..=16, 5);
+        assert_eq!(cigar.to_string(), "8M2D10M");
+        assert_eq!(res, AlignResult { score: 8 - 5 + 10, query_idx: 18, reference_idx: 20 });
+        let prefix = Cigar::from_ops(vec![OpLen { op: Operation::M, len: 8 }, OpLen { op: Operation::I, len: 1 }]);
+        let (res, cigar) = extend_alignment(r, q, &prefix, 8 - 4, &NW1, gaps, 16..=16, 5);
+        assert_eq!(cigar.to_string(), "8M2I10M");
+        assert_eq!(res, AlignResult { score: 8 - 5 + 10, query_idx: 20, reference_idx: 18 });
+
+        // leading clips and skipped reference regions
+        let q = b"CCAAAAAAAATTTTTTTTTT";
+        let r = b"AAAAAAAAGGGGGTTTTTTTTTT";
+        let prefix = Cigar::from_ops(vec![
+            OpLen { op: Operation::H, len: 5 },
+            OpLen { op: Operation::S, len: 2 },
+            OpLen { op: Operation::M, len: 8 },
+            OpLen { op: Operation::N, len: 5 }
+        ]);
+        let (res, cigar) = extend_alignment(q, r, &prefix, 8, &NW1, gaps, 16..=16, 5);
+        assert_eq!(res, AlignResult { score: 18, query_idx: 20, reference_idx: 23 });
+        assert_eq!(cigar.to_string(), "5H2S8M5N10M");
+    }
+
+    #[test]
+    #[should_panic(expected = "Clips are only allowed at the start")]
+    fn test_extend_alignment_trailing_clip() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let prefix = Cigar::from_ops(vec![OpLen { op: Operation::M, len: 4 }, OpLen { op: Operation::S, len: 2 }]);
+        extend_alignment(b"AAAAAAAA", b"AAAAAAAA", &prefix, 4, &NW1, gaps, 16..=16, 5);
+    }
+}
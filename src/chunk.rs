@@ -0,0 +1,142 @@
+//! Chunked global alignment of very long (for example, chromosome-scale) sequences.
+//!
+//! Even though block aligner only computes a band of the DP matrix, the traceback for an
+//! entire chromosome can still take too much memory. Instead, the alignment is split into
+//! overlapping chunks along the expected diagonal. Each chunk is aligned with traceback,
+//! but only the part of its alignment that is before the overlap with the next chunk is
+//! kept, and the next chunk starts where the kept part ends. This way, the end of each chunk
+//! (which is forced to be at the corner of the chunk) does not affect the final alignment.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::cmp;
+use std::ops::RangeInclusive;
+
+/// Data structure storing the settings for chunked global alignment.
+pub struct ChunkedAligner<'a, M: 'static + Matrix> {
+    matrix: &'a M,
+    gaps: Gaps,
+    size: RangeInclusive<usize>,
+    chunk_len: usize,
+    overlap: usize
+}
+
+impl<'a, M: 'static + Matrix> ChunkedAligner<'a, M> {
+    /// Create a new chunked aligner.
+    ///
+    /// By default, chunks span 65536 query bases and adjacent chunks overlap by 4096 bases.
+    pub fn new(matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>) -> Self {
+        Self { matrix, gaps, size, chunk_len: 1 << 16, overlap: 1 << 12 }
+    }
+
+    /// Set the number of query bases in each chunk, which bounds the memory used for traceback.
+    pub fn chunk_len(mut self, chunk_len: usize) -> Self {
+        self.chunk_len = chunk_len;
+        self
+    }
+
+    /// Set the number of bases that adjacent chunks overlap.
+    ///
+    /// This should be larger than the largest expected drift of the alignment away from
+    /// the diagonal, since the end of each chunk is only reliable outside of the overlap.
+    pub fn overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Globally align the query and the reference, one chunk at a time.
+    ///
+    /// Returns the score and the CIGAR string of the whole alignment.
+    /// `query` and `reference` are the unpadded strings.
+    pub fn align(&self, query: &[u8], reference: &[u8]) -> (i32, Cigar) {
+        // checked here since the chunk length and overlap can be set in any order
+        assert!(self.overlap < self.chunk_len, "Overlap must be less than the chunk length!");
+        // slope of the expected diagonal
+        let slope = (reference.len() as f64) / (cmp::max(query.len(), 1) as f64);
+        let ref_chunk_len = ((self.chunk_len as f64) * slope).ceil() as usize + self.overlap;
+        let mut ops = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        loop {
+            let last = i + self.chunk_len >= query.len() || j + ref_chunk_len >= reference.len();
+            let (i_end, j_end) = if last {
+                (query.len(), reference.len())
+            } else {
+                (i + self.chunk_len, j + ref_chunk_len)
+            };
+
+            let q_padded = PaddedBytes::from_bytes::<M>(&query[i..i_end], *self.size.end());
+            let r_padded = PaddedBytes::from_bytes::<M>(&reference[j..j_end], *self.size.end());
+            let a = Block::<_, true, false>::align(&q_padded, &r_padded, self.matrix, self.gaps, self.size.clone(), 0);
+            let res = a.res();
+            let chunk_ops = a.trace().cigar(res.query_idx, res.reference_idx).to_vec();
+
+            if last {
+                ops.extend(chunk_ops);
+                break;
+            }
+
+            // only keep the alignment before the overlap with the next chunk
+            let (i_keep, j_keep) = (self.chunk_len - self.overlap, ref_chunk_len - self.overlap);
+            let (mut ci, mut cj) = (0, 0);
+            for mut op_len in chunk_ops {
+                let remaining = match op_len.op {
+                    Operation::M => cmp::min(i_keep - ci, j_keep - cj),
+                    Operation::I => i_keep - ci,
+                    _ => j_keep - cj
+                };
+                op_len.len = cmp::min(op_len.len, remaining);
+                match op_len.op {
+                    Operation::M => { ci += op_len.len; cj += op_len.len; },
+                    Operation::I => ci += op_len.len,
+                    _ => cj += op_len.len
+                }
+                ops.push(op_len);
+                if ci == i_keep || cj == j_keep {
+                    break;
+                }
+            }
+
+            i += ci;
+            j += cj;
+        }
+
+        let cigar = Cigar::from_ops(ops);
+        (cigar_score(&cigar, query, reference, self.matrix, self.gaps), cigar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_chunked_aligner() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+        let r = rand_str(5000, &NUC, &mut rng);
+        let q = rand_mutate(&r, 250, &NUC, &mut rng);
+
+        let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 256);
+        let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 256);
+        let a = Block::<_, true, false>::align(&q_padded, &r_padded, &NW1, gaps, 32..=256, 0);
+
+        let (score, cigar) = ChunkedAligner::new(&NW1, gaps, 32..=256).chunk_len(1000).overlap(200).align(&q, &r);
+        let (q_gapped, r_gapped) = cigar.format(&q, &r);
+        assert_eq!(q_gapped.replace('-', "").as_bytes(), &q[..]);
+        assert_eq!(r_gapped.replace('-', "").as_bytes(), &r[..]);
+        assert!(score <= a.res().score && score >= a.res().score - 5);
+
+        // short sequences are aligned in a single chunk
+        let (score, cigar) = ChunkedAligner::new(&NW1, gaps, 32..=256).align(&q[..100], &r[..100]);
+        let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q[..100], 256);
+        let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r[..100], 256);
+        let a = Block::<_, true, false>::align(&q_padded, &r_padded, &NW1, gaps, 32..=256, 0);
+        assert_eq!(score, a.res().score);
+        assert_eq!(cigar.to_string(), a.trace().cigar(100, 100).to_string());
+    }
+}
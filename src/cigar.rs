@@ -1,8 +1,16 @@
 //! Data structures and functions for working with CIGAR strings.
 
-use std::fmt;
+use crate::scores::{Gaps, Matrix};
 
-/// A match/mistmatch, insertion, or deletion operation.
+use std::{cmp, fmt};
+use std::collections::{HashMap, HashSet};
+
+/// Max length of an operation in the BAM format, which stores lengths in 28 bits.
+pub const BAM_MAX_OP_LEN: usize = (1 << 28) - 1;
+/// Max length of an operation for older formats and tools that store lengths in 16 bits.
+pub const U16_MAX_OP_LEN: usize = u16::MAX as usize;
+
+/// A match/mistmatch, insertion, deletion, or clip operation.
 #[derive(Debug, PartialEq, Copy, Clone)]
 #[repr(u8)]
 pub enum Operation {
@@ -13,7 +21,15 @@ pub enum Operation {
     /// Insertion.
     I = 2u8,
     /// Deletion.
-    D = 3u8
+    D = 3u8,
+    /// Soft clip (query bases that are not aligned, but are present in the read).
+    S = 4u8,
+    /// Hard clip (query bases that are not aligned and not present in the read).
+    H = 5u8,
+    /// Skipped region in the reference (for example, an intron in a spliced alignment).
+    ///
+    /// This is never produced by block aligner, but it can be added by external logic.
+    N = 6u8
 }
 
 /// An operation and how many times that operation is repeated.
@@ -61,6 +77,168 @@ impl Cigar {
         (*self.s.as_mut_ptr().add(self.idx - 1)).len += 1;
     }
 
+    /// Create a CIGAR string from a list of operations in order.
+    ///
+    /// Adjacent operations that are the same are merged and empty operations are removed.
+    pub(crate) fn from_ops(ops: Vec<OpLen>) -> Self {
+        let mut res = Cigar { s: Vec::new(), idx: 0 };
+        res.set_ops(ops);
+        res
+    }
+
+    /// Remove all operations, while keeping the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.s.clear();
+        self.s.push(OpLen { op: Operation::Sentinel, len: 0 });
+        self.idx = 1;
+    }
+
+    /// Clear the CIGAR string and make room for adding a certain number of operations.
+    ///
+    /// This is like `new`, but the allocated memory is reused.
+    pub(crate) unsafe fn reset(&mut self, max_len: usize) {
+        self.clear();
+        self.s.resize(max_len + 1, OpLen { op: Operation::Sentinel, len: 0 });
+    }
+
+    /// Add a soft or hard clip operation to the start of the CIGAR string.
+    ///
+    /// Nothing is added if `len` is zero.
+    pub fn prepend_clip(&mut self, op: Operation, len: usize) {
+        assert!(op == Operation::S || op == Operation::H, "Clip operation must be S or H!");
+        if len == 0 {
+            return;
+        }
+
+        // operations are stored in reverse order, so the start is at the end
+        if self.s[self.idx - 1].op == op {
+            self.s[self.idx - 1].len += len;
+        } else {
+            let op_len = OpLen { op, len };
+            if self.idx < self.s.len() {
+                self.s[self.idx] = op_len;
+            } else {
+                self.s.push(op_len);
+            }
+            self.idx += 1;
+        }
+    }
+
+    /// Add a soft or hard clip operation to the end of the CIGAR string.
+    ///
+    /// Nothing is added if `len` is zero.
+    pub fn append_clip(&mut self, op: Operation, len: usize) {
+        assert!(op == Operation::S || op == Operation::H, "Clip operation must be S or H!");
+        if len == 0 {
+            return;
+        }
+
+        self.append_ops(&[OpLen { op, len }]);
+    }
+
+    /// Add all of the operations in another CIGAR string to the end of this CIGAR string.
+    ///
+    /// If the last operation of this CIGAR string and the first operation of the other
+    /// CIGAR string are the same, then they are merged.
+    pub fn append_cigar(&mut self, other: &Cigar) {
+        self.append_ops(&other.to_vec());
+    }
+
+    /// Add operations (in order) to the end of the CIGAR string, merging the first operation
+    /// with the last existing operation if they are the same.
+    fn append_ops(&mut self, ops: &[OpLen]) {
+        // drop the unused space after the operations so only the operations are shifted
+        self.s.truncate(self.idx);
+        let merge = self.idx > 1 && !ops.is_empty() && self.s[1].op == ops[0].op;
+        if merge {
+            self.s[1].len += ops[0].len;
+        }
+        let ops = &ops[merge as usize..];
+        // operations are stored in reverse order, so the end is right after the first sentinel
+        self.s.reserve(ops.len());
+        self.s.splice(1..1, ops.iter().rev().copied());
+        self.idx += ops.len();
+    }
+
+    /// Remove leading and trailing insertions and deletions, which global alignment produces
+    /// when one string overhangs the other.
+    ///
+    /// Clip operations at the ends are kept, and the indels right inside them are removed.
+    /// Terminal deletions are always removed. If `clip` is true, terminal insertions are
+    /// converted into soft clips. Otherwise, they are removed.
+    ///
+    /// Returns the number of bases that were removed from each end, which can be used to
+    /// adjust the start and end positions of the alignment.
+    pub fn trim_terminal_indels(&mut self, clip: bool) -> TrimmedIndels {
+        let ops = self.to_vec();
+        let is_clip = |op_len: &&OpLen| op_len.op == Operation::S || op_len.op == Operation::H;
+        let is_indel = |op_len: &&OpLen| op_len.op == Operation::I || op_len.op == Operation::D;
+        let sum = |ops: &[OpLen], op: Operation| ops.iter().filter(|op_len| op_len.op == op).map(|op_len| op_len.len).sum();
+
+        let start = ops.iter().take_while(is_clip).count();
+        let end = ops.len() - ops[start..].iter().rev().take_while(is_clip).count();
+        let lead = start + ops[start..end].iter().take_while(is_indel).count();
+        let trail = end - ops[lead..end].iter().rev().take_while(is_indel).count();
+        let (lead_ops, trail_ops) = (&ops[start..lead], &ops[trail..end]);
+        let (lead_i, trail_i) = (sum(lead_ops, Operation::I), sum(trail_ops, Operation::I));
+
+        let mut res = ops[..start].to_vec();
+        if clip {
+            res.push(OpLen { op: Operation::S, len: lead_i });
+        }
+        res.extend_from_slice(&ops[lead..trail]);
+        if clip {
+            res.push(OpLen { op: Operation::S, len: trail_i });
+        }
+        res.extend_from_slice(&ops[end..]);
+        self.set_ops(res);
+
+        TrimmedIndels {
+            query_start: if clip { 0 } else { lead_i },
+            query_end: if clip { 0 } else { trail_i },
+            reference_start: sum(lead_ops, Operation::D),
+            reference_end: sum(trail_ops, Operation::D)
+        }
+    }
+
+    /// Split operations that are longer than `max_len` into multiple consecutive operations
+    /// of the same type, so the CIGAR string can be serialized in formats that limit the
+    /// length of each operation (see `BAM_MAX_OP_LEN` and `U16_MAX_OP_LEN`).
+    pub fn split_long_ops(&mut self, max_len: usize) {
+        assert!(max_len > 0, "Max operation length must be positive!");
+        let mut s = vec![OpLen { op: Operation::Sentinel, len: 0 }];
+        for &op_len in &self.s[1..self.idx] {
+            let mut len = op_len.len;
+            // operations are stored in reverse order, so the remainder goes first
+            let rem = len % max_len;
+            if rem > 0 {
+                s.push(OpLen { op: op_len.op, len: rem });
+                len -= rem;
+            }
+            while len > 0 {
+                s.push(OpLen { op: op_len.op, len: max_len });
+                len -= max_len;
+            }
+        }
+        self.idx = s.len();
+        self.s = s;
+    }
+
+    /// Replace all operations, merging adjacent operations that are the same and
+    /// removing empty operations.
+    fn set_ops(&mut self, ops: Vec<OpLen>) {
+        let mut s = vec![OpLen { op: Operation::Sentinel, len: 0 }];
+        // operations are stored in reverse order
+        for op_len in ops.into_iter().rev().filter(|op_len| op_len.len > 0) {
+            match s.last_mut() {
+                Some(last) if last.op == op_len.op => last.len += op_len.len,
+                _ => s.push(op_len)
+            }
+        }
+        self.idx = s.len();
+        self.s = s;
+    }
+
     /// Length of the CIGAR string, not including the first sentinel.
     pub fn len(&self) -> usize {
         self.idx - 1
@@ -72,11 +250,23 @@ impl Cigar {
     }
 
     /// Generate two strings to visualize the edit operations.
+    ///
+    /// The strings are gapped versions of the query and the reference,
+    /// with '-' characters representing gaps.
     pub fn format(&self, q: &[u8], r: &[u8]) -> (String, String) {
+        self.format_at(q, r, 0, 0)
+    }
+
+    /// Generate two strings to visualize the edit operations, for an alignment
+    /// that starts at a certain position in the query and the reference.
+    ///
+    /// This is useful for X-drop and glocal alignments, which do not
+    /// necessarily start at the beginning of both strings.
+    pub fn format_at(&self, q: &[u8], r: &[u8], query_start: usize, reference_start: usize) -> (String, String) {
         let mut a = String::with_capacity(self.s.len());
         let mut b = String::with_capacity(self.s.len());
-        let mut i = 0;
-        let mut j = 0;
+        let mut i = query_start;
+        let mut j = reference_start;
 
         for &op_len in self.s.iter().rev() {
             match op_len.op {
@@ -102,6 +292,9 @@ impl Cigar {
                         j += 1;
                     }
                 },
+                // soft clipped bases and skipped reference regions are not shown
+                Operation::S => i += op_len.len,
+                Operation::N => j += op_len.len,
                 _ => continue
             }
         }
@@ -121,6 +314,456 @@ impl Cigar {
             .map(|&op_len| op_len)
             .collect::<Vec<OpLen>>()
     }
+
+    /// Encode the CIGAR string in the binary format used by BAM records.
+    ///
+    /// Each operation is a little-endian 32-bit integer with the operation code in the
+    /// low 4 bits and the length in the high 28 bits. Operations longer than `BAM_MAX_OP_LEN`
+    /// are split, like with `split_long_ops`.
+    pub fn to_bam_bytes(&self) -> Vec<u8> {
+        let mut ops = Cigar { s: self.s[..self.idx].to_vec(), idx: self.idx };
+        ops.split_long_ops(BAM_MAX_OP_LEN);
+        let mut res = Vec::with_capacity(ops.len() * 4);
+        for op_len in ops.to_vec() {
+            let code = match op_len.op {
+                Operation::M => 0u32,
+                Operation::I => 1,
+                Operation::D => 2,
+                Operation::S => 4,
+                Operation::H => 5,
+                Operation::N => 3,
+                Operation::Sentinel => unreachable!()
+            };
+            res.extend_from_slice(&(((op_len.len as u32) << 4) | code).to_le_bytes());
+        }
+        res
+    }
+
+    /// Iterate over the aligned pairs of 0-based query and reference positions.
+    ///
+    /// Matches/mismatches yield `(Some(query_pos), Some(reference_pos))`, insertions yield
+    /// `(Some(query_pos), None)`, and deletions yield `(None, Some(reference_pos))`.
+    /// Like insertions, soft clipped bases yield `(Some(query_pos), None)`, and like deletions,
+    /// skipped reference regions yield `(None, Some(reference_pos))`.
+    /// The positions start at `query_start` and `reference_start`.
+    pub fn aligned_pairs(&self, query_start: usize, reference_start: usize) -> AlignedPairs {
+        AlignedPairs::new(self.to_vec(), query_start, reference_start)
+    }
+
+    /// Iterate over the aligned pairs, like `aligned_pairs`, along with whether each pair
+    /// falls in a soft-masked (lowercase) region of either string.
+    ///
+    /// `q` and `r` are the original strings, before they are converted to `PaddedBytes`.
+    pub fn masked_pairs<'a>(&self, q: &'a [u8], r: &'a [u8], query_start: usize, reference_start: usize) -> impl Iterator<Item = ((Option<usize>, Option<usize>), bool)> + 'a {
+        self.aligned_pairs(query_start, reference_start).map(move |pair| {
+            let masked = pair.0.is_some_and(|i| q[i].is_ascii_lowercase()) || pair.1.is_some_and(|j| r[j].is_ascii_lowercase());
+            (pair, masked)
+        })
+    }
+
+    /// Count the alignment columns that fall in soft-masked (lowercase) regions of either string.
+    ///
+    /// This is useful for discounting alignments that are mostly driven by repeats.
+    /// Only match/mismatch, insertion, and deletion columns are counted.
+    pub fn mask_stats(&self, q: &[u8], r: &[u8], query_start: usize, reference_start: usize) -> MaskStats {
+        let mut res = MaskStats::default();
+        let (mut i, mut j) = (query_start, reference_start);
+        for op_len in self.to_vec() {
+            for _ in 0..op_len.len {
+                match op_len.op {
+                    Operation::M => {
+                        res.pairs += 1;
+                        res.masked_pairs += (q[i].is_ascii_lowercase() || r[j].is_ascii_lowercase()) as usize;
+                        i += 1;
+                        j += 1;
+                    },
+                    Operation::I => {
+                        res.gaps += 1;
+                        res.masked_gaps += q[i].is_ascii_lowercase() as usize;
+                        i += 1;
+                    },
+                    Operation::D => {
+                        res.gaps += 1;
+                        res.masked_gaps += r[j].is_ascii_lowercase() as usize;
+                        j += 1;
+                    },
+                    Operation::S => i += 1,
+                    Operation::N => j += 1,
+                    _ => ()
+                }
+            }
+        }
+        res
+    }
+
+    /// List the differences between the query and the reference: mismatches (SNVs),
+    /// insertions, and deletions.
+    ///
+    /// Residues are compared case-insensitively, so soft-masked regions do not create SNVs.
+    /// Adjacent mismatches are reported as separate SNVs. Soft clipped and skipped regions are
+    /// not reported. The positions start at `query_start` and `reference_start`.
+    pub fn variants(&self, q: &[u8], r: &[u8], query_start: usize, reference_start: usize) -> Vec<Variant> {
+        let mut res = Vec::new();
+        let (mut i, mut j) = (query_start, reference_start);
+        for op_len in self.to_vec() {
+            let len = op_len.len;
+            match op_len.op {
+                Operation::M => {
+                    for k in 0..len {
+                        if !q[i + k].eq_ignore_ascii_case(&r[j + k]) {
+                            res.push(Variant {
+                                kind: VariantKind::Snv,
+                                query_pos: i + k,
+                                reference_pos: j + k,
+                                ref_allele: vec![r[j + k]],
+                                alt_allele: vec![q[i + k]]
+                            });
+                        }
+                    }
+                    i += len;
+                    j += len;
+                },
+                Operation::I => {
+                    res.push(Variant { kind: VariantKind::Insertion, query_pos: i, reference_pos: j, ref_allele: Vec::new(), alt_allele: q[i..i + len].to_vec() });
+                    i += len;
+                },
+                Operation::D => {
+                    res.push(Variant { kind: VariantKind::Deletion, query_pos: i, reference_pos: j, ref_allele: r[j..j + len].to_vec(), alt_allele: Vec::new() });
+                    j += len;
+                },
+                Operation::S => i += len,
+                Operation::N => j += len,
+                _ => ()
+            }
+        }
+        res
+    }
+}
+
+impl Cigar {
+    /// Compare this CIGAR string with another CIGAR string for the same pair of strings.
+    ///
+    /// This is useful for quantifying the agreement between two aligners, or between an
+    /// aligner and the ground truth. Both CIGAR strings must start at the same query and
+    /// reference positions. Soft clipped bases are treated like insertions.
+    pub fn compare(&self, other: &Cigar) -> CigarComparison {
+        let (self_q, self_r) = partners(self);
+        let (other_q, other_r) = partners(other);
+        let same = |a: &[Option<usize>], b: &[Option<usize>]| {
+            a.iter().zip(b.iter()).filter(|(x, y)| x == y).count()
+        };
+        let bases = cmp::max(self_q.len(), other_q.len()) + cmp::max(self_r.len(), other_r.len());
+        let identical_bases = same(&self_q, &other_q) + same(&self_r, &other_r);
+
+        let self_indels = indels(self);
+        let other_indels = indels(other);
+        let mut identical_indels = 0;
+        let mut shifted = HashMap::new();
+        for &(op, len, i, j) in &other_indels {
+            if !self_indels.contains(&(op, len, i, j)) {
+                *shifted.entry((op, len, (i as isize) - (j as isize))).or_insert(0usize) += 1;
+            }
+        }
+        let mut shifted_indels = 0;
+        for &(op, len, i, j) in &self_indels {
+            if other_indels.contains(&(op, len, i, j)) {
+                identical_indels += 1;
+            } else if let Some(count) = shifted.get_mut(&(op, len, (i as isize) - (j as isize))) {
+                // same type and length, but moved along the same diagonal (for example,
+                // a different placement of a gap in a repeat)
+                if *count > 0 {
+                    *count -= 1;
+                    shifted_indels += 1;
+                }
+            }
+        }
+
+        CigarComparison { bases, identical_bases, indels: self_indels.len(), identical_indels, shifted_indels }
+    }
+}
+
+/// For each query and reference position, the position in the other string that it is
+/// aligned to, if any.
+fn partners(cigar: &Cigar) -> (Vec<Option<usize>>, Vec<Option<usize>>) {
+    let (mut q, mut r) = (Vec::new(), Vec::new());
+    for (i, j) in cigar.aligned_pairs(0, 0) {
+        if i.is_some() {
+            q.push(j);
+        }
+        if j.is_some() {
+            r.push(i);
+        }
+    }
+    (q, r)
+}
+
+/// Insertions and deletions as (operation, length, query position, reference position).
+fn indels(cigar: &Cigar) -> HashSet<(u8, usize, usize, usize)> {
+    let mut res = HashSet::new();
+    let (mut i, mut j) = (0, 0);
+    for op_len in cigar.to_vec() {
+        match op_len.op {
+            Operation::M => {
+                i += op_len.len;
+                j += op_len.len;
+            },
+            Operation::I | Operation::D => {
+                res.insert((op_len.op as u8, op_len.len, i, j));
+                if op_len.op == Operation::I { i += op_len.len; } else { j += op_len.len; }
+            },
+            Operation::S => i += op_len.len,
+            Operation::N => j += op_len.len,
+            _ => ()
+        }
+    }
+    res
+}
+
+/// Agreement between two CIGAR strings, from `Cigar::compare`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CigarComparison {
+    /// Total number of query and reference bases.
+    pub bases: usize,
+    /// Number of query and reference bases that are aligned to the same position (or to
+    /// a gap) in both CIGAR strings.
+    pub identical_bases: usize,
+    /// Number of insertions and deletions in the first CIGAR string.
+    pub indels: usize,
+    /// Number of indels that are at exactly the same position in both CIGAR strings.
+    pub identical_indels: usize,
+    /// Number of indels that have the same type and length in both CIGAR strings, but that
+    /// are shifted along the same diagonal, like equivalent placements of a gap in a repeat.
+    pub shifted_indels: usize
+}
+
+impl CigarComparison {
+    /// Fraction of bases that are aligned identically.
+    pub fn frac_identical(&self) -> f64 {
+        if self.bases == 0 { 1.0 } else { (self.identical_bases as f64) / (self.bases as f64) }
+    }
+}
+
+/// Number of alignment columns that fall in soft-masked regions, from `Cigar::mask_stats`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MaskStats {
+    /// Number of aligned pairs (matches and mismatches).
+    pub pairs: usize,
+    /// Number of aligned pairs where either residue is lowercase.
+    pub masked_pairs: usize,
+    /// Number of insertion and deletion columns.
+    pub gaps: usize,
+    /// Number of insertion and deletion columns where the residue is lowercase.
+    pub masked_gaps: usize
+}
+
+impl MaskStats {
+    /// Fraction of all columns that are soft-masked.
+    pub fn masked_frac(&self) -> f64 {
+        let columns = self.pairs + self.gaps;
+        if columns == 0 { 0.0 } else { ((self.masked_pairs + self.masked_gaps) as f64) / (columns as f64) }
+    }
+}
+
+/// Type of a difference between the query and the reference, from `Cigar::variants`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VariantKind {
+    /// Single nucleotide (or residue) substitution.
+    Snv,
+    /// Bases in the query that are not in the reference.
+    Insertion,
+    /// Bases in the reference that are not in the query.
+    Deletion
+}
+
+/// A difference between the query and the reference, from `Cigar::variants`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Variant {
+    pub kind: VariantKind,
+    /// Position of the first query base in the variant. For deletions, this is the position
+    /// of the query base right after the deletion.
+    pub query_pos: usize,
+    /// Position of the first reference base in the variant. For insertions, this is the position
+    /// of the reference base right after the insertion.
+    pub reference_pos: usize,
+    /// Reference bases, which are empty for insertions.
+    pub ref_allele: Vec<u8>,
+    /// Query bases, which are empty for deletions.
+    pub alt_allele: Vec<u8>
+}
+
+/// Number of bases removed from each end by `Cigar::trim_terminal_indels`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TrimmedIndels {
+    /// Query bases removed from the start, so the query start moves forward by this amount.
+    pub query_start: usize,
+    /// Query bases removed from the end.
+    pub query_end: usize,
+    /// Reference bases removed from the start, so the reference start moves forward by this amount.
+    pub reference_start: usize,
+    /// Reference bases removed from the end.
+    pub reference_end: usize
+}
+
+/// Iterator over aligned pairs of query and reference positions, similar to
+/// pysam's `get_aligned_pairs`.
+#[derive(Clone, Debug)]
+pub struct AlignedPairs {
+    ops: Vec<OpLen>,
+    op_idx: usize,
+    len_idx: usize,
+    i: usize,
+    j: usize
+}
+
+impl AlignedPairs {
+    pub(crate) fn new(ops: Vec<OpLen>, query_start: usize, reference_start: usize) -> Self {
+        Self { ops, op_idx: 0, len_idx: 0, i: query_start, j: reference_start }
+    }
+}
+
+impl Iterator for AlignedPairs {
+    type Item = (Option<usize>, Option<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.op_idx < self.ops.len() && self.len_idx >= self.ops[self.op_idx].len {
+            self.op_idx += 1;
+            self.len_idx = 0;
+        }
+
+        if self.op_idx >= self.ops.len() {
+            return None;
+        }
+
+        self.len_idx += 1;
+        let (i, j) = (self.i, self.j);
+
+        match self.ops[self.op_idx].op {
+            Operation::M => {
+                self.i += 1;
+                self.j += 1;
+                Some((Some(i), Some(j)))
+            },
+            Operation::I | Operation::S => {
+                self.i += 1;
+                Some((Some(i), None))
+            },
+            Operation::D | Operation::N => {
+                self.j += 1;
+                Some((None, Some(j)))
+            },
+            _ => self.next()
+        }
+    }
+}
+
+/// Conventions for displaying CIGAR strings with `Cigar::to_string_with` and
+/// `Cigar::to_string_extended`.
+///
+/// The default is the standard SAM convention.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CigarFormat {
+    // indexed by the operation, with the sentinel unused
+    chars: [char; 7],
+    eq: char,
+    x: char,
+    separator: String
+}
+
+impl CigarFormat {
+    /// Standard SAM convention, like `Cigar::to_string`.
+    pub fn sam() -> Self {
+        Self { chars: ['?', 'M', 'I', 'D', 'S', 'H', 'N'], eq: '=', x: 'X', separator: String::new() }
+    }
+
+    /// Set the character used for an operation.
+    pub fn op_char(mut self, op: Operation, c: char) -> Self {
+        assert!(op != Operation::Sentinel, "Sentinel operations are never displayed!");
+        self.chars[op as usize] = c;
+        self
+    }
+
+    /// Set the characters used for matches and mismatches with `Cigar::to_string_extended`.
+    pub fn match_chars(mut self, eq: char, x: char) -> Self {
+        self.eq = eq;
+        self.x = x;
+        self
+    }
+
+    /// Set the separator placed between operations, like a space or a comma.
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_owned();
+        self
+    }
+}
+
+impl Default for CigarFormat {
+    fn default() -> Self {
+        Self::sam()
+    }
+}
+
+impl Cigar {
+    /// Display the CIGAR string with a certain convention.
+    pub fn to_string_with(&self, format: &CigarFormat) -> String {
+        let ops = self.to_vec().into_iter().map(|op_len| (op_len.len, format.chars[op_len.op as usize]));
+        Self::join(ops, format)
+    }
+
+    /// Display the CIGAR string with the extended convention, where match/mismatch operations
+    /// are split into matches ('=') and mismatches ('X').
+    ///
+    /// The query and the reference are needed to distinguish between matches and mismatches,
+    /// and the alignment starts at `query_start` and `reference_start`. Bytes are compared
+    /// case-insensitively.
+    pub fn to_string_extended(&self, format: &CigarFormat, q: &[u8], r: &[u8], query_start: usize, reference_start: usize) -> String {
+        let mut ops: Vec<(usize, char)> = Vec::with_capacity(self.len());
+        let mut push = |len: usize, c: char| match ops.last_mut() {
+            Some((prev_len, prev_c)) if *prev_c == c => *prev_len += len,
+            _ => ops.push((len, c))
+        };
+        let (mut i, mut j) = (query_start, reference_start);
+
+        for op_len in self.to_vec() {
+            match op_len.op {
+                Operation::M => {
+                    for _k in 0..op_len.len {
+                        push(1, if q[i].eq_ignore_ascii_case(&r[j]) { format.eq } else { format.x });
+                        i += 1;
+                        j += 1;
+                    }
+                },
+                op => {
+                    match op {
+                        Operation::I | Operation::S => i += op_len.len,
+                        Operation::D | Operation::N => j += op_len.len,
+                        _ => ()
+                    }
+                    push(op_len.len, format.chars[op as usize]);
+                }
+            }
+        }
+
+        Self::join(ops.into_iter(), format)
+    }
+
+    fn join(ops: impl Iterator<Item = (usize, char)>, format: &CigarFormat) -> String {
+        let mut res = String::new();
+        for (idx, (len, c)) in ops.enumerate() {
+            if idx > 0 {
+                res.push_str(&format.separator);
+            }
+            res.push_str(&len.to_string());
+            res.push(c);
+        }
+        res
+    }
+}
+
+impl Default for Cigar {
+    /// Create an empty CIGAR string.
+    fn default() -> Self {
+        Cigar { s: vec![OpLen { op: Operation::Sentinel, len: 0 }], idx: 1 }
+    }
 }
 
 impl fmt::Display for Cigar {
@@ -131,6 +774,9 @@ impl fmt::Display for Cigar {
                 Operation::M => 'M',
                 Operation::I => 'I',
                 Operation::D => 'D',
+                Operation::S => 'S',
+                Operation::H => 'H',
+                Operation::N => 'N',
                 _ => continue
             };
             write!(f, "{}{}", op_len.len, c)?;
@@ -138,3 +784,246 @@ impl fmt::Display for Cigar {
         Ok(())
     }
 }
+
+/// Score of a global alignment of `query` and `reference` with a CIGAR string.
+///
+/// Clipped bases and skipped reference regions do not contribute to the score.
+pub(crate) fn cigar_score<M: Matrix>(cigar: &Cigar, query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps) -> i32 {
+    let (mut i, mut j, mut score) = (0, 0, 0i32);
+    for op_len in cigar.to_vec() {
+        match op_len.op {
+            Operation::M => {
+                for _ in 0..op_len.len {
+                    score += matrix.get(query[i], reference[j]) as i32;
+                    i += 1;
+                    j += 1;
+                }
+            },
+            Operation::I | Operation::D => {
+                score += (gaps.open as i32) + (gaps.extend as i32) * ((op_len.len as i32) - 1);
+                if op_len.op == Operation::I { i += op_len.len; } else { j += op_len.len; }
+            },
+            // soft clipped bases are still in the query, but they are not scored
+            Operation::S => i += op_len.len,
+            Operation::H => (),
+            // skipped reference regions are free
+            Operation::N => j += op_len.len,
+            Operation::Sentinel => unreachable!()
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clip() {
+        let mut cigar = unsafe { Cigar::new(4) };
+        unsafe {
+            cigar.add(Operation::D);
+            cigar.add(Operation::M);
+            cigar.add(Operation::M);
+        }
+        assert_eq!(cigar.to_string(), "2M1D");
+
+        cigar.prepend_clip(Operation::S, 3);
+        cigar.append_clip(Operation::S, 2);
+        cigar.append_clip(Operation::H, 0);
+        cigar.prepend_clip(Operation::H, 5);
+        cigar.prepend_clip(Operation::H, 1);
+        assert_eq!(cigar.to_string(), "6H3S2M1D2S");
+        assert_eq!(cigar.len(), 5);
+        assert_eq!(cigar.get(0).op, Operation::H);
+
+        assert_eq!(cigar.format(b"AAACCGG", b"CCT"), ("CC-".to_owned(), "CCT".to_owned()));
+        assert_eq!(
+            cigar.aligned_pairs(0, 0).collect::<Vec<_>>(),
+            vec![(Some(0), None), (Some(1), None), (Some(2), None), (Some(3), Some(0)), (Some(4), Some(1)),
+                 (None, Some(2)), (Some(5), None), (Some(6), None)]
+        );
+    }
+
+    #[test]
+    fn test_mask_stats() {
+        let mut cigar = unsafe { Cigar::new(8) };
+        unsafe {
+            for &op in &[Operation::M, Operation::D, Operation::M, Operation::M, Operation::I, Operation::M] {
+                cigar.add(op);
+            }
+        }
+        // operations are added in reverse order
+        assert_eq!(cigar.to_string(), "1M1I2M1D1M");
+        cigar.prepend_clip(Operation::S, 1);
+
+        let (q, r) = (b"NACgtA", b"TACgaA");
+        let stats = cigar.mask_stats(q, r, 0, 1);
+        assert_eq!(stats, MaskStats { pairs: 4, masked_pairs: 2, gaps: 2, masked_gaps: 1 });
+        assert_eq!(stats.masked_frac(), 0.5);
+        let masked = cigar.masked_pairs(q, r, 0, 1).filter(|&(_, m)| m).map(|(p, _)| p).collect::<Vec<_>>();
+        assert_eq!(masked, vec![(Some(3), Some(2)), (Some(4), Some(3)), (None, Some(4))]);
+    }
+
+    #[test]
+    fn test_compare() {
+        let ops = |v: &[(Operation, usize)]| Cigar::from_ops(v.iter().map(|&(op, len)| OpLen { op, len }).collect());
+        let a = ops(&[(Operation::M, 3), (Operation::D, 1), (Operation::M, 4)]);
+        let c = a.compare(&a);
+        assert_eq!(c, CigarComparison { bases: 15, identical_bases: 15, indels: 1, identical_indels: 1, shifted_indels: 0 });
+        assert_eq!(c.frac_identical(), 1.0);
+
+        // the same deletion placed at a different position in a repeat
+        let b = ops(&[(Operation::M, 5), (Operation::D, 1), (Operation::M, 2)]);
+        assert_eq!(a.compare(&b), CigarComparison { bases: 15, identical_bases: 10, indels: 1, identical_indels: 0, shifted_indels: 1 });
+
+        let b = ops(&[(Operation::M, 3), (Operation::I, 1), (Operation::M, 4)]);
+        let c = a.compare(&b);
+        assert_eq!(c.identical_indels + c.shifted_indels, 0);
+    }
+
+    #[test]
+    fn test_variants() {
+        let ops = [(Operation::S, 1), (Operation::M, 3), (Operation::I, 2), (Operation::M, 2), (Operation::D, 1), (Operation::M, 1)];
+        let cigar = Cigar::from_ops(ops.iter().map(|&(op, len)| OpLen { op, len }).collect());
+        let (q, r) = (b"NAcGTTATG", b"TACAATCG");
+        let v = cigar.variants(q, r, 0, 1);
+        assert_eq!(v, vec![
+            Variant { kind: VariantKind::Snv, query_pos: 3, reference_pos: 3, ref_allele: b"A".to_vec(), alt_allele: b"G".to_vec() },
+            Variant { kind: VariantKind::Insertion, query_pos: 4, reference_pos: 4, ref_allele: vec![], alt_allele: b"TT".to_vec() },
+            Variant { kind: VariantKind::Deletion, query_pos: 8, reference_pos: 6, ref_allele: b"C".to_vec(), alt_allele: vec![] }
+        ]);
+        assert!(cigar.variants(b"NaCATTATG", r, 0, 1).iter().all(|v| v.kind != VariantKind::Snv));
+    }
+
+    #[test]
+    fn test_trim_terminal_indels() {
+        let mut cigar = unsafe { Cigar::new(8) };
+        unsafe {
+            for &op in &[Operation::I, Operation::I, Operation::D, Operation::M, Operation::M, Operation::I, Operation::M, Operation::D, Operation::D] {
+                cigar.add(op);
+            }
+        }
+        // operations are added in reverse order
+        assert_eq!(cigar.to_string(), "2D1M1I2M1D2I");
+
+        let mut trimmed = unsafe { Cigar::new(0) };
+        trimmed.append_cigar(&cigar);
+        let res = trimmed.trim_terminal_indels(false);
+        assert_eq!(trimmed.to_string(), "1M1I2M");
+        assert_eq!(res, TrimmedIndels { query_start: 0, query_end: 2, reference_start: 2, reference_end: 1 });
+
+        // appending merges the operations at the boundary, even with unused space after them
+        let mut appended = unsafe { Cigar::new(4) };
+        unsafe {
+            appended.add(Operation::M);
+            appended.add(Operation::I);
+        }
+        appended.append_cigar(&Cigar::from_ops(vec![OpLen { op: Operation::M, len: 2 }, OpLen { op: Operation::D, len: 1 }]));
+        assert_eq!(appended.to_string(), "1I3M1D");
+        appended.append_cigar(&trimmed);
+        assert_eq!(appended.to_string(), "1I3M1D1M1I2M");
+
+        cigar.prepend_clip(Operation::H, 3);
+        let res = cigar.trim_terminal_indels(true);
+        assert_eq!(cigar.to_string(), "3H1M1I2M2S");
+        assert_eq!(res, TrimmedIndels { query_start: 0, query_end: 0, reference_start: 2, reference_end: 1 });
+        assert_eq!(cigar.len(), 5);
+        assert_eq!(cigar.get(4).op, Operation::S);
+    }
+
+    #[test]
+    fn test_cigar_score() {
+        use crate::scores::NW1;
+
+        let gaps = Gaps { open: -2, extend: -1 };
+        let ops = vec![
+            OpLen { op: Operation::H, len: 3 },
+            OpLen { op: Operation::S, len: 2 },
+            OpLen { op: Operation::M, len: 2 },
+            OpLen { op: Operation::N, len: 4 },
+            OpLen { op: Operation::M, len: 1 },
+            OpLen { op: Operation::I, len: 2 },
+            OpLen { op: Operation::M, len: 1 }
+        ];
+        let cigar = Cigar::from_ops(ops);
+        assert_eq!(cigar_score(&cigar, b"TTAACGGC", b"AAGGGGCC", &NW1, gaps), 1);
+    }
+
+    #[test]
+    fn test_bam_bytes() {
+        let mut cigar = unsafe { Cigar::new(3) };
+        unsafe {
+            cigar.add(Operation::D);
+            cigar.add(Operation::M);
+            cigar.add(Operation::M);
+        }
+        cigar.prepend_clip(Operation::S, 1);
+        assert_eq!(cigar.to_bam_bytes(), vec![0x14, 0, 0, 0, 0x20, 0, 0, 0, 0x12, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_split_long_ops() {
+        let mut cigar = unsafe { Cigar::new(0) };
+        cigar.prepend_clip(Operation::S, 3);
+        cigar.append_clip(Operation::S, 1);
+        cigar.prepend_clip(Operation::H, 100_000);
+        cigar.split_long_ops(U16_MAX_OP_LEN);
+        assert_eq!(cigar.to_string(), "65535H34465H4S");
+        assert_eq!(cigar.get(1).len, 34465);
+
+        let mut cigar = unsafe { Cigar::new(0) };
+        cigar.prepend_clip(Operation::S, BAM_MAX_OP_LEN + 2);
+        let bytes = cigar.to_bam_bytes();
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]), (2 << 4) | 4);
+        assert_eq!(cigar.len(), 1);
+
+        cigar.split_long_ops(1 << 27);
+        assert_eq!(cigar.to_string(), "134217728S134217728S1S");
+    }
+
+    #[test]
+    fn test_reference_skip() {
+        let mut cigar = unsafe { Cigar::new(3) };
+        unsafe {
+            cigar.add(Operation::M);
+            cigar.add(Operation::N);
+            cigar.add(Operation::N);
+            cigar.add(Operation::M);
+        }
+        assert_eq!(cigar.to_string(), "1M2N1M");
+        assert_eq!(cigar.format(b"AC", b"AGGC"), ("AC".to_owned(), "AC".to_owned()));
+        assert_eq!(
+            cigar.aligned_pairs(0, 10).collect::<Vec<_>>(),
+            vec![(Some(0), Some(10)), (None, Some(11)), (None, Some(12)), (Some(1), Some(13))]
+        );
+        assert_eq!(cigar.to_bam_bytes()[4], 0x23);
+    }
+
+    #[test]
+    fn test_cigar_format() {
+        let mut cigar = unsafe { Cigar::new(10) };
+        unsafe {
+            cigar.add(Operation::M);
+            cigar.add(Operation::M);
+            cigar.add(Operation::D);
+            cigar.add(Operation::M);
+            cigar.add(Operation::M);
+            cigar.add(Operation::M);
+            cigar.add(Operation::I);
+        }
+        cigar.prepend_clip(Operation::S, 2);
+
+        assert_eq!(cigar.to_string_with(&CigarFormat::default()), cigar.to_string());
+        assert_eq!(cigar.to_string(), "2S1I3M1D2M");
+        let format = CigarFormat::sam().op_char(Operation::M, 'X').separator(", ");
+        assert_eq!(cigar.to_string_with(&format), "2S, 1I, 3X, 1D, 2X");
+
+        let q = b"NNTaGTAC";
+        let r = b"ACTTAC";
+        assert_eq!(cigar.to_string_extended(&CigarFormat::sam(), q, r, 0, 0), "2S1I1=1X1=1D2=");
+        let format = CigarFormat::sam().match_chars('M', 'm').separator(" ");
+        assert_eq!(cigar.to_string_extended(&format, q, r, 0, 0), "2S 1I 1M 1m 1M 1D 2M");
+    }
+}
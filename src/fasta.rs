@@ -0,0 +1,95 @@
+//! Minimal FASTA and FASTQ parsing, for reading sequences from files.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// A single FASTA or FASTQ record.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Record {
+    /// Identifier, which is the first word of the header line.
+    pub id: String,
+    /// Sequence, without any line breaks.
+    pub seq: Vec<u8>,
+    /// Quality string for FASTQ records (with the ASCII offset).
+    pub qual: Option<Vec<u8>>
+}
+
+/// Read all records from a FASTA or FASTQ reader.
+///
+/// The format is detected from the first character of the first record. Multi-line
+/// FASTA sequences are supported, but FASTQ records must have exactly four lines.
+pub fn read_records<R: BufRead>(reader: R) -> io::Result<Vec<Record>> {
+    let mut res: Vec<Record> = Vec::new();
+    let mut lines = reader.lines();
+
+    while let Some(line) = lines.next() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('>') {
+            res.push(Record { id: parse_id(header), seq: Vec::new(), qual: None });
+        } else if let Some(header) = line.strip_prefix('@') {
+            let mut next = || lines
+                .next()
+                .unwrap_or_else(|| Err(invalid("Truncated FASTQ record!")))
+                .map(|l| l.trim_end().as_bytes().to_owned());
+            let seq = next()?;
+            if !next()?.starts_with(b"+") {
+                return Err(invalid("Expected '+' line in FASTQ record!"));
+            }
+            let qual = next()?;
+            if qual.len() != seq.len() {
+                return Err(invalid("FASTQ sequence and quality lengths differ!"));
+            }
+            res.push(Record { id: parse_id(header), seq, qual: Some(qual) });
+        } else {
+            // continuation of a multi-line FASTA sequence
+            match res.last_mut() {
+                Some(r) if r.qual.is_none() => r.seq.extend_from_slice(line.as_bytes()),
+                _ => return Err(invalid("Expected a FASTA or FASTQ header!"))
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Read all records from a FASTA or FASTQ file.
+pub fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<Record>> {
+    read_records(BufReader::new(File::open(path)?))
+}
+
+fn parse_id(header: &str) -> String {
+    header.split_whitespace().next().unwrap_or("").to_owned()
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_records() {
+        let fasta = b">a first\nACGT\nAC\n\n>b\nTTTT\n";
+        let res = read_records(&fasta[..]).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0], Record { id: "a".to_owned(), seq: b"ACGTAC".to_vec(), qual: None });
+        assert_eq!(res[1].seq, b"TTTT");
+
+        let fastq = b"@r1 x\nACGT\n+\nIIII\n@r2\nA\n+r2\n#\n";
+        let res = read_records(&fastq[..]).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].qual.as_deref(), Some(&b"IIII"[..]));
+        assert_eq!(res[1].id, "r2");
+
+        assert!(read_records(&b"@r1\nACGT\n+\nII\n"[..]).is_err());
+        assert!(read_records(&b"ACGT\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Exporting the trace of an alignment to JSON.
+//!
+//! This is meant for external visualization dashboards and for attaching the internal
+//! state of an alignment to bug reports about accuracy. Only available with the `json`
+//! feature.
+//!
+//! The schema is a single object:
+//!
+//! ```text
+//! {
+//!   "blocks": [{"row": 0, "col": 0, "height": 32, "width": 32, "max": 12}, ...],
+//!   "checkpoints": [3, 8, ...],
+//!   "path": {
+//!     "start": [0, 0],
+//!     "end": [100, 110],
+//!     "cigar": "50M10D50M",
+//!     "cells": [[0, 0], [1, 1], ...]
+//!   }
+//! }
+//! ```
+//!
+//! * `blocks` are the rectangular regions from `Trace::blocks`, in the order that they were
+//!   computed, along with their max scores from `Trace::block_maxes` (`null` if every cell
+//!   in the region is unreachable). Rows and columns are indexes into the DP matrix, where
+//!   row `i` and column `j` correspond to the first `i` query characters and the first `j`
+//!   reference characters.
+//! * `checkpoints` are from `Trace::checkpoints`. Each entry is the number of blocks that
+//!   were computed when the checkpoint was saved.
+//! * `path` is the traceback path ending at `end`. `cells` lists every DP cell on the path,
+//!   from `start` to `end`.
+
+use crate::scan_block::*;
+use crate::cigar::*;
+
+use std::fmt::Write;
+
+impl Trace {
+    /// Export the blocks, checkpoints, and the traceback path ending at `(i, j)` to JSON.
+    ///
+    /// See the `json` module for the schema.
+    pub fn to_json(&self, i: usize, j: usize) -> String {
+        let cigar = self.cigar(i, j);
+        let ops = cigar.to_vec();
+        let query_consumed = ops.iter().filter(|op_len| op_len.op != Operation::D).map(|op_len| op_len.len).sum::<usize>();
+        let reference_consumed = ops.iter().filter(|op_len| op_len.op != Operation::I).map(|op_len| op_len.len).sum::<usize>();
+        let start = (i - query_consumed, j - reference_consumed);
+
+        let mut res = String::new();
+        res.push_str("{\"blocks\":[");
+        for (idx, (rect, &max)) in self.blocks_iter().zip(&self.block_maxes()).enumerate() {
+            if idx > 0 {
+                res.push(',');
+            }
+            write!(res, "{{\"row\":{},\"col\":{},\"height\":{},\"width\":{},\"max\":", rect.row, rect.col, rect.height, rect.width).unwrap();
+            if max == i32::MIN {
+                res.push_str("null}");
+            } else {
+                write!(res, "{}}}", max).unwrap();
+            }
+        }
+
+        res.push_str("],\"checkpoints\":[");
+        let ckpts = self.checkpoints().iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        res.push_str(&ckpts.join(","));
+
+        write!(res, "],\"path\":{{\"start\":[{},{}],\"end\":[{},{}],\"cigar\":\"{}\",\"cells\":[[{},{}]", start.0, start.1, i, j, cigar, start.0, start.1).unwrap();
+        let (mut ci, mut cj) = start;
+        for op_len in &ops {
+            for _ in 0..op_len.len {
+                match op_len.op {
+                    Operation::M => { ci += 1; cj += 1; },
+                    Operation::I => ci += 1,
+                    _ => cj += 1
+                }
+                write!(res, ",[{},{}]", ci, cj).unwrap();
+            }
+        }
+        res.push_str("]}}");
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scores::*;
+
+    #[test]
+    fn test_to_json() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAA", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AACAA", 16);
+        let a = Block::<_, true, false>::align(&q, &r, &NW1, gaps, 16..=16, 0);
+        let json = a.trace().to_json(5, 4);
+
+        assert!(json.starts_with("{\"blocks\":[{\"row\":0,\"col\":0,"));
+        assert!(json.ends_with("\"path\":{\"start\":[0,0],\"end\":[5,4],\"cigar\":\"2M1I2M\",\"cells\":[[0,0],[1,1],[2,2],[3,2],[4,3],[5,4]]}}"));
+        assert_eq!(json.matches("\"row\"").count(), a.trace().blocks_iter().len());
+    }
+}
@@ -0,0 +1,199 @@
+//! Compatibility shim with semantics similar to ksw2's `ksw_extz2_sse`.
+//!
+//! This makes it easier to port code that uses minimap2's ksw2 internals. Instead of a
+//! fixed band, block aligner adaptively grows its block up to a size derived from the
+//! bandwidth, and ksw2's Z-drop is approximated with X-drop.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::cmp;
+use std::ops::RangeInclusive;
+
+/// Only compute the score, without the CIGAR string.
+pub const KSW_EZ_SCORE_ONLY: i32 = 0x01;
+/// Right-align gaps. Not supported, so this is ignored.
+pub const KSW_EZ_RIGHT: i32 = 0x02;
+/// Generic scoring matrix. Always the case, so this is ignored.
+pub const KSW_EZ_GENERIC_SC: i32 = 0x04;
+/// Approximate max. Always the case, so this is ignored.
+pub const KSW_EZ_APPROX_MAX: i32 = 0x08;
+/// Approximate Z-drop. Always the case, so this is ignored.
+pub const KSW_EZ_APPROX_DROP: i32 = 0x10;
+/// Only do extension, so the global score is not computed.
+pub const KSW_EZ_EXTZ_ONLY: i32 = 0x40;
+/// Reverse the CIGAR string.
+pub const KSW_EZ_REV_CIGAR: i32 = 0x80;
+
+/// Score used when a score is not available.
+pub const KSW_NEG_INF: i32 = -0x40000000;
+
+/// Block size used when the bandwidth is negative (unbanded).
+const UNBANDED_SIZE: usize = 2048;
+const MIN_SIZE: usize = 32;
+
+/// Result of extension alignment, like ksw2's `ksw_extz_t`.
+///
+/// All positions are 0-based and inclusive, and they are -1 if there is no such position.
+#[derive(Clone, Debug)]
+pub struct ExtzResult {
+    /// Max score of any cell.
+    pub max: i32,
+    /// Query position of the max scoring cell.
+    pub max_q: i32,
+    /// Target position of the max scoring cell.
+    pub max_t: i32,
+    /// Max score when reaching the end of the query.
+    pub mqe: i32,
+    /// Target position where `mqe` is reached.
+    pub mqe_t: i32,
+    /// Max score when reaching the end of the target.
+    pub mte: i32,
+    /// Query position where `mte` is reached.
+    pub mte_q: i32,
+    /// Score of the global alignment, or `KSW_NEG_INF` with Z-drop or extension only.
+    pub score: i32,
+    /// Whether the alignment was terminated early.
+    pub zdropped: bool,
+    /// Whether the CIGAR string reaches the end of the query with extension only.
+    pub reach_end: bool,
+    /// CIGAR string, which is empty with `KSW_EZ_SCORE_ONLY`.
+    pub cigar: Vec<OpLen>
+}
+
+/// Align the query to the target with semantics similar to ksw2's `ksw_extz2_sse`.
+///
+/// The sequences are ASCII strings that are converted with the scoring matrix. Like ksw2,
+/// a gap of length `k` costs `q + k * e`, and `q` and `e` are positive.
+///
+/// The bandwidth `w` determines the max block size, and the block grows up to it only
+/// when needed. A negative bandwidth means that the band is unlimited (in practice, a large
+/// max block size is used). A negative `zdrop` disables early termination. With
+/// `KSW_EZ_EXTZ_ONLY`, the CIGAR string reaches the end of the query if `mqe + end_bonus`
+/// is greater than `max`.
+#[allow(clippy::too_many_arguments)]
+pub fn extz2<M: 'static + Matrix>(query: &[u8], target: &[u8], matrix: &M, q: i8, e: i8, w: i32, zdrop: i32, end_bonus: i32, flag: i32) -> ExtzResult {
+    assert!(q >= 0 && e > 0, "Gap open must be nonnegative and gap extend must be positive!");
+    let gaps = Gaps { open: -(q + e), extend: -e };
+    let max_size = if w < 0 {
+        UNBANDED_SIZE
+    } else {
+        cmp::max((2 * (w as usize) + 1).next_power_of_two(), MIN_SIZE)
+    };
+    let size = MIN_SIZE..=max_size;
+
+    let q_padded = PaddedBytes::from_bytes::<M>(query, max_size);
+    let t_padded = PaddedBytes::from_bytes::<M>(target, max_size);
+    let score_only = (flag & KSW_EZ_SCORE_ONLY) != 0;
+
+    match (score_only, zdrop >= 0) {
+        (true, true) => run::<M, false, true>(&q_padded, &t_padded, matrix, gaps, size, zdrop, end_bonus, flag),
+        (true, false) => run::<M, false, false>(&q_padded, &t_padded, matrix, gaps, size, 0, end_bonus, flag),
+        (false, true) => run::<M, true, true>(&q_padded, &t_padded, matrix, gaps, size, zdrop, end_bonus, flag),
+        (false, false) => run::<M, true, false>(&q_padded, &t_padded, matrix, gaps, size, 0, end_bonus, flag)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run<M: 'static + Matrix, const TRACE: bool, const X_DROP: bool>(query: &PaddedBytes, target: &PaddedBytes, matrix: &M, gaps: Gaps, size: RangeInclusive<usize>, zdrop: i32, end_bonus: i32, flag: i32) -> ExtzResult {
+    let a = Block::<_, TRACE, X_DROP>::align_with(query, target, matrix, gaps, size, zdrop, &AlignOptions::new().track_max().end_scores());
+    let max_res = a.max_res();
+    let (q_len, t_len) = (query.len(), target.len());
+    let zdropped = a.diagnostics().x_drop_terminated;
+
+    // the first entry is before any characters are aligned, which ksw2 does not report
+    let best_end = |scores: &[i32]| scores
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|&(_, &s)| s > i32::MIN)
+        .max_by_key(|&(i, &s)| (s, cmp::Reverse(i)))
+        .map(|(i, &s)| (s, (i as i32) - 1))
+        .unwrap_or((KSW_NEG_INF, -1));
+    let (mqe, mqe_t) = best_end(a.end_row_scores());
+    let (mte, mte_q) = best_end(a.end_col_scores());
+
+    let extz_only = (flag & KSW_EZ_EXTZ_ONLY) != 0;
+    let score = match a.end_row_scores()[t_len] {
+        s if !zdropped && !extz_only && s > i32::MIN => s,
+        _ => KSW_NEG_INF
+    };
+
+    let mut res = ExtzResult {
+        max: max_res.score,
+        max_q: (max_res.query_idx as i32) - 1,
+        max_t: (max_res.reference_idx as i32) - 1,
+        mqe,
+        mqe_t,
+        mte,
+        mte_q,
+        score,
+        zdropped,
+        reach_end: false,
+        cigar: Vec::new()
+    };
+
+    let end = if !zdropped && !extz_only {
+        Some((q_len, t_len))
+    } else if !zdropped && extz_only && mqe_t >= 0 && mqe + end_bonus > res.max {
+        res.reach_end = true;
+        Some((q_len, (mqe_t + 1) as usize))
+    } else if res.max_q >= 0 && res.max_t >= 0 {
+        Some((max_res.query_idx, max_res.reference_idx))
+    } else {
+        None
+    };
+
+    if let (true, Some((i, j))) = (TRACE, end) {
+        res.cigar = a.trace().cigar(i, j).to_vec();
+        if (flag & KSW_EZ_REV_CIGAR) != 0 {
+            res.cigar.reverse();
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cigar_string(cigar: &[OpLen]) -> String {
+        cigar.iter().map(|op_len| format!("{}{:?}", op_len.len, op_len.op)).collect()
+    }
+
+    #[test]
+    fn test_extz2() {
+        let matrix = NucMatrix::new_simple(2, -4);
+        let q = b"ACGTACGTACGTTTTTACGTACGTACGTGGGGGGGGCCCCCCCC";
+        let t = b"ACGTACGTACGTACGTACGTACGTAAAAAAAACCCCCCCCAAAA";
+
+        // global alignment
+        let res = extz2(&q[..28], &t[..24], &matrix, 4, 2, -1, -1, 0, 0);
+        assert_eq!(res.score, 24 * 2 - 4 - 4 * 2);
+        assert_eq!(res.score, res.mqe);
+        assert_eq!(res.mqe_t, 23);
+        assert_eq!(cigar_string(&res.cigar), "12M4I12M");
+        assert!(!res.zdropped);
+
+        // extension stops at the max, unless the end bonus is large enough
+        let res = extz2(q, t, &matrix, 4, 2, 16, 20, 0, KSW_EZ_EXTZ_ONLY);
+        assert_eq!(res.score, KSW_NEG_INF);
+        assert_eq!((res.max, res.max_q, res.max_t), (36, 27, 23));
+        assert!(!res.reach_end);
+        assert_eq!(cigar_string(&res.cigar), "12M4I12M");
+
+        let res = extz2(q, t, &matrix, 4, 2, 16, 100, 100, KSW_EZ_EXTZ_ONLY | KSW_EZ_REV_CIGAR);
+        assert!(res.reach_end);
+        assert_eq!(res.cigar.iter().filter(|op_len| op_len.op != Operation::D).map(|op_len| op_len.len).sum::<usize>(), q.len());
+        assert_eq!(res.cigar.last().unwrap().len, 12);
+
+        // z-drop terminates early
+        let res = extz2(q, t, &matrix, 4, 2, 16, 10, 0, KSW_EZ_SCORE_ONLY);
+        assert!(res.zdropped);
+        assert_eq!(res.score, KSW_NEG_INF);
+        assert_eq!(res.max, 36);
+        assert!(res.cigar.is_empty());
+    }
+}
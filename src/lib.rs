@@ -23,7 +23,28 @@
 //! ```
 //!
 //! When building your code that uses this library, it is important to specify the
-//! correct feature flags: `simd_avx2` or `simd_wasm`.
+//! correct feature flags: `simd_avx2` or `simd_wasm`. Enabling both is allowed, but only
+//! the native backend that matches the target architecture is compiled. The portable
+//! `scalar` backend is always compiled alongside it, and the backend can be selected for
+//! each alignment with the last generic parameter of `Block`, for example to fall back to
+//! `Scalar` when `NativeBackend::is_supported()` is false at runtime:
+//! ```
+//! use block_aligner::backend::*;
+//! use block_aligner::scalar::Scalar;
+//! use block_aligner::scan_block::*;
+//! use block_aligner::scores::*;
+//!
+//! let gaps = Gaps { open: -2, extend: -1 };
+//! let r = PaddedBytes::from_bytes::<NucMatrix>(b"TTAAAAAAATTTTTTTTTTTT", 16);
+//! let q = PaddedBytes::from_bytes::<NucMatrix>(b"TTTTTTTTAAAAAAATTTTTTTTT", 16);
+//!
+//! let res = if NativeBackend::is_supported() {
+//!     Block::<_, false, false>::align(&q, &r, &NW1, gaps, 16..=16, 0).res()
+//! } else {
+//!     Block::<_, false, false, false, Scalar>::align(&q, &r, &NW1, gaps, 16..=16, 0).res()
+//! };
+//! assert_eq!(res.score, 7);
+//! ```
 
 #![cfg_attr(feature = "mca", feature(asm))]
 
@@ -35,33 +56,96 @@
 // special SIMD instruction set modules adapted for this library
 // their types and lengths are abstracted out
 
-#[cfg(feature = "simd_avx2")]
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod backend;
+
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub use backend::{backend_info, backend_info_for, BackendInfo};
+
+#[cfg(block_aligner_avx2)]
 #[macro_use]
 #[doc(hidden)]
 /// cbindgen:ignore
 pub mod avx2;
 
-#[cfg(feature = "simd_avx2")]
+#[cfg(block_aligner_avx2)]
 pub use avx2::L;
 
-#[cfg(feature = "simd_wasm")]
+#[cfg(block_aligner_wasm)]
 #[macro_use]
 #[doc(hidden)]
 /// cbindgen:ignore
 pub mod simd128;
 
-#[cfg(feature = "simd_wasm")]
+#[cfg(block_aligner_wasm)]
 pub use simd128::L;
 
-#[cfg(any(feature = "simd_avx2", feature = "simd_wasm"))]
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+/// cbindgen:ignore
+pub mod scalar;
+
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
 pub mod scan_block;
-#[cfg(any(feature = "simd_avx2", feature = "simd_wasm"))]
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
 pub mod scores;
-#[cfg(any(feature = "simd_avx2", feature = "simd_wasm"))]
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
 pub mod cigar;
-#[cfg(any(feature = "simd_avx2", feature = "simd_wasm"))]
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
 pub mod simulate;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod multi;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod pool;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod profile;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod poa;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod chain;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod chunk;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod tokens;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod stats;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod pair_hmm;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod search;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod ksw2;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod parasail;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod wfa;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod myers;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod auto;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod strand;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod sketch;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod race;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod sv;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod weights;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod simple;
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub use simple::{align_score, align_with_cigar};
+#[cfg(any(block_aligner_avx2, block_aligner_wasm))]
+pub mod prelude;
+pub mod fasta;
+#[cfg(all(feature = "json", any(block_aligner_avx2, block_aligner_wasm)))]
+pub mod json;
+#[cfg(all(feature = "reference", any(block_aligner_avx2, block_aligner_wasm)))]
+pub mod reference;
+#[cfg(all(feature = "bench_accuracy", any(block_aligner_avx2, block_aligner_wasm)))]
+pub mod bench_accuracy;
 
-#[cfg(feature = "simd_avx2")]
+#[cfg(block_aligner_avx2)]
 #[doc(hidden)]
 pub mod ffi;
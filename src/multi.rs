@@ -0,0 +1,214 @@
+//! Many-to-many alignment of sets of queries and references on multiple threads.
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{cmp, thread};
+
+/// A single hit between a query and a reference.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Hit {
+    /// Index of the reference in the list of references.
+    pub reference: usize,
+    /// X-drop alignment result for the query and the reference.
+    pub res: AlignResult
+}
+
+/// Data structure storing the settings for aligning many queries against many references.
+///
+/// Each pair is aligned with X-drop alignment (no traceback), which acts as a cheap
+/// prefilter, and only the best `top_k` hits for each query are kept.
+pub struct MultiAligner<'a, M: 'static + Matrix + Sync> {
+    matrix: &'a M,
+    gaps: Gaps,
+    size: RangeInclusive<usize>,
+    x_drop: i32,
+    min_score: i32,
+    top_k: usize,
+    threads: usize
+}
+
+impl<'a, M: 'static + Matrix + Sync> MultiAligner<'a, M> {
+    /// Create a new many-to-many aligner.
+    ///
+    /// By default, all available threads are used, only the single best hit is kept
+    /// for each query, and hits of any score are reported.
+    pub fn new(matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>, x_drop: i32) -> Self {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Self { matrix, gaps, size, x_drop, min_score: i32::MIN, top_k: 1, threads }
+    }
+
+    /// Set the number of hits to keep for each query.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Set the number of worker threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "Number of threads must be positive!");
+        self.threads = threads;
+        self
+    }
+
+    /// Discard hits whose X-drop score is below `min_score`.
+    pub fn min_score(mut self, min_score: i32) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Align every query against every reference.
+    ///
+    /// Returns the top hits for each query, sorted by decreasing score.
+    pub fn align_all(&self, queries: &[PaddedBytes], references: &[PaddedBytes]) -> Vec<Vec<Hit>> {
+        let pairs = (0..queries.len())
+            .map(|_| (0..references.len()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        self.align_grouped(queries, references, &pairs)
+    }
+
+    /// Align only the specified `(query, reference)` index pairs.
+    ///
+    /// Returns the top hits for each query, sorted by decreasing score.
+    pub fn align_pairs(&self, queries: &[PaddedBytes], references: &[PaddedBytes], pairs: &[(usize, usize)]) -> Vec<Vec<Hit>> {
+        let mut grouped = vec![Vec::new(); queries.len()];
+        for &(q, r) in pairs {
+            assert!(q < queries.len() && r < references.len(), "Pair indexes must be in bounds!");
+            grouped[q].push(r);
+        }
+        self.align_grouped(queries, references, &grouped)
+    }
+
+    /// Align every query against a single shared reference.
+    ///
+    /// The reference only needs to be converted and padded once with `PaddedBytes`, which
+    /// is shared by all threads without copying, even when thousands of queries (for example,
+    /// amplicon reads) are aligned against it. Windows of a long reference can be aligned
+    /// without copying with `PaddedBytes::slice`.
+    ///
+    /// Returns the result for each query, or `None` if the score is below the min score.
+    pub fn align_reference(&self, queries: &[PaddedBytes], reference: &PaddedBytes) -> Vec<Option<AlignResult>> {
+        assert!(reference.padded_bytes().len() > reference.len() + *self.size.end(), "Reference must be padded with at least the max block size!");
+        let grouped = vec![vec![0]; queries.len()];
+        self.align_grouped(queries, std::slice::from_ref(reference), &grouped)
+            .iter()
+            .map(|hits| hits.first().map(|h| h.res))
+            .collect()
+    }
+
+    fn align_grouped(&self, queries: &[PaddedBytes], references: &[PaddedBytes], grouped: &[Vec<usize>]) -> Vec<Vec<Hit>> {
+        // each query is a unit of work that is handed out to the next free thread
+        let next = AtomicUsize::new(0);
+        let threads = cmp::max(cmp::min(self.threads, queries.len()), 1);
+
+        let mut res = vec![Vec::new(); queries.len()];
+
+        if threads == 1 {
+            // avoid spawning threads, which is not supported on some platforms (eg., WASM)
+            for (q_idx, query) in queries.iter().enumerate() {
+                res[q_idx] = self.align_query(query, references, &grouped[q_idx]);
+            }
+            return res;
+        }
+
+        thread::scope(|s| {
+            let handles = (0..threads)
+                .map(|_| s.spawn(|| {
+                    let mut local = Vec::new();
+                    loop {
+                        let q_idx = next.fetch_add(1, Ordering::Relaxed);
+                        if q_idx >= queries.len() {
+                            break;
+                        }
+                        local.push((q_idx, self.align_query(&queries[q_idx], references, &grouped[q_idx])));
+                    }
+                    local
+                }))
+                .collect::<Vec<_>>();
+
+            for h in handles {
+                for (q_idx, hits) in h.join().unwrap() {
+                    res[q_idx] = hits;
+                }
+            }
+        });
+
+        res
+    }
+
+    fn align_query(&self, query: &PaddedBytes, references: &[PaddedBytes], ref_idxs: &[usize]) -> Vec<Hit> {
+        let mut hits = Vec::with_capacity(cmp::min(ref_idxs.len(), self.top_k + 1));
+
+        for &r_idx in ref_idxs {
+            let a = Block::<_, false, true>::align(query, &references[r_idx], self.matrix, self.gaps, self.size.clone(), self.x_drop);
+            let res = a.res();
+
+            if res.score < self.min_score {
+                continue;
+            }
+
+            // keep the hits sorted and only retain the top k
+            let idx = hits.partition_point(|h: &Hit| h.res.score >= res.score);
+            if idx < self.top_k {
+                hits.insert(idx, Hit { reference: r_idx, res });
+                hits.truncate(self.top_k);
+            }
+        }
+
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_k() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let queries = vec![
+            PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAA", 16),
+            PaddedBytes::from_bytes::<NucMatrix>(b"CCCCCCCCCCCCCCCC", 16)
+        ];
+        let references = vec![
+            PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAATTTTTTTT", 16),
+            PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAA", 16),
+            PaddedBytes::from_bytes::<NucMatrix>(b"CCCCCCCCCCCCAAAA", 16)
+        ];
+
+        let aligner = MultiAligner::new(&NW1, gaps, 16..=16, 4).top_k(2).threads(2);
+        let res = aligner.align_all(&queries, &references);
+        assert_eq!(res[0].iter().map(|h| h.reference).collect::<Vec<_>>(), vec![1, 0]);
+        assert_eq!(res[0][0].res.score, 16);
+        assert_eq!(res[1][0].reference, 2);
+        assert_eq!(res[1][0].res.score, 12);
+
+        let res = aligner.min_score(10).align_pairs(&queries, &references, &[(0, 0), (1, 1)]);
+        assert!(res[0].is_empty());
+        assert!(res[1].is_empty());
+    }
+
+    #[test]
+    fn test_align_reference() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let reference = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAACCCCCCCCGGGGGGGG", 16);
+        let queries = (0..8)
+            .map(|i| PaddedBytes::from_bytes::<NucMatrix>(&b"AAAAAAAACCCCCCCCTTTTTTTT"[..16 + i], 16))
+            .collect::<Vec<_>>();
+
+        let aligner = MultiAligner::new(&NW1, gaps, 16..=16, 4).threads(3);
+        let res = aligner.align_reference(&queries, &reference);
+        for (q, r) in queries.iter().zip(&res) {
+            let a = Block::<_, false, true>::align(q, &reference, &NW1, gaps, 16..=16, 4);
+            assert_eq!(*r, Some(a.res()));
+        }
+        assert_eq!(res[0].unwrap().score, 16);
+        assert!(aligner.min_score(17).align_reference(&queries, &reference).iter().all(|r| r.is_none()));
+
+        let window = reference.slice(8..24);
+        let a = Block::<_, false, true>::align(&queries[0], &window, &NW1, gaps, 16..=16, 4);
+        assert_eq!(a.res().score, 0);
+    }
+}
@@ -0,0 +1,270 @@
+//! Myers' bit-parallel edit distance, as a fast path for near-identical sequences.
+//!
+//! The edit distance between two strings is computed 64 query positions at a time.
+//! If the sequences are within a small number of edits, then the edit alignment is
+//! directly converted into a CIGAR string and the full aligner does not need to run.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::cmp;
+use std::ops::RangeInclusive;
+
+const WORD: usize = 64;
+
+/// Result of `align_near_identical`.
+pub struct NearIdenticalAlignment {
+    /// Result of the global alignment.
+    pub res: AlignResult,
+    /// CIGAR string of the global alignment.
+    pub cigar: Cigar,
+    /// Edit distance, if the sequences were within the edit cutoff.
+    ///
+    /// If this is `None`, then the full aligner was used.
+    pub edits: Option<usize>
+}
+
+/// Bit vectors for each character in the query.
+struct Peq {
+    words: usize,
+    bits: Vec<u64>
+}
+
+impl Peq {
+    fn new<M: Matrix>(query: &[u8]) -> Self {
+        let words = div_ceil(query.len(), WORD).max(1);
+        let mut bits = vec![0u64; 256 * words];
+        for (i, &c) in query.iter().enumerate() {
+            bits[(M::convert_char(c) as usize) * words + i / WORD] |= 1u64 << (i % WORD);
+        }
+        // positions past the end of the query match everything, so they never add edits
+        let rem = query.len() % WORD;
+        if rem > 0 || query.is_empty() {
+            let pad = if query.is_empty() { u64::MAX } else { !0u64 << rem };
+            for c in 0..256 {
+                bits[c * words + words - 1] |= pad;
+            }
+        }
+        Self { words, bits }
+    }
+
+    #[inline]
+    fn get(&self, c: u8) -> &[u64] {
+        &self.bits[(c as usize) * self.words..(c as usize + 1) * self.words]
+    }
+}
+
+/// Compute one column of the vertical score deltas, 64 rows at a time.
+///
+/// `pv` and `mv` are the positive and negative vertical deltas.
+#[inline]
+fn advance_column(pv: &mut [u64], mv: &mut [u64], eq: &[u64]) {
+    // the first row of global alignment always increases by one
+    let mut h_in = 1i32;
+    for w in 0..pv.len() {
+        let (p, m) = (pv[w], mv[w]);
+        let h_neg = (h_in < 0) as u64;
+        let xv = eq[w] | m;
+        let e = eq[w] | h_neg;
+        let xh = ((e & p).wrapping_add(p) ^ p) | e;
+        let mut ph = m | !(xh | p);
+        let mut mh = p & xh;
+        let h_out = ((ph >> 63) as i32) - ((mh >> 63) as i32);
+        ph = (ph << 1) | ((h_in > 0) as u64);
+        mh = (mh << 1) | h_neg;
+        pv[w] = mh | !(xv | ph);
+        mv[w] = ph & xv;
+        h_in = h_out;
+    }
+}
+
+/// Score in row `i` of a column, given the score in the first row and the vertical deltas.
+#[inline]
+fn score_at(top: usize, pv: &[u64], mv: &[u64], i: usize) -> usize {
+    let (full, rem) = (i / WORD, i % WORD);
+    let mut s = top as isize;
+    for w in 0..full {
+        s += (pv[w].count_ones() as isize) - (mv[w].count_ones() as isize);
+    }
+    if rem > 0 {
+        let mask = (1u64 << rem) - 1;
+        s += ((pv[full] & mask).count_ones() as isize) - ((mv[full] & mask).count_ones() as isize);
+    }
+    s as usize
+}
+
+/// Compute the global edit distance between two strings with Myers' bit-parallel algorithm.
+///
+/// Bytes are compared after they are converted by the matrix.
+pub fn edit_distance<M: Matrix>(query: &[u8], reference: &[u8]) -> usize {
+    let peq = Peq::new::<M>(query);
+    let mut pv = vec![u64::MAX; peq.words];
+    let mut mv = vec![0u64; peq.words];
+    for &c in reference {
+        advance_column(&mut pv, &mut mv, peq.get(M::convert_char(c)));
+    }
+    score_at(reference.len(), &pv, &mv, query.len())
+}
+
+/// Number of words of vertical deltas that are stored for each column of the traceback
+/// in `edit_align`.
+///
+/// Only the rows within `k` of the diagonal are needed, so the traceback uses
+/// `2 * band_words(query_len, k) * (reference_len + 1)` words of memory.
+pub(crate) fn band_words(query_len: usize, k: usize) -> usize {
+    cmp::min(div_ceil(2 * k + 1, WORD) + 1, div_ceil(query_len, WORD).max(1))
+}
+
+/// Compute a global edit alignment if the edit distance is at most `k`.
+///
+/// Since an alignment with at most `k` edits stays within `k` diagonals of the main
+/// diagonal, only a band of rows around the diagonal is stored for the traceback, so the
+/// memory use is proportional to `k` times the reference length instead of the product
+/// of the lengths.
+///
+/// Returns the edit distance and the CIGAR string, or `None` if there are too many edits.
+pub fn edit_align<M: Matrix>(query: &[u8], reference: &[u8], k: usize) -> Option<(usize, Cigar)> {
+    if query.len().abs_diff(reference.len()) > k {
+        return None;
+    }
+    // check the distance first so the traceback is only stored for near-identical sequences
+    let dist = edit_distance::<M>(query, reference);
+    if dist > k {
+        return None;
+    }
+
+    let peq = Peq::new::<M>(query);
+    let words = peq.words;
+    let band = band_words(query.len(), k);
+    let mut pv = vec![u64::MAX; words];
+    let mut mv = vec![0u64; words];
+    // for each column, the first stored word, the score at its first row, and the stored words
+    let mut lo = vec![0usize; reference.len() + 1];
+    let mut top = vec![0usize; reference.len() + 1];
+    let mut band_pv = vec![0u64; band * (reference.len() + 1)];
+    let mut band_mv = vec![0u64; band * (reference.len() + 1)];
+    for j in 0..=reference.len() {
+        if j > 0 {
+            advance_column(&mut pv, &mut mv, peq.get(M::convert_char(reference[j - 1])));
+        }
+        let w = cmp::min(j.saturating_sub(k) / WORD, words - band);
+        lo[j] = w;
+        top[j] = score_at(j, &pv, &mv, w * WORD);
+        band_pv[j * band..(j + 1) * band].copy_from_slice(&pv[w..w + band]);
+        band_mv[j * band..(j + 1) * band].copy_from_slice(&mv[w..w + band]);
+    }
+
+    let d = |i: usize, j: usize| {
+        // cells outside of the band cannot be on a path with at most k edits
+        if i.abs_diff(j) > k {
+            return usize::MAX / 2;
+        }
+        score_at(top[j], &band_pv[j * band..(j + 1) * band], &band_mv[j * band..(j + 1) * band], i - lo[j] * WORD)
+    };
+    let mut cigar = unsafe { Cigar::new(query.len() + reference.len()) };
+    let (mut i, mut j) = (query.len(), reference.len());
+    while i > 0 || j > 0 {
+        let curr = d(i, j);
+        if i > 0 && j > 0 && d(i - 1, j - 1) + (M::convert_char(query[i - 1]) != M::convert_char(reference[j - 1])) as usize == curr {
+            unsafe { cigar.add(Operation::M); }
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && d(i - 1, j) + 1 == curr {
+            unsafe { cigar.add(Operation::I); }
+            i -= 1;
+        } else {
+            unsafe { cigar.add(Operation::D); }
+            j -= 1;
+        }
+    }
+
+    Some((dist, cigar))
+}
+
+/// Globally align two strings, first checking whether they are within `k` edits.
+///
+/// This is meant for sequences that are expected to be highly similar. If they are within
+/// `k` edits, then the edit alignment is returned and scored with the matrix and gap costs,
+/// which is very fast but not necessarily optimal for the scoring scheme. Otherwise, block
+/// aligner is used with the range of block sizes in `size`.
+pub fn align_near_identical<M: 'static + Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>, k: usize) -> NearIdenticalAlignment {
+    if let Some((edits, cigar)) = edit_align::<M>(query, reference, k) {
+        let score = cigar_score(&cigar, query, reference, matrix, gaps);
+        let res = AlignResult { score, query_idx: query.len(), reference_idx: reference.len() };
+        return NearIdenticalAlignment { res, cigar, edits: Some(edits) };
+    }
+
+    let q = PaddedBytes::from_bytes::<M>(query, *size.end());
+    let r = PaddedBytes::from_bytes::<M>(reference, *size.end());
+    let a = Block::<_, true, false>::align(&q, &r, matrix, gaps, size, 0);
+    let res = a.res();
+    NearIdenticalAlignment { res, cigar: a.trace().cigar(res.query_idx, res.reference_idx), edits: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::*;
+    use rand::prelude::*;
+
+    fn slow_edit_distance(q: &[u8], r: &[u8]) -> usize {
+        let mut prev = (0..=r.len()).collect::<Vec<_>>();
+        for i in 1..=q.len() {
+            let mut curr = vec![i; r.len() + 1];
+            for j in 1..=r.len() {
+                curr[j] = (prev[j - 1] + (q[i - 1] != r[j - 1]) as usize).min(prev[j] + 1).min(curr[j - 1] + 1);
+            }
+            prev = curr;
+        }
+        prev[r.len()]
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        let mut rng = StdRng::seed_from_u64(1234);
+        for &len in &[0, 1, 10, 63, 64, 65, 200] {
+            for _i in 0..10 {
+                let r = rand_str(len, &NUC, &mut rng);
+                let q = rand_mutate(&r, (len / 10 + 1).min(len), &NUC, &mut rng);
+                assert_eq!(edit_distance::<NucMatrix>(&q, &r), slow_edit_distance(&q, &r));
+            }
+        }
+        assert_eq!(edit_distance::<NucMatrix>(b"acgt", b"ACGT"), 0);
+    }
+
+    #[test]
+    fn test_align_near_identical() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let q = b"ACGTACGTACGTTACGTACGT";
+        let r = b"ACGTACGTACGTACGTACCT";
+
+        let (edits, cigar) = edit_align::<NucMatrix>(q, r, 2).unwrap();
+        assert_eq!(edits, 2);
+        assert_eq!(cigar.to_string(), "11M1I9M");
+        assert!(edit_align::<NucMatrix>(q, r, 1).is_none());
+
+        // the band covers a small part of long queries
+        let mut rng = StdRng::seed_from_u64(1234);
+        for &(len, k) in &[(1000, 5), (1000, 40), (300, 100), (64, 0), (128, 1)] {
+            let r = rand_str(len, &NUC, &mut rng);
+            let q = rand_mutate(&r, k, &NUC, &mut rng);
+            let dist = slow_edit_distance(&q, &r);
+            let (edits, cigar) = edit_align::<NucMatrix>(&q, &r, dist).unwrap();
+            assert_eq!(edits, dist);
+            let ops = cigar.to_vec();
+            let mismatches = cigar.aligned_pairs(0, 0).filter(|&p| matches!(p, (Some(i), Some(j)) if q[i] != r[j])).count();
+            let indels = ops.iter().filter(|o| o.op != Operation::M).map(|o| o.len).sum::<usize>();
+            assert_eq!(mismatches + indels, dist);
+        }
+        assert!(band_words(100000, 100) < 10);
+
+        let a = align_near_identical(q, r, &NW1, gaps, 16..=32, 2);
+        assert_eq!(a.edits, Some(2));
+        assert_eq!(a.res, AlignResult { score: 16, query_idx: 21, reference_idx: 20 });
+
+        let a = align_near_identical(q, r, &NW1, gaps, 16..=32, 1);
+        assert_eq!(a.edits, None);
+        assert_eq!(a.res.score, 16);
+    }
+}
@@ -0,0 +1,285 @@
+//! Pair hidden Markov model (pair-HMM) for computing read likelihoods and per-base
+//! alignment posterior probabilities, as used by GATK-style genotypers.
+//!
+//! The read must be aligned end-to-end, but the haplotype can start and end anywhere.
+//! Match/mismatch emission probabilities are based on the Phred-scaled base qualities
+//! of the read.
+//!
+//! The forward and backward algorithms are scalar and use per-row scaling to avoid
+//! underflow. To avoid computing and storing the full DP matrix, they can be restricted to
+//! a band around the alignment found by block aligner, which is obtained with `PairHmm::band`.
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+use std::cmp;
+use std::ops::{Range, RangeInclusive};
+
+/// Pair-HMM with gap open and gap extend probabilities.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PairHmm {
+    gap_open: f64,
+    gap_extend: f64
+}
+
+/// Posterior alignment probabilities for a single read base.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BasePosterior {
+    /// Most likely 0-based haplotype position that the read base is aligned to.
+    pub hap_idx: usize,
+    /// Posterior probability that the read base is aligned to `hap_idx`.
+    pub prob: f64,
+    /// Posterior probability that the read base is aligned to any haplotype base,
+    /// instead of being inserted.
+    pub aligned: f64
+}
+
+/// Result of running the forward and backward algorithms.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PairHmmResult {
+    /// Log10 likelihood of the read, given the haplotype.
+    pub log10_likelihood: f64,
+    /// Posterior probabilities for each read base.
+    pub posteriors: Vec<BasePosterior>
+}
+
+/// Band of haplotype positions for each row of the DP matrix.
+///
+/// For each row `i` in `0..=read.len()`, the band is the range of DP matrix
+/// columns `start..end`.
+pub type Band = Vec<(usize, usize)>;
+
+impl PairHmm {
+    /// Create a new pair-HMM with the probability of opening a gap from a match and
+    /// the probability of extending a gap.
+    pub fn new(gap_open: f64, gap_extend: f64) -> Self {
+        assert!(gap_open > 0.0 && gap_open < 0.5, "Gap open probability must be between 0 and 0.5!");
+        assert!(gap_extend > 0.0 && gap_extend < 1.0, "Gap extend probability must be between 0 and 1!");
+        Self { gap_open, gap_extend }
+    }
+
+    /// Compute a band around the alignment between the read and the haplotype with
+    /// glocal block aligner.
+    ///
+    /// The band covers all of the DP cells that were computed by block aligner, so
+    /// a larger max block size results in a wider band around difficult regions.
+    pub fn band<M: 'static + Matrix>(read: &[u8], hap: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>) -> Band {
+        let read_padded = PaddedBytes::from_bytes::<M>(read, *size.end());
+        let hap_padded = PaddedBytes::from_bytes::<M>(hap, *size.end());
+        let a = Block::<_, true, false>::align_with(&read_padded, &hap_padded, matrix, gaps, size, 0, &AlignOptions::new().glocal());
+
+        let mut band = vec![(usize::MAX, 0); read.len() + 1];
+        for rect in a.trace().blocks_iter() {
+            let row_end = cmp::min(rect.row + rect.height, read.len() + 1);
+            let col_end = cmp::min(rect.col + rect.width, hap.len() + 1);
+            for b in band.iter_mut().take(row_end).skip(rect.row) {
+                *b = (cmp::min(b.0, rect.col), cmp::max(b.1, col_end));
+            }
+        }
+        band.iter_mut().filter(|b| b.0 >= b.1).for_each(|b| *b = (0, 0));
+        band
+    }
+
+    /// Compute the likelihood of the read and the posterior probabilities of each read
+    /// base with the forward and backward algorithms.
+    ///
+    /// `quals` are the Phred-scaled base qualities of the read (without any ASCII offset).
+    /// If `band` is specified, then only cells in the band are computed and stored.
+    pub fn forward_backward(&self, read: &[u8], quals: &[u8], hap: &[u8], band: Option<&[(usize, usize)]>) -> PairHmmResult {
+        assert!(!read.is_empty() && !hap.is_empty(), "Read and haplotype must not be empty!");
+        assert_eq!(read.len(), quals.len(), "There must be one quality score per read base!");
+        if let Some(band) = band {
+            assert_eq!(band.len(), read.len() + 1, "There must be one band range per row!");
+        }
+
+        let (n, m) = (read.len(), hap.len());
+        let width = m + 1;
+        // columns in row i, excluding column 0 since no haplotype bases are aligned there
+        let cols = |i: usize| match band {
+            Some(band) => cmp::max(band[i].0, 1)..cmp::min(band[i].1, width),
+            None => 1..width
+        };
+        // only the columns in the band are stored, except in the first row, since the
+        // haplotype can start anywhere
+        let layout = BandLayout::new((0..=n).map(|i| if i == 0 { 0..width } else { cols(i) }));
+        let get = |v: &[f64], i: usize, j: usize| layout.idx(i, j).map_or(0.0, |k| v[k]);
+
+        // transition probabilities
+        let mm = 1.0 - 2.0 * self.gap_open;
+        let gap = self.gap_open;
+        let ext = self.gap_extend;
+        let gm = 1.0 - self.gap_extend;
+
+        // emission probability of read base i and haplotype base j (1-based)
+        let prior = |i: usize, j: usize| {
+            let err = 10f64.powf(-(quals[i - 1] as f64) / 10.0);
+            let (a, b) = (read[i - 1].to_ascii_uppercase(), hap[j - 1].to_ascii_uppercase());
+            if a == b || a == b'N' || b == b'N' { 1.0 - err } else { err / 3.0 }
+        };
+
+        let mut f_m = vec![0f64; layout.len()];
+        let mut f_i = vec![0f64; layout.len()];
+        let mut f_d = vec![0f64; layout.len()];
+        // scaling factor of each row, with no scaling for the first row
+        let mut scale = Vec::with_capacity(n + 1);
+        scale.push(1f64);
+
+        // the haplotype can start anywhere
+        f_d[layout.row(0)].iter_mut().for_each(|f| *f = 1.0 / (m as f64));
+
+        for i in 1..=n {
+            let mut sum = 0f64;
+            for j in cols(i) {
+                let fm = prior(i, j) * (get(&f_m, i - 1, j - 1) * mm + (get(&f_i, i - 1, j - 1) + get(&f_d, i - 1, j - 1)) * gm);
+                let fi = get(&f_m, i - 1, j) * gap + get(&f_i, i - 1, j) * ext;
+                let fd = get(&f_m, i, j - 1) * gap + get(&f_d, i, j - 1) * ext;
+                let k = layout.idx(i, j).unwrap();
+                f_m[k] = fm;
+                f_i[k] = fi;
+                f_d[k] = fd;
+                sum += fm + fi + fd;
+            }
+
+            if sum <= 0.0 {
+                // no path through the band
+                return PairHmmResult { log10_likelihood: f64::NEG_INFINITY, posteriors: Vec::new() };
+            }
+
+            for k in layout.row(i) {
+                f_m[k] /= sum;
+                f_i[k] /= sum;
+                f_d[k] /= sum;
+            }
+            scale.push(sum);
+        }
+
+        // the haplotype can end anywhere
+        let end = layout.row(n).map(|k| f_m[k] + f_i[k]).sum::<f64>();
+        let log10_likelihood = scale.iter().map(|s| s.log10()).sum::<f64>() + end.log10();
+
+        let mut b_m = vec![0f64; layout.len()];
+        let mut b_i = vec![0f64; layout.len()];
+        let mut b_d = vec![0f64; layout.len()];
+
+        for k in layout.row(n) {
+            b_m[k] = 1.0;
+            b_i[k] = 1.0;
+        }
+
+        for i in (1..n).rev() {
+            let s = scale[i + 1];
+            for j in cols(i).rev() {
+                let e = if j < m { prior(i + 1, j + 1) * get(&b_m, i + 1, j + 1) } else { 0.0 };
+                let (next_d, next_i) = (if j < m { get(&b_d, i, j + 1) } else { 0.0 }, get(&b_i, i + 1, j));
+                let k = layout.idx(i, j).unwrap();
+                b_m[k] = (mm * e + gap * next_i) / s + gap * next_d;
+                b_i[k] = (gm * e + ext * next_i) / s;
+                b_d[k] = (gm * e) / s + ext * next_d;
+            }
+        }
+
+        let posteriors = (1..=n)
+            .map(|i| {
+                let mut res = BasePosterior { hap_idx: 0, prob: 0.0, aligned: 0.0 };
+                let mut total = 0.0;
+                for (j, k) in cols(i).zip(layout.row(i)) {
+                    let p = f_m[k] * b_m[k] / end;
+                    if p > res.prob {
+                        res.hap_idx = j - 1;
+                        res.prob = p;
+                    }
+                    res.aligned += p;
+                    total += p + f_i[k] * b_i[k] / end;
+                }
+                // each read base must be either aligned or inserted
+                debug_assert!((total - 1.0f64).abs() < 1e-6);
+                res
+            })
+            .collect::<Vec<_>>();
+
+        PairHmmResult { log10_likelihood, posteriors }
+    }
+}
+
+/// Positions of the cells of each row in the flattened DP matrices, which only store a
+/// range of columns for each row.
+struct BandLayout {
+    cols: Vec<Range<usize>>,
+    // index of the first cell of each row, and the total number of cells at the end
+    offsets: Vec<usize>
+}
+
+impl BandLayout {
+    fn new<I: Iterator<Item = Range<usize>>>(rows: I) -> Self {
+        let cols = rows.collect::<Vec<_>>();
+        let mut offsets = Vec::with_capacity(cols.len() + 1);
+        offsets.push(0);
+        for c in &cols {
+            offsets.push(offsets.last().unwrap() + c.len());
+        }
+        Self { cols, offsets }
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        *self.offsets.last().unwrap()
+    }
+
+    /// Indexes of the cells in row `i`.
+    #[inline]
+    fn row(&self, i: usize) -> Range<usize> {
+        self.offsets[i]..self.offsets[i + 1]
+    }
+
+    /// Index of the cell at row `i` and column `j`, if it is stored.
+    #[inline]
+    fn idx(&self, i: usize, j: usize) -> Option<usize> {
+        let cols = &self.cols[i];
+        if cols.contains(&j) { Some(self.offsets[i] + j - cols.start) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pair_hmm() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let hmm = PairHmm::new(1e-3, 0.1);
+
+        let hap = b"TTTTTACGTACGGATCCAGTTTTT";
+        let read = b"ACGTACGGATCCAG";
+        let quals = vec![30; read.len()];
+        let res = hmm.forward_backward(read, &quals, hap, None);
+        assert!(res.log10_likelihood < 0.0);
+        for (k, p) in res.posteriors.iter().enumerate() {
+            assert_eq!(p.hap_idx, k + 5);
+            assert!(p.prob > 0.99);
+        }
+
+        // a mismatch makes the read less likely
+        let read2 = b"ACGTACGCATCCAG";
+        let res2 = hmm.forward_backward(read2, &quals, hap, None);
+        assert!(res2.log10_likelihood < res.log10_likelihood);
+        assert_eq!(res2.posteriors[7].hap_idx, 12);
+
+        // banding around the block aligner alignment barely changes the likelihood
+        let mut rng = StdRng::seed_from_u64(1234);
+        let gaps = Gaps { open: -2, extend: -1 };
+        for _i in 0..10 {
+            let hap = rand_str(200, &NUC, &mut rng);
+            let read = rand_mutate(&hap[50..150], 5, &NUC, &mut rng);
+            let quals = vec![20; read.len()];
+            let band = PairHmm::band(&read, &hap, &NW1, gaps, 16..=32);
+            assert!(band.iter().map(|b| b.1 - b.0).sum::<usize>() < read.len() * hap.len());
+
+            let full = hmm.forward_backward(&read, &quals, &hap, None);
+            let banded = hmm.forward_backward(&read, &quals, &hap, Some(&band));
+            assert!((full.log10_likelihood - banded.log10_likelihood).abs() < 1e-3);
+            assert!(full.posteriors.iter().zip(&banded.posteriors).all(|(f, b)| f.hap_idx == b.hap_idx));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! API shaped like parasail's profile functions, for easier migration from parasail
+//! and for comparing the two libraries with the same calls.
+//!
+//! Like parasail, a profile is created once for the query and then reused for aligning
+//! against many database sequences. Gap penalties are positive, and a gap of length `k`
+//! costs `open + (k - 1) * gap`. Local (Smith-Waterman) alignment is not supported, since
+//! block aligner only does global and semi-global alignment.
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+use std::ops::RangeInclusive;
+
+/// Query profile, like parasail's `parasail_profile_t`.
+pub struct Profile<'a, M: 'static + Matrix> {
+    query: Vec<u8>,
+    padded: PaddedBytes,
+    matrix: &'a M,
+    size: RangeInclusive<usize>
+}
+
+/// Default range of block sizes used by profiles.
+pub const DEFAULT_SIZE: RangeInclusive<usize> = 32..=256;
+
+/// Create a query profile, like parasail's `parasail_profile_create_16`.
+///
+/// The profile uses the default range of block sizes. See `Profile::size` to change it.
+pub fn profile_create<'a, M: 'static + Matrix>(query: &[u8], matrix: &'a M) -> Profile<'a, M> {
+    Profile {
+        query: query.to_owned(),
+        padded: PaddedBytes::from_bytes::<M>(query, *DEFAULT_SIZE.end()),
+        matrix,
+        size: DEFAULT_SIZE
+    }
+}
+
+impl<'a, M: 'static + Matrix> Profile<'a, M> {
+    /// Set the range of block sizes used for alignment.
+    pub fn size(mut self, size: RangeInclusive<usize>) -> Self {
+        self.padded = PaddedBytes::from_bytes::<M>(&self.query, *size.end());
+        self.size = size;
+        self
+    }
+
+    /// Length of the query.
+    pub fn len(&self) -> usize {
+        self.query.len()
+    }
+
+    /// Whether the query is empty.
+    pub fn is_empty(&self) -> bool {
+        self.query.is_empty()
+    }
+}
+
+/// Statistics about an alignment, like parasail's stats results.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Stats {
+    /// Number of aligned pairs with identical bytes.
+    pub matches: usize,
+    /// Number of aligned pairs with a positive score.
+    pub similar: usize,
+    /// Length of the alignment, including gaps.
+    pub length: usize
+}
+
+/// Result of an alignment, like parasail's `parasail_result_t`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ParasailResult {
+    /// Score of the alignment.
+    pub score: i32,
+    /// 0-based inclusive end position in the query, or -1 if nothing is aligned.
+    pub end_query: i32,
+    /// 0-based inclusive end position in the database sequence, or -1 if nothing is aligned.
+    pub end_ref: i32,
+    /// Statistics, which are only computed by the stats variants.
+    pub stats: Option<Stats>
+}
+
+/// Global alignment, like parasail's `parasail_nw_striped_profile_16`.
+pub fn nw_profile<M: 'static + Matrix>(profile: &Profile<M>, s2: &[u8], open: i8, gap: i8) -> ParasailResult {
+    align::<M, false>(profile, s2, open, gap, false)
+}
+
+/// Global alignment with statistics, like parasail's `parasail_nw_stats_striped_profile_16`.
+pub fn nw_stats_profile<M: 'static + Matrix>(profile: &Profile<M>, s2: &[u8], open: i8, gap: i8) -> ParasailResult {
+    align::<M, true>(profile, s2, open, gap, false)
+}
+
+/// Semi-global alignment where gaps at the beginning and end of the database sequence
+/// are free, like parasail's `parasail_sg_dx_striped_profile_16`.
+pub fn sg_dx_profile<M: 'static + Matrix>(profile: &Profile<M>, s2: &[u8], open: i8, gap: i8) -> ParasailResult {
+    align::<M, false>(profile, s2, open, gap, true)
+}
+
+/// Semi-global alignment with statistics, like parasail's `parasail_sg_dx_stats_striped_profile_16`.
+pub fn sg_dx_stats_profile<M: 'static + Matrix>(profile: &Profile<M>, s2: &[u8], open: i8, gap: i8) -> ParasailResult {
+    align::<M, true>(profile, s2, open, gap, true)
+}
+
+fn align<M: 'static + Matrix, const STATS: bool>(profile: &Profile<M>, s2: &[u8], open: i8, gap: i8, glocal: bool) -> ParasailResult {
+    assert!(open > 0 && gap > 0, "Gap penalties must be positive!");
+    let gaps = Gaps { open: -open, extend: -gap };
+    let r = PaddedBytes::from_bytes::<M>(s2, *profile.size.end());
+    let q = &profile.padded;
+    let size = profile.size.clone();
+
+    let a = match glocal {
+        true => Block::<_, STATS, false>::align_with(q, &r, profile.matrix, gaps, size, 0, &AlignOptions::new().glocal()),
+        false => Block::<_, STATS, false>::align(q, &r, profile.matrix, gaps, size, 0)
+    };
+    let res = a.res();
+
+    let stats = if STATS {
+        let start = if glocal { a.ref_flanks().0 } else { 0 };
+        let cigar = a.trace().cigar(res.query_idx, res.reference_idx);
+        let mut stats = Stats { matches: 0, similar: 0, length: 0 };
+        for pair in cigar.aligned_pairs(0, start) {
+            stats.length += 1;
+            if let (Some(i), Some(j)) = pair {
+                let (c1, c2) = (profile.query[i], s2[j]);
+                stats.matches += c1.eq_ignore_ascii_case(&c2) as usize;
+                stats.similar += (profile.matrix.get(c1, c2) > 0) as usize;
+            }
+        }
+        Some(stats)
+    } else {
+        None
+    };
+
+    ParasailResult {
+        score: res.score,
+        end_query: (res.query_idx as i32) - 1,
+        end_ref: (res.reference_idx as i32) - 1,
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let profile = profile_create(b"HEAGAWGHEE", &BLOSUM62).size(16..=32);
+        assert_eq!(profile.len(), 10);
+
+        let res = nw_profile(&profile, b"PAWHEAE", 11, 1);
+        let stats_res = nw_stats_profile(&profile, b"PAWHEAE", 11, 1);
+        assert_eq!(res.stats, None);
+        assert_eq!(res.score, stats_res.score);
+        assert_eq!((res.end_query, res.end_ref), (9, 6));
+        let stats = stats_res.stats.unwrap();
+        assert!(stats.matches <= stats.similar && stats.length >= 10);
+
+        let profile = profile_create(b"ACGTACGT", &NW1).size(16..=16);
+        let res = sg_dx_stats_profile(&profile, b"TTTTACGTACGTTTTT", 1, 1);
+        assert_eq!(res.score, 8);
+        assert_eq!((res.end_query, res.end_ref), (7, 11));
+        assert_eq!(res.stats, Some(Stats { matches: 8, similar: 8, length: 8 }));
+        assert_eq!(sg_dx_profile(&profile, b"TTTTACGTACGTTTTT", 1, 1).score, 8);
+    }
+}
@@ -0,0 +1,480 @@
+//! Partial order alignment (POA) of sequences against a graph, for consensus generation.
+//!
+//! The graph is a directed acyclic graph (DAG) where each node is a single residue, and
+//! each edge is weighted by the number of sequences that traverse it. New sequences are
+//! globally aligned to the graph and then fused into it. The consensus is the heaviest
+//! path through the graph.
+//!
+//! This is a scalar reference implementation that does not use the block aligner kernel
+//! or SIMD, since graph nodes can have multiple predecessors that do not line up with SIMD
+//! lanes. It is meant for small inputs, like the reads in a consensus window, and it is much
+//! slower than block aligner on long sequences. By default, the full DP matrix is computed.
+//! With `PoaGraph::with_band`, only a band of sequence positions is computed and stored for
+//! each node, which follows the best scoring cells of the node's predecessors.
+
+use crate::scores::*;
+
+use std::cmp;
+
+const NEG_INF: i32 = i32::MIN / 2;
+
+/// A pair of aligned graph node and sequence position, where `None` represents a gap.
+pub type PoaPair = (Option<usize>, Option<usize>);
+
+#[derive(Clone, Debug)]
+struct Node {
+    base: u8,
+    // (node, weight)
+    in_edges: Vec<(usize, usize)>,
+    out_edges: Vec<(usize, usize)>,
+    // other nodes that are aligned to this node, but with different residues
+    aligned_to: Vec<usize>
+}
+
+/// Partial order alignment graph.
+#[derive(Clone, Debug)]
+pub struct PoaGraph {
+    nodes: Vec<Node>,
+    order: Vec<usize>,
+    num_seqs: usize,
+    band: Option<usize>
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum State {
+    H,
+    I,
+    D
+}
+
+/// DP matrices that only store `width` columns starting at `lo[r]` for each row `r`.
+///
+/// Cells outside of the stored columns are unreachable.
+struct BandedDp {
+    width: usize,
+    lo: Vec<usize>,
+    h: Vec<i32>,
+    ins: Vec<i32>,
+    del: Vec<i32>
+}
+
+impl BandedDp {
+    fn new(rows: usize, width: usize) -> Self {
+        Self {
+            width,
+            lo: vec![0; rows],
+            h: vec![NEG_INF; rows * width],
+            ins: vec![NEG_INF; rows * width],
+            del: vec![NEG_INF; rows * width]
+        }
+    }
+
+    #[inline]
+    fn idx(&self, r: usize, i: usize) -> Option<usize> {
+        let lo = self.lo[r];
+        if i >= lo && i < lo + self.width { Some(r * self.width + i - lo) } else { None }
+    }
+
+    #[inline]
+    fn h(&self, r: usize, i: usize) -> i32 {
+        self.idx(r, i).map_or(NEG_INF, |k| self.h[k])
+    }
+
+    #[inline]
+    fn ins(&self, r: usize, i: usize) -> i32 {
+        self.idx(r, i).map_or(NEG_INF, |k| self.ins[k])
+    }
+
+    #[inline]
+    fn del(&self, r: usize, i: usize) -> i32 {
+        self.idx(r, i).map_or(NEG_INF, |k| self.del[k])
+    }
+}
+
+impl PoaGraph {
+    /// Create a new graph from a single sequence.
+    pub fn new(seq: &[u8]) -> Self {
+        assert!(!seq.is_empty(), "Sequence must not be empty!");
+        let mut res = Self { nodes: Vec::new(), order: Vec::new(), num_seqs: 1, band: None };
+        let mut prev = None;
+        for &c in seq {
+            let v = res.add_node(c);
+            if let Some(u) = prev {
+                res.add_edge(u, v);
+            }
+            prev = Some(v);
+        }
+        res.topo_sort();
+        res
+    }
+
+    /// Only compute `band` sequence positions before and after the expected position of
+    /// each node, instead of the full DP matrix.
+    ///
+    /// The band of a node is centered after the best scoring cell of its predecessors, so
+    /// it follows the alignment even if the sequence and the graph have different lengths,
+    /// but gaps longer than `band` cannot be found. If the end of the sequence is not
+    /// reachable within the band, then the full DP matrix is computed instead.
+    pub fn with_band(mut self, band: usize) -> Self {
+        self.band = Some(band);
+        self
+    }
+
+    /// Number of nodes in the graph.
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Number of sequences that were added to the graph.
+    pub fn num_seqs(&self) -> usize {
+        self.num_seqs
+    }
+
+    /// Globally align a sequence to the graph and fuse it into the graph.
+    ///
+    /// Returns the alignment score.
+    pub fn add_sequence<M: Matrix>(&mut self, seq: &[u8], matrix: &M, gaps: Gaps) -> i32 {
+        assert!(!seq.is_empty(), "Sequence must not be empty!");
+        let (score, path) = self.align(seq, matrix, gaps);
+        self.fuse(seq, &path);
+        self.num_seqs += 1;
+        score
+    }
+
+    /// Globally align a sequence to the graph without modifying the graph.
+    ///
+    /// Returns the score and the aligned pairs of (node, sequence position), where
+    /// `None` represents a gap.
+    pub fn align<M: Matrix>(&self, seq: &[u8], matrix: &M, gaps: Gaps) -> (i32, Vec<PoaPair>) {
+        if let Some(band) = self.band {
+            if let Some(res) = self.align_band(seq, matrix, gaps, Some(band)) {
+                return res;
+            }
+        }
+        self.align_band(seq, matrix, gaps, None).unwrap()
+    }
+
+    /// Align with an optional band, or return `None` if no sink node is reachable at the
+    /// end of the sequence.
+    fn align_band<M: Matrix>(&self, seq: &[u8], matrix: &M, gaps: Gaps, band: Option<usize>) -> Option<(i32, Vec<PoaPair>)> {
+        let n = seq.len();
+        let (open, extend) = (gaps.open as i32, gaps.extend as i32);
+        // row 0 is a virtual start node, and row k + 1 is the node at order[k]
+        let rows = self.order.len() + 1;
+        let mut rank = vec![0usize; self.nodes.len()];
+        for (k, &v) in self.order.iter().enumerate() {
+            rank[v] = k + 1;
+        }
+
+        let width = match band {
+            Some(band) => cmp::min(2 * band + 1, n + 1),
+            None => n + 1
+        };
+        let mut dp = BandedDp::new(rows, width);
+        // column of the best score in each row, which is used to place the bands
+        let mut best_col = vec![0usize; rows];
+
+        dp.h[0] = 0;
+        for i in 1..width {
+            dp.ins[i] = open + extend * ((i as i32) - 1);
+            dp.h[i] = dp.ins[i];
+        }
+
+        for (k, &v) in self.order.iter().enumerate() {
+            let r = k + 1;
+            let preds = self.pred_rows(v, &rank);
+            if band.is_some() {
+                let (_, center) = preds.iter().map(|&p| (dp.h(p, best_col[p]), best_col[p] + 1)).max().unwrap();
+                dp.lo[r] = cmp::min(center.saturating_sub(width / 2), n + 1 - width);
+            }
+            let lo = dp.lo[r];
+            let mut best = NEG_INF;
+
+            for i in lo..lo + width {
+                let mut d = NEG_INF;
+                let mut m = NEG_INF;
+                for &p in &preds {
+                    d = cmp::max(d, cmp::max(dp.h(p, i) + open, dp.del(p, i) + extend));
+                    if i > 0 {
+                        m = cmp::max(m, dp.h(p, i - 1) + (matrix.get(self.nodes[v].base, seq[i - 1]) as i32));
+                    }
+                }
+                let g = if i > 0 { cmp::max(dp.h(r, i - 1) + open, dp.ins(r, i - 1) + extend) } else { NEG_INF };
+                let h = cmp::max(m, cmp::max(d, g));
+                let idx = r * width + i - lo;
+                dp.del[idx] = d;
+                dp.ins[idx] = g;
+                dp.h[idx] = h;
+                if h > best {
+                    best = h;
+                    best_col[r] = i;
+                }
+            }
+        }
+
+        // the alignment must end at a sink node
+        let mut best = NEG_INF;
+        let mut best_v = 0;
+        for (v, node) in self.nodes.iter().enumerate() {
+            if node.out_edges.is_empty() && dp.h(rank[v], n) > best {
+                best = dp.h(rank[v], n);
+                best_v = v;
+            }
+        }
+        // scores of unreachable cells stay close to NEG_INF
+        if best <= NEG_INF / 2 {
+            return None;
+        }
+
+        // traceback with a state machine over the three DP matrices
+        let mut path = Vec::with_capacity(n + self.order.len());
+        let mut r = rank[best_v];
+        let mut i = n;
+        let mut state = State::H;
+        while r > 0 || i > 0 {
+            if r == 0 {
+                path.push((None, Some(i - 1)));
+                i -= 1;
+                continue;
+            }
+            let v = self.order[r - 1];
+            let preds = self.pred_rows(v, &rank);
+
+            match state {
+                State::H => {
+                    let curr = dp.h(r, i);
+                    if curr == dp.del(r, i) {
+                        state = State::D;
+                    } else if curr == dp.ins(r, i) {
+                        state = State::I;
+                    } else {
+                        let s = matrix.get(self.nodes[v].base, seq[i - 1]) as i32;
+                        let p = *preds.iter().find(|&&p| dp.h(p, i - 1) + s == curr).unwrap();
+                        path.push((Some(v), Some(i - 1)));
+                        r = p;
+                        i -= 1;
+                    }
+                },
+                State::I => {
+                    let curr = dp.ins(r, i);
+                    if curr == dp.h(r, i - 1) + open {
+                        state = State::H;
+                    }
+                    path.push((None, Some(i - 1)));
+                    i -= 1;
+                },
+                State::D => {
+                    let curr = dp.del(r, i);
+                    let p = match preds.iter().find(|&&p| dp.h(p, i) + open == curr) {
+                        Some(&p) => {
+                            state = State::H;
+                            p
+                        },
+                        None => *preds.iter().find(|&&p| dp.del(p, i) + extend == curr).unwrap()
+                    };
+                    path.push((Some(v), None));
+                    r = p;
+                }
+            }
+        }
+
+        path.reverse();
+        Some((best, path))
+    }
+
+    /// Generate the consensus sequence by finding the heaviest path through the graph.
+    pub fn consensus(&self) -> Vec<u8> {
+        let mut score = vec![0usize; self.nodes.len()];
+        let mut next = vec![usize::MAX; self.nodes.len()];
+
+        // go in reverse topological order so that each node's best successor is known
+        for &v in self.order.iter().rev() {
+            for &(u, w) in &self.nodes[v].out_edges {
+                let s = w + score[u];
+                let better = w > self.edge_weight(v, next[v])
+                    || (w == self.edge_weight(v, next[v]) && s > score[v]);
+                if next[v] == usize::MAX || better {
+                    score[v] = s;
+                    next[v] = u;
+                }
+            }
+        }
+
+        let mut v = *self.order
+            .iter()
+            .filter(|&&v| self.nodes[v].in_edges.is_empty())
+            .max_by_key(|&&v| score[v])
+            .unwrap();
+        let mut res = vec![self.nodes[v].base];
+        while next[v] != usize::MAX {
+            v = next[v];
+            res.push(self.nodes[v].base);
+        }
+        res
+    }
+
+    fn edge_weight(&self, u: usize, v: usize) -> usize {
+        if v == usize::MAX {
+            return 0;
+        }
+        self.nodes[u].out_edges.iter().find(|e| e.0 == v).map(|e| e.1).unwrap_or(0)
+    }
+
+    fn pred_rows(&self, v: usize, rank: &[usize]) -> Vec<usize> {
+        if self.nodes[v].in_edges.is_empty() {
+            vec![0]
+        } else {
+            self.nodes[v].in_edges.iter().map(|&(u, _)| rank[u]).collect()
+        }
+    }
+
+    fn fuse(&mut self, seq: &[u8], path: &[PoaPair]) {
+        let mut prev: Option<usize> = None;
+        for &(v, i) in path {
+            let i = match i {
+                Some(i) => i,
+                None => continue
+            };
+            let c = seq[i];
+            let node = match v {
+                Some(v) if self.nodes[v].base == c => v,
+                Some(v) => {
+                    // mismatch, so reuse an aligned node with the same residue if possible
+                    match self.nodes[v].aligned_to.iter().copied().find(|&u| self.nodes[u].base == c) {
+                        Some(u) => u,
+                        None => {
+                            let u = self.add_node(c);
+                            let mut group = self.nodes[v].aligned_to.clone();
+                            group.push(v);
+                            for &g in &group {
+                                self.nodes[g].aligned_to.push(u);
+                            }
+                            self.nodes[u].aligned_to = group;
+                            u
+                        }
+                    }
+                },
+                None => self.add_node(c)
+            };
+            if let Some(p) = prev {
+                self.add_edge(p, node);
+            }
+            prev = Some(node);
+        }
+        self.topo_sort();
+    }
+
+    fn add_node(&mut self, base: u8) -> usize {
+        self.nodes.push(Node { base, in_edges: Vec::new(), out_edges: Vec::new(), aligned_to: Vec::new() });
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, u: usize, v: usize) {
+        match self.nodes[u].out_edges.iter().position(|e| e.0 == v) {
+            Some(k) => {
+                self.nodes[u].out_edges[k].1 += 1;
+                let k = self.nodes[v].in_edges.iter().position(|e| e.0 == u).unwrap();
+                self.nodes[v].in_edges[k].1 += 1;
+            },
+            None => {
+                self.nodes[u].out_edges.push((v, 1));
+                self.nodes[v].in_edges.push((u, 1));
+            }
+        }
+    }
+
+    fn topo_sort(&mut self) {
+        let mut in_deg = self.nodes.iter().map(|n| n.in_edges.len()).collect::<Vec<_>>();
+        let mut stack = (0..self.nodes.len()).filter(|&v| in_deg[v] == 0).rev().collect::<Vec<_>>();
+        self.order.clear();
+        while let Some(v) = stack.pop() {
+            self.order.push(v);
+            for &(u, _) in self.nodes[v].out_edges.iter().rev() {
+                in_deg[u] -= 1;
+                if in_deg[u] == 0 {
+                    stack.push(u);
+                }
+            }
+        }
+        debug_assert_eq!(self.order.len(), self.nodes.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poa() {
+        let gaps = Gaps { open: -2, extend: -1 };
+
+        let mut g = PoaGraph::new(b"ACGTACGT");
+        assert_eq!(g.add_sequence(b"ACGTACGT", &NW1, gaps), 8);
+        assert_eq!(g.num_nodes(), 8);
+        assert_eq!(g.add_sequence(b"ACGTTACGT", &NW1, gaps), 6);
+        assert_eq!(g.num_nodes(), 9);
+        assert_eq!(g.add_sequence(b"ACCTACGT", &NW1, gaps), 6);
+        assert_eq!(g.add_sequence(b"ACGTAGT", &NW1, gaps), 5);
+        assert_eq!(g.num_seqs(), 5);
+        assert_eq!(g.consensus(), b"ACGTACGT");
+
+        // the inserted T is now in the graph, so it can be matched
+        let (score, path) = g.align(b"ACGTTACGT", &NW1, gaps);
+        assert_eq!(score, 9);
+        assert!(path.iter().all(|&(v, i)| v.is_some() && i.is_some()));
+
+        let mut g = PoaGraph::new(b"AAAACCCC");
+        g.add_sequence(b"AAAAGCCC", &NW1, gaps);
+        g.add_sequence(b"AAAAGCCC", &NW1, gaps);
+        assert_eq!(g.consensus(), b"AAAAGCCC");
+    }
+
+    #[test]
+    fn test_poa_branches() {
+        let gaps = Gaps { open: -2, extend: -1 };
+
+        // a mismatch creates a bubble, and a deletion creates an edge that skips a node
+        let mut g = PoaGraph::new(b"ACGTACGTAC");
+        for _i in 0..3 {
+            g.add_sequence(b"ACGAACGTAC", &NW1, gaps);
+        }
+        assert_eq!(g.add_sequence(b"ACGTACTAC", &NW1, gaps), 9 - 2);
+        assert_eq!(g.num_nodes(), 11);
+
+        // every branch can be matched exactly
+        for seq in [&b"ACGTACGTAC"[..], b"ACGAACGTAC", b"ACGTACTAC"] {
+            let (score, path) = g.align(seq, &NW1, gaps);
+            assert_eq!(score, seq.len() as i32);
+            assert!(path.iter().all(|&(v, i)| v.is_some() && i.is_some()));
+        }
+        assert_eq!(g.consensus(), b"ACGAACGTAC");
+    }
+
+    #[test]
+    fn test_poa_indels() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let gaps = Gaps { open: -2, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+        let r = rand_str(200, &NUC, &mut rng);
+        let seqs = (0..8).map(|_| rand_mutate_identity(&r, 0.95, 0.5, &NUC, &mut rng).0).collect::<Vec<_>>();
+
+        let mut full = PoaGraph::new(&seqs[0]);
+        let mut banded = PoaGraph::new(&seqs[0]).with_band(16);
+        for seq in &seqs[1..] {
+            assert_eq!(full.add_sequence(seq, &NW1, gaps), banded.add_sequence(seq, &NW1, gaps));
+        }
+        assert_eq!(full.consensus(), r);
+        assert_eq!(banded.consensus(), r);
+
+        // a long deletion only fits in a wide band
+        let seq = [&r[..100], &r[150..]].concat();
+        assert!(PoaGraph::new(&r).with_band(4).align(&seq, &NW1, gaps).0 < 150 - 2 - 49);
+        assert_eq!(PoaGraph::new(&r).with_band(64).align(&seq, &NW1, gaps).0, 150 - 2 - 49);
+
+        // the end of the sequence is too far from the band, so the full DP matrix is used
+        let seq = [&r[..10], &[b'A'; 40][..]].concat();
+        assert_eq!(PoaGraph::new(&r[..10]).with_band(4).align(&seq, &NW1, gaps).0, 10 - 2 - 39);
+    }
+}
@@ -0,0 +1,195 @@
+//! Pool of reusable scratch states for aligning many pairs of sequences on multiple threads.
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+use std::alloc;
+use std::ops::{Deref, DerefMut, RangeInclusive};
+use std::sync::Mutex;
+
+/// A pointer to a free buffer that can be sent between threads.
+struct FreeBuf(alloc::Layout, *mut u8);
+
+unsafe impl Send for FreeBuf {}
+
+/// Scratch state that holds reusable buffers for alignment.
+///
+/// A scratch state can be used as a `BufferAllocator`, so internal buffers that are freed
+/// after one alignment are reused by the next alignment instead of going through the
+/// global allocator. Traces that are given back with `Block::recycle` are also reused.
+/// It also holds padded query and reference buffers that are refilled for each alignment.
+pub struct Scratch {
+    free: Mutex<Vec<FreeBuf>>,
+    traces: Mutex<Vec<Trace>>,
+    query: PaddedBytes,
+    reference: PaddedBytes
+}
+
+impl Scratch {
+    /// Create a new, empty scratch state.
+    pub fn new() -> Self {
+        Self::with_capacity(0, 0)
+    }
+
+    /// Create a new scratch state with enough memory for padding strings of up to `len`
+    /// bytes with a max block size of `block_size`, so the first alignments do not need to
+    /// grow the padded query and reference buffers.
+    pub fn with_capacity(len: usize, block_size: usize) -> Self {
+        // padding an empty string reserves the memory, which is reused by `set_bytes`
+        Self {
+            free: Mutex::new(Vec::new()),
+            traces: Mutex::new(Vec::new()),
+            query: PaddedBytes::from_bytes::<NucMatrix>(b"", len + block_size),
+            reference: PaddedBytes::from_bytes::<NucMatrix>(b"", len + block_size)
+        }
+    }
+
+    /// Align two byte strings with block aligner, reusing the buffers in this scratch state.
+    ///
+    /// The strings are padded using the max block size. Use `Block::recycle` on the result
+    /// to give its trace back to this scratch state. See `Block::align` for more details.
+    pub fn align<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool>(&'a mut self,
+                                                                                query: &[u8],
+                                                                                reference: &[u8],
+                                                                                matrix: &'a M,
+                                                                                gaps: Gaps,
+                                                                                size: RangeInclusive<usize>,
+                                                                                x_drop: i32) -> Block<'a, M, TRACE, X_DROP> {
+        self.query.set_bytes::<M>(query, *size.end());
+        self.reference.set_bytes::<M>(reference, *size.end());
+        Block::align_with(&self.query, &self.reference, matrix, gaps, size, x_drop, &AlignOptions::new().allocator(&*self))
+    }
+}
+
+impl Default for Scratch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl BufferAllocator for Scratch {
+    unsafe fn alloc(&self, layout: alloc::Layout) -> *mut u8 {
+        let mut free = self.free.lock().unwrap();
+        match free.iter().position(|b| b.0 == layout) {
+            Some(idx) => free.swap_remove(idx).1,
+            None => alloc::alloc(layout)
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: alloc::Layout) {
+        self.free.lock().unwrap().push(FreeBuf(layout, ptr));
+    }
+
+    fn take_trace(&self) -> Option<Trace> {
+        self.traces.lock().unwrap().pop()
+    }
+
+    fn put_trace(&self, trace: Trace) {
+        self.traces.lock().unwrap().push(trace);
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        for b in self.free.get_mut().unwrap().drain(..) {
+            unsafe { alloc::dealloc(b.1, b.0); }
+        }
+    }
+}
+
+/// Thread-safe pool of scratch states.
+///
+/// Worker threads check out a scratch state with `get`, and it is automatically returned
+/// to the pool when the guard is dropped.
+pub struct ScratchPool {
+    free: Mutex<Vec<Scratch>>
+}
+
+impl ScratchPool {
+    /// Create a new pool with a certain number of preallocated scratch states.
+    ///
+    /// More scratch states are created if necessary.
+    pub fn new(capacity: usize) -> Self {
+        Self { free: Mutex::new((0..capacity).map(|_| Scratch::new()).collect()) }
+    }
+
+    /// Check out a scratch state from the pool.
+    pub fn get(&self) -> PooledScratch<'_> {
+        let scratch = self.free.lock().unwrap().pop().unwrap_or_default();
+        PooledScratch { pool: self, scratch: Some(scratch) }
+    }
+}
+
+/// A scratch state that is returned to its pool when dropped.
+pub struct PooledScratch<'a> {
+    pool: &'a ScratchPool,
+    scratch: Option<Scratch>
+}
+
+impl<'a> Deref for PooledScratch<'a> {
+    type Target = Scratch;
+
+    fn deref(&self) -> &Scratch {
+        self.scratch.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for PooledScratch<'a> {
+    fn deref_mut(&mut self) -> &mut Scratch {
+        self.scratch.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for PooledScratch<'a> {
+    fn drop(&mut self) {
+        if let Some(scratch) = self.scratch.take() {
+            self.pool.free.lock().unwrap().push(scratch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool() {
+        let pool = ScratchPool::new(2);
+        let gaps = Gaps { open: -2, extend: -1 };
+
+        std::thread::scope(|s| {
+            for _t in 0..4 {
+                s.spawn(|| {
+                    for _i in 0..10 {
+                        let mut scratch = pool.get();
+                        let a = scratch.align::<_, true, false>(b"TTTTTTTTAAAAAAATTTTTTTTT", b"TTAAAAAAATTTTTTTTTTTT", &NW1, gaps, 16..=32, 0);
+                        let res = a.res();
+                        assert_eq!(res, AlignResult { score: 7, query_idx: 24, reference_idx: 21 });
+                        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "2M6I16M3D");
+                        a.recycle();
+                    }
+                });
+            }
+        });
+
+        let free = pool.free.lock().unwrap();
+        assert!(free.len() >= 2);
+        // each scratch state that was used keeps reusing the same trace
+        assert!(free.iter().all(|scratch| scratch.traces.lock().unwrap().len() <= 1));
+        assert!(free.iter().any(|scratch| scratch.traces.lock().unwrap().len() == 1));
+    }
+
+    #[test]
+    fn test_scratch() {
+        let mut scratch = Scratch::with_capacity(32, 32);
+        let gaps = Gaps { open: -2, extend: -1 };
+        // the matrix does not need to be static
+        let matrix = NucMatrix::new_simple(1, -1);
+        for _i in 0..2 {
+            let a = scratch.align::<_, true, false>(b"AACAA", b"AAAA", &matrix, gaps, 16..=32, 0);
+            assert_eq!(a.res(), AlignResult { score: 2, query_idx: 5, reference_idx: 4 });
+            a.recycle();
+        }
+        assert_eq!(scratch.traces.lock().unwrap().len(), 1);
+    }
+}
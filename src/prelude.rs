@@ -0,0 +1,15 @@
+//! Commonly used types and functions, for glob importing.
+//!
+//! ```
+//! use block_aligner::prelude::*;
+//!
+//! let gaps = Gaps { open: -2, extend: -1 };
+//! let (score, cigar) = align_with_cigar(b"TTTTTTTTAAAAAAATTTTTTTTT", b"TTAAAAAAATTTTTTTTTTTT", &NW1, gaps, 16..=16);
+//! assert_eq!(score, 7);
+//! assert_eq!(cigar.to_string(), "2M6I16M3D");
+//! ```
+
+pub use crate::scan_block::{Block, PaddedBytes, AlignResult};
+pub use crate::scores::*;
+pub use crate::cigar::{Cigar, Operation, OpLen};
+pub use crate::simple::{align_score, align_with_cigar};
@@ -0,0 +1,209 @@
+//! Profile-vs-profile alignment, for using block aligner as the pairwise engine in
+//! progressive multiple sequence alignment.
+
+use crate::backend::SimdBackend;
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+/// Position-specific "matrix" for aligning two column profiles.
+///
+/// The query is profile `a` and the reference is profile `b`. Each column of a profile is
+/// represented by a vector, and the score between two columns is the rounded dot product
+/// of their vectors. Use `query_bytes` and `reference_bytes` to get the placeholder strings
+/// to align with `Block`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProfilePair {
+    dim: usize,
+    a: Vec<f32>,
+    b: Vec<f32>
+}
+
+impl ProfilePair {
+    /// Create a profile pair with sum-of-pairs scoring.
+    ///
+    /// `freqs_a` and `freqs_b` are row-major arrays of residue frequencies, with one row per
+    /// column of the profile and one entry per residue in `residues`. The score between two
+    /// columns is the expected substitution score `sum(freqs_a[i][x] * freqs_b[j][y] * matrix[x][y])`.
+    pub fn sum_of_pairs<M: Matrix>(residues: &[u8], freqs_a: &[f32], freqs_b: &[f32], matrix: &M) -> Self {
+        let dim = residues.len();
+        assert!(dim > 0, "There must be at least one residue!");
+        assert_eq!(freqs_a.len() % dim, 0, "Frequency arrays must have one entry per residue for each column!");
+        assert_eq!(freqs_b.len() % dim, 0, "Frequency arrays must have one entry per residue for each column!");
+
+        // convert profile b into a PSSM so each score is a single dot product
+        let mut b = vec![0f32; freqs_b.len()];
+        for (col, pssm) in freqs_b.chunks_exact(dim).zip(b.chunks_exact_mut(dim)) {
+            for (x, &r_x) in residues.iter().enumerate() {
+                pssm[x] = residues.iter().zip(col).map(|(&r_y, &f)| f * (matrix.get(r_x, r_y) as f32)).sum();
+            }
+        }
+
+        Self { dim, a: freqs_a.to_owned(), b }
+    }
+
+    /// Create a profile pair with PSSM-vs-PSSM scoring.
+    ///
+    /// Each profile is given as a row-major PSSM and the corresponding residue frequencies,
+    /// with `dim` entries per column. The score between two columns is the average of
+    /// each PSSM column scored against the other profile's frequencies:
+    /// `(pssm_a[i] . freqs_b[j] + pssm_b[j] . freqs_a[i]) / 2`.
+    pub fn pssm_pssm(dim: usize, pssm_a: &[f32], freqs_a: &[f32], pssm_b: &[f32], freqs_b: &[f32]) -> Self {
+        assert!(dim > 0, "PSSM dimension must be positive!");
+        assert_eq!(pssm_a.len() % dim, 0, "PSSM arrays must have dim entries for each column!");
+        assert_eq!(pssm_b.len() % dim, 0, "PSSM arrays must have dim entries for each column!");
+        assert!(pssm_a.len() == freqs_a.len() && pssm_b.len() == freqs_b.len(), "PSSM and frequency arrays must have the same length!");
+
+        // concatenate the vectors so each score is a single dot product
+        let a = pssm_a
+            .chunks_exact(dim)
+            .zip(freqs_a.chunks_exact(dim))
+            .flat_map(|(p, f)| p.iter().chain(f.iter()).copied())
+            .collect::<Vec<_>>();
+        let b = freqs_b
+            .chunks_exact(dim)
+            .zip(pssm_b.chunks_exact(dim))
+            .flat_map(|(f, p)| f.iter().chain(p.iter()).map(|&x| x / 2.0))
+            .collect::<Vec<_>>();
+
+        Self { dim: dim * 2, a, b }
+    }
+
+    /// Number of columns in profile `a` (the query).
+    #[inline]
+    pub fn a_len(&self) -> usize {
+        self.a.len() / self.dim
+    }
+
+    /// Number of columns in profile `b` (the reference).
+    #[inline]
+    pub fn b_len(&self) -> usize {
+        self.b.len() / self.dim
+    }
+
+    /// Get the score between column `i` of profile `a` and column `j` of profile `b`.
+    #[inline]
+    pub fn score(&self, i: usize, j: usize) -> i8 {
+        let a = &self.a[i * self.dim..(i + 1) * self.dim];
+        let b = &self.b[j * self.dim..(j + 1) * self.dim];
+        let s = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        s.round().clamp(-(i8::MAX as f32), i8::MAX as f32) as i8
+    }
+
+    /// Create the placeholder query string for profile `a`.
+    pub fn query_bytes(&self, block_size: usize) -> PaddedBytes {
+        PaddedBytes::from_len::<Self>(self.a_len(), block_size)
+    }
+
+    /// Create the placeholder reference string for profile `b`.
+    pub fn reference_bytes(&self, block_size: usize) -> PaddedBytes {
+        PaddedBytes::from_len::<Self>(self.b_len(), block_size)
+    }
+
+    /// Min and max scores over all pairs of positions.
+    fn score_range(&self) -> (i8, i8) {
+        score_range((0..self.a_len()).flat_map(|i| (0..self.b_len()).map(move |j| self.score(i, j))))
+    }
+
+    /// Score for a 1-based padded position pair, where out of bounds positions are padding.
+    #[inline]
+    fn padded_score(&self, i: usize, j: usize) -> i16 {
+        if i == 0 || j == 0 || i > self.a_len() || j > self.b_len() {
+            i8::MIN as i16
+        } else {
+            self.score(i - 1, j - 1) as i16
+        }
+    }
+}
+
+impl Matrix for ProfilePair {
+    const NULL: u8 = b'\0';
+
+    fn new() -> Self {
+        Self { dim: 1, a: Vec::new(), b: Vec::new() }
+    }
+
+    fn set(&mut self, _a: u8, _b: u8, _score: i8) {
+        unimplemented!();
+    }
+
+    /// Get the score between column `a` of profile `a` and column `b` of profile `b`.
+    ///
+    /// Only the first 256 columns can be indexed with bytes, so use `score` for longer profiles.
+    fn get(&self, a: u8, b: u8) -> i8 {
+        self.score(a as usize, b as usize)
+    }
+
+    #[inline]
+    fn as_ptr(&self, _i: usize) -> *const i8 {
+        unimplemented!()
+    }
+
+    unsafe fn get_scores<B: SimdBackend>(&self, _c: u8, _v: B::HalfSimd, _right: bool) -> B::Simd {
+        unimplemented!()
+    }
+
+    #[inline]
+    unsafe fn get_scores_at<B: SimdBackend>(&self, _c: u8, _v: B::HalfSimd, right: bool, c_idx: usize, v_idx: usize) -> B::Simd {
+        position_scores::<B, _>(right, c_idx, v_idx, |i, j| self.padded_score(i, j))
+    }
+
+    #[inline]
+    fn convert_char(c: u8) -> u8 {
+        c
+    }
+
+    /// Get the max score over all pairs of positions, which is slow for long sequences.
+    fn max_score(&self) -> i8 {
+        self.score_range().1
+    }
+
+    /// Get the min score over all pairs of positions, which is slow for long sequences.
+    fn min_score(&self) -> i8 {
+        self.score_range().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one_hot(s: &[u8]) -> Vec<f32> {
+        s.iter().flat_map(|&c| b"ACGT".iter().map(move |&r| if r == c { 1.0 } else { 0.0 })).collect()
+    }
+
+    #[test]
+    fn test_sum_of_pairs() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let q = b"TTTTTTTTAAAAAAATTTTTTTTT";
+        let r = b"TTAAAAAAATTTTTTTTTTTT";
+
+        let p = ProfilePair::sum_of_pairs(b"ACGT", &one_hot(q), &one_hot(r), &NW1);
+        assert_eq!(p.a_len(), q.len());
+        assert_eq!(p.b_len(), r.len());
+        let (q_padded, r_padded) = (p.query_bytes(16), p.reference_bytes(16));
+        let a = Block::<_, true, false>::align(&q_padded, &r_padded, &p, gaps, 16..=16, 0);
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 7, query_idx: 24, reference_idx: 21 });
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "2M6I16M3D");
+
+        // a column that is half A and half T
+        let mut freqs = one_hot(b"A");
+        freqs.iter_mut().zip(one_hot(b"T")).for_each(|(x, y)| *x = (*x + y) / 2.0);
+        let p = ProfilePair::sum_of_pairs(b"ACGT", &freqs, &one_hot(b"AC"), &NucMatrix::new_simple(4, -2));
+        assert_eq!(p.score(0, 0), 1);
+        assert_eq!(p.score(0, 1), -2);
+        assert_eq!(p.get(0, 1), p.score(0, 1));
+    }
+
+    #[test]
+    fn test_pssm_pssm() {
+        let freqs_a = one_hot(b"AC");
+        let freqs_b = one_hot(b"A");
+        let pssm_a = vec![2.0, -1.0, -1.0, -1.0, -1.0, 2.0, -1.0, -1.0];
+        let pssm_b = vec![4.0, -2.0, -2.0, -2.0];
+        let p = ProfilePair::pssm_pssm(4, &pssm_a, &freqs_a, &pssm_b, &freqs_b);
+        assert_eq!(p.score(0, 0), 3);
+        assert_eq!(p.score(1, 0), -2);
+    }
+}
@@ -0,0 +1,85 @@
+//! Speculatively racing alignments with a small and a large max block size.
+//!
+//! Most pairs of sequences are easy, so alignment with a small max block size is accurate
+//! and fast. A large max block size is only needed for the few difficult pairs, but it is
+//! not known beforehand which pairs are difficult. Instead, both alignments are run at the
+//! same time on two threads, so the latency for easy pairs is not affected by the large
+//! max block size, and difficult pairs do not need to be aligned twice one after another.
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// Which of the raced alignments was returned by `race_align`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RaceWinner {
+    /// The alignment with the small max block size.
+    Small,
+    /// The alignment with the large max block size.
+    Large
+}
+
+/// Align two strings with a small and a large max block size concurrently.
+///
+/// Both alignments start with the min block size in `size`. The alignment with the small
+/// max block size `small_max` runs on the current thread and the alignment with the max block
+/// size in `size` runs on another thread. Both alignments take exactly the same steps until
+/// the small one needs to grow past `small_max`, so their scores match at every step up to
+/// that point. If the small alignment finishes without needing to grow past `small_max`, then
+/// its result is identical to the large alignment, so it is returned immediately and the
+/// large alignment is cancelled at its next step. Otherwise, the alignment with the better
+/// score is returned once the large alignment finishes.
+///
+/// The strings must be padded for the max block size in `size`. Only exponential growth of the
+/// block size is used.
+pub fn race_align<'a, M: 'static + Matrix + Sync, const TRACE: bool, const X_DROP: bool>(query: &'a PaddedBytes, reference: &'a PaddedBytes, matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>, small_max: usize, x_drop: i32) -> (Block<'a, M, TRACE, X_DROP>, RaceWinner) {
+    assert!(size.contains(&small_max), "Small max block size must be within the range of block sizes!");
+    let small_size = *size.start()..=small_max;
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    thread::scope(|s| {
+        let large_cancel = Arc::clone(&cancel);
+        let large = s.spawn(move || Block::<_, TRACE, X_DROP>::align_with(query, reference, matrix, gaps, size, x_drop, &AlignOptions::new().cancel(large_cancel)));
+        let small = Block::<_, TRACE, X_DROP>::align(query, reference, matrix, gaps, small_size, x_drop);
+
+        if !small.diagnostics().max_size_reached {
+            cancel.store(true, Ordering::Relaxed);
+            return (small, RaceWinner::Small);
+        }
+
+        let large = large.join().unwrap();
+        if small.res().score > large.res().score {
+            (small, RaceWinner::Small)
+        } else {
+            (large, RaceWinner::Large)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_race_align() {
+        let gaps = Gaps { open: -11, extend: -1 };
+        let r = PaddedBytes::from_bytes::<AAMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAARRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRR", 64);
+        let q = PaddedBytes::from_bytes::<AAMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAARRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRRR", 64);
+        let (a, winner) = race_align::<_, true, false>(&q, &r, &BLOSUM62, gaps, 16..=64, 16, 0);
+        assert_eq!(winner, RaceWinner::Small);
+        assert_eq!(a.res(), Block::<_, false, false>::align(&q, &r, &BLOSUM62, gaps, 16..=64, 0).res());
+        assert_eq!(a.trace().cigar(128, 128).to_string(), "128M");
+
+        // a long gap needs the large block size
+        let gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 64);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAACCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCAAAAAAAAAAAAAAAA", 64);
+        let (a, winner) = race_align::<_, false, false>(&q, &r, &NW1, gaps, 16..=64, 16, 0);
+        assert_eq!(winner, RaceWinner::Large);
+        assert_eq!(a.res(), Block::<_, false, false>::align(&q, &r, &NW1, gaps, 16..=64, 0).res());
+    }
+}
@@ -0,0 +1,160 @@
+//! Simple scalar reference implementations of affine gap alignment, for verification.
+//!
+//! These compute the full O(nm) dynamic programming matrix with 32-bit scores, so they are
+//! much slower than block aligner, but they are obviously correct. They are useful for
+//! differential testing of block aligner results on real data, and for quantifying the
+//! accuracy of the block aligner heuristic.
+//!
+//! Unlike block aligner, the strings here are not padded.
+
+use crate::scan_block::AlignResult;
+use crate::scores::*;
+
+use std::cmp;
+
+const NEG_INF: i32 = i32::MIN / 2;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Mode {
+    Global,
+    Local,
+    XDrop(i32)
+}
+
+/// Global alignment of the query and the reference.
+///
+/// The result is always at the end of both strings.
+pub fn global<M: Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps) -> AlignResult {
+    align(query, reference, matrix, gaps, Mode::Global)
+}
+
+/// Local (Smith-Waterman) alignment of the query and the reference.
+///
+/// The result is the end of the best local alignment. If there are ties, then the cell that is
+/// first in row-major order is returned.
+pub fn local<M: Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps) -> AlignResult {
+    align(query, reference, matrix, gaps, Mode::Local)
+}
+
+/// X-drop alignment that starts at the beginning of both strings.
+///
+/// Cells with scores that drop more than `x_drop` below the best score seen so far (in
+/// row-major order) are pruned. The result is the best scoring cell.
+///
+/// Note that block aligner prunes entire blocks instead of single cells, so its results
+/// can differ slightly.
+pub fn x_drop<M: Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, x_drop: i32) -> AlignResult {
+    assert!(x_drop >= 0, "X-drop threshold amount must be nonnegative!");
+    align(query, reference, matrix, gaps, Mode::XDrop(x_drop))
+}
+
+fn align<M: Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, mode: Mode) -> AlignResult {
+    let (open, extend) = (gaps.open as i32, gaps.extend as i32);
+    let gap = |len: usize| if mode == Mode::Local { 0 } else { open + extend * ((len as i32) - 1) };
+    let m = reference.len();
+
+    // D and R (gaps along the query) for the previous and current rows
+    let mut d_prev = vec![NEG_INF; m + 1];
+    let mut r_prev = vec![NEG_INF; m + 1];
+    let mut d_curr = vec![NEG_INF; m + 1];
+    let mut r_curr = vec![NEG_INF; m + 1];
+    let mut best = AlignResult { score: 0, query_idx: 0, reference_idx: 0 };
+
+    d_prev[0] = 0;
+    for (j, d) in d_prev.iter_mut().enumerate().skip(1) {
+        *d = gap(j);
+        if let Mode::XDrop(x) = mode {
+            if *d < best.score - x {
+                *d = NEG_INF;
+            }
+        }
+    }
+
+    for (i, &q) in query.iter().enumerate() {
+        d_curr[0] = gap(i + 1);
+        r_curr[0] = NEG_INF;
+        let mut c = NEG_INF;
+        let mut alive = false;
+
+        if let Mode::XDrop(x) = mode {
+            if d_curr[0] < best.score - x {
+                d_curr[0] = NEG_INF;
+            }
+        }
+
+        for j in 1..=m {
+            c = cmp::max(c + extend, d_curr[j - 1] + open);
+            r_curr[j] = cmp::max(r_prev[j] + extend, d_prev[j] + open);
+            let mut d = cmp::max(d_prev[j - 1] + (matrix.get(q, reference[j - 1]) as i32), cmp::max(c, r_curr[j]));
+
+            match mode {
+                Mode::Local => d = cmp::max(d, 0),
+                Mode::XDrop(x) if d < best.score - x => {
+                    d = NEG_INF;
+                    c = NEG_INF;
+                    r_curr[j] = NEG_INF;
+                },
+                _ => ()
+            }
+
+            if mode != Mode::Global && d > best.score {
+                best = AlignResult { score: d, query_idx: i + 1, reference_idx: j };
+            }
+
+            alive |= d > NEG_INF;
+            d_curr[j] = d;
+        }
+
+        std::mem::swap(&mut d_prev, &mut d_curr);
+        std::mem::swap(&mut r_prev, &mut r_curr);
+
+        if !alive && d_prev[0] == NEG_INF {
+            // every cell in the row was pruned
+            break;
+        }
+    }
+
+    if mode == Mode::Global {
+        best = AlignResult { score: d_prev[m], query_idx: query.len(), reference_idx: m };
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan_block::*;
+
+    #[test]
+    fn test_reference() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let gaps = Gaps { open: -2, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for _i in 0..20 {
+            let r = rand_str(100, &NUC, &mut rng);
+            let q = rand_mutate(&r, 20, &NUC, &mut rng);
+            let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 128);
+            let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 128);
+            // the block covers the entire DP matrix, so the result is exact
+            let a = Block::<_, false, false>::align(&q_padded, &r_padded, &NW1, gaps, 128..=128, 0);
+            assert_eq!(global(&q, &r, &NW1, gaps), a.res());
+        }
+
+        assert_eq!(global(b"AAAA", b"", &NW1, gaps).score, -5);
+        assert_eq!(global(b"", b"", &NW1, gaps).score, 0);
+
+        assert_eq!(local(b"TTTTACGTTTTT", b"GGACGTGG", &NW1, gaps), AlignResult { score: 4, query_idx: 8, reference_idx: 6 });
+        assert_eq!(local(b"TTTT", b"GGGG", &NW1, gaps), AlignResult { score: 0, query_idx: 0, reference_idx: 0 });
+
+        let gaps = Gaps { open: -11, extend: -1 };
+        let r = b"AAAAAAAAAAAAAAARRRRRRRRRRRRRRRRAAAAAAAAAAAAA";
+        let q = b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+        assert_eq!(x_drop(q, r, &BLOSUM62, gaps, 1), AlignResult { score: 60, query_idx: 15, reference_idx: 15 });
+        assert_eq!(local(q, r, &BLOSUM62, gaps).score, 60 + 4 * 13 - 16);
+        assert_eq!(x_drop(q, r, &BLOSUM62, gaps, 100).score, 60 + 4 * 13 - 16);
+    }
+}
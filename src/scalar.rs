@@ -0,0 +1,364 @@
+//! Portable backend that emulates the SIMD functions one lane at a time.
+//!
+//! It is compiled alongside the native backend and works on any CPU, so it is the fallback when
+//! the CPU does not support the native instruction set, and a reference for testing the native
+//! backend. It uses the same number of lanes and the same trace format as the native backend,
+//! and it gives the same results, but it is a lot slower.
+
+use crate::backend::{SimdBackend, TraceType, L, ZERO};
+
+use std::ptr;
+
+pub type Simd = [i16; L];
+pub type HalfSimd = [i8; 16];
+pub type PrefixScanConsts = i16;
+
+/// Name of the instruction set.
+pub const NAME: &str = "scalar";
+
+/// Number of lanes that are scanned together before the last lane is carried over,
+/// like the 128-bit halves of an AVX2 vector.
+const SCAN_LANES: usize = 8;
+
+/// Backend that does not use any SIMD instructions.
+#[derive(Copy, Clone, Debug)]
+pub struct Scalar;
+
+#[inline]
+fn map2(a: Simd, b: Simd, f: impl Fn(i16, i16) -> i16) -> Simd {
+    let mut res = [0i16; L];
+    for i in 0..L {
+        res[i] = f(a[i], b[i]);
+    }
+    res
+}
+
+#[inline]
+fn widen(f: impl Fn(usize) -> i8) -> Simd {
+    let mut res = [0i16; L];
+    for (i, r) in res.iter_mut().enumerate() {
+        *r = f(i) as i16;
+    }
+    res
+}
+
+#[inline]
+fn shuffle(lut: HalfSimd, b: i8) -> i8 {
+    if b < 0 { 0 } else { lut[(b & 0b1111) as usize] }
+}
+
+unsafe impl SimdBackend for Scalar {
+    type Simd = Simd;
+    type HalfSimd = HalfSimd;
+    type PrefixScanConsts = PrefixScanConsts;
+
+    const NAME: &'static str = NAME;
+
+    #[inline]
+    fn is_supported() -> bool { true }
+
+    #[inline]
+    unsafe fn with_features<R, F: FnOnce() -> R>(f: F) -> R { f() }
+
+    #[inline]
+    unsafe fn store_trace(ptr: *mut TraceType, trace: TraceType) { ptr::write(ptr, trace); }
+
+    #[inline]
+    unsafe fn simd_adds_i16(a: Simd, b: Simd) -> Simd { map2(a, b, i16::saturating_add) }
+
+    #[inline]
+    unsafe fn simd_subs_i16(a: Simd, b: Simd) -> Simd { map2(a, b, i16::saturating_sub) }
+
+    #[inline]
+    unsafe fn simd_max_i16(a: Simd, b: Simd) -> Simd { map2(a, b, i16::max) }
+
+    #[inline]
+    unsafe fn simd_cmpeq_i16(a: Simd, b: Simd) -> Simd { map2(a, b, |a, b| if a == b { -1 } else { 0 }) }
+
+    #[inline]
+    unsafe fn simd_blend_i8(a: Simd, b: Simd, mask: Simd) -> Simd {
+        let mut res = [0i16; L];
+        for i in 0..L {
+            let (a, b, mask) = (a[i].to_le_bytes(), b[i].to_le_bytes(), mask[i].to_le_bytes());
+            let lo = if (mask[0] as i8) < 0 { b[0] } else { a[0] };
+            let hi = if (mask[1] as i8) < 0 { b[1] } else { a[1] };
+            res[i] = i16::from_le_bytes([lo, hi]);
+        }
+        res
+    }
+
+    #[inline]
+    unsafe fn simd_load(ptr: *const Simd) -> Simd { ptr::read(ptr) }
+
+    #[inline]
+    unsafe fn simd_store(ptr: *mut Simd, a: Simd) { ptr::write(ptr, a) }
+
+    #[inline]
+    unsafe fn simd_set1_i16(v: i16) -> Simd { [v; L] }
+
+    #[inline]
+    unsafe fn simd_extract_i16<const I: usize>(a: Simd) -> i16 { a[I] }
+
+    #[inline]
+    unsafe fn simd_insert_i16<const I: usize>(mut a: Simd, v: i16) -> Simd {
+        a[I] = v;
+        a
+    }
+
+    #[inline]
+    unsafe fn simd_movemask_i8(a: Simd) -> u32 {
+        let mut res = 0u32;
+        for (i, &x) in a.iter().enumerate() {
+            res |= ((x as u32 >> 7) & 1) << (2 * i);
+            res |= ((x as u32 >> 15) & 1) << (2 * i + 1);
+        }
+        res
+    }
+
+    #[inline]
+    unsafe fn simd_sl_i16<const N: usize>(a: Simd, b: Simd) -> Simd {
+        debug_assert!(2 * N <= L);
+        let mut res = [0i16; L];
+        for i in 0..L {
+            res[i] = if i >= N { a[i - N] } else { b[L - N + i] };
+        }
+        res
+    }
+
+    #[inline]
+    unsafe fn simd_sr_i16<const N: usize>(a: Simd, b: Simd) -> Simd {
+        debug_assert!(2 * N <= L);
+        let mut res = [0i16; L];
+        for i in 0..L {
+            res[i] = if i + N < L { b[i + N] } else { a[i + N - L] };
+        }
+        res
+    }
+
+    #[inline]
+    unsafe fn simd_broadcasthi_i16(v: Simd) -> Simd { [v[L - 1]; L] }
+
+    #[inline]
+    unsafe fn simd_slow_extract_i16(v: Simd, i: usize) -> i16 {
+        debug_assert!(i < L);
+        v[i]
+    }
+
+    #[inline]
+    unsafe fn simd_hmax_i16(v: Simd) -> i16 { v.iter().copied().max().unwrap() }
+
+    #[inline]
+    unsafe fn simd_prefix_hadd_i16<const N: usize>(a: Simd) -> i16 {
+        debug_assert!(2 * N <= L);
+        // add in the same order as the native backends, since the additions saturate
+        let mut v = Self::simd_subs_i16(a, [ZERO; L]);
+        for &step in &[4, 2, 1] {
+            if N > step {
+                for i in 0..step {
+                    v[i] = v[i].saturating_add(v[i + step]);
+                }
+            }
+        }
+        v[0]
+    }
+
+    #[inline]
+    unsafe fn simd_hargmax_i16(v: Simd, max: i16) -> usize {
+        v.iter().position(|&x| x == max).unwrap_or(L)
+    }
+
+    #[inline]
+    unsafe fn get_gap_extend_all(gap: i16) -> Simd {
+        let mut res = [0i16; L];
+        for (i, r) in res.iter_mut().enumerate() {
+            *r = gap * (i as i16 + 1);
+        }
+        res
+    }
+
+    #[inline]
+    unsafe fn get_prefix_scan_consts(gap: i16) -> PrefixScanConsts { gap }
+
+    #[inline]
+    #[allow(non_snake_case)]
+    unsafe fn simd_prefix_scan_i16(R_max: Simd, gap: PrefixScanConsts) -> Simd {
+        // the native backends scan groups of lanes with zeros shifted in, and then carry
+        // the last lane of each group over to the next group
+        let mut res = [0i16; L];
+        let mut carry = 0i16;
+        for (i, &r) in R_max.iter().enumerate() {
+            if i > 0 && i % SCAN_LANES == 0 {
+                carry = res[i - 1].max(0);
+            }
+            let prev = if i % SCAN_LANES == 0 { carry } else { res[i - 1] };
+            res[i] = r.max(prev.saturating_add(gap));
+        }
+        res
+    }
+
+    #[inline]
+    unsafe fn halfsimd_lookup2_i16(lut1: HalfSimd, lut2: HalfSimd, v: HalfSimd) -> Simd {
+        widen(|i| if v[i] & 0b10000 != 0 { shuffle(lut2, v[i]) } else { shuffle(lut1, v[i]) })
+    }
+
+    #[inline]
+    unsafe fn halfsimd_lookup1_i16(lut: HalfSimd, v: HalfSimd) -> Simd {
+        widen(|i| shuffle(lut, v[i]))
+    }
+
+    #[inline]
+    unsafe fn halfsimd_lookup_bytes_i16(match_scores: HalfSimd, mismatch_scores: HalfSimd, a: HalfSimd, b: HalfSimd) -> Simd {
+        widen(|i| if a[i] == b[i] { match_scores[i] } else { mismatch_scores[i] })
+    }
+
+    #[inline]
+    unsafe fn halfsimd_gather_i16(table: *const i8, v: HalfSimd) -> Simd {
+        widen(|i| *table.add(v[i] as u8 as usize))
+    }
+
+    #[inline]
+    unsafe fn halfsimd_load(ptr: *const HalfSimd) -> HalfSimd { ptr::read(ptr) }
+
+    #[inline]
+    unsafe fn halfsimd_loadu(ptr: *const HalfSimd) -> HalfSimd { ptr::read_unaligned(ptr) }
+
+    #[inline]
+    unsafe fn halfsimd_set1_i8(v: i8) -> HalfSimd { [v; 16] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::NativeBackend;
+    use crate::scan_block::*;
+    use crate::scores::*;
+    use crate::simulate::*;
+
+    use rand::prelude::*;
+
+    type N = NativeBackend;
+
+    #[repr(C, align(32))]
+    struct A([i16; L]);
+
+    unsafe fn native(a: Simd) -> <N as SimdBackend>::Simd {
+        N::simd_load(A(a).0.as_ptr() as _)
+    }
+
+    unsafe fn lanes(v: <N as SimdBackend>::Simd) -> Simd {
+        let mut a = A([0i16; L]);
+        N::simd_store(a.0.as_mut_ptr() as _, v);
+        a.0
+    }
+
+    unsafe fn native_half(a: HalfSimd) -> <N as SimdBackend>::HalfSimd {
+        N::halfsimd_loadu(a.as_ptr() as _)
+    }
+
+    fn rand_simd<R: Rng>(rng: &mut R) -> Simd {
+        // mostly scores around zero, with some extreme values to test saturation
+        let mut res = [0i16; L];
+        for r in res.iter_mut() {
+            *r = match rng.gen_range(0..4) {
+                0 => rng.gen(),
+                1 => rng.gen_range(-2..=2),
+                _ => ZERO + rng.gen_range(-100..=100)
+            };
+        }
+        res
+    }
+
+    fn rand_half<R: Rng>(rng: &mut R, max: i8) -> HalfSimd {
+        let mut res = [0i8; 16];
+        for r in res.iter_mut() {
+            *r = rng.gen_range(0..max);
+        }
+        res
+    }
+
+    #[test]
+    fn test_simd_matches_native() {
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        unsafe {
+            N::with_features(|| {
+                for _i in 0..1000 {
+                    let (a, b) = (rand_simd(&mut rng), rand_simd(&mut rng));
+                    let mask = Scalar::simd_cmpeq_i16(a.map(|x| x & 1), [0; L]);
+                    let (na, nb, nmask) = (native(a), native(b), native(mask));
+                    let max = Scalar::simd_hmax_i16(a);
+
+                    assert_eq!(Scalar::simd_adds_i16(a, b), lanes(N::simd_adds_i16(na, nb)));
+                    assert_eq!(Scalar::simd_subs_i16(a, b), lanes(N::simd_subs_i16(na, nb)));
+                    assert_eq!(Scalar::simd_max_i16(a, b), lanes(N::simd_max_i16(na, nb)));
+                    assert_eq!(Scalar::simd_blend_i8(a, b, mask), lanes(N::simd_blend_i8(na, nb, nmask)));
+                    assert_eq!(Scalar::simd_blend_i8(a, b, a), lanes(N::simd_blend_i8(na, nb, na)));
+                    assert_eq!(Scalar::simd_movemask_i8(a), N::simd_movemask_i8(na));
+                    assert_eq!(Scalar::simd_sl_i16::<1>(a, b), lanes(N::simd_sl_i16::<1>(na, nb)));
+                    assert_eq!(Scalar::simd_sl_i16::<{ L / 2 }>(a, b), lanes(N::simd_sl_i16::<{ L / 2 }>(na, nb)));
+                    assert_eq!(Scalar::simd_sr_i16::<1>(a, b), lanes(N::simd_sr_i16::<1>(na, nb)));
+                    assert_eq!(Scalar::simd_sr_i16::<{ L / 2 }>(a, b), lanes(N::simd_sr_i16::<{ L / 2 }>(na, nb)));
+                    assert_eq!(Scalar::simd_broadcasthi_i16(a), lanes(N::simd_broadcasthi_i16(na)));
+                    assert_eq!(max, N::simd_hmax_i16(na));
+                    assert_eq!(Scalar::simd_hargmax_i16(a, max), N::simd_hargmax_i16(na, max));
+                    assert_eq!(Scalar::simd_hargmax_i16(a, ZERO), N::simd_hargmax_i16(na, ZERO));
+                    assert_eq!(Scalar::simd_prefix_hadd_i16::<{ L / 2 }>(a), N::simd_prefix_hadd_i16::<{ L / 2 }>(na));
+                    assert_eq!(Scalar::simd_prefix_hadd_i16::<{ L / 4 }>(a), N::simd_prefix_hadd_i16::<{ L / 4 }>(na));
+
+                    // scores are never below the lowest score
+                    let r = a.map(|x| x.max(0));
+                    let gap = rng.gen_range(-5..=0);
+                    assert_eq!(Scalar::get_gap_extend_all(gap), lanes(N::get_gap_extend_all(gap)));
+                    assert_eq!(
+                        Scalar::simd_prefix_scan_i16(r, Scalar::get_prefix_scan_consts(gap)),
+                        lanes(N::simd_prefix_scan_i16(native(r), N::get_prefix_scan_consts(gap)))
+                    );
+
+                    let (lut1, lut2) = (rand_half(&mut rng, i8::MAX), rand_half(&mut rng, i8::MAX));
+                    let (c, d) = (rand_half(&mut rng, 32), rand_half(&mut rng, 4));
+                    let (nlut1, nlut2, nc, nd) = (native_half(lut1), native_half(lut2), native_half(c), native_half(d));
+                    assert_eq!(Scalar::halfsimd_lookup2_i16(lut1, lut2, c), lanes(N::halfsimd_lookup2_i16(nlut1, nlut2, nc)));
+                    assert_eq!(Scalar::halfsimd_lookup1_i16(lut1, c), lanes(N::halfsimd_lookup1_i16(nlut1, nc)));
+                    assert_eq!(Scalar::halfsimd_lookup_bytes_i16(lut1, lut2, c, d), lanes(N::halfsimd_lookup_bytes_i16(nlut1, nlut2, nc, nd)));
+
+                    // padded for the native gather
+                    let table = (0..256 + 3).map(|_| rng.gen()).collect::<Vec<i8>>();
+                    let e = rand_half(&mut rng, i8::MAX).map(|x| x.wrapping_mul(2));
+                    assert_eq!(Scalar::halfsimd_gather_i16(table.as_ptr(), e), lanes(N::halfsimd_gather_i16(table.as_ptr(), native_half(e))));
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_align_matches_native() {
+        let mut rng = StdRng::seed_from_u64(1234);
+        let test_gaps = Gaps { open: -3, extend: -1 };
+
+        for &len in &[10, 100, 1000] {
+            for _i in 0..10 {
+                let r = rand_str(len, &NUC, &mut rng);
+                let q = rand_mutate(&r, len / 10, &NUC, &mut rng);
+                let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 64);
+                let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 64);
+
+                let a = Block::<_, true, false>::align(&q_padded, &r_padded, &NW1, test_gaps, 16..=64, 0);
+                let b = Block::<_, true, false, false, Scalar>::align(&q_padded, &r_padded, &NW1, test_gaps, 16..=64, 0);
+                assert_eq!(a.res(), b.res());
+                let res = a.res();
+                assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), b.trace().cigar(res.query_idx, res.reference_idx).to_string());
+
+                let r = rand_str(len, &AMINO_ACIDS, &mut rng);
+                let q = rand_mutate(&r, len / 10, &AMINO_ACIDS, &mut rng);
+                let r_padded = PaddedBytes::from_bytes::<AAMatrix>(&r, 64);
+                let q_padded = PaddedBytes::from_bytes::<AAMatrix>(&q, 64);
+
+                let a = Block::<_, true, true>::align(&q_padded, &r_padded, &BLOSUM62, test_gaps, 16..=64, 50);
+                let b = Block::<_, true, true, false, Scalar>::align(&q_padded, &r_padded, &BLOSUM62, test_gaps, 16..=64, 50);
+                assert_eq!(a.res(), b.res());
+                let res = a.res();
+                assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), b.trace().cigar(res.query_idx, res.reference_idx).to_string());
+            }
+        }
+    }
+}
@@ -1,17 +1,16 @@
 //! Main block aligner algorithm and supporting data structures.
 
-#[cfg(feature = "simd_avx2")]
-use crate::avx2::*;
-
-#[cfg(feature = "simd_wasm")]
-use crate::simd128::*;
+use crate::backend::*;
 
 use crate::scores::*;
 use crate::cigar::*;
 
-use std::{cmp, ptr, i16, alloc};
-use std::ops::RangeInclusive;
+use std::{cmp, ptr, slice, i16, alloc};
+use std::ops::{Deref, Range, RangeInclusive};
 use std::any::TypeId;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 // Notes:
 //
@@ -39,7 +38,7 @@ use std::any::TypeId;
 // computed in each step.
 
 /// Data structure storing the settings for block aligner.
-pub struct Block<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> {
+pub struct Block<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool, const EXACT_GAPS: bool = false, B: SimdBackend = NativeBackend> {
     res: AlignResult,
     trace: Trace,
     query: &'a PaddedBytes,
@@ -50,17 +49,45 @@ pub struct Block<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool>
     max_size: usize,
     matrix: &'a M,
     gaps: Gaps,
-    x_drop: i32
+    x_drop: i32,
+    allocator: &'a dyn BufferAllocator,
+    growth: Growth,
+    shrink_iter: usize,
+    adaptive_step: bool,
+    grow_threshold: f32,
+    free_ref_ends: bool,
+    terminal_gaps: Option<Gaps>,
+    start_gap: Option<Operation>,
+    track_max: bool,
+    max_res: AlignResult,
+    suboptimal_radius: Option<usize>,
+    suboptimal_res: Option<AlignResult>,
+    end_max: i32,
+    end_argmax: (usize, usize),
+    record_ends: bool,
+    end_row_scores: Vec<i32>,
+    end_col_scores: Vec<i32>,
+    best_scores: Option<Vec<(usize, i32)>>,
+    steps: Option<Vec<Step>>,
+    cancel: Option<Arc<AtomicBool>>,
+    path_stats: PathStats,
+    diagnostics: Diagnostics,
+    #[cfg(feature = "stats")]
+    counters: Counters,
+    #[cfg(feature = "block_values")]
+    block_values: Vec<BlockValues>,
+    // start cell of the DP in the strings, which is added to all string indices
+    start: (usize, usize),
+    backend: PhantomData<B>
 }
 
 // increasing step size gives a bit extra speed but results in lower accuracy
-// current settings are fast, at the expense of some accuracy, and step size does not grow
+// current settings are fast, at the expense of some accuracy, and step size does not change
+// unless adaptive step sizes are enabled
 const STEP: usize = if L / 2 < 8 { L / 2 } else { 8 };
-const LARGE_STEP: usize = STEP; // use larger step size when the block size gets large
-const GROW_STEP: usize = L; // used when not growing by powers of 2
-const GROW_EXP: bool = true; // grow by powers of 2
+const SMALL_STEP: usize = STEP / 2; // use smaller step size in difficult regions, if adaptive
 const X_DROP_ITER: usize = 2; // make sure that the X-drop iteration is truly met instead of just one "bad" step
-impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M, { TRACE }, { X_DROP }> {
+impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool, const EXACT_GAPS: bool, B: SimdBackend> Block<'a, M, { TRACE }, { X_DROP }, { EXACT_GAPS }, B> {
     /// Align two strings with block aligner.
     ///
     /// If `TRACE` is true, then information for computing the traceback will be stored.
@@ -71,10 +98,17 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
     /// the max score in the current block drops by `x_drop` below the max score encountered
     /// so far. If `X_DROP` is false, then global alignment is done.
     ///
+    /// With affine gaps, the trace cannot always tell whether a gap was opened or extended
+    /// in tie cases, so the score of the traceback CIGAR string can differ from the score
+    /// of the alignment. If `EXACT_GAPS` is true (it is false by default), then an extra
+    /// bit for each cell is stored to resolve this, so the traceback is exactly score-consistent.
+    /// This doubles the memory used by the trace and slows down alignment with traceback.
+    ///
     /// Since larger scores are better, gap and mismatches penalties should be negative.
     ///
     /// The minimum and maximum sizes of the block must be powers of 2 that are greater than the
-    /// number of 16-bit lanes in a SIMD vector.
+    /// number of 16-bit lanes in a SIMD vector. The block size doubles when it grows.
+    /// See `AlignOptions::growth` for growing the block by a fixed increment instead.
     ///
     /// The block aligner algorithm will dynamically shift a block down or right and grow its size
     /// to efficiently calculate the alignment between two strings.
@@ -83,28 +117,91 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
     /// other potentially difficult regions to be handled correctly.
     /// 16-bit deltas and 32-bit offsets are used to ensure that accurate scores are
     /// computed, even when the the strings are long.
+    ///
+    /// To align substrings (for example, windows around candidate variants) without
+    /// copying them into new `PaddedBytes`, use `PaddedBytes::slice`.
+    ///
+    /// This uses the default options. See `align_with` for glocal alignment, terminal gap
+    /// costs, recording extra information about the alignment, and other options.
     pub fn align(query: &'a PaddedBytes, reference: &'a PaddedBytes, matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>, x_drop: i32) -> Self {
+        Self::align_with(query, reference, matrix, gaps, size, x_drop, &AlignOptions::new())
+    }
+
+    /// Align two strings with block aligner, with a set of options.
+    ///
+    /// Options can be combined, unless noted otherwise in `AlignOptions`. For example,
+    /// `AlignOptions::new().glocal().start((10, 20)).steps()` does glocal alignment from
+    /// the cell `(10, 20)` and records every step of the block.
+    ///
+    /// See `align` for more details.
+    pub fn align_with(query: &'a PaddedBytes, reference: &'a PaddedBytes, matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>, x_drop: i32, options: &AlignOptions<'a>) -> Self {
+        let max_size_cap = match options.max_size_cap {
+            Some(cap) => cap,
+            None => return Self::new(query, reference, matrix, gaps, size, x_drop, options).run()
+        };
+
+        assert!(max_size_cap >= *size.end(), "Max block size cap must be at least the max block size!");
+        let mut a = Self::new(query, reference, matrix, gaps, size.clone(), x_drop, options).run();
+        let mut max_size = a.max_size;
+
+        while a.diagnostics.max_size_reached && max_size < max_size_cap {
+            max_size = cmp::min(max_size * 2, max_size_cap);
+            let b = Self::new(query, reference, matrix, gaps, *size.start()..=max_size, x_drop, options).run();
+            if b.res.score < a.res.score {
+                break;
+            }
+            a = b;
+        }
+
+        a
+    }
+
+    /// Check the settings and create a new block aligner without running alignment.
+    fn new(query: &'a PaddedBytes, reference: &'a PaddedBytes, matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>, x_drop: i32, options: &AlignOptions<'a>) -> Self {
         // check invariants so bad stuff doesn't happen later
         assert!(gaps.open < 0 && gaps.extend < 0, "Gap costs must be negative!");
-        // there are edge cases with calculating traceback that doesn't work if
-        // gap open does not cost more than gap extend
-        assert!(gaps.open < gaps.extend, "Gap open must cost more than gap extend!");
+        // gap open == gap extend is a linear gap model, where every gap cell is marked as
+        // opened in the trace, so the traceback can freely leave gaps at any cell
+        assert!(gaps.open <= gaps.extend, "Gap open must not cost less than gap extend!");
         let min_size = if *size.start() < L { L } else { *size.start() };
         let max_size = if *size.end() < L { L } else { *size.end() };
         assert!(min_size < (u16::MAX as usize) && max_size < (u16::MAX as usize), "Block sizes must be smaller than 2^16 - 1!");
-        if GROW_EXP {
-            assert!(min_size.is_power_of_two() && max_size.is_power_of_two(), "Block sizes must be powers of two!");
-        } else {
-            assert!(min_size % L == 0 && max_size % L == 0, "Block sizes must be multiples of {}!", L);
-        }
         if X_DROP {
             assert!(x_drop >= 0, "X-drop threshold amount must be nonnegative!");
             assert!(TypeId::of::<M>() != TypeId::of::<ByteMatrix>(), "X-drop alignment with ByteMatrix is not fully supported!");
+            assert!(!options.glocal, "Glocal alignment cannot be combined with X-drop alignment!");
+            assert!(options.terminal_gaps.is_none(), "Terminal gap costs cannot be combined with X-drop alignment!");
         }
+        assert!(!(options.glocal && options.terminal_gaps.is_some()), "Glocal alignment cannot be combined with terminal gap costs!");
+        assert!(options.start_gap.is_none() || !(options.glocal || options.terminal_gaps.is_some()), "Start gap state cannot be combined with glocal alignment or terminal gap costs!");
+        let start = options.start;
+        assert!(start.0 <= query.len() && start.1 <= reference.len(), "Start cell must be within bounds!");
+        let allocator = options.allocator;
+
+        let mut trace = if TRACE {
+            let (query_len, reference_len, exact_gaps) = (query.len() - start.0, reference.len() - start.1, EXACT_GAPS && gaps.open != gaps.extend);
+            match allocator.take_trace() {
+                Some(mut trace) => {
+                    trace.reset(query_len, reference_len, exact_gaps);
+                    trace
+                },
+                None => Trace::new(query_len, reference_len, exact_gaps)
+            }
+        } else {
+            Trace::new(0, 0, false)
+        };
+        trace.start = start;
+        trace.free_ref_start = options.glocal;
 
-        let mut a = Self {
+        let (end_row_scores, end_col_scores) = if options.end_scores {
+            (vec![i32::MIN; reference.len() - start.1 + 1], vec![i32::MIN; query.len() - start.0 + 1])
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        Self {
             res: AlignResult { score: 0, query_idx: 0, reference_idx: 0 },
-            trace: if TRACE { Trace::new(query.len(), reference.len()) } else { Trace::new(0, 0) },
+            trace,
             query,
             i: 0,
             reference,
@@ -113,22 +210,80 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
             max_size,
             matrix,
             gaps,
-            x_drop
-        };
+            x_drop,
+            allocator,
+            growth: options.growth,
+            shrink_iter: options.shrink_iter,
+            adaptive_step: options.adaptive_step,
+            grow_threshold: options.grow_threshold,
+            free_ref_ends: options.glocal,
+            terminal_gaps: options.terminal_gaps,
+            start_gap: options.start_gap,
+            track_max: options.track_max || options.suboptimal_radius.is_some(),
+            max_res: AlignResult { score: 0, query_idx: 0, reference_idx: 0 },
+            suboptimal_radius: options.suboptimal_radius,
+            suboptimal_res: None,
+            end_max: i32::MIN,
+            end_argmax: (0, 0),
+            record_ends: options.end_scores,
+            end_row_scores,
+            end_col_scores,
+            best_scores: if options.best_scores { Some(Vec::new()) } else { None },
+            steps: if options.steps { Some(Vec::new()) } else { None },
+            cancel: options.cancel.clone(),
+            path_stats: PathStats { right: 0, down: 0, grows: 0, shrinks: 0, final_size: 0, largest_size: 0 },
+            diagnostics: Diagnostics::default(),
+            #[cfg(feature = "stats")]
+            counters: Counters::default(),
+            #[cfg(feature = "block_values")]
+            block_values: Vec::new(),
+            start,
+            backend: PhantomData
+        }
+    }
 
-        unsafe { a.align_core(); }
-        a
+    #[inline]
+    fn run(mut self) -> Self {
+        // block sizes are checked here since the growth strategy can be changed after `new`
+        match self.growth {
+            Growth::Exponential => {
+                assert!(self.min_size.is_power_of_two() && self.max_size.is_power_of_two(), "Block sizes must be powers of two!");
+            },
+            Growth::Additive(grow_step) => {
+                assert_eq!(self.min_size % L, 0, "Block sizes must be multiples of {}!", L);
+                assert_eq!(self.max_size % L, 0, "Block sizes must be multiples of {}!", L);
+                assert!(grow_step > 0, "Growth increment must be positive!");
+                assert_eq!(grow_step % L, 0, "Growth increment must be a multiple of {}!", L);
+            }
+        }
+
+        // options that need extra work in the inner loop are rarely used, so the common case
+        // gets a separately compiled kernel without any of that work
+        let extra = self.free_ref_ends || self.terminal_gaps.is_some() || self.start_gap.is_some() || self.record_ends || self.track_max
+            || self.query.sliced || self.reference.sliced || self.start != (0, 0);
+        unsafe {
+            if extra {
+                B::with_features(#[inline(always)] || self.align_core::<true>());
+            } else {
+                B::with_features(#[inline(always)] || self.align_core::<false>());
+            }
+        }
+        self
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[allow(non_snake_case)]
-    unsafe fn align_core(&mut self) {
+    #[inline(always)]
+    unsafe fn align_core<const EXTRA: bool>(&mut self) {
         // store the best alignment ending location for x drop alignment
         let mut best_max = 0i32;
         let mut best_argmax_i = 0usize;
         let mut best_argmax_j = 0usize;
 
+        // store the best alignment ending location in the last row or column for glocal
+        // alignment or alignment with terminal gaps
+        let mut best_end = i32::MIN;
+        let mut best_end_idx = (0usize, 0usize);
+
         let mut prev_dir = Direction::Grow;
         let mut dir = Direction::Grow;
         let mut prev_size = 0;
@@ -141,15 +296,15 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
         let mut off_max = 0i32;
 
         // bottom and right borders of the current block
-        let mut D_col = Aligned::new(self.max_size);
-        let mut C_col = Aligned::new(self.max_size);
-        let mut D_row = Aligned::new(self.max_size);
-        let mut R_row = Aligned::new(self.max_size);
+        let mut D_col = Aligned::new(self.allocator, self.max_size);
+        let mut C_col = Aligned::new(self.allocator, self.max_size);
+        let mut D_row = Aligned::new(self.allocator, self.max_size);
+        let mut R_row = Aligned::new(self.allocator, self.max_size);
 
         // reused buffers for storing values that must be shifted
         // into the other border when the block moves in one direction
-        let mut temp_buf1 = Aligned::new(L);
-        let mut temp_buf2 = Aligned::new(L);
+        let mut temp_buf1 = Aligned::new(self.allocator, L);
+        let mut temp_buf2 = Aligned::new(self.allocator, L);
 
         // how many steps since the latest best score was encountered
         let mut y_drop_iter = 0;
@@ -157,39 +312,48 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
         // how many steps where the X-drop threshold is met
         let mut x_drop_iter = 0;
 
+        // how many steps where the best score improved since the block size last changed
+        let mut improve_iter = 0;
+
+        // max score and its location in each step, for finding suboptimal alignments
+        let mut step_maxes = Vec::new();
+
         // the state at the previous checkpoint (where latest best score was encountered)
         let mut i_ckpt = self.i;
         let mut j_ckpt = self.j;
         let mut off_ckpt = 0i32;
-        let mut D_col_ckpt = Aligned::new(self.max_size);
-        let mut C_col_ckpt = Aligned::new(self.max_size);
-        let mut D_row_ckpt = Aligned::new(self.max_size);
-        let mut R_row_ckpt = Aligned::new(self.max_size);
+        let mut D_col_ckpt = Aligned::new(self.allocator, self.max_size);
+        let mut C_col_ckpt = Aligned::new(self.allocator, self.max_size);
+        let mut D_row_ckpt = Aligned::new(self.allocator, self.max_size);
+        let mut R_row_ckpt = Aligned::new(self.allocator, self.max_size);
 
-        let prefix_scan_consts = get_prefix_scan_consts(self.gaps.extend as i16);
-        let gap_extend_all = get_gap_extend_all(self.gaps.extend as i16);
+        let prefix_scan_consts = B::get_prefix_scan_consts(self.gaps.extend as i16);
+        let gap_extend_all = B::get_gap_extend_all(self.gaps.extend as i16);
 
         // corner value that affects the score when shifting down then right, or right then down
-        let mut D_corner = simd_set1_i16(MIN);
+        let mut D_corner = B::simd_set1_i16(MIN);
 
         loop {
             #[cfg(feature = "debug")]
-            {
-                println!("i: {}", self.i);
-                println!("j: {}", self.j);
-                println!("{:?}", dir);
-                println!("block size: {}", block_size);
-            }
+            log::debug!("i: {}, j: {}, direction: {:?}, block size: {}", self.i, self.j, dir, block_size);
 
             prev_off = off;
-            let mut grow_D_max = simd_set1_i16(MIN);
-            let mut grow_D_argmax = simd_set1_i16(0);
+            self.end_max = i32::MIN;
+            let mut grow_D_max = B::simd_set1_i16(MIN);
+            let mut grow_D_argmax = B::simd_set1_i16(0);
+            match dir {
+                Direction::Right => self.path_stats.right += 1,
+                Direction::Down => self.path_stats.down += 1,
+                Direction::Grow => if prev_size > 0 { self.path_stats.grows += 1; }
+            }
+            self.path_stats.largest_size = cmp::max(self.path_stats.largest_size, block_size);
+
             let (D_max, D_argmax, right_max, down_max) = match dir {
                 Direction::Right => {
                     off = off_max;
                     #[cfg(feature = "debug")]
-                    println!("off: {}", off);
-                    let off_add = simd_set1_i16(clamp(prev_off - off));
+                    log::debug!("off: {}", off);
+                    let off_add = B::simd_set1_i16(clamp(prev_off - off));
 
                     if TRACE {
                         self.trace.add_block(self.i, self.j + block_size - step, step, block_size, true);
@@ -200,7 +364,7 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
 
                     // compute new elements in the block as a result of shifting by the step size
                     // this region should be block_size x step
-                    let (D_max, D_argmax) = self.place_block(
+                    let (D_max, D_argmax) = self.place_block::<EXTRA>(
                         self.query,
                         self.reference,
                         self.i,
@@ -211,11 +375,15 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                         C_col.as_mut_ptr(),
                         temp_buf1.as_mut_ptr(),
                         temp_buf2.as_mut_ptr(),
-                        if prev_dir == Direction::Down { simd_adds_i16(D_corner, off_add) } else { simd_set1_i16(MIN) },
+                        if prev_dir == Direction::Down { B::simd_adds_i16(D_corner, off_add) } else { B::simd_set1_i16(MIN) },
                         true,
+                        off,
                         prefix_scan_consts,
                         gap_extend_all
                     );
+                    if TRACE {
+                        self.trace.set_block_max(block_max_score::<B>(D_max, off));
+                    }
 
                     // sum of a couple elements on the right border
                     let right_max = self.prefix_max(D_col.as_ptr(), step);
@@ -238,8 +406,8 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                 Direction::Down => {
                     off = off_max;
                     #[cfg(feature = "debug")]
-                    println!("off: {}", off);
-                    let off_add = simd_set1_i16(clamp(prev_off - off));
+                    log::debug!("off: {}", off);
+                    let off_add = B::simd_set1_i16(clamp(prev_off - off));
 
                     if TRACE {
                         self.trace.add_block(self.i + block_size - step, self.j, block_size, step, false);
@@ -250,7 +418,7 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
 
                     // compute new elements in the block as a result of shifting by the step size
                     // this region should be step x block_size
-                    let (D_max, D_argmax) = self.place_block(
+                    let (D_max, D_argmax) = self.place_block::<EXTRA>(
                         self.reference,
                         self.query,
                         self.j,
@@ -261,11 +429,15 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                         R_row.as_mut_ptr(),
                         temp_buf1.as_mut_ptr(),
                         temp_buf2.as_mut_ptr(),
-                        if prev_dir == Direction::Right { simd_adds_i16(D_corner, off_add) } else { simd_set1_i16(MIN) },
+                        if prev_dir == Direction::Right { B::simd_adds_i16(D_corner, off_add) } else { B::simd_set1_i16(MIN) },
                         false,
+                        off,
                         prefix_scan_consts,
                         gap_extend_all
                     );
+                    if TRACE {
+                        self.trace.set_block_max(block_max_score::<B>(D_max, off));
+                    }
 
                     // sum of a couple elements on the bottom border
                     let down_max = self.prefix_max(D_row.as_ptr(), step);
@@ -286,24 +458,24 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                     (D_max, D_argmax, right_max, down_max)
                 },
                 Direction::Grow => {
-                    D_corner = simd_set1_i16(MIN);
+                    D_corner = B::simd_set1_i16(MIN);
                     let grow_step = block_size - prev_size;
 
                     #[cfg(feature = "debug")]
-                    println!("off: {}", off);
+                    log::debug!("off: {}", off);
                     #[cfg(feature = "debug")]
-                    println!("Grow down");
+                    log::debug!("grow down");
 
                     if TRACE {
                         // with a larger block, the size of the trace array might need to be
                         // increased
-                        self.trace.resize_trace(self.i, self.j, self.query.len(), self.reference.len(), block_size);
+                        self.trace.resize_trace(self.i, self.j, self.query_len(), self.reference_len(), block_size);
                         self.trace.add_block(self.i + prev_size, self.j, prev_size, grow_step, false);
                     }
 
                     // down
                     // this region should be prev_size x prev_size
-                    let (D_max1, D_argmax1) = self.place_block(
+                    let (D_max1, D_argmax1) = self.place_block::<EXTRA>(
                         self.reference,
                         self.query,
                         self.j,
@@ -314,14 +486,18 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                         R_row.as_mut_ptr(),
                         D_col.as_mut_ptr().add(prev_size),
                         C_col.as_mut_ptr().add(prev_size),
-                        simd_set1_i16(MIN),
+                        B::simd_set1_i16(MIN),
                         false,
+                        off,
                         prefix_scan_consts,
                         gap_extend_all
                     );
+                    if TRACE {
+                        self.trace.set_block_max(block_max_score::<B>(D_max1, off));
+                    }
 
                     #[cfg(feature = "debug")]
-                    println!("Grow right");
+                    log::debug!("grow right");
 
                     if TRACE {
                         self.trace.add_block(self.i, self.j + prev_size, grow_step, block_size, true);
@@ -329,7 +505,7 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
 
                     // right
                     // this region should be block_size x prev_size
-                    let (D_max2, D_argmax2) = self.place_block(
+                    let (D_max2, D_argmax2) = self.place_block::<EXTRA>(
                         self.query,
                         self.reference,
                         self.i,
@@ -340,11 +516,15 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                         C_col.as_mut_ptr(),
                         D_row.as_mut_ptr().add(prev_size),
                         R_row.as_mut_ptr().add(prev_size),
-                        simd_set1_i16(MIN),
+                        B::simd_set1_i16(MIN),
                         true,
+                        off,
                         prefix_scan_consts,
                         gap_extend_all
                     );
+                    if TRACE {
+                        self.trace.set_block_max(block_max_score::<B>(D_max2, off));
+                    }
 
                     let right_max = self.prefix_max(D_col.as_ptr(), step);
                     let down_max = self.prefix_max(D_row.as_ptr(), step);
@@ -370,52 +550,72 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                 }
             };
 
+            #[cfg(feature = "block_values")]
+            self.record_block_values(block_size, off, &D_col, &D_row);
+
+            if self.end_max > i32::MIN {
+                let end = off + self.end_max - (ZERO as i32);
+                if end > best_end {
+                    best_end = end;
+                    best_end_idx = self.end_argmax;
+                }
+            }
+
             prev_dir = dir;
-            let D_max_max = simd_hmax_i16(D_max);
+            let D_max_max = B::simd_hmax_i16(D_max);
             // grow max is an auxiliary value used when growing because it requires two separate
             // place_block steps
-            let grow_max = simd_hmax_i16(grow_D_max);
+            let grow_max = B::simd_hmax_i16(grow_D_max);
             // max score of the entire block
             let max = cmp::max(D_max_max, grow_max);
             off_max = off + (max as i32) - (ZERO as i32);
+            // scores are saturated if the block max hits the 16-bit limit or if the
+            // difference between offsets is too large to be represented
+            if max == i16::MAX || prev_off - off != clamp(prev_off - off) as i32 {
+                self.diagnostics.score_saturated = true;
+            }
             #[cfg(feature = "debug")]
-            println!("down max: {}, right max: {}", down_max, right_max);
+            log::debug!("down max: {}, right max: {}", down_max, right_max);
 
             y_drop_iter += 1;
             // if block grows but the best score does not improve, then the block must grow again
             let mut grow_no_max = dir == Direction::Grow;
 
-            if off_max > best_max {
-                if X_DROP {
-                    // calculate location with the best score
-                    let lane_idx = simd_hargmax_i16(D_max, D_max_max);
-                    let idx = simd_slow_extract_i16(D_argmax, lane_idx) as usize;
-                    let r = (idx % (block_size / L)) * L + lane_idx;
-                    let c = (block_size - step) + idx / (block_size / L);
-
-                    match dir {
-                        Direction::Right => {
-                            best_argmax_i = self.i + r;
-                            best_argmax_j = self.j + c;
-                        },
-                        Direction::Down => {
-                            best_argmax_i = self.i + c;
-                            best_argmax_j = self.j + r;
-                        },
-                        Direction::Grow => {
-                            // max could be in either block
-                            if max >= grow_max {
-                                best_argmax_i = self.i + (idx % (block_size / L)) * L + lane_idx;
-                                best_argmax_j = self.j + prev_size + idx / (block_size / L);
-                            } else {
-                                let lane_idx = simd_hargmax_i16(grow_D_max, grow_max);
-                                let idx = simd_slow_extract_i16(grow_D_argmax, lane_idx) as usize;
-                                best_argmax_i = self.i + prev_size + idx / (prev_size / L);
-                                best_argmax_j = self.j + (idx % (prev_size / L)) * L + lane_idx;
-                            }
+            let track_argmax = ((X_DROP || (EXTRA && self.track_max)) && off_max > best_max) || (EXTRA && self.suboptimal_radius.is_some());
+            let (max_i, max_j) = if track_argmax {
+                // calculate location with the best score in the current step
+                let lane_idx = B::simd_hargmax_i16(D_max, D_max_max);
+                let idx = B::simd_slow_extract_i16(D_argmax, lane_idx) as usize;
+                let r = (idx % (block_size / L)) * L + lane_idx;
+                let c = (block_size - step) + idx / (block_size / L);
+
+                match dir {
+                    Direction::Right => (self.i + r, self.j + c),
+                    Direction::Down => (self.i + c, self.j + r),
+                    Direction::Grow => {
+                        // max could be in either block
+                        if D_max_max >= grow_max {
+                            (self.i + (idx % (block_size / L)) * L + lane_idx, self.j + prev_size + idx / (block_size / L))
+                        } else {
+                            let lane_idx = B::simd_hargmax_i16(grow_D_max, grow_max);
+                            let idx = B::simd_slow_extract_i16(grow_D_argmax, lane_idx) as usize;
+                            (self.i + prev_size + idx / (prev_size / L), self.j + (idx % (prev_size / L)) * L + lane_idx)
                         }
                     }
                 }
+            } else {
+                (0, 0)
+            };
+
+            if self.suboptimal_radius.is_some() {
+                step_maxes.push(AlignResult { score: off_max, query_idx: max_i, reference_idx: max_j });
+            }
+
+            if off_max > best_max {
+                if X_DROP || (EXTRA && self.track_max) {
+                    best_argmax_i = max_i;
+                    best_argmax_j = max_j;
+                }
 
                 if block_size < self.max_size {
                     // if able to grow in the future, then save the current location
@@ -443,6 +643,24 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                 best_max = off_max;
 
                 y_drop_iter = 0;
+                improve_iter += 1;
+            }
+
+            if let Some(steps) = self.steps.as_mut() {
+                steps.push(Step { direction: dir, i: self.i, j: self.j, block_size, off_max });
+            }
+
+            let query_len = self.query_len();
+            if let Some(best_scores) = self.best_scores.as_mut() {
+                // sample by the last query position that the block reached
+                best_scores.push((cmp::min(self.i + block_size - 1, query_len), best_max));
+            }
+
+            if let Some(cancel) = self.cancel.as_ref() {
+                if cancel.load(Ordering::Relaxed) {
+                    // the block is not at the end, so there is no valid result
+                    return;
+                }
             }
 
             if X_DROP {
@@ -451,6 +669,7 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                         x_drop_iter += 1;
                     } else {
                         // x drop termination
+                        self.diagnostics.x_drop_terminated = true;
                         break;
                     }
                 } else {
@@ -458,35 +677,97 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                 }
             }
 
-            if self.i + block_size > self.query.len() && self.j + block_size > self.reference.len() {
+            if self.shrink_iter > 0 && improve_iter >= self.shrink_iter && block_size > self.min_size && dir != Direction::Grow {
+                let next_size = match self.growth {
+                    Growth::Exponential => block_size / 2,
+                    Growth::Additive(grow_step) => block_size.saturating_sub(grow_step)
+                };
+                let next_size = cmp::max(next_size, self.min_size);
+                let shift = block_size - next_size;
+
+                // keep the bottom right corner of the block in place by moving its top left
+                // corner, so only the ends of the borders need to be kept
+                ptr::copy(D_col.as_ptr().add(shift), D_col.as_mut_ptr(), next_size);
+                ptr::copy(C_col.as_ptr().add(shift), C_col.as_mut_ptr(), next_size);
+                ptr::copy(D_row.as_ptr().add(shift), D_row.as_mut_ptr(), next_size);
+                ptr::copy(R_row.as_ptr().add(shift), R_row.as_mut_ptr(), next_size);
+                self.i += shift;
+                self.j += shift;
+                block_size = next_size;
+                self.path_stats.shrinks += 1;
+
+                // the corner value is not valid anymore
+                D_corner = B::simd_set1_i16(MIN);
+                prev_dir = Direction::Grow;
+                improve_iter = 0;
+
+                // the shrunk block is the new checkpoint, since the last checkpoint could be
+                // for a larger block
+                i_ckpt = self.i;
+                j_ckpt = self.j;
+                off_ckpt = off;
+
+                let mut i = 0;
+                while i < block_size {
+                    D_col_ckpt.set_vec(&D_col, i);
+                    C_col_ckpt.set_vec(&C_col, i);
+                    D_row_ckpt.set_vec(&D_row, i);
+                    R_row_ckpt.set_vec(&R_row, i);
+                    i += L;
+                }
+
+                if TRACE {
+                    self.trace.save_ckpt();
+                }
+            }
+
+            if self.adaptive_step {
+                if y_drop_iter == 0 {
+                    // the best score is improving, so take large steps
+                    step = STEP;
+                } else if y_drop_iter > self.grow_iter(block_size, step) / 2 {
+                    // halfway to growing, so take small steps to avoid missing the optimal path
+                    step = SMALL_STEP;
+                }
+            }
+
+            if self.i + block_size > self.query_len() && self.j + block_size > self.reference_len() {
                 // reached the end of the strings
                 break;
             }
 
             // first check if the shift direction is "forced" to avoid going out of bounds
-            if self.j + block_size > self.reference.len() {
+            if self.j + block_size > self.reference_len() {
                 self.i += step;
                 dir = Direction::Down;
                 continue;
             }
-            if self.i + block_size > self.query.len() {
+            if self.i + block_size > self.query_len() {
                 self.j += step;
                 dir = Direction::Right;
                 continue;
             }
 
             // check if it is possible to grow
-            let next_size = if GROW_EXP { block_size * 2 } else { block_size + GROW_STEP };
+            let next_size = match self.growth {
+                Growth::Exponential => block_size * 2,
+                // the last increment can be smaller so the max size is always reachable
+                Growth::Additive(grow_step) if block_size < self.max_size => cmp::min(block_size + grow_step, self.max_size),
+                Growth::Additive(grow_step) => block_size + grow_step
+            };
             if next_size <= self.max_size {
                 // if approximately (block_size / step) iterations has passed since the last best
-                // max, then it is time to grow
-                if y_drop_iter > (block_size / step) - 1 || grow_no_max {
+                // max (scaled by the growth threshold), then it is time to grow
+                if y_drop_iter >= self.grow_iter(block_size, step) || grow_no_max {
                     // y drop grow block
                     prev_size = block_size;
                     block_size = next_size;
                     dir = Direction::Grow;
-                    if STEP != LARGE_STEP && block_size >= (LARGE_STEP / STEP) * self.min_size {
-                        step = LARGE_STEP;
+
+                    #[cfg(feature = "stats")]
+                    {
+                        self.counters.grows += 1;
+                        self.counters.restores += 1;
                     }
 
                     // return to checkpoint
@@ -508,8 +789,12 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                     }
 
                     y_drop_iter = 0;
+                    improve_iter = 0;
                     continue;
                 }
+            } else if y_drop_iter >= self.grow_iter(block_size, step) || (dir == Direction::Grow && prev_size > 0 && y_drop_iter > 0) {
+                // would have grown, but the block is already at the max size
+                self.diagnostics.max_size_reached = true;
             }
 
             // move according to where the max is
@@ -522,109 +807,112 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
             }
         }
 
-        #[cfg(any(feature = "debug", feature = "debug_size"))]
-        {
-            println!("query size: {}, reference size: {}", self.query.len() - 1, self.reference.len() - 1);
-            println!("end block size: {}", block_size);
+        self.path_stats.final_size = block_size;
+        self.max_res = AlignResult {
+            score: best_max,
+            query_idx: best_argmax_i,
+            reference_idx: best_argmax_j
+        };
+
+        if let Some(radius) = self.suboptimal_radius {
+            let far = |r: &AlignResult| {
+                r.query_idx.abs_diff(best_argmax_i) > radius || r.reference_idx.abs_diff(best_argmax_j) > radius
+            };
+            self.suboptimal_res = step_maxes.into_iter().filter(far).max_by_key(|r| r.score);
         }
 
+        #[cfg(any(feature = "debug", feature = "debug_size"))]
+        log::debug!("query size: {}, reference size: {}, end block size: {}", self.query_len() - 1, self.reference_len() - 1, block_size);
+
         self.res = if X_DROP {
+            self.max_res
+        } else if self.free_ref_ends || self.terminal_gaps.is_some() {
             AlignResult {
-                score: best_max,
-                query_idx: best_argmax_i,
-                reference_idx: best_argmax_j
+                score: best_end,
+                query_idx: best_end_idx.0,
+                reference_idx: best_end_idx.1
             }
         } else {
-            debug_assert!(self.i <= self.query.len());
+            debug_assert!(self.i <= self.query_len());
             let score = off + match dir {
                 Direction::Right | Direction::Grow => {
-                    let idx = self.query.len() - self.i;
+                    let idx = self.query_len() - self.i;
                     debug_assert!(idx < block_size);
                     (D_col.get(idx) as i32) - (ZERO as i32)
                 },
                 Direction::Down => {
-                    let idx = self.reference.len() - self.j;
+                    let idx = self.reference_len() - self.j;
                     debug_assert!(idx < block_size);
                     (D_row.get(idx) as i32) - (ZERO as i32)
                 }
             };
             AlignResult {
                 score,
-                query_idx: self.query.len(),
-                reference_idx: self.reference.len()
+                query_idx: self.query_len(),
+                reference_idx: self.reference_len()
             }
         };
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[allow(non_snake_case)]
-    #[inline]
-    unsafe fn just_offset(&self, block_size: usize, buf1: *mut i16, buf2: *mut i16, off_add: Simd) {
+    #[inline(always)]
+    unsafe fn just_offset(&self, block_size: usize, buf1: *mut i16, buf2: *mut i16, off_add: B::Simd) {
         let mut i = 0;
         while i < block_size {
-            let a = simd_adds_i16(simd_load(buf1.add(i) as _), off_add);
-            let b = simd_adds_i16(simd_load(buf2.add(i) as _), off_add);
-            simd_store(buf1.add(i) as _, a);
-            simd_store(buf2.add(i) as _, b);
+            let a = B::simd_adds_i16(B::simd_load(buf1.add(i) as _), off_add);
+            let b = B::simd_adds_i16(B::simd_load(buf2.add(i) as _), off_add);
+            B::simd_store(buf1.add(i) as _, a);
+            B::simd_store(buf2.add(i) as _, b);
             i += L;
         }
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[allow(non_snake_case)]
-    #[inline]
+    #[inline(always)]
     unsafe fn prefix_max(&self, buf: *const i16, step: usize) -> i16 {
-        if STEP == LARGE_STEP {
-            simd_prefix_hadd_i16!(simd_load(buf as _), STEP)
+        if step == STEP {
+            B::simd_prefix_hadd_i16::<STEP>(B::simd_load(buf as _))
         } else {
-            if step == STEP {
-                simd_prefix_hadd_i16!(simd_load(buf as _), STEP)
-            } else {
-                simd_prefix_hadd_i16!(simd_load(buf as _), LARGE_STEP)
-            }
+            B::simd_prefix_hadd_i16::<SMALL_STEP>(B::simd_load(buf as _))
         }
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[allow(non_snake_case)]
-    #[inline]
-    unsafe fn shift_and_offset(&self, block_size: usize, buf1: *mut i16, buf2: *mut i16, temp_buf1: *mut i16, temp_buf2: *mut i16, off_add: Simd, step: usize) -> Simd {
-        #[inline]
-        unsafe fn sr(a: Simd, b: Simd, step: usize) -> Simd {
-            if STEP == LARGE_STEP {
-                simd_sr_i16!(a, b, STEP)
+    #[inline(always)]
+    unsafe fn shift_and_offset(&self, block_size: usize, buf1: *mut i16, buf2: *mut i16, temp_buf1: *mut i16, temp_buf2: *mut i16, off_add: B::Simd, step: usize) -> B::Simd {
+        #[inline(always)]
+        unsafe fn sr<B: SimdBackend>(a: B::Simd, b: B::Simd, step: usize) -> B::Simd {
+            if step == STEP {
+                B::simd_sr_i16::<STEP>(a, b)
             } else {
-                if step == STEP {
-                    simd_sr_i16!(a, b, STEP)
-                } else {
-                    simd_sr_i16!(a, b, LARGE_STEP)
-                }
+                B::simd_sr_i16::<SMALL_STEP>(a, b)
             }
         }
-        let mut curr1 = simd_adds_i16(simd_load(buf1 as _), off_add);
-        let D_corner = simd_set1_i16(simd_extract_i16!(curr1, STEP - 1));
-        let mut curr2 = simd_adds_i16(simd_load(buf2 as _), off_add);
+        let mut curr1 = B::simd_adds_i16(B::simd_load(buf1 as _), off_add);
+        let D_corner = if step == STEP {
+            B::simd_set1_i16(B::simd_extract_i16::<{ STEP - 1 }>(curr1))
+        } else {
+            B::simd_set1_i16(B::simd_extract_i16::<{ SMALL_STEP - 1 }>(curr1))
+        };
+        let mut curr2 = B::simd_adds_i16(B::simd_load(buf2 as _), off_add);
 
         let mut i = 0;
         while i < block_size - L {
-            let next1 = simd_adds_i16(simd_load(buf1.add(i + L) as _), off_add);
-            let next2 = simd_adds_i16(simd_load(buf2.add(i + L) as _), off_add);
-            let shifted = sr(next1, curr1, step);
-            simd_store(buf1.add(i) as _, shifted);
-            simd_store(buf2.add(i) as _, sr(next2, curr2, step));
+            let next1 = B::simd_adds_i16(B::simd_load(buf1.add(i + L) as _), off_add);
+            let next2 = B::simd_adds_i16(B::simd_load(buf2.add(i + L) as _), off_add);
+            let shifted = sr::<B>(next1, curr1, step);
+            B::simd_store(buf1.add(i) as _, shifted);
+            B::simd_store(buf2.add(i) as _, sr::<B>(next2, curr2, step));
             curr1 = next1;
             curr2 = next2;
             i += L;
         }
 
-        let next1 = simd_load(temp_buf1 as _);
-        let next2 = simd_load(temp_buf2 as _);
-        let shifted = sr(next1, curr1, step);
-        simd_store(buf1.add(block_size - L) as _, shifted);
-        simd_store(buf2.add(block_size - L) as _, sr(next2, curr2, step));
+        let next1 = B::simd_load(temp_buf1 as _);
+        let next2 = B::simd_load(temp_buf2 as _);
+        let shifted = sr::<B>(next1, curr1, step);
+        B::simd_store(buf1.add(block_size - L) as _, shifted);
+        B::simd_store(buf2.add(block_size - L) as _, sr::<B>(next2, curr2, step));
         D_corner
     }
 
@@ -635,12 +923,12 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
     /// Inside this function, everything will be treated as shifting right,
     /// conceptually. The same process can be trivially used for shifting
     /// down by calling this function with different parameters.
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
+    ///
+    /// Linear gap costs (gap open == gap extend) use a specialized kernel.
     #[allow(non_snake_case)]
-    // Want this to be inlined in some places and not others, so let
-    // compiler decide.
-    unsafe fn place_block(&mut self,
+    #[allow(clippy::too_many_arguments)]
+    #[inline(always)]
+    unsafe fn place_block<const EXTRA: bool>(&mut self,
                           query: &PaddedBytes,
                           reference: &PaddedBytes,
                           start_i: usize,
@@ -651,109 +939,200 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
                           C_col: *mut i16,
                           D_row: *mut i16,
                           R_row: *mut i16,
-                          mut D_corner: Simd,
+                          D_corner: B::Simd,
                           right: bool,
-                          prefix_scan_consts: PrefixScanConsts,
-                          gap_extend_all: Simd) -> (Simd, Simd) {
+                          off: i32,
+                          prefix_scan_consts: B::PrefixScanConsts,
+                          gap_extend_all: B::Simd) -> (B::Simd, B::Simd) {
+        // want the kernel to be inlined in some places and not others, so it is called
+        // through a separate function that the compiler can decide whether to inline
+        if self.gaps.open == self.gaps.extend {
+            B::with_features(#[inline(always)] || self.place_block_gaps::<true, EXTRA>(query, reference, start_i, start_j, width, height, D_col, C_col, D_row, R_row, D_corner, right, off, prefix_scan_consts, gap_extend_all))
+        } else {
+            B::with_features(#[inline(always)] || self.place_block_gaps::<false, EXTRA>(query, reference, start_i, start_j, width, height, D_col, C_col, D_row, R_row, D_corner, right, off, prefix_scan_consts, gap_extend_all))
+        }
+    }
+
+    /// Place block with either linear (`LINEAR = true`) or affine gap costs.
+    ///
+    /// With linear gap costs, C11 is always D10 + gap extend, so the C and R values do not
+    /// need to be separately stored, and gaps can be opened from every cell.
+    ///
+    /// If `EXTRA` is false, then the checks for glocal alignment, terminal gaps, recording
+    /// the last row and column, tracking the max scoring cell without X-drop, and masking the
+    /// padding around substrings and before the start cell are compiled out.
+    #[allow(non_snake_case)]
+    #[allow(clippy::too_many_arguments)]
+    #[inline(always)]
+    unsafe fn place_block_gaps<const LINEAR: bool, const EXTRA: bool>(&mut self,
+                                                   query: &PaddedBytes,
+                                                   reference: &PaddedBytes,
+                                                   start_i: usize,
+                                                   start_j: usize,
+                                                   width: usize,
+                                                   height: usize,
+                                                   D_col: *mut i16,
+                                                   C_col: *mut i16,
+                                                   D_row: *mut i16,
+                                                   R_row: *mut i16,
+                                                   mut D_corner: B::Simd,
+                                                   right: bool,
+                                                   off: i32,
+                                                   prefix_scan_consts: B::PrefixScanConsts,
+                                                   gap_extend_all: B::Simd) -> (B::Simd, B::Simd) {
         let (gap_open, gap_extend) = self.get_const_simd();
-        let mut D_max = simd_set1_i16(MIN);
-        let mut D_argmax = simd_set1_i16(0);
-        let mut curr_i = simd_set1_i16(0);
+        let mut D_max = B::simd_set1_i16(MIN);
+        let mut D_argmax = B::simd_set1_i16(0);
+        let mut curr_i = B::simd_set1_i16(0);
 
         if width == 0 || height == 0 {
             return (D_max, D_argmax);
         }
 
+        #[cfg(feature = "stats")]
+        {
+            self.counters.blocks += 1;
+        }
+
+        // offsets of the start cell in the strings, which are swapped when shifting down
+        let (query_start, reference_start) = if right { self.start } else { (self.start.1, self.start.0) };
+
         // hottest loop in the whole program
         for j in 0..width {
-            let mut R01 = simd_set1_i16(MIN);
-            let mut D11 = simd_set1_i16(MIN);
-            let mut R11 = simd_set1_i16(MIN);
+            let mut R01 = B::simd_set1_i16(MIN);
+            let mut D11 = B::simd_set1_i16(MIN);
+            let mut R11 = B::simd_set1_i16(MIN);
 
-            let c = reference.get(start_j + j);
+            let c = reference.get(reference_start + start_j + j);
 
             let mut i = 0;
             while i < height {
                 #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), feature = "mca"))]
                 asm!("# LLVM-MCA-BEGIN place_block inner loop", options(nomem, nostack, preserves_flags));
 
-                let D10 = simd_load(D_col.add(i) as _);
-                let C10 = simd_load(C_col.add(i) as _);
-                let D00 = simd_sl_i16!(D10, D_corner, 1);
+                let D10 = B::simd_load(D_col.add(i) as _);
+                let D00 = B::simd_sl_i16::<1>(D10, D_corner);
                 D_corner = D10;
 
-                let scores = self.matrix.get_scores(c, halfsimd_loadu(query.as_ptr(start_i + i) as _), right);
-                D11 = simd_adds_i16(D00, scores);
-                if start_i + i == 0 && start_j + j == 0 {
-                    D11 = simd_insert_i16!(D11, ZERO, 0);
+                let (q_idx, r_idx) = (query_start + start_i + i, reference_start + start_j + j);
+                let mut scores = self.matrix.get_scores_at::<B>(c, B::halfsimd_loadu(query.as_ptr(q_idx) as _), right, r_idx, q_idx);
+                if EXTRA && (query.sliced || reference.sliced || query_start > 0 || reference_start > 0) {
+                    scores = Self::mask_padding(scores, query, reference, query_start, reference_start, q_idx, r_idx);
+                }
+                D11 = B::simd_adds_i16(D00, scores);
+                // for glocal alignment, the entire first row is free
+                if start_i + i == 0 && (start_j + j == 0 || (EXTRA && right && self.free_ref_ends)) {
+                    D11 = B::simd_insert_i16::<0>(D11, self.leading_gap(0, true, off));
                 }
 
-                let C11 = simd_max_i16(simd_adds_i16(C10, gap_extend), simd_adds_i16(D10, gap_open));
-                D11 = simd_max_i16(D11, C11);
+                let mut C11 = if LINEAR {
+                    B::simd_adds_i16(D10, gap_extend)
+                } else {
+                    let C10 = B::simd_load(C_col.add(i) as _);
+                    B::simd_max_i16(B::simd_adds_i16(C10, gap_extend), B::simd_adds_i16(D10, gap_open))
+                };
+                D11 = B::simd_max_i16(D11, C11);
                 // at this point, C11 is fully calculated and D11 is partially calculated
 
-                let D11_open = simd_adds_i16(D11, simd_subs_i16(gap_open, gap_extend));
-                R11 = simd_prefix_scan_i16(D11_open, prefix_scan_consts);
+                // leading terminal gaps along the first row (or column, if not right)
+                if EXTRA && self.leading_gaps() && start_i + i == 0 && start_j + j > 0 {
+                    D11 = B::simd_insert_i16::<0>(D11, self.leading_gap(start_j + j, right, off));
+                    C11 = B::simd_insert_i16::<0>(C11, MIN);
+                }
+
+                let D11_open = if LINEAR { D11 } else { B::simd_adds_i16(D11, B::simd_subs_i16(gap_open, gap_extend)) };
+                R11 = B::simd_prefix_scan_i16(D11_open, prefix_scan_consts);
                 // do prefix scan before using R01 to break up dependency chain that depends on
                 // the last element of R01 from the previous loop iteration
-                R11 = simd_max_i16(R11, simd_adds_i16(simd_broadcasthi_i16(R01), gap_extend_all));
+                R11 = B::simd_max_i16(R11, B::simd_adds_i16(B::simd_broadcasthi_i16(R01), gap_extend_all));
                 // fully calculate D11 using R11
-                D11 = simd_max_i16(D11, R11);
-                R01 = R11;
+                D11 = B::simd_max_i16(D11, R11);
+
+                // leading terminal gaps along the first column
+                if EXTRA && self.leading_gaps() && right && start_j + j == 0 {
+                    for k in 0..L {
+                        ptr::write(D_col.add(i + k), self.leading_gap(start_i + i + k, false, off));
+                    }
+                    D11 = B::simd_load(D_col.add(i) as _);
+                    C11 = B::simd_set1_i16(MIN);
+                    R11 = B::simd_set1_i16(MIN);
+                }
 
                 #[cfg(feature = "debug")]
-                {
-                    print!("s:   ");
-                    simd_dbg_i16(scores);
-                    print!("D00: ");
-                    simd_dbg_i16(simd_subs_i16(D00, simd_set1_i16(ZERO)));
-                    print!("C11: ");
-                    simd_dbg_i16(simd_subs_i16(C11, simd_set1_i16(ZERO)));
-                    print!("R11: ");
-                    simd_dbg_i16(simd_subs_i16(R11, simd_set1_i16(ZERO)));
-                    print!("D11: ");
-                    simd_dbg_i16(simd_subs_i16(D11, simd_set1_i16(ZERO)));
+                if log::log_enabled!(log::Level::Trace) {
+                    log::trace!("s:   {}", B::simd_fmt_i16(scores));
+                    log::trace!("D00: {}", B::simd_fmt_i16(B::simd_subs_i16(D00, B::simd_set1_i16(ZERO))));
+                    log::trace!("C11: {}", B::simd_fmt_i16(B::simd_subs_i16(C11, B::simd_set1_i16(ZERO))));
+                    log::trace!("R11: {}", B::simd_fmt_i16(B::simd_subs_i16(R11, B::simd_set1_i16(ZERO))));
+                    log::trace!("D11: {}", B::simd_fmt_i16(B::simd_subs_i16(D11, B::simd_set1_i16(ZERO))));
                 }
 
                 if TRACE {
-                    let trace_D_C = simd_cmpeq_i16(D11, C11);
-                    let trace_D_R = simd_cmpeq_i16(D11, R11);
+                    let trace_D_C = B::simd_cmpeq_i16(D11, C11);
+                    // the prefix scan includes gaps opened from the current cell, which
+                    // tie with D11 when gap open == gap extend, so shift to exclude them
+                    let R11_prev = B::simd_adds_i16(B::simd_sl_i16::<1>(R11, R01), gap_extend);
+                    let trace_D_R = B::simd_cmpeq_i16(D11, R11_prev);
                     #[cfg(feature = "debug")]
-                    {
-                        print!("D_C: ");
-                        simd_dbg_i16(trace_D_C);
-                        print!("D_R: ");
-                        simd_dbg_i16(trace_D_R);
+                    if log::log_enabled!(log::Level::Trace) {
+                        log::trace!("D_C: {}", B::simd_fmt_i16(trace_D_C));
+                        log::trace!("D_R: {}", B::simd_fmt_i16(trace_D_R));
                     }
                     // compress trace with movemask to save space
-                    let trace = simd_movemask_i8(simd_blend_i8(trace_D_C, trace_D_R, simd_set1_i16(0xFF00u16 as i16)));
-                    self.trace.add_trace(trace as TraceType);
+                    let mask = B::simd_set1_i16(0xFF00u16 as i16);
+                    let trace = B::simd_movemask_i8(B::simd_blend_i8(trace_D_C, trace_D_R, mask));
+                    // with linear gap costs, gaps can be opened from every cell, so this is
+                    // not needed
+                    if EXACT_GAPS && !LINEAR {
+                        // whether gaps should be opened (instead of extended) from the current cell,
+                        // which disambiguates gap open vs gap extend during traceback
+                        let D11_open_final = B::simd_adds_i16(D11, B::simd_subs_i16(gap_open, gap_extend));
+                        let trace_open_C = B::simd_cmpeq_i16(B::simd_max_i16(C11, D11_open_final), D11_open_final);
+                        let trace_open_R = B::simd_cmpeq_i16(B::simd_max_i16(R11, D11_open_final), D11_open_final);
+                        let trace_open = B::simd_movemask_i8(B::simd_blend_i8(trace_open_C, trace_open_R, mask));
+                        self.trace.add_trace_open::<B>(trace_open as TraceType);
+                    }
+                    self.trace.add_trace::<B>(trace as TraceType);
                 }
+                R01 = R11;
 
-                D_max = simd_max_i16(D_max, D11);
+                D_max = B::simd_max_i16(D_max, D11);
 
-                if X_DROP {
+                if X_DROP || (EXTRA && self.track_max) {
                     // keep track of the best score and its location
-                    let mask = simd_cmpeq_i16(D_max, D11);
-                    D_argmax = simd_blend_i8(D_argmax, curr_i, mask);
-                    curr_i = simd_adds_i16(curr_i, simd_set1_i16(1));
+                    let mask = B::simd_cmpeq_i16(D_max, D11);
+                    D_argmax = B::simd_blend_i8(D_argmax, curr_i, mask);
+                    curr_i = B::simd_adds_i16(curr_i, B::simd_set1_i16(1));
                 }
 
-                simd_store(D_col.add(i) as _, D11);
-                simd_store(C_col.add(i) as _, C11);
+                B::simd_store(D_col.add(i) as _, D11);
+                if !LINEAR {
+                    B::simd_store(C_col.add(i) as _, C11);
+                }
                 i += L;
 
                 #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), feature = "mca"))]
                 asm!("# LLVM-MCA-END", options(nomem, nostack, preserves_flags));
             }
 
-            D_corner = simd_set1_i16(MIN);
+            D_corner = B::simd_set1_i16(MIN);
+
+            #[cfg(feature = "stats")]
+            {
+                self.counters.cells += height as u64;
+            }
+
+            if EXTRA && self.track_ends() {
+                self.update_ends(start_i, start_j + j, height, D_col, right, off);
+            }
 
-            ptr::write(D_row.add(j), simd_extract_i16!(D11, L - 1));
-            ptr::write(R_row.add(j), simd_extract_i16!(R11, L - 1));
+            ptr::write(D_row.add(j), B::simd_extract_i16::<{ L - 1 }>(D11));
+            if !LINEAR {
+                ptr::write(R_row.add(j), B::simd_extract_i16::<{ L - 1 }>(R11));
+            }
 
-            if !X_DROP && start_i + height > query.len()
-                && start_j + j >= reference.len() {
+            if !X_DROP && query_start + start_i + height > query.len()
+                && reference_start + start_j + j >= reference.len() {
                 if TRACE {
                     // make sure that the trace index is updated since the rest of the loop
                     // iterations are skipped
@@ -766,212 +1145,835 @@ impl<'a, M: 'static + Matrix, const TRACE: bool, const X_DROP: bool> Block<'a, M
         (D_max, D_argmax)
     }
 
-    /// Get the resulting score and ending location of the alignment.
-    #[inline]
-    pub fn res(&self) -> AlignResult {
-        self.res
-    }
-
-    /// Get the trace of the alignment, assuming `TRACE` is true.
+    /// Number of steps without a new best score before the block grows.
     #[inline]
-    pub fn trace(&self) -> &Trace {
-        assert!(TRACE);
-        &self.trace
+    fn grow_iter(&self, block_size: usize, step: usize) -> usize {
+        cmp::max((((block_size / step) as f32) * self.grow_threshold) as usize, 1)
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
+    /// Length of the query after the start cell.
     #[inline]
-    unsafe fn get_const_simd(&self) -> (Simd, Simd) {
-        // some useful constant simd vectors
-        let gap_open = simd_set1_i16(self.gaps.open as i16);
-        let gap_extend = simd_set1_i16(self.gaps.extend as i16);
-        (gap_open, gap_extend)
+    fn query_len(&self) -> usize {
+        self.query.len() - self.start.0
     }
-}
-
-/// Holds the trace generated by block aligner.
-#[derive(Clone)]
-pub struct Trace {
-    trace: Vec<TraceType>,
-    right: Vec<u64>,
-    block_start: Vec<u32>,
-    block_size: Vec<u16>,
-    trace_idx: usize,
-    block_idx: usize,
-    ckpt_trace_idx: usize,
-    ckpt_block_idx: usize,
-    query_len: usize,
-    reference_len: usize
-}
 
-impl Trace {
+    /// Length of the reference after the start cell.
     #[inline]
-    fn new(query_len: usize, reference_len: usize) -> Self {
-        let len = query_len + reference_len;
-        let trace = Vec::new();
-        let right = vec![0u64; div_ceil(len, 64)];
-        let block_start = vec![0u32; len * 2];
-        let block_size = vec![0u16; len * 2];
-
-        Self {
-            trace,
-            right,
-            block_start,
-            block_size,
-            trace_idx: 0,
-            block_idx: 0,
-            ckpt_trace_idx: 0,
-            ckpt_block_idx: 0,
-            query_len,
-            reference_len
-        }
+    fn reference_len(&self) -> usize {
+        self.reference.len() - self.start.1
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
+    /// Whether the max score in the last row or column must be tracked.
     #[inline]
-    unsafe fn add_trace(&mut self, t: TraceType) {
-        debug_assert!(self.trace_idx < self.trace.len());
-        store_trace(self.trace.as_mut_ptr().add(self.trace_idx), t);
-        self.trace_idx += 1;
+    fn track_ends(&self) -> bool {
+        self.free_ref_ends || self.terminal_gaps.is_some() || self.record_ends
     }
 
+    /// Cost of a terminal gap of a certain length.
     #[inline]
-    fn add_block(&mut self, i: usize, j: usize, width: usize, height: usize, right: bool) {
-        debug_assert!(self.block_idx * 2 < self.block_start.len());
-        unsafe {
-            *self.block_start.as_mut_ptr().add(self.block_idx * 2) = i as u32;
-            *self.block_start.as_mut_ptr().add(self.block_idx * 2 + 1) = j as u32;
-            *self.block_size.as_mut_ptr().add(self.block_idx * 2) = height as u16;
-            *self.block_size.as_mut_ptr().add(self.block_idx * 2 + 1) = width as u16;
-
-            let a = self.block_idx / 64;
-            let b = self.block_idx % 64;
-            let v = *self.right.as_ptr().add(a) & !(1 << b); // clear bit
-            *self.right.as_mut_ptr().add(a) = v | ((right as u64) << b);
-
-            self.block_idx += 1;
+    fn terminal_gap_cost(&self, len: usize) -> i32 {
+        match self.terminal_gaps {
+            Some(g) if len > 0 => (g.open as i32) + (g.extend as i32) * ((len as i32) - 1),
+            _ => 0
         }
     }
 
-    /// This must be used before adding new traces to make sure the trace array is large enough.
+    /// Whether the gaps along the first row and column have custom costs.
     #[inline]
-    fn resize_trace(&mut self, i: usize, j: usize, q_len: usize, r_len: usize, block_size: usize) {
-        self.trace.resize(self.trace_idx + (block_size / L) * (q_len + block_size - i + r_len + block_size - j), 0 as TraceType);
+    fn leading_gaps(&self) -> bool {
+        self.terminal_gaps.is_some() || self.start_gap.is_some()
     }
 
+    /// Cost of a gap of a certain length along the first row (`ref_gap` is true) or the
+    /// first column.
+    ///
+    /// If the DP starts in a gap state, then a gap of the same type is only extended.
     #[inline]
-    fn add_trace_idx(&mut self, add: usize) {
-        self.trace_idx += add;
+    fn leading_gap_cost(&self, len: usize, ref_gap: bool) -> i32 {
+        let gap_op = if ref_gap { Operation::D } else { Operation::I };
+        match (self.terminal_gaps, self.start_gap) {
+            (Some(_), _) => self.terminal_gap_cost(len),
+            _ if len == 0 => 0,
+            (None, Some(op)) if op == gap_op => (self.gaps.extend as i32) * (len as i32),
+            _ => (self.gaps.open as i32) + (self.gaps.extend as i32) * ((len as i32) - 1)
+        }
     }
 
+    /// Score of a cell in the first row or column, relative to the current offset.
     #[inline]
-    fn save_ckpt(&mut self) {
-        self.ckpt_trace_idx = self.trace_idx;
-        self.ckpt_block_idx = self.block_idx;
+    fn leading_gap(&self, len: usize, ref_gap: bool, off: i32) -> i16 {
+        cmp::max(clamp((ZERO as i32) - off + self.leading_gap_cost(len, ref_gap)), MIN)
     }
 
-    /// The trace data structure is like a stack, so all trace values and blocks after the
-    /// checkpoint is essentially popped off the stack.
+    /// Cost of a trailing gap of a certain length, or `None` if it is not allowed.
     #[inline]
-    fn restore_ckpt(&mut self) {
-        unsafe { self.trace.set_len(self.ckpt_trace_idx); }
-        self.trace_idx = self.ckpt_trace_idx;
-        self.block_idx = self.ckpt_block_idx;
+    fn trailing_gap(&self, len: usize, ref_gap: bool) -> Option<i32> {
+        if len == 0 || self.terminal_gaps.is_some() {
+            Some(self.terminal_gap_cost(len))
+        } else if self.free_ref_ends && ref_gap {
+            Some(0)
+        } else {
+            None
+        }
     }
 
-    /// Create a CIGAR string that represents a single traceback path ending on the specified
-    /// location.
-    pub fn cigar(&self, mut i: usize, mut j: usize) -> Cigar {
-        assert!(i <= self.query_len && j <= self.reference_len, "Traceback cigar end position must be in bounds!");
-
-        unsafe {
-            let mut res = Cigar::new(i + j + 5);
-            let mut block_idx = self.block_idx;
-            let mut trace_idx = self.trace_idx;
-            let mut block_i;
-            let mut block_j;
-            let mut block_width;
-            let mut block_height;
-            let mut right;
+    /// Keep track of the max score in the last row and column of the DP matrix, including
+    /// the cost of the trailing gap, for glocal alignment and terminal gaps. The scores are
+    /// also recorded for `AlignOptions::end_scores`.
+    ///
+    /// `col` is the newly calculated column (or row, if `right` is false) at index `j`.
+    #[inline]
+    unsafe fn update_ends(&mut self, start_i: usize, j: usize, height: usize, col: *const i16, right: bool, off: i32) {
+        let (q_len, r_len) = (self.query_len(), self.reference_len());
+        // lanes are along the query when shifting right, and along the reference otherwise
+        let (lane_end, col_end) = if right { (q_len, r_len) } else { (r_len, q_len) };
 
-            // use lookup table instead of hard to predict branches
-            static OP_LUT: [(Operation, usize, usize); 8] = [
-                (Operation::M, 1, 1), // 0b000
-                (Operation::I, 1, 0), // 0b001
-                (Operation::D, 0, 1), // 0b010
-                (Operation::I, 1, 0), // 0b011, bias towards i -= 1 to avoid going out of bounds
-                (Operation::M, 1, 1), // 0b100
-                (Operation::D, 0, 1), // 0b101
-                (Operation::I, 1, 0), // 0b110
-                (Operation::D, 0, 1) // 0b111, bias towards j -= 1 to avoid going out of bounds
-            ];
-
-            while i > 0 || j > 0 {
-                loop {
-                    block_idx -= 1;
-                    block_i = *self.block_start.as_ptr().add(block_idx * 2) as usize;
-                    block_j = *self.block_start.as_ptr().add(block_idx * 2 + 1) as usize;
-                    block_height = *self.block_size.as_ptr().add(block_idx * 2) as usize;
-                    block_width = *self.block_size.as_ptr().add(block_idx * 2 + 1) as usize;
-                    trace_idx -= block_width * block_height / L;
+        if j > col_end {
+            return;
+        }
 
-                    if i >= block_i && j >= block_j {
-                        right = (((*self.right.as_ptr().add(block_idx / 64) >> (block_idx % 64)) & 0b1) << 2) as usize;
-                        break;
-                    }
-                }
+        if start_i <= lane_end && lane_end < start_i + height {
+            let cost = self.trailing_gap(col_end - j, right);
+            self.update_end_max(*col.add(lane_end - start_i), cost, lane_end, j, right, off);
+        }
 
-                if right > 0 {
-                    while i >= block_i && j >= block_j && (i > 0 || j > 0) {
-                        let curr_i = i - block_i;
-                        let curr_j = j - block_j;
-                        let idx = trace_idx + curr_i / L + curr_j * (block_height / L);
-                        let t = ((*self.trace.as_ptr().add(idx) >> ((curr_i % L) * 2)) & 0b11) as usize;
-                        let lut_idx = right | t;
-                        let op = OP_LUT[lut_idx].0;
-                        i -= OP_LUT[lut_idx].1;
-                        j -= OP_LUT[lut_idx].2;
-                        res.add(op);
-                    }
-                } else {
-                    while i >= block_i && j >= block_j && (i > 0 || j > 0) {
-                        let curr_i = i - block_i;
-                        let curr_j = j - block_j;
-                        let idx = trace_idx + curr_j / L + curr_i * (block_width / L);
-                        let t = ((*self.trace.as_ptr().add(idx) >> ((curr_j % L) * 2)) & 0b11) as usize;
-                        let lut_idx = right | t;
-                        let op = OP_LUT[lut_idx].0;
-                        i -= OP_LUT[lut_idx].1;
-                        j -= OP_LUT[lut_idx].2;
-                        res.add(op);
-                    }
-                }
+        if j == col_end {
+            let end = cmp::min(height, (lane_end + 1).saturating_sub(start_i));
+            for k in 0..end {
+                let cost = self.trailing_gap(lane_end - (start_i + k), !right);
+                self.update_end_max(*col.add(k), cost, start_i + k, j, right, off);
             }
-
-            res
         }
     }
 
-    /// Return all of the rectangular regions that were calculated separately as
-    /// block aligner shifts and grows.
-    pub fn blocks(&self) -> Vec<Rectangle> {
-        let mut res = Vec::with_capacity(self.block_idx);
-
-        for i in 0..self.block_idx {
-            unsafe {
-                res.push(Rectangle {
-                    row: *self.block_start.as_ptr().add(i * 2) as usize,
-                    col: *self.block_start.as_ptr().add(i * 2 + 1) as usize,
-                    height: *self.block_size.as_ptr().add(i * 2) as usize,
-                    width: *self.block_size.as_ptr().add(i * 2 + 1) as usize
-                });
+    #[inline]
+    fn update_end_max(&mut self, v: i16, cost: Option<i32>, lane_idx: usize, col_idx: usize, right: bool, off: i32) {
+        if v <= MIN {
+            return;
+        }
+        let (i, j) = if right { (lane_idx, col_idx) } else { (col_idx, lane_idx) };
+
+        if self.record_ends {
+            // later blocks overwrite scores from blocks that were discarded
+            let score = off + (v as i32) - (ZERO as i32);
+            if i == self.query_len() {
+                self.end_row_scores[j] = score;
+            }
+            if j == self.reference_len() {
+                self.end_col_scores[i] = score;
             }
         }
 
-        res
-    }
+        let v = match cost {
+            Some(cost) => (v as i32) + cost,
+            None => return
+        };
+        if v > self.end_max {
+            self.end_max = v;
+            self.end_argmax = (i, j);
+        }
+    }
+
+    /// Get the resulting score and ending location of the alignment.
+    #[inline]
+    pub fn res(&self) -> AlignResult {
+        self.shift(self.res)
+    }
+
+    /// Get the score and location of the max scoring cell that was computed, assuming
+    /// `X_DROP` is true or `AlignOptions::track_max` was used.
+    ///
+    /// For X-drop alignment, this is the same as `res`.
+    #[inline]
+    pub fn max_res(&self) -> AlignResult {
+        assert!(X_DROP || self.track_max, "The max scoring cell is only tracked for X-drop alignment or with AlignOptions::track_max!");
+        self.shift(self.max_res)
+    }
+
+    /// Get the best suboptimal alignment end outside of the neighborhood of the max scoring
+    /// cell, assuming `AlignOptions::suboptimal` was used.
+    ///
+    /// Returns `None` if every cell that was computed is in the neighborhood.
+    #[inline]
+    pub fn suboptimal_res(&self) -> Option<AlignResult> {
+        assert!(self.suboptimal_radius.is_some(), "Suboptimal alignments are only tracked with AlignOptions::suboptimal!");
+        self.suboptimal_res.map(|res| self.shift(res))
+    }
+
+    /// Convert a result relative to the start cell to the coordinates of the original strings.
+    #[inline]
+    fn shift(&self, res: AlignResult) -> AlignResult {
+        AlignResult { score: res.score, query_idx: res.query_idx + self.start.0, reference_idx: res.reference_idx + self.start.1 }
+    }
+
+    /// Get the running best score after each step, assuming `AlignOptions::best_scores` was used.
+    ///
+    /// Each entry is the last query position reached by the block in a step and the best
+    /// score of any cell computed up to and including that step. The entries are in the order
+    /// that the steps were computed, so the query positions are usually (but not always)
+    /// nondecreasing, since the block can return to a previous position when it grows.
+    pub fn best_scores(&self) -> &[(usize, i32)] {
+        assert!(self.best_scores.is_some(), "Best scores are only recorded with AlignOptions::best_scores!");
+        self.best_scores.as_ref().unwrap()
+    }
+
+    /// Iterate over the steps that the block took, assuming `AlignOptions::steps` was used.
+    ///
+    /// Steps are in the order that they were computed, including steps that were discarded
+    /// when the block returned to a previous checkpoint to grow.
+    pub fn steps(&self) -> impl Iterator<Item = Step> + '_ {
+        assert!(self.steps.is_some(), "Steps are only recorded with AlignOptions::steps!");
+        self.steps.as_ref().unwrap().iter().copied()
+    }
+
+    /// Get the difference between the max score and the best suboptimal score, assuming
+    /// `AlignOptions::suboptimal` was used.
+    ///
+    /// Returns `None` if there is no suboptimal alignment end.
+    #[inline]
+    pub fn score_gap(&self) -> Option<i32> {
+        self.suboptimal_res().map(|r| self.max_res.score - r.score)
+    }
+
+    /// Get the scores of aligning the full query against every prefix of the reference,
+    /// indexed by the prefix length, assuming `AlignOptions::end_scores` was used.
+    ///
+    /// Prefixes that were never reached by the block have a score of `i32::MIN`.
+    #[inline]
+    pub fn end_row_scores(&self) -> &[i32] {
+        assert!(self.record_ends, "End scores are only recorded with AlignOptions::end_scores!");
+        &self.end_row_scores
+    }
+
+    /// Get the scores of aligning the full reference against every prefix of the query,
+    /// indexed by the prefix length, assuming `AlignOptions::end_scores` was used.
+    ///
+    /// Prefixes that were never reached by the block have a score of `i32::MIN`.
+    #[inline]
+    pub fn end_col_scores(&self) -> &[i32] {
+        assert!(self.record_ends, "End scores are only recorded with AlignOptions::end_scores!");
+        &self.end_col_scores
+    }
+
+    /// Get statistics about the path that the block took while shifting and growing.
+    ///
+    /// This is available even if `TRACE` is false, and it is useful for tuning
+    /// the min and max block sizes.
+    #[inline]
+    pub fn path_stats(&self) -> PathStats {
+        self.path_stats
+    }
+
+    /// Get the fraction of the query and the reference that is covered by the alignment.
+    ///
+    /// The covered region is from the start of the alignment to its end in `res`. Since
+    /// alignments start at the beginning of both strings, this only requires `TRACE` to be
+    /// true for glocal alignment, where the start in the reference is found with the traceback.
+    pub fn coverage(&self) -> Coverage {
+        let res = self.res;
+        let start_j = if self.free_ref_ends {
+            assert!(TRACE, "Coverage of glocal alignment requires traceback!");
+            self.trace.traceback_start(res.query_idx, res.reference_idx).1
+        } else {
+            0
+        };
+        let frac = |len: usize, total: usize| if total == 0 { 1.0 } else { (len as f32) / (total as f32) };
+        Coverage {
+            query: frac(res.query_idx, self.query_len()),
+            reference: frac(res.reference_idx - start_j, self.reference_len())
+        }
+    }
+
+    /// Get flags that describe potential accuracy problems with the alignment.
+    ///
+    /// This can be used to decide whether to rerun the alignment with different settings,
+    /// like a larger max block size or X-drop threshold.
+    #[inline]
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics
+    }
+
+    /// Get the instrumentation counters that measure how much work was done.
+    ///
+    /// Only available with the `stats` feature.
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    /// Get the DP scores on the right and bottom borders of the block after each step,
+    /// for visualizing what block aligner computed.
+    ///
+    /// Only available with the `block_values` feature.
+    #[cfg(feature = "block_values")]
+    #[inline]
+    pub fn block_values(&self) -> &[BlockValues] {
+        &self.block_values
+    }
+
+    #[cfg(feature = "block_values")]
+    #[allow(non_snake_case)]
+    fn record_block_values(&mut self, block_size: usize, off: i32, D_col: &Aligned, D_row: &Aligned) {
+        // only keep the cells that are within the DP matrix
+        let (i, j) = (self.i, self.j);
+        let height = cmp::min(block_size, self.query_len() + 1 - cmp::min(i, self.query_len() + 1));
+        let width = cmp::min(block_size, self.reference_len() + 1 - cmp::min(j, self.reference_len() + 1));
+        let score = |d: i16| if d == MIN { i32::MIN } else { off + (d as i32) - (ZERO as i32) };
+        let right = if width == block_size { (0..height).map(|k| score(D_col.get(k))).collect() } else { Vec::new() };
+        let bottom = if height == block_size { (0..width).map(|k| score(D_row.get(k))).collect() } else { Vec::new() };
+        self.block_values.push(BlockValues {
+            block: Rectangle { row: i, col: j, width, height },
+            right,
+            bottom
+        });
+    }
+
+    /// Give the trace back to the allocator that was used for the alignment, so the memory
+    /// of its buffers can be reused by a later alignment.
+    ///
+    /// See `BufferAllocator::put_trace`. If the block aligner is just dropped, then the trace
+    /// is freed instead.
+    pub fn recycle(self) {
+        if TRACE {
+            self.allocator.put_trace(self.trace);
+        }
+    }
+
+    /// Get the trace of the alignment, assuming `TRACE` is true.
+    #[inline]
+    pub fn trace(&self) -> &Trace {
+        assert!(TRACE);
+        &self.trace
+    }
+
+    /// Get the CIGAR string of the alignment ending at `res`, along with the number of
+    /// exact matches, assuming `TRACE` is true.
+    ///
+    /// The matches are counted during the traceback, so this avoids another pass over
+    /// the CIGAR string and the strings (for example, for PAF's number of matching bases
+    /// or for computing identity). Bytes are compared after they are converted by the matrix.
+    pub fn cigar_with_matches(&self) -> (Cigar, usize) {
+        assert!(TRACE, "Counting matches requires traceback!");
+        let res = self.res;
+        let (cigar, _, _, matches) = self.trace.traceback_matches(res.query_idx, res.reference_idx, Some((self.query, self.reference)));
+        (cigar, matches)
+    }
+
+    /// Split the score of the alignment ending at `res` into the total contribution from
+    /// substitutions and the total contribution from gaps, assuming `TRACE` is true.
+    ///
+    /// This is useful for diagnosing the choice of scoring parameters.
+    pub fn score_breakdown(&self) -> ScoreBreakdown {
+        assert!(TRACE, "Score breakdown requires traceback!");
+        let res = self.res;
+        let (cigar, mut i, mut j) = self.trace.traceback(res.query_idx, res.reference_idx);
+        let mut substitution = 0;
+        for op_len in cigar.to_vec() {
+            match op_len.op {
+                Operation::M => {
+                    for _ in 0..op_len.len {
+                        i += 1;
+                        j += 1;
+                        substitution += unsafe { self.pair_score(i, j) };
+                    }
+                },
+                Operation::I => i += op_len.len,
+                _ => j += op_len.len
+            }
+        }
+        // the rest of the score includes terminal gap costs, if any
+        ScoreBreakdown { substitution, gap: res.score - substitution }
+    }
+
+    /// Get the cumulative score along the traceback path of the alignment ending at `res`,
+    /// assuming `TRACE` is true.
+    ///
+    /// This is useful for finding low scoring regions of an alignment, like the junction
+    /// of a chimeric read or bad ends that should be trimmed. See `ScoreProfile::lowest_window`.
+    /// A trailing terminal gap is not included, since it is not part of the traceback path.
+    pub fn score_profile(&self) -> ScoreProfile {
+        assert!(TRACE, "Score profile requires traceback!");
+        let res = self.res;
+        let (cigar, mut i, mut j) = self.trace.traceback(res.query_idx, res.reference_idx);
+        let start = (i + self.start.0, j + self.start.1);
+        let mut cumulative = Vec::with_capacity((res.query_idx - i) + (res.reference_idx - j));
+        let mut score = 0;
+        for op_len in cigar.to_vec() {
+            match op_len.op {
+                Operation::M => {
+                    for _ in 0..op_len.len {
+                        i += 1;
+                        j += 1;
+                        score += unsafe { self.pair_score(i, j) };
+                        cumulative.push(score);
+                    }
+                },
+                op => {
+                    // leading gaps that touch the start of either string are terminal gaps
+                    let gaps = match self.terminal_gaps {
+                        Some(g) if i == 0 || j == 0 => g,
+                        _ => self.gaps
+                    };
+                    // a gap that continues the gap state at the start cell is only extended
+                    let open = if i == 0 && j == 0 && self.start_gap == Some(op) { gaps.extend } else { gaps.open };
+                    for k in 0..op_len.len {
+                        score += if k == 0 { open as i32 } else { gaps.extend as i32 };
+                        cumulative.push(score);
+                    }
+                    if op == Operation::I { i += op_len.len; } else { j += op_len.len; }
+                }
+            }
+        }
+        ScoreProfile { start, cumulative }
+    }
+
+    /// Score of a single pair of 1-based padded query and reference positions.
+    unsafe fn pair_score(&self, i: usize, j: usize) -> i32 {
+        // when shifting right, the first lane is the query byte at `i`
+        let (i, j) = (self.start.0 + i, self.start.1 + j);
+        B::with_features(#[inline(always)] || {
+            let scores = self.matrix.get_scores_at::<B>(self.reference.get(j), B::halfsimd_loadu(self.query.as_ptr(i) as _), true, j, i);
+            B::simd_slow_extract_i16(scores, 0) as i32
+        })
+    }
+
+    /// Get the number of unaligned reference bases before and after the aligned
+    /// region, assuming `TRACE` is true and glocal alignment was used.
+    pub fn ref_flanks(&self) -> (usize, usize) {
+        assert!(TRACE && self.free_ref_ends, "Reference flanks require traceback and glocal alignment!");
+        let res = self.res;
+        let (_, start_j) = self.trace.traceback_start(res.query_idx, res.reference_idx);
+        (start_j, self.reference_len() - res.reference_idx)
+    }
+
+    /// Replace the scores of positions that are outside of substrings created with
+    /// `PaddedBytes::slice`, or at or before the start cell, with the score of padding bytes.
+    #[inline(always)]
+    unsafe fn mask_padding(scores: B::Simd, query: &PaddedBytes, reference: &PaddedBytes, query_start: usize, reference_start: usize, i: usize, j: usize) -> B::Simd {
+        let null_scores = B::simd_set1_i16(i8::MIN as i16);
+        if j == reference_start || reference.is_padding(j) {
+            return null_scores;
+        }
+        if i > query_start && i + L <= query.len() + 1 {
+            return scores;
+        }
+        let mask = position_scores::<B, _>(true, j, i, |k, _| -((k == query_start || query.is_padding(k)) as i16));
+        B::simd_blend_i8(scores, null_scores, mask)
+    }
+
+    #[inline(always)]
+    unsafe fn get_const_simd(&self) -> (B::Simd, B::Simd) {
+        // some useful constant simd vectors
+        let gap_open = B::simd_set1_i16(self.gaps.open as i16);
+        let gap_extend = B::simd_set1_i16(self.gaps.extend as i16);
+        (gap_open, gap_extend)
+    }
+}
+
+impl<'a, M: 'static + Matrix> Block<'a, M, false, false> {
+    /// Compute only the score of a global alignment, with a fixed and small block size.
+    ///
+    /// This is the same as `align` with the min and max block sizes both set to `block_size`,
+    /// so the block never grows and the score is only accurate when the alignment stays
+    /// within a narrow band. It is meant to be used as a prefilter: its score can be used
+    /// to decide whether the full adaptive alignment is worth running.
+    ///
+    /// `block_size` must be a power of two, and it is clamped to be at least the number of
+    /// 16-bit lanes in a SIMD vector.
+    pub fn quick_score(query: &'a PaddedBytes, reference: &'a PaddedBytes, matrix: &'a M, gaps: Gaps, block_size: usize) -> i32 {
+        assert!(block_size.is_power_of_two(), "Block size for quick_score must be a power of two!");
+        Self::align(query, reference, matrix, gaps, block_size..=block_size, 0).res.score
+    }
+}
+
+/// Holds the trace generated by block aligner.
+#[derive(Clone)]
+pub struct Trace {
+    trace: Vec<TraceType>,
+    // whether gaps are opened from a cell, to disambiguate between gap open and gap extend
+    // (only stored if `exact_gaps` is true)
+    trace_open: Vec<TraceType>,
+    exact_gaps: bool,
+    right: Vec<u64>,
+    block_start: Vec<u32>,
+    block_size: Vec<u16>,
+    block_max: Vec<i32>,
+    trace_idx: usize,
+    block_idx: usize,
+    ckpt_trace_idx: usize,
+    ckpt_block_idx: usize,
+    // number of blocks at each checkpoint that was not discarded
+    #[cfg(feature = "json")]
+    ckpt_blocks: Vec<usize>,
+    query_len: usize,
+    reference_len: usize,
+    free_ref_start: bool,
+    // start cell of the alignment in the original strings
+    start: (usize, usize)
+}
+
+impl Trace {
+    #[inline]
+    fn new(query_len: usize, reference_len: usize, exact_gaps: bool) -> Self {
+        let len = query_len + reference_len;
+        let trace = Vec::new();
+        let trace_open = Vec::new();
+        let right = vec![0u64; div_ceil(len, 64)];
+        let block_start = vec![0u32; len * 2];
+        let block_size = vec![0u16; len * 2];
+        let block_max = vec![0i32; len];
+
+        Self {
+            trace,
+            trace_open,
+            exact_gaps,
+            right,
+            block_start,
+            block_size,
+            block_max,
+            trace_idx: 0,
+            block_idx: 0,
+            ckpt_trace_idx: 0,
+            ckpt_block_idx: 0,
+            #[cfg(feature = "json")]
+            ckpt_blocks: Vec::new(),
+            query_len,
+            reference_len,
+            free_ref_start: false,
+            start: (0, 0)
+        }
+    }
+
+    /// Reset the trace for a new alignment, like `new`, but reuse the memory of its buffers.
+    fn reset(&mut self, query_len: usize, reference_len: usize, exact_gaps: bool) {
+        let len = query_len + reference_len;
+        self.trace.clear();
+        self.trace_open.clear();
+        self.exact_gaps = exact_gaps;
+        self.right.clear();
+        self.right.resize(div_ceil(len, 64), 0);
+        self.block_start.clear();
+        self.block_start.resize(len * 2, 0);
+        self.block_size.clear();
+        self.block_size.resize(len * 2, 0);
+        self.block_max.clear();
+        self.block_max.resize(len, 0);
+        self.trace_idx = 0;
+        self.block_idx = 0;
+        self.ckpt_trace_idx = 0;
+        self.ckpt_block_idx = 0;
+        #[cfg(feature = "json")]
+        self.ckpt_blocks.clear();
+        self.query_len = query_len;
+        self.reference_len = reference_len;
+        self.free_ref_start = false;
+        self.start = (0, 0);
+    }
+
+    #[inline(always)]
+    unsafe fn add_trace<B: SimdBackend>(&mut self, t: TraceType) {
+        debug_assert!(self.trace_idx < self.trace.len());
+        B::store_trace(self.trace.as_mut_ptr().add(self.trace_idx), t);
+        self.trace_idx += 1;
+    }
+
+    /// Add the gap open bits for the next trace value. This must be used before `add_trace`.
+    #[inline(always)]
+    unsafe fn add_trace_open<B: SimdBackend>(&mut self, t_open: TraceType) {
+        debug_assert!(self.trace_idx < self.trace_open.len());
+        B::store_trace(self.trace_open.as_mut_ptr().add(self.trace_idx), t_open);
+    }
+
+    #[inline]
+    fn add_block(&mut self, i: usize, j: usize, width: usize, height: usize, right: bool) {
+        debug_assert!(self.block_idx * 2 < self.block_start.len());
+        unsafe {
+            *self.block_start.as_mut_ptr().add(self.block_idx * 2) = i as u32;
+            *self.block_start.as_mut_ptr().add(self.block_idx * 2 + 1) = j as u32;
+            *self.block_size.as_mut_ptr().add(self.block_idx * 2) = height as u16;
+            *self.block_size.as_mut_ptr().add(self.block_idx * 2 + 1) = width as u16;
+
+            let a = self.block_idx / 64;
+            let b = self.block_idx % 64;
+            let v = *self.right.as_ptr().add(a) & !(1 << b); // clear bit
+            *self.right.as_mut_ptr().add(a) = v | ((right as u64) << b);
+
+            self.block_idx += 1;
+        }
+    }
+
+    /// Set the max score of the latest block that was added.
+    #[inline]
+    fn set_block_max(&mut self, max: i32) {
+        debug_assert!(self.block_idx > 0 && self.block_idx - 1 < self.block_max.len());
+        unsafe { *self.block_max.as_mut_ptr().add(self.block_idx - 1) = max; }
+    }
+
+    /// This must be used before adding new traces to make sure the trace array is large enough.
+    #[inline]
+    fn resize_trace(&mut self, i: usize, j: usize, q_len: usize, r_len: usize, block_size: usize) {
+        let len = self.trace_idx + (block_size / L) * (q_len + block_size - i + r_len + block_size - j);
+        self.trace.resize(len, 0 as TraceType);
+        if self.exact_gaps {
+            self.trace_open.resize(len, 0 as TraceType);
+        }
+    }
+
+    #[inline]
+    fn add_trace_idx(&mut self, add: usize) {
+        self.trace_idx += add;
+    }
+
+    #[inline]
+    fn save_ckpt(&mut self) {
+        self.ckpt_trace_idx = self.trace_idx;
+        self.ckpt_block_idx = self.block_idx;
+        #[cfg(feature = "json")]
+        if self.ckpt_blocks.last() != Some(&self.block_idx) {
+            self.ckpt_blocks.push(self.block_idx);
+        }
+    }
+
+    /// The trace data structure is like a stack, so all trace values and blocks after the
+    /// checkpoint is essentially popped off the stack.
+    #[inline]
+    fn restore_ckpt(&mut self) {
+        unsafe {
+            self.trace.set_len(self.ckpt_trace_idx);
+            if self.exact_gaps {
+                self.trace_open.set_len(self.ckpt_trace_idx);
+            }
+        }
+        self.trace_idx = self.ckpt_trace_idx;
+        self.block_idx = self.ckpt_block_idx;
+        #[cfg(feature = "json")]
+        {
+            let len = self.ckpt_blocks.partition_point(|&b| b <= self.block_idx);
+            self.ckpt_blocks.truncate(len);
+        }
+    }
+
+    /// Create a CIGAR string that represents a single traceback path ending on the specified
+    /// location.
+    ///
+    /// For glocal alignment, the traceback stops once the start of the query is reached.
+    pub fn cigar(&self, i: usize, j: usize) -> Cigar {
+        self.shifted_traceback(i, j).0
+    }
+
+    /// Write the CIGAR string of the traceback path ending on the specified location into
+    /// an existing `Cigar`, like `cigar`.
+    ///
+    /// The memory of the existing `Cigar` is reused, which avoids an allocation for every
+    /// alignment when tracing back many alignments in a loop.
+    pub fn cigar_into(&self, i: usize, j: usize, cigar: &mut Cigar) {
+        assert!(i >= self.start.0 && j >= self.start.1, "Traceback cigar end position must be after the start!");
+        self.traceback_into(i - self.start.0, j - self.start.1, None, cigar);
+    }
+
+    /// Create a CIGAR string like `cigar`, but with soft clip operations added for the
+    /// unaligned query prefix and suffix.
+    ///
+    /// This is useful for X-drop alignments that terminate early, since the resulting
+    /// CIGAR string covers the full query length.
+    pub fn cigar_soft_clipped(&self, i: usize, j: usize) -> Cigar {
+        let (mut cigar, start_i, _) = self.shifted_traceback(i, j);
+        cigar.prepend_clip(Operation::S, start_i);
+        cigar.append_clip(Operation::S, self.start.0 + self.query_len - i);
+        cigar
+    }
+
+    /// Iterate over the aligned pairs of 0-based query and reference positions for the
+    /// traceback path ending on the specified location.
+    ///
+    /// See `Cigar::aligned_pairs` for more details.
+    pub fn aligned_pairs(&self, i: usize, j: usize) -> AlignedPairs {
+        let (cigar, start_i, start_j) = self.shifted_traceback(i, j);
+        cigar.aligned_pairs(start_i, start_j)
+    }
+
+    /// Get the location where the traceback path ending on the specified location starts,
+    /// in the coordinates of the original strings.
+    ///
+    /// This is the position of the first operation in the CIGAR string that is not a clip.
+    pub fn path_start(&self, i: usize, j: usize) -> (usize, usize) {
+        let (_, start_i, start_j) = self.shifted_traceback(i, j);
+        (start_i, start_j)
+    }
+
+    /// Traceback with positions in the coordinates of the original strings.
+    fn shifted_traceback(&self, i: usize, j: usize) -> (Cigar, usize, usize) {
+        assert!(i >= self.start.0 && j >= self.start.1, "Traceback cigar end position must be after the start!");
+        let (cigar, start_i, start_j) = self.traceback(i - self.start.0, j - self.start.1);
+        (cigar, start_i + self.start.0, start_j + self.start.1)
+    }
+
+    /// Get the location where the traceback path ending on the specified location starts.
+    pub(crate) fn traceback_start(&self, i: usize, j: usize) -> (usize, usize) {
+        let (_, i, j) = self.traceback(i, j);
+        (i, j)
+    }
+
+    fn traceback(&self, i: usize, j: usize) -> (Cigar, usize, usize) {
+        let (cigar, i, j, _) = self.traceback_matches(i, j, None);
+        (cigar, i, j)
+    }
+
+    /// Traceback that also counts the number of exact matches, if the strings are provided.
+    fn traceback_matches(&self, i: usize, j: usize, strs: Option<(&PaddedBytes, &PaddedBytes)>) -> (Cigar, usize, usize, usize) {
+        let mut res = Cigar::default();
+        let (i, j, matches) = self.traceback_into(i, j, strs, &mut res);
+        (res, i, j, matches)
+    }
+
+    /// Traceback into an existing CIGAR string, which is cleared first.
+    fn traceback_into(&self, mut i: usize, mut j: usize, strs: Option<(&PaddedBytes, &PaddedBytes)>, res: &mut Cigar) -> (usize, usize, usize) {
+        assert!(i <= self.query_len && j <= self.reference_len, "Traceback cigar end position must be in bounds!");
+        // leading reference gaps are free for glocal alignment
+        let free_j = self.free_ref_start;
+
+        unsafe {
+            res.reset(i + j + 5);
+            let mut block_idx = self.block_idx;
+            let mut trace_idx = self.trace_idx;
+            let mut block_i;
+            let mut block_j;
+            let mut block_width;
+            let mut block_height;
+            let mut right;
+
+            // the traceback is a state machine that tracks whether the current cell is
+            // on the path through the match/mismatch (M), insertion (I), or deletion (D)
+            // DP matrix, so gap open vs gap extend is resolved exactly for affine gaps
+            // if the gap open bits are stored
+            let mut state = Operation::M;
+            let mut matches = 0;
+
+            while i > 0 || (j > 0 && !free_j) {
+                loop {
+                    block_idx -= 1;
+                    block_i = *self.block_start.as_ptr().add(block_idx * 2) as usize;
+                    block_j = *self.block_start.as_ptr().add(block_idx * 2 + 1) as usize;
+                    block_height = *self.block_size.as_ptr().add(block_idx * 2) as usize;
+                    block_width = *self.block_size.as_ptr().add(block_idx * 2 + 1) as usize;
+                    trace_idx -= block_width * block_height / L;
+
+                    if i >= block_i && j >= block_j {
+                        right = ((*self.right.as_ptr().add(block_idx / 64) >> (block_idx % 64)) & 0b1) > 0;
+                        break;
+                    }
+                }
+
+                while i >= block_i && j >= block_j && (i > 0 || (j > 0 && !free_j)) {
+                    let curr_i = i - block_i;
+                    let curr_j = j - block_j;
+                    // when shifting right, lanes are along the query and C represents deletions,
+                    // and vice versa when shifting down
+                    let (idx, shift) = if right {
+                        (trace_idx + curr_i / L + curr_j * (block_height / L), (curr_i % L) * 2)
+                    } else {
+                        (trace_idx + curr_j / L + curr_i * (block_width / L), (curr_j % L) * 2)
+                    };
+                    let t = (*self.trace.as_ptr().add(idx) >> shift) & 0b11;
+                    // without the gap open bits, a gap is left as soon as the trace of a cell
+                    // does not point to the gap, which is exact for linear gap costs
+                    let t_open = if self.exact_gaps { (*self.trace_open.as_ptr().add(idx) >> shift) & 0b11 } else { !t & 0b11 };
+                    let (eq_i, eq_d, open_i, open_d) = if right {
+                        (t & 0b10 > 0, t & 0b01 > 0, t_open & 0b10 > 0, t_open & 0b01 > 0)
+                    } else {
+                        (t & 0b01 > 0, t & 0b10 > 0, t_open & 0b01 > 0, t_open & 0b10 > 0)
+                    };
+
+                    // leave the gap if it was opened from this cell
+                    if (state == Operation::I && (open_i || i == 0)) || (state == Operation::D && (open_d || j == 0)) {
+                        state = Operation::M;
+                    }
+
+                    if i == 0 {
+                        // leading gaps along the first row or column
+                        state = Operation::D;
+                    } else if j == 0 {
+                        state = Operation::I;
+                    } else if state == Operation::M {
+                        if eq_i && eq_d {
+                            // bias towards the direction that avoids going out of bounds
+                            state = if right { Operation::D } else { Operation::I };
+                        } else if eq_i {
+                            state = Operation::I;
+                        } else if eq_d {
+                            state = Operation::D;
+                        }
+                    }
+
+                    match state {
+                        Operation::M => {
+                            if let Some((query, reference)) = strs {
+                                matches += (query.get(self.start.0 + i) == reference.get(self.start.1 + j)) as usize;
+                            }
+                            i -= 1;
+                            j -= 1;
+                        },
+                        Operation::I => i -= 1,
+                        _ => j -= 1
+                    }
+                    res.add(state);
+                }
+            }
+
+            (i, j, matches)
+        }
+    }
+
+    /// Return all of the rectangular regions that were calculated separately as
+    /// block aligner shifts and grows.
+    pub fn blocks(&self) -> Vec<Rectangle> {
+        self.blocks_iter().collect()
+    }
+
+    /// Iterate over the rectangular regions returned by `blocks`, in the same order,
+    /// without allocating.
+    pub fn blocks_iter(&self) -> impl ExactSizeIterator<Item = Rectangle> + '_ {
+        (0..self.block_idx).map(move |i| unsafe {
+            Rectangle {
+                row: *self.block_start.as_ptr().add(i * 2) as usize + self.start.0,
+                col: *self.block_start.as_ptr().add(i * 2 + 1) as usize + self.start.1,
+                height: *self.block_size.as_ptr().add(i * 2) as usize,
+                width: *self.block_size.as_ptr().add(i * 2 + 1) as usize
+            }
+        })
+    }
+
+    /// Return the checkpoints that were saved while aligning, as the number of blocks
+    /// (returned by `blocks`) that were computed when each checkpoint was saved.
+    ///
+    /// Checkpoints are saved when the best score improves, and the block returns to the
+    /// last checkpoint when it needs to grow. Checkpoints that were discarded by returning
+    /// to an earlier checkpoint are not included.
+    ///
+    /// Only available with the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn checkpoints(&self) -> &[usize] {
+        &self.ckpt_blocks
+    }
+
+    /// Return the max score in each of the rectangular regions returned by `blocks`,
+    /// in the same order.
+    ///
+    /// This is the max score of any cell in the region, which may not be on the traceback
+    /// path. It can be used to find high scoring regions for chaining, break point detection,
+    /// or confidence scoring. Regions where every cell is unreachable have a score of `i32::MIN`.
+    pub fn block_maxes(&self) -> Vec<i32> {
+        self.block_max[..self.block_idx].to_vec()
+    }
+}
+
+/// Max score of a block, given the max of each lane.
+#[allow(non_snake_case)]
+#[inline(always)]
+unsafe fn block_max_score<B: SimdBackend>(D_max: B::Simd, off: i32) -> i32 {
+    let max = B::simd_hmax_i16(D_max);
+    if max == MIN { i32::MIN } else { off + (max as i32) - (ZERO as i32) }
 }
 
 /// A rectangular region.
@@ -989,36 +1991,80 @@ fn clamp(x: i32) -> i16 {
 }
 
 #[inline]
-fn div_ceil(n: usize, d: usize) -> usize {
+pub(crate) fn div_ceil(n: usize, d: usize) -> usize {
     (n + d - 1) / d
 }
 
+/// Allocator for the internal SIMD-aligned buffers and the traces used by block aligner.
+///
+/// The allocator must be `Sync` so blocks can still be sent between threads.
+///
+/// # Safety
+/// Implementations must return memory that satisfies the size and alignment of the
+/// requested layout, or a null pointer on failure, like `std::alloc::GlobalAlloc`.
+pub unsafe trait BufferAllocator: Sync {
+    /// Allocate memory for a certain layout.
+    ///
+    /// # Safety
+    /// The layout must have a nonzero size.
+    unsafe fn alloc(&self, layout: alloc::Layout) -> *mut u8;
+    /// Deallocate memory that was previously allocated by this allocator.
+    ///
+    /// # Safety
+    /// The pointer must have been allocated by this allocator with the same layout.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: alloc::Layout);
+
+    /// Take a trace from a previous alignment, so the memory of its buffers can be reused.
+    ///
+    /// The trace is reset before it is used. By default, a new trace is always allocated.
+    fn take_trace(&self) -> Option<Trace> {
+        None
+    }
+
+    /// Give back the trace of a block aligner, from `Block::recycle`.
+    ///
+    /// By default, the trace is freed.
+    fn put_trace(&self, _trace: Trace) {}
+}
+
+/// Allocate buffers with the global allocator.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GlobalAllocator;
+
+unsafe impl BufferAllocator for GlobalAllocator {
+    #[inline]
+    unsafe fn alloc(&self, layout: alloc::Layout) -> *mut u8 {
+        alloc::alloc(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: alloc::Layout) {
+        alloc::dealloc(ptr, layout);
+    }
+}
+
 /// Same alignment as SIMD vectors.
-struct Aligned {
+struct Aligned<'a> {
     layout: alloc::Layout,
-    ptr: *const i16
+    ptr: *const i16,
+    allocator: &'a dyn BufferAllocator
 }
 
-impl Aligned {
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
-    pub unsafe fn new(block_size: usize) -> Self {
+impl<'a> Aligned<'a> {
+    pub unsafe fn new(allocator: &'a dyn BufferAllocator, block_size: usize) -> Self {
         // custom alignment
         let layout = alloc::Layout::from_size_align_unchecked(block_size * 2, L_BYTES);
-        let ptr = alloc::alloc_zeroed(layout) as *const i16;
-        let mut i = 0;
-        while i < block_size {
-            simd_store(ptr.add(i) as _, simd_set1_i16(MIN));
-            i += L;
+        let ptr = allocator.alloc(layout) as *const i16;
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
         }
-        Self { layout, ptr }
+        slice::from_raw_parts_mut(ptr as *mut i16, block_size).fill(MIN);
+        Self { layout, ptr, allocator }
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[inline]
     pub unsafe fn set_vec(&mut self, o: &Aligned, idx: usize) {
-        simd_store(self.ptr.add(idx) as _, simd_load(o.as_ptr().add(idx) as _));
+        ptr::copy_nonoverlapping(o.as_ptr().add(idx), self.ptr.add(idx) as *mut i16, L);
     }
 
     #[inline]
@@ -1041,103 +2087,674 @@ impl Aligned {
     pub fn as_ptr(&self) -> *const i16 {
         self.ptr
     }
-}
+}
+
+impl<'a> Drop for Aligned<'a> {
+    fn drop(&mut self) {
+        unsafe { self.allocator.dealloc(self.ptr as _, self.layout); }
+    }
+}
+
+/// A padded string that helps avoid out of bounds access when using SIMD.
+///
+/// A single padding byte in inserted before the start of the string,
+/// and `block_size` bytes are inserted after the end of the string.
+#[derive(Debug)]
+pub struct PaddedBytes {
+    s: Vec<u8>,
+    // start of the padded bytes, which are either in `s` or in a caller-managed buffer
+    ptr: *const u8,
+    padded_len: usize,
+    len: usize,
+    // whether the bytes around the string are from a larger string instead of padding
+    sliced: bool
+}
+
+// the padded bytes are never mutated through a shared reference
+unsafe impl Send for PaddedBytes {}
+unsafe impl Sync for PaddedBytes {}
+
+impl PaddedBytes {
+    #[inline]
+    fn from_vec(s: Vec<u8>, len: usize) -> Self {
+        let (ptr, padded_len) = (s.as_ptr(), s.len());
+        Self { s, ptr, padded_len, len, sliced: false }
+    }
+
+    /// Create from a byte slice.
+    ///
+    /// Make sure that `block_size` is greater than or equal to the upper bound
+    /// block size used in the `Block::align` function.
+    #[inline]
+    pub fn from_bytes<M: Matrix>(b: &[u8], block_size: usize) -> Self {
+        let mut v = b.to_owned();
+        let len = v.len();
+        v.insert(0, M::NULL);
+        v.resize(v.len() + block_size, M::NULL);
+        v.iter_mut().for_each(|c| *c = M::convert_char(*c));
+        Self::from_vec(v, len)
+    }
+
+    /// Borrow a caller-managed buffer that is already padded, without copying it.
+    ///
+    /// The buffer must contain a padding byte, then the `len` bytes of the string, then
+    /// at least `block_size` padding bytes, with all bytes already converted by the matrix.
+    /// This is the same layout as `padded_bytes` returns, so large references can be padded
+    /// once and then reused for many alignments.
+    ///
+    /// Make sure that `block_size` is greater than or equal to the upper bound
+    /// block size used in the `Block::align` function.
+    pub fn view<M: Matrix>(buf: &[u8], len: usize, block_size: usize) -> PaddedView<'_> {
+        assert!(buf.len() >= len + 1 + block_size, "Buffer is too short for the string and its padding!");
+        let null = M::convert_char(M::NULL);
+        assert!(buf[0] == null && buf[len + 1..len + 1 + block_size].iter().all(|&c| c == null), "Buffer must be padded with the null byte!");
+        debug_assert!(buf[1..len + 1].iter().all(|&c| M::convert_char(c) == c), "Buffer bytes must be converted!");
+        let bytes = Self { s: Vec::new(), ptr: buf.as_ptr(), padded_len: len + 1 + block_size, len, sliced: false };
+        PaddedView { bytes, _marker: PhantomData }
+    }
+
+    /// Borrow a substring without copying, so it can be aligned like a separate string.
+    ///
+    /// `range` is a 0-based range in the original string. Alignment results (like `AlignResult`
+    /// and CIGAR strings) are relative to the start of the substring. The bytes around the
+    /// substring are treated as padding, so alignments cannot extend past the substring.
+    pub fn slice(&self, range: Range<usize>) -> PaddedView<'_> {
+        assert!(range.start <= range.end && range.end <= self.len, "Substring range must be within the string!");
+        let bytes = Self {
+            s: Vec::new(),
+            ptr: unsafe { self.ptr.add(range.start) },
+            padded_len: self.padded_len - range.start,
+            len: range.end - range.start,
+            sliced: self.sliced || range.start > 0 || range.end < self.len
+        };
+        PaddedView { bytes, _marker: PhantomData }
+    }
+
+    /// Create a placeholder string of a certain length, for position-specific matrices
+    /// that ignore the bytes (like `ProfilePair` and `TokenMatrix`).
+    ///
+    /// Make sure that `block_size` is greater than or equal to the upper bound
+    /// block size used in the `Block::align` function.
+    #[inline]
+    pub fn from_len<M: Matrix>(len: usize, block_size: usize) -> Self {
+        Self::from_vec(vec![M::convert_char(M::NULL); len + 1 + block_size], len)
+    }
+
+    /// Create from a byte slice, in reverse order.
+    ///
+    /// This is useful for minus strand alignment, along with a matrix that
+    /// complements bytes on the fly (see `AsymNucMatrix::complement_query`).
+    ///
+    /// Make sure that `block_size` is greater than or equal to the upper bound
+    /// block size used in the `Block::align` function.
+    #[inline]
+    pub fn from_bytes_rev<M: Matrix>(b: &[u8], block_size: usize) -> Self {
+        let mut v = Vec::with_capacity(b.len() + 1 + block_size);
+        v.push(M::NULL);
+        v.extend(b.iter().rev());
+        v.resize(v.len() + block_size, M::NULL);
+        v.iter_mut().for_each(|c| *c = M::convert_char(*c));
+        Self::from_vec(v, b.len())
+    }
+
+    /// Create from the bytes in a string slice.
+    ///
+    /// Make sure that `block_size` is greater than or equal to the upper bound
+    /// block size used in the `Block::align` function.
+    #[inline]
+    pub fn from_str<M: Matrix>(s: &str, block_size: usize) -> Self {
+        Self::from_bytes::<M>(s.as_bytes(), block_size)
+    }
+
+    /// Create from the bytes in a string.
+    ///
+    /// Make sure that `block_size` is greater than or equal to the upper bound
+    /// block size used in the `Block::align` function.
+    #[inline]
+    pub fn from_string<M: Matrix>(s: String, block_size: usize) -> Self {
+        let mut v = s.into_bytes();
+        let len = v.len();
+        v.insert(0, M::NULL);
+        v.resize(v.len() + block_size, M::NULL);
+        v.iter_mut().for_each(|c| *c = M::convert_char(*c));
+        Self::from_vec(v, len)
+    }
+
+    /// Replace the contents with a new byte slice, reusing the existing allocation.
+    ///
+    /// Make sure that `block_size` is greater than or equal to the upper bound
+    /// block size used in the `Block::align` function.
+    #[inline]
+    pub fn set_bytes<M: Matrix>(&mut self, b: &[u8], block_size: usize) {
+        self.s.clear();
+        self.s.push(M::NULL);
+        self.s.extend_from_slice(b);
+        self.s.resize(b.len() + 1 + block_size, M::NULL);
+        self.s.iter_mut().for_each(|c| *c = M::convert_char(*c));
+        self.ptr = self.s.as_ptr();
+        self.padded_len = self.s.len();
+        self.len = b.len();
+    }
+
+    /// Get the byte at a certain index (unchecked).
+    #[inline]
+    pub unsafe fn get(&self, i: usize) -> u8 {
+        *self.ptr.add(i)
+    }
+
+    /// Set the byte at a certain index (unchecked).
+    #[inline]
+    pub unsafe fn set(&mut self, i: usize, c: u8) {
+        // borrowed buffers are only accessible through a shared reference
+        *self.s.as_mut_ptr().add(i) = c;
+    }
+
+    /// Create a pointer to a specific index.
+    #[inline]
+    pub unsafe fn as_ptr(&self, i: usize) -> *const u8 {
+        self.ptr.add(i)
+    }
+
+    /// Get all of the padded bytes, including the padding before and after the string.
+    ///
+    /// For substrings created with `slice`, the bytes around the substring are from the
+    /// original string.
+    #[inline]
+    pub fn padded_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.padded_len) }
+    }
+
+    /// Length of the original string (no padding).
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether a 1-based padded position is outside of the string.
+    #[inline]
+    fn is_padding(&self, i: usize) -> bool {
+        i == 0 || i > self.len
+    }
+}
+
+impl Clone for PaddedBytes {
+    /// Copy the padded bytes, even if they are borrowed.
+    fn clone(&self) -> Self {
+        let mut res = Self::from_vec(self.padded_bytes().to_owned(), self.len);
+        res.sliced = self.sliced;
+        res
+    }
+}
+
+impl PartialEq for PaddedBytes {
+    /// Compare the strings, without the padding.
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.padded_bytes()[1..self.len + 1] == other.padded_bytes()[1..other.len + 1]
+    }
+}
+
+/// A `PaddedBytes` that borrows a caller-managed buffer, created with `PaddedBytes::view`.
+///
+/// It can be used anywhere a `&PaddedBytes` is expected through `Deref`.
+#[derive(Debug)]
+pub struct PaddedView<'a> {
+    bytes: PaddedBytes,
+    _marker: PhantomData<&'a [u8]>
+}
+
+impl<'a> Deref for PaddedView<'a> {
+    type Target = PaddedBytes;
+
+    #[inline]
+    fn deref(&self) -> &PaddedBytes {
+        &self.bytes
+    }
+}
+
+/// Resulting score and alignment end position.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AlignResult {
+    pub score: i32,
+    pub query_idx: usize,
+    pub reference_idx: usize
+}
+
+/// Strategy for growing the block size.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Growth {
+    /// Double the block size. This is the default.
+    Exponential,
+    /// Add a fixed increment to the block size.
+    Additive(usize)
+}
+
+/// Options for `Block::align_with`.
+///
+/// By default, global (or X-drop) alignment is done from the start of both strings, without
+/// recording any extra information. Options can be combined, except for glocal alignment,
+/// terminal gap costs, and X-drop alignment, which are mutually exclusive.
+#[derive(Clone)]
+pub struct AlignOptions<'a> {
+    glocal: bool,
+    terminal_gaps: Option<Gaps>,
+    track_max: bool,
+    suboptimal_radius: Option<usize>,
+    best_scores: bool,
+    steps: bool,
+    end_scores: bool,
+    max_size_cap: Option<usize>,
+    cancel: Option<Arc<AtomicBool>>,
+    allocator: &'a dyn BufferAllocator,
+    growth: Growth,
+    shrink_iter: usize,
+    adaptive_step: bool,
+    grow_threshold: f32,
+    start: (usize, usize),
+    start_gap: Option<Operation>
+}
+
+impl<'a> AlignOptions<'a> {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self {
+            glocal: false,
+            terminal_gaps: None,
+            track_max: false,
+            suboptimal_radius: None,
+            best_scores: false,
+            steps: false,
+            end_scores: false,
+            max_size_cap: None,
+            cancel: None,
+            allocator: &GlobalAllocator,
+            growth: Growth::Exponential,
+            shrink_iter: 0,
+            adaptive_step: false,
+            grow_threshold: 1.0,
+            start: (0, 0),
+            start_gap: None
+        }
+    }
+
+    /// Align the entire query to a substring of the reference.
+    ///
+    /// This is a semi-global ("glocal") alignment where the query must be aligned
+    /// end-to-end, but leading and trailing reference bases are free. This is commonly
+    /// used for primer/adapter trimming and amplicon placement.
+    ///
+    /// The resulting `reference_idx` is the end of the aligned region in the reference.
+    /// If `TRACE` is true, then the unaligned reference flanks can be retrieved with
+    /// `Block::ref_flanks`.
+    pub fn glocal(mut self) -> Self {
+        self.glocal = true;
+        self
+    }
+
+    /// Use separate gap costs for terminal gaps.
+    ///
+    /// Terminal gaps are gaps that touch the start or the end of either string. They are
+    /// often penalized less than internal gaps, which is a compromise between global and
+    /// semi-global alignment. The terminal gap costs must be nonpositive, and they may be zero.
+    ///
+    /// The resulting `query_idx` and `reference_idx` is the end of the alignment before the
+    /// trailing terminal gap, if there is one. The score includes the cost of the trailing
+    /// gap, but the CIGAR string from the trace does not.
+    ///
+    /// Note that a trailing terminal gap is always charged separately from any internal gap
+    /// right before it.
+    pub fn terminal_gaps(mut self, terminal_gaps: Gaps) -> Self {
+        assert!(terminal_gaps.open <= 0 && terminal_gaps.extend <= 0, "Terminal gap costs must be nonpositive!");
+        self.terminal_gaps = Some(terminal_gaps);
+        self
+    }
+
+    /// Keep track of the max scoring cell in the DP matrix for global alignment.
+    ///
+    /// The max scoring cell can be retrieved with `Block::max_res`, and it is useful to check
+    /// whether the global alignment goes through a strong local core or whether it is
+    /// dragged down by divergent ends. This is slightly slower.
+    pub fn track_max(mut self) -> Self {
+        self.track_max = true;
+        self
+    }
+
+    /// Find the best suboptimal alignment end that is not in the neighborhood of the best
+    /// alignment end.
+    ///
+    /// The neighborhood is all cells within `radius` rows and columns of the max scoring cell.
+    /// The difference between the max score and the suboptimal score, which is available
+    /// through `Block::score_gap`, is a measure of the confidence or uniqueness of the alignment.
+    /// Repetitive regions often result in alternative alignments with small score gaps.
+    ///
+    /// This is approximate, since only the max scoring cell in each step of block aligner
+    /// is considered as a suboptimal alignment end.
+    pub fn suboptimal(mut self, radius: usize) -> Self {
+        self.suboptimal_radius = Some(radius);
+        self
+    }
+
+    /// Record the running best score after each step.
+    ///
+    /// This is mostly useful for X-drop alignment, to diagnose where an extension decayed
+    /// or to implement custom break point logic (for example, for detecting chimeric reads,
+    /// where the best score stops increasing partway through the query). See `Block::best_scores`.
+    pub fn best_scores(mut self) -> Self {
+        self.best_scores = true;
+        self
+    }
+
+    /// Record every step that the block took.
+    ///
+    /// This is useful for tuning parameters, since it shows exactly when the block shifted
+    /// or grew, and the scores that led to those decisions. See `Block::steps`.
+    pub fn steps(mut self) -> Self {
+        self.steps = true;
+        self
+    }
+
+    /// Record the scores in the last row and the last column of the DP matrix.
+    ///
+    /// The last row contains the scores of aligning the full query against every prefix
+    /// of the reference, and the last column contains the scores of aligning the full
+    /// reference against every prefix of the query. This is useful for picking the best
+    /// overlap position without multiple alignment calls. See `Block::end_row_scores` and
+    /// `Block::end_col_scores`. For X-drop alignment, only the cells that were computed
+    /// before the alignment terminated are recorded.
+    pub fn end_scores(mut self) -> Self {
+        self.end_scores = true;
+        self
+    }
+
+    /// Retry with a larger max block size if the alignment may be inaccurate.
+    ///
+    /// If the block needed to grow past the max block size (see `Diagnostics`), then the
+    /// alignment is retried with double the max block size, up to `max_size_cap`. This stops
+    /// once the max block size is large enough or a retry does not improve the score, and the
+    /// alignment with the best score is returned.
+    ///
+    /// Make sure that the strings are padded for `max_size_cap`.
+    pub fn retry(mut self, max_size_cap: usize) -> Self {
+        self.max_size_cap = Some(max_size_cap);
+        self
+    }
+
+    /// Stop early if `cancel` is set.
+    ///
+    /// The flag is checked after every step, so alignment stops shortly after it is set
+    /// by another thread. The results of a cancelled alignment are only partial, so they
+    /// should be discarded.
+    pub fn cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Use a custom allocator for the internal SIMD-aligned buffers (the block borders and
+    /// their checkpoints) and the trace.
+    ///
+    /// This allows embedders to control where the workspace lives (for example, in a pool,
+    /// a bump arena, or an allocator that does memory accounting). The trace is taken from
+    /// the allocator with `BufferAllocator::take_trace` and it can be given back with
+    /// `Block::recycle`. Other returned data structures still use the global allocator.
+    pub fn allocator(mut self, allocator: &'a dyn BufferAllocator) -> Self {
+        self.allocator = allocator;
+        self
+    }
 
-impl Drop for Aligned {
-    fn drop(&mut self) {
-        unsafe { alloc::dealloc(self.ptr as _, self.layout); }
+    /// Set the strategy for growing the block.
+    ///
+    /// With `Growth::Additive`, the block size grows by a fixed increment instead of doubling,
+    /// which allows finer control over the amount of memory and computation used for
+    /// difficult regions. The block sizes and the increment must be multiples of the number
+    /// of 16-bit lanes in a SIMD vector, but they do not need to be powers of 2.
+    pub fn growth(mut self, growth: Growth) -> Self {
+        self.growth = growth;
+        self
     }
-}
 
-/// A padded string that helps avoid out of bounds access when using SIMD.
-///
-/// A single padding byte in inserted before the start of the string,
-/// and `block_size` bytes are inserted after the end of the string.
-#[derive(Clone, PartialEq, Debug)]
-pub struct PaddedBytes {
-    s: Vec<u8>,
-    len: usize
-}
+    /// Allow the block to shrink after it grows.
+    ///
+    /// Once the block grows to handle a difficult region, it normally stays large for the
+    /// rest of the alignment. With this, the block shrinks back toward the min size once
+    /// the best score improves in `shrink_iter` steps without the block needing to grow,
+    /// which indicates that the alignment has become easy again. The block shrinks in the
+    /// opposite way it grows.
+    pub fn shrink(mut self, shrink_iter: usize) -> Self {
+        assert!(shrink_iter > 0, "Number of steps before shrinking must be positive!");
+        self.shrink_iter = shrink_iter;
+        self
+    }
 
-impl PaddedBytes {
-    /// Create from a byte slice.
+    /// Adapt the step size to the difficulty of the region being aligned.
     ///
-    /// Make sure that `block_size` is greater than or equal to the upper bound
-    /// block size used in the `Block::align` function.
-    #[inline]
-    pub fn from_bytes<M: Matrix>(b: &[u8], block_size: usize) -> Self {
-        let mut v = b.to_owned();
-        let len = v.len();
-        v.insert(0, M::NULL);
-        v.resize(v.len() + block_size, M::NULL);
-        v.iter_mut().for_each(|c| *c = M::convert_char(*c));
-        Self { s: v, len }
+    /// The block normally shifts by a fixed step size, which is the largest step size
+    /// supported by the SIMD shifts (half of the SIMD vector). With this, the step size is
+    /// halved in difficult regions where the best score stops improving, which trades speed
+    /// for accuracy, and it is restored once the best score improves again.
+    pub fn adaptive_step(mut self) -> Self {
+        self.adaptive_step = true;
+        self
     }
 
-    /// Create from the bytes in a string slice.
+    /// Set the threshold for growing the block.
     ///
-    /// Make sure that `block_size` is greater than or equal to the upper bound
-    /// block size used in the `Block::align` function.
-    #[inline]
-    pub fn from_str<M: Matrix>(s: &str, block_size: usize) -> Self {
-        Self::from_bytes::<M>(s.as_bytes(), block_size)
+    /// The block grows when the best score does not improve in approximately
+    /// `block_size / step` steps, which is roughly when the block has moved its own size
+    /// without finding a better score. This number of steps is multiplied by `grow_threshold`,
+    /// so a threshold less than 1 grows earlier (more accurate), and a threshold greater than 1
+    /// grows later (faster). The default threshold is 1.
+    pub fn grow_threshold(mut self, grow_threshold: f32) -> Self {
+        assert!(grow_threshold > 0.0, "Growth threshold must be positive!");
+        self.grow_threshold = grow_threshold;
+        self
     }
 
-    /// Create from the bytes in a string.
+    /// Start the DP at the cell `start` instead of `(0, 0)`.
     ///
-    /// Make sure that `block_size` is greater than or equal to the upper bound
-    /// block size used in the `Block::align` function.
-    #[inline]
-    pub fn from_string<M: Matrix>(s: String, block_size: usize) -> Self {
-        let mut v = s.into_bytes();
-        let len = v.len();
-        v.insert(0, M::NULL);
-        v.resize(v.len() + block_size, M::NULL);
-        v.iter_mut().for_each(|c| *c = M::convert_char(*c));
-        Self { s: v, len }
+    /// The cell `(i, j)` is after the first `i` query characters and the first `j` reference
+    /// characters, so only `query[i..]` and `reference[j..]` are aligned. This is useful for
+    /// extending seeds and realigning part of an alignment without creating new `PaddedBytes`.
+    ///
+    /// Alignment results (`res`, `max_res`, and `suboptimal_res`) and traceback positions are
+    /// in the coordinates of the original strings. Other results, like `end_row_scores` and
+    /// `block_values`, are relative to `start`. Position-specific matrices are still indexed
+    /// by positions in the original strings.
+    pub fn start(mut self, start: (usize, usize)) -> Self {
+        self.start = start;
+        self
     }
 
-    /// Get the byte at a certain index (unchecked).
-    #[inline]
-    pub unsafe fn get(&self, i: usize) -> u8 {
-        *self.s.as_ptr().add(i)
+    /// Start the DP in a gap state, where `op` is `Operation::I` or `Operation::D`.
+    ///
+    /// A gap of this type that begins at the start cell continues a gap before the start
+    /// cell, so it is only charged the gap extend cost. This is useful for continuing an
+    /// alignment that ends in a gap (see `start`). This cannot be combined with glocal
+    /// alignment or terminal gap costs.
+    pub fn start_gap(mut self, op: Operation) -> Self {
+        assert!(op == Operation::I || op == Operation::D, "Start gap state must be an insertion or a deletion!");
+        self.start_gap = Some(op);
+        self
     }
+}
 
-    /// Set the byte at a certain index (unchecked).
-    #[inline]
-    pub unsafe fn set(&mut self, i: usize, c: u8) {
-        *self.s.as_mut_ptr().add(i) = c;
+impl<'a> Default for AlignOptions<'a> {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    /// Create a pointer to a specific index.
-    #[inline]
-    pub unsafe fn as_ptr(&self, i: usize) -> *const u8 {
-        self.s.as_ptr().add(i)
+/// Contributions of substitutions and gaps to the score of an alignment.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ScoreBreakdown {
+    /// Total score of all aligned pairs (matches and mismatches).
+    pub substitution: i32,
+    /// Total score of all gaps, including terminal gap costs.
+    pub gap: i32
+}
+
+/// Cumulative score along the traceback path of an alignment.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ScoreProfile {
+    /// Query and reference positions where the path starts.
+    pub start: (usize, usize),
+    /// Cumulative score after each column (aligned pair or gap position) of the alignment.
+    pub cumulative: Vec<i32>
+}
+
+impl ScoreProfile {
+    /// Get the score of each column of the alignment.
+    pub fn per_column(&self) -> Vec<i32> {
+        self.cumulative
+            .iter()
+            .scan(0, |prev, &s| {
+                let d = s - *prev;
+                *prev = s;
+                Some(d)
+            })
+            .collect()
     }
 
-    /// Length of the original string (no padding).
-    #[inline]
-    pub fn len(&self) -> usize {
-        self.len
+    /// Find the window of `width` consecutive columns with the lowest total score.
+    ///
+    /// Returns the index of the first column in the window and the score of the window,
+    /// or `None` if the alignment has fewer than `width` columns.
+    pub fn lowest_window(&self, width: usize) -> Option<(usize, i32)> {
+        assert!(width > 0, "Window width must be positive!");
+        if self.cumulative.len() < width {
+            return None;
+        }
+        (0..=self.cumulative.len() - width)
+            .map(|start| {
+                let before = if start == 0 { 0 } else { self.cumulative[start - 1] };
+                (start, self.cumulative[start + width - 1] - before)
+            })
+            .min_by_key(|&(_, score)| score)
     }
 }
 
-/// Resulting score and alignment end position.
-#[repr(C)]
+/// Statistics about the path of the block as it shifts and grows.
 #[derive(Copy, Clone, PartialEq, Debug)]
-pub struct AlignResult {
-    pub score: i32,
-    pub query_idx: usize,
-    pub reference_idx: usize
+pub struct PathStats {
+    /// Number of times the block was shifted right.
+    pub right: usize,
+    /// Number of times the block was shifted down.
+    pub down: usize,
+    /// Number of times the block was grown.
+    pub grows: usize,
+    /// Number of times the block was shrunk.
+    pub shrinks: usize,
+    /// Size of the block at the end of the alignment.
+    pub final_size: usize,
+    /// Largest block size used during the alignment.
+    pub largest_size: usize
 }
 
+/// Fractions of the query and the reference that are covered by an alignment.
+///
+/// An empty string is considered to be fully covered.
 #[derive(Copy, Clone, PartialEq, Debug)]
-enum Direction {
+pub struct Coverage {
+    pub query: f32,
+    pub reference: f32
+}
+
+/// Flags that describe potential accuracy problems with an alignment.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Diagnostics {
+    /// The block needed to grow past the max block size, so large gaps or
+    /// difficult regions may not have been handled correctly.
+    pub max_size_reached: bool,
+    /// X-drop alignment terminated before reaching the end of the strings.
+    pub x_drop_terminated: bool,
+    /// Some 16-bit scores saturated, so the score may be inaccurate.
+    pub score_saturated: bool
+}
+
+/// DP scores on the borders of the block after a step.
+///
+/// Only available with the `block_values` feature.
+#[cfg(feature = "block_values")]
+#[derive(Clone, PartialEq, Debug)]
+pub struct BlockValues {
+    /// The entire block (not just the newly computed cells), clipped to the DP matrix.
+    ///
+    /// The row and column are indexes into the DP matrix, where row `i` and column `j`
+    /// correspond to the first `i` query characters and the first `j` reference characters.
+    pub block: Rectangle,
+    /// Scores in the last column of the block, from top to bottom.
+    ///
+    /// This is empty if the last column is outside of the DP matrix. Cells with
+    /// `i32::MIN` are unreachable.
+    pub right: Vec<i32>,
+    /// Scores in the last row of the block, from left to right.
+    ///
+    /// This is empty if the last row is outside of the DP matrix. Cells with
+    /// `i32::MIN` are unreachable.
+    pub bottom: Vec<i32>
+}
+
+/// Instrumentation counters for performance analysis.
+///
+/// Only available with the `stats` feature.
+#[cfg(feature = "stats")]
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Counters {
+    /// Number of DP cells computed.
+    pub cells: u64,
+    /// Number of rectangular blocks placed (computed).
+    pub blocks: u64,
+    /// Number of times the block returned to a previous checkpoint.
+    pub restores: u64,
+    /// Number of times the block grew.
+    pub grows: u64
+}
+
+#[cfg(feature = "stats")]
+impl Counters {
+    /// Compute the throughput in billions of cell updates per second (GCUPS), based on the
+    /// number of cells that were actually computed.
+    #[inline]
+    pub fn gcups(&self, elapsed: std::time::Duration) -> f64 {
+        crate::stats::gcups(self.cells, elapsed)
+    }
+}
+
+#[cfg(feature = "stats")]
+impl std::ops::AddAssign for Counters {
+    /// Accumulate counters across multiple alignments.
+    fn add_assign(&mut self, o: Self) {
+        self.cells += o.cells;
+        self.blocks += o.blocks;
+        self.restores += o.restores;
+        self.grows += o.grows;
+    }
+}
+
+/// How the block moved to compute new cells in a step.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// Shifted right along the reference.
     Right,
+    /// Shifted down along the query.
     Down,
+    /// Grew to a larger size (this is also the first step).
     Grow
 }
 
+/// A step that the block took while aligning.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Step {
+    /// How the block moved in this step.
+    pub direction: Direction,
+    /// Top left corner of the block in the DP matrix.
+    pub i: usize,
+    pub j: usize,
+    /// Size of the block.
+    pub block_size: usize,
+    /// Max score of the block, which determines whether the best score improved.
+    pub off_max: i32
+}
+
 #[cfg(test)]
 mod tests {
     use crate::scores::*;
@@ -1184,6 +2801,19 @@ mod tests {
         let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
         let a = Block::<_, false, false>::align(&q, &r, &NW1, test_gaps2, 16..=16, 0);
         assert_eq!(a.res().score, 32);
+        assert!(!a.diagnostics().max_size_reached);
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAACCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCAAAAAAAAAAAAAAAA", 16);
+        let a = Block::<_, false, false>::align(&q, &r, &NW1, test_gaps2, 16..=16, 0);
+        assert!(a.diagnostics().max_size_reached);
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps2, 16..=16, 0, &AlignOptions::new().track_max());
+        assert_eq!(a.max_res(), AlignResult { score: 16, query_idx: 16, reference_idx: 16 });
+        let a = Block::<_, false, false>::align(&q, &r, &NW1, test_gaps2, 16..=64, 0);
+        assert_eq!(a.res().score, 32 - 33);
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps2, 16..=16, 0, &AlignOptions::new().retry(64));
+        assert_eq!(a.res().score, 32 - 33);
+        assert!(!a.diagnostics().max_size_reached);
 
         let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
         let q = PaddedBytes::from_bytes::<NucMatrix>(b"TTTTTTTTTTTTTTTTTTTTTTTTTTTTTTTT", 16);
@@ -1208,6 +2838,123 @@ mod tests {
         assert_eq!(a.res().score, -5);
     }
 
+    #[test]
+    fn test_growth() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAACCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCAAAAAAAAAAAAAAAA", 16);
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=48, 0, &AlignOptions::new().growth(Growth::Additive(16)));
+        assert_eq!(a.res().score, 32 - 33);
+        assert_eq!(a.path_stats().largest_size, 32);
+
+        // the last increment is smaller to reach the max size
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=48, 0, &AlignOptions::new().growth(Growth::Additive(64)));
+        assert_eq!(a.res().score, 32 - 33);
+        assert_eq!(a.path_stats().largest_size, 48);
+        assert_eq!(a.path_stats().grows, 1);
+
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=64, 0, &AlignOptions::new().growth(Growth::Exponential));
+        assert_eq!(a.res().score, 32 - 33);
+        assert_eq!(a.path_stats().largest_size, 32);
+    }
+
+    #[test]
+    fn test_shrink() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for _i in 0..10 {
+            // large insertion followed by an easy region
+            let r = rand_str(1000, &NUC, &mut rng);
+            let mut q = r[..100].to_owned();
+            q.extend_from_slice(&rand_str(40, &NUC, &mut rng));
+            q.extend_from_slice(&r[100..]);
+            let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 128);
+            let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 128);
+
+            let a = Block::<_, true, false>::align(&q_padded, &r_padded, &NW1, test_gaps, 16..=128, 0);
+            let b = Block::<_, true, false, true>::align_with(&q_padded, &r_padded, &NW1, test_gaps, 16..=128, 0, &AlignOptions::new().shrink(16));
+            assert!(a.path_stats().largest_size > 16);
+            assert!(b.path_stats().shrinks > 0);
+            assert!(b.path_stats().final_size < a.path_stats().final_size);
+            assert_eq!(a.res(), b.res());
+
+            let res = b.res();
+            let cigar = b.trace().cigar(res.query_idx, res.reference_idx);
+            assert_eq!(cigar_score(&cigar, &q, &r, &NW1, test_gaps), res.score);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_step() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for &(len, k) in &[(100, 20), (500, 100)] {
+            for _i in 0..10 {
+                let r = rand_str(len, &NUC, &mut rng);
+                let q = rand_mutate_insert(&r, k, &NUC, len / 10, &mut rng);
+                let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 128);
+                let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 128);
+
+                let a = Block::<_, true, false, true>::align_with(&q_padded, &r_padded, &NW1, test_gaps, 16..=128, 0, &AlignOptions::new().adaptive_step());
+                let res = a.res();
+                let cigar = a.trace().cigar(res.query_idx, res.reference_idx);
+                assert_eq!(cigar_score(&cigar, &q, &r, &NW1, test_gaps), res.score);
+                assert!(res.score <= terminal_score(&q, &r, &NW1, test_gaps, test_gaps));
+            }
+        }
+    }
+
+    #[test]
+    fn test_grow_threshold() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAACCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCAAAAAAAAAAAAAAAA", 16);
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=64, 0, &AlignOptions::new().grow_threshold(1.0));
+        let b = Block::<_, false, false>::align(&q, &r, &NW1, test_gaps, 16..=64, 0);
+        assert_eq!(a.res(), b.res());
+        assert_eq!(a.path_stats(), b.path_stats());
+
+        // growing earlier still finds the optimal alignment
+        let c = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=64, 0, &AlignOptions::new().grow_threshold(0.5));
+        assert_eq!(c.res().score, 32 - 33);
+
+        // growing too late misses the long gap
+        let c = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=64, 0, &AlignOptions::new().grow_threshold(4.0));
+        assert!(c.res().score < b.res().score);
+    }
+
+    #[test]
+    fn test_suboptimal() {
+        let test_gaps = Gaps { open: -11, extend: -1 };
+
+        let r = PaddedBytes::from_bytes::<AAMatrix>(b"AAAAAAAAAAAAAAARRRRRRRRRRRRRRRRAAAAAAAAAAAAA", 16);
+        let q = PaddedBytes::from_bytes::<AAMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
+        let a = Block::<_, false, true>::align_with(&q, &r, &BLOSUM62, test_gaps, 16..=16, 100, &AlignOptions::new().suboptimal(4));
+        let res = a.res();
+        assert_eq!(res, a.max_res());
+        let sub = a.suboptimal_res().unwrap();
+        assert!(sub.score < res.score);
+        assert!(sub.query_idx.abs_diff(res.query_idx) > 4 || sub.reference_idx.abs_diff(res.reference_idx) > 4);
+        assert_eq!(a.score_gap(), Some(res.score - sub.score));
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGTAAAAAAAACGTACGT", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGT", 16);
+        let gaps = Gaps { open: -2, extend: -1 };
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, gaps, 32..=32, 0, &AlignOptions::new().suboptimal(100));
+        assert_eq!(a.suboptimal_res(), None);
+        assert_eq!(a.score_gap(), None);
+    }
+
     #[test]
     fn test_x_drop() {
         let test_gaps = Gaps { open: -11, extend: -1 };
@@ -1221,6 +2968,55 @@ mod tests {
         let q = PaddedBytes::from_bytes::<AAMatrix>(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
         let a = Block::<_, false, true>::align(&q, &r, &BLOSUM62, test_gaps, 16..=16, 1);
         assert_eq!(a.res(), AlignResult { score: 60, query_idx: 15, reference_idx: 15 });
+        let a_trace = Block::<_, true, true>::align(&q, &r, &BLOSUM62, test_gaps, 16..=16, 1);
+        let res = a_trace.res();
+        assert_eq!(res, a.res());
+        assert_eq!(a_trace.trace().cigar(res.query_idx, res.reference_idx).to_string(), "15M");
+        assert_eq!(a_trace.trace().cigar_soft_clipped(res.query_idx, res.reference_idx).to_string(), "15M29S");
+        assert!(a.diagnostics().x_drop_terminated);
+        assert!(!a.diagnostics().score_saturated);
+        let stats = a.path_stats();
+        assert_eq!(stats.grows, 0);
+        assert_eq!(stats.final_size, 16);
+        assert!(stats.right + stats.down > 0);
+
+        // the best score stops increasing once the query is past the run of A's
+        let b = Block::<_, false, true>::align_with(&q, &r, &BLOSUM62, test_gaps, 16..=16, 1, &AlignOptions::new().best_scores());
+        let best_scores = b.best_scores();
+        assert_eq!(best_scores.last().unwrap().1, 60);
+        assert!(best_scores.windows(2).all(|w| w[0].1 <= w[1].1));
+        let first_best = best_scores.iter().find(|&&(_, s)| s == 60).unwrap().0;
+        assert!((15..44).contains(&first_best));
+
+        let b = Block::<_, false, true>::align_with(&q, &r, &BLOSUM62, test_gaps, 16..=32, 1, &AlignOptions::new().steps());
+        let steps = b.steps().collect::<Vec<_>>();
+        let stats = b.path_stats();
+        assert_eq!(steps.len(), stats.right + stats.down + stats.grows + 1);
+        assert_eq!(steps[0], Step { direction: Direction::Grow, i: 0, j: 0, block_size: 16, off_max: steps[0].off_max });
+        assert!(steps.iter().all(|step| step.block_size == 16 || step.block_size == 32));
+
+        #[cfg(feature = "stats")]
+        {
+            let counters = a.counters();
+            assert_eq!(counters.grows, 0);
+            assert!(counters.cells >= 44 * 16);
+        }
+    }
+
+    #[cfg(feature = "block_values")]
+    #[test]
+    fn test_block_values() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(&[b'A'; 40], 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(&[b'A'; 40], 16);
+        let a = Block::<_, false, false>::align(&q, &r, &NW1, test_gaps, 16..=16, 0);
+        let values = a.block_values();
+        assert_eq!(values.len(), a.path_stats().right + a.path_stats().down + 1);
+        assert_eq!(values[0].block, Rectangle { row: 0, col: 0, width: 16, height: 16 });
+        // the last column is a gap followed by matches, except for the diagonal
+        let right = (0..15).map(|k| 2 * k - 16).chain(std::iter::once(15)).collect::<Vec<_>>();
+        assert_eq!(values[0].right, right);
+        assert_eq!(values[0].bottom, right);
     }
 
     #[test]
@@ -1232,7 +3028,12 @@ mod tests {
         let a = Block::<_, true, false>::align(&q, &r, &BLOSUM62, test_gaps, 16..=16, 0);
         let res = a.res();
         assert_eq!(res, AlignResult { score: 14, query_idx: 6, reference_idx: 6 });
+        assert_eq!(a.diagnostics(), Diagnostics::default());
         assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "6M");
+        let (cigar, matches) = a.cigar_with_matches();
+        assert_eq!(cigar.to_string(), "6M");
+        assert_eq!(matches, 4);
+        assert_eq!(a.score_breakdown(), ScoreBreakdown { substitution: 14, gap: 0 });
 
         let r = PaddedBytes::from_bytes::<AAMatrix>(b"AAAA", 16);
         let q = PaddedBytes::from_bytes::<AAMatrix>(b"AAA", 16);
@@ -1240,6 +3041,10 @@ mod tests {
         let res = a.res();
         assert_eq!(res, AlignResult { score: 1, query_idx: 3, reference_idx: 4 });
         assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "3M1D");
+        assert_eq!(
+            a.trace().aligned_pairs(res.query_idx, res.reference_idx).collect::<Vec<_>>(),
+            vec![(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), Some(2)), (None, Some(3))]
+        );
 
         let test_gaps2 = Gaps { open: -2, extend: -1 };
 
@@ -1249,6 +3054,294 @@ mod tests {
         let res = a.res();
         assert_eq!(res, AlignResult { score: 7, query_idx: 24, reference_idx: 21 });
         assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "2M6I16M3D");
+        assert_eq!(a.score_breakdown(), ScoreBreakdown { substitution: 18, gap: -11 });
+        let profile = a.score_profile();
+        assert_eq!(profile.start, (0, 0));
+        assert_eq!(profile.cumulative.len(), 27);
+        assert_eq!(*profile.cumulative.last().unwrap(), 7);
+        assert_eq!(&profile.per_column()[..4], &[1, 1, -2, -1]);
+        // the insertion is the worst region
+        assert_eq!(profile.lowest_window(6), Some((2, -7)));
+        assert_eq!(profile.lowest_window(28), None);
+
+        // reuse a longer CIGAR string from a previous alignment
+        let mut cigar = a.trace().cigar(res.query_idx, res.reference_idx);
+        a.trace().cigar_into(10, 3, &mut cigar);
+        assert_eq!(cigar.to_string(), a.trace().cigar(10, 3).to_string());
+        cigar.clear();
+        assert_eq!(cigar.len(), 0);
+        a.trace().cigar_into(res.query_idx, res.reference_idx, &mut cigar);
+        assert_eq!(cigar.to_string(), "2M6I16M3D");
+        assert_eq!(
+            a.trace().cigar(res.query_idx, res.reference_idx).format(b"TTTTTTTTAAAAAAATTTTTTTTT", b"TTAAAAAAATTTTTTTTTTTT"),
+            ("TTTTTTTTAAAAAAATTTTTTTTT---".to_owned(), "TT------AAAAAAATTTTTTTTTTTT".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_trace_score() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for &(len, k) in &[(20, 4), (100, 20), (500, 100)] {
+            for _i in 0..20 {
+                let r = rand_str(len, &NUC, &mut rng);
+                let q = rand_mutate_insert(&r, k, &NUC, len / 10, &mut rng);
+                let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 256);
+                let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 256);
+                let gaps = Gaps { open: -2, extend: -1 };
+                let a = Block::<_, true, false, true>::align(&q_padded, &r_padded, &NW1, gaps, 32..=256, 0);
+                let res = a.res();
+                let cigar = a.trace().cigar(res.query_idx, res.reference_idx);
+                assert_eq!(cigar_score(&cigar, &q, &r, &NW1, gaps), res.score);
+
+                // without the gap open bits, gaps may be opened more than necessary
+                let b = Block::<_, true, false>::align(&q_padded, &r_padded, &NW1, gaps, 32..=256, 0);
+                assert_eq!(b.res(), res);
+                let cigar = b.trace().cigar(res.query_idx, res.reference_idx);
+                assert!(cigar_score(&cigar, &q, &r, &NW1, gaps) <= res.score);
+
+                let r = rand_str(len, &AMINO_ACIDS, &mut rng);
+                let q = rand_mutate_insert(&r, k, &AMINO_ACIDS, len / 10, &mut rng);
+                let r_padded = PaddedBytes::from_bytes::<AAMatrix>(&r, 256);
+                let q_padded = PaddedBytes::from_bytes::<AAMatrix>(&q, 256);
+                let gaps = Gaps { open: -11, extend: -1 };
+                let a = Block::<_, true, false, true>::align(&q_padded, &r_padded, &BLOSUM62, gaps, 32..=256, 0);
+                let res = a.res();
+                let cigar = a.trace().cigar(res.query_idx, res.reference_idx);
+                assert_eq!(cigar_score(&cigar, &q, &r, &BLOSUM62, gaps), res.score);
+            }
+        }
+    }
+
+    #[test]
+    fn test_glocal() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"GGGGGGGGACGTACGTGGGGGGGG", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGT", 16);
+        let a = Block::<_, true, false>::align_with(&q, &r, &NW1, test_gaps, 16..=16, 0, &AlignOptions::new().glocal());
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 8, query_idx: 8, reference_idx: 16 });
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "8M");
+        assert_eq!(a.ref_flanks(), (8, 8));
+        assert_eq!(a.trace().aligned_pairs(res.query_idx, res.reference_idx).next(), Some((Some(0), Some(8))));
+
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTTACGT", 16);
+        let a = Block::<_, true, false>::align_with(&q, &r, &NW1, test_gaps, 16..=16, 0, &AlignOptions::new().glocal());
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 6, query_idx: 9, reference_idx: 16 });
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "4M1I4M");
+        assert_eq!(a.ref_flanks(), (8, 8));
+        let (q_gapped, r_gapped) = a.trace().cigar(res.query_idx, res.reference_idx).format_at(b"ACGTTACGT", b"GGGGGGGGACGTACGTGGGGGGGG", 0, 8);
+        assert_eq!(q_gapped.len(), r_gapped.len());
+        assert_eq!(q_gapped.replace('-', ""), "ACGTTACGT");
+        assert_eq!(r_gapped.replace('-', ""), "ACGTACGT");
+
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=32, 0, &AlignOptions::new().glocal());
+        assert_eq!(a.res().score, 6);
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGTGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGGACGTACGTGGGGGGGGGGGGGGGGGGGGG", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGT", 16);
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, test_gaps, 16..=16, 0, &AlignOptions::new().glocal());
+        assert_eq!(a.res().score, 8);
+
+        // glocal alignment combined with other options
+        let options = AlignOptions::new().glocal().start((0, 30)).steps().track_max();
+        let a = Block::<_, true, false>::align_with(&q, &r, &NW1, test_gaps, 16..=16, 0, &options);
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 8, query_idx: 8, reference_idx: 48 });
+        assert_eq!(a.trace().path_start(res.query_idx, res.reference_idx), (0, 40));
+        assert!(a.steps().count() > 0);
+        assert_eq!(a.max_res().score, 8);
+    }
+
+    fn terminal_score<M: Matrix>(q: &[u8], r: &[u8], matrix: &M, gaps: Gaps, terminal_gaps: Gaps) -> i32 {
+        let neg = i32::MIN / 2;
+        let (o, e) = (gaps.open as i32, gaps.extend as i32);
+        let t = |len: usize| if len == 0 { 0 } else { (terminal_gaps.open as i32) + (terminal_gaps.extend as i32) * ((len as i32) - 1) };
+        let (n, m) = (q.len(), r.len());
+        let mut d = vec![vec![neg; m + 1]; n + 1];
+        let mut c = vec![vec![neg; m + 1]; n + 1];
+        let mut g = vec![vec![neg; m + 1]; n + 1];
+
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = t(i);
+        }
+        for (j, x) in d[0].iter_mut().enumerate() {
+            *x = t(j);
+        }
+        for i in 1..=n {
+            for j in 1..=m {
+                c[i][j] = cmp::max(c[i][j - 1] + e, d[i][j - 1] + o);
+                g[i][j] = cmp::max(g[i - 1][j] + e, d[i - 1][j] + o);
+                d[i][j] = cmp::max(d[i - 1][j - 1] + (matrix.get(q[i - 1], r[j - 1]) as i32), cmp::max(c[i][j], g[i][j]));
+            }
+        }
+
+        let end_row = d[n].iter().enumerate().map(|(j, &x)| x + t(m - j)).max().unwrap();
+        let end_col = d.iter().enumerate().map(|(i, row)| row[m] + t(n - i)).max().unwrap();
+        cmp::max(end_row, end_col)
+    }
+
+    #[test]
+    fn test_linear_gaps() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for &gaps in &[Gaps { open: -1, extend: -1 }, Gaps { open: -2, extend: -2 }] {
+            for &(len, k) in &[(20, 4), (100, 20), (500, 100)] {
+                for _i in 0..10 {
+                    let r = rand_str(len, &NUC, &mut rng);
+                    let q = rand_mutate_insert(&r, k, &NUC, len / 10, &mut rng);
+                    let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 256);
+                    let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 256);
+                    let a = Block::<_, true, false>::align(&q_padded, &r_padded, &NW1, gaps, 32..=256, 0);
+                    let res = a.res();
+                    let cigar = a.trace().cigar(res.query_idx, res.reference_idx);
+                    assert_eq!(cigar_score(&cigar, &q, &r, &NW1, gaps), res.score);
+                    assert!(res.score <= terminal_score(&q, &r, &NW1, gaps, gaps));
+                }
+            }
+        }
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAACCCCCAAAAA", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAA", 16);
+        let a = Block::<_, true, false>::align(&q, &r, &NW1, Gaps { open: -1, extend: -1 }, 16..=16, 0);
+        let res = a.res();
+        assert_eq!(res.score, 5);
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "5M5D5M");
+    }
+
+    #[test]
+    fn test_terminal() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let gaps = Gaps { open: -4, extend: -2 };
+        let terminal_gaps = Gaps { open: -1, extend: -1 };
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"GGGGACGTACGTGG", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGT", 16);
+        let a = Block::<_, true, false>::align_with(&q, &r, &NW1, gaps, 16..=16, 0, &AlignOptions::new().terminal_gaps(terminal_gaps));
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 2, query_idx: 8, reference_idx: 12 });
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "4D8M");
+        let a = Block::<_, true, false>::align_with(&r, &q, &NW1, gaps, 16..=16, 0, &AlignOptions::new().terminal_gaps(terminal_gaps));
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 2, query_idx: 12, reference_idx: 8 });
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "4I8M");
+
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for &(terminal_gaps, len) in &[(terminal_gaps, 30), (gaps, 30), (Gaps { open: 0, extend: 0 }, 40), (Gaps { open: -8, extend: -3 }, 50)] {
+            for _i in 0..20 {
+                let r = rand_str(len, &NUC, &mut rng);
+                let mut q = rand_mutate(&r, len / 5, &NUC, &mut rng);
+                q.drain(..rng.gen_range(0..len / 4));
+                let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 64);
+                let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 64);
+                let a = Block::<_, false, false>::align_with(&q_padded, &r_padded, &NW1, gaps, 64..=64, 0, &AlignOptions::new().terminal_gaps(terminal_gaps));
+                assert_eq!(a.res().score, terminal_score(&q, &r, &NW1, gaps, terminal_gaps));
+            }
+        }
+    }
+
+    #[test]
+    fn test_end_scores() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGTAA", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGT", 16);
+        let a = Block::<_, false, false>::align_with(&q, &r, &NW1, gaps, 16..=16, 0, &AlignOptions::new().end_scores());
+        assert_eq!(a.res().score, 5);
+        let row = a.end_row_scores();
+        assert_eq!(row.len(), 11);
+        assert_eq!(row[8], 8);
+        assert_eq!(row[9], 6);
+        assert_eq!(row[10], 5);
+        assert_eq!(row[0], -9);
+        let col = a.end_col_scores();
+        assert_eq!(col.len(), 9);
+        assert_eq!(col[8], 5);
+        assert_eq!(col[0], -11);
+    }
+
+    #[test]
+    fn test_asym() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let matrix = AsymNucMatrix::new_bisulfite(1, -1, false);
+
+        // long enough for both right and down shifts
+        let r = PaddedBytes::from_bytes::<AsymNucMatrix>(b"ACGTCCGATCGATCGGACTCGATCAGCTACGACTGCATCGACTCGCATCAGCATCGAC", 16);
+        let q = PaddedBytes::from_bytes::<AsymNucMatrix>(b"ATGTTTGATTGATTGGATTTGATTAGTTATGATTGTATTGATTTGTATTAGTATTGAT", 16);
+        let a = Block::<_, false, false>::align(&q, &r, &matrix, test_gaps, 16..=16, 0);
+        assert_eq!(a.res().score, 58);
+
+        let a = Block::<_, false, false>::align(&r, &q, &matrix, test_gaps, 16..=16, 0);
+        assert!(a.res().score < 58);
+
+        // reverse complement of the reference
+        let matrix = AsymNucMatrix::complement_query(&NW1);
+        let q = PaddedBytes::from_bytes_rev::<AsymNucMatrix>(b"GTCGATGCTGATGCGAGTCGATGCAGTCGTAGCTGATCGAGTCCGATCGATCGGACGT", 16);
+        let a = Block::<_, false, false>::align(&q, &r, &matrix, test_gaps, 16..=16, 0);
+        assert_eq!(a.res().score, 58);
+    }
+
+    #[test]
+    fn test_allocator() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct CountingAllocator {
+            allocated: AtomicUsize,
+            freed: AtomicUsize,
+            traces: std::sync::Mutex<Vec<Trace>>
+        }
+
+        unsafe impl BufferAllocator for CountingAllocator {
+            unsafe fn alloc(&self, layout: alloc::Layout) -> *mut u8 {
+                self.allocated.fetch_add(layout.size(), Ordering::Relaxed);
+                alloc::alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: alloc::Layout) {
+                self.freed.fetch_add(layout.size(), Ordering::Relaxed);
+                alloc::dealloc(ptr, layout);
+            }
+
+            fn take_trace(&self) -> Option<Trace> {
+                self.traces.lock().unwrap().pop()
+            }
+
+            fn put_trace(&self, trace: Trace) {
+                self.traces.lock().unwrap().push(trace);
+            }
+        }
+
+        let allocator = CountingAllocator { allocated: AtomicUsize::new(0), freed: AtomicUsize::new(0), traces: Default::default() };
+        let test_gaps = Gaps { open: -11, extend: -1 };
+        let r = PaddedBytes::from_bytes::<AAMatrix>(b"AAAA", 16);
+        let q = PaddedBytes::from_bytes::<AAMatrix>(b"AARA", 16);
+        let a = Block::<_, false, false>::align_with(&q, &r, &BLOSUM62, test_gaps, 16..=16, 0, &AlignOptions::new().allocator(&allocator));
+        assert_eq!(a.res().score, 11);
+        assert!(allocator.allocated.load(Ordering::Relaxed) > 0);
+        assert_eq!(allocator.allocated.load(Ordering::Relaxed), allocator.freed.load(Ordering::Relaxed));
+
+        // the trace of a longer alignment is reused for a shorter alignment
+        let r_long = PaddedBytes::from_bytes::<AAMatrix>(b"AAAAAAAAAARAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", 16);
+        let a = Block::<_, true, false>::align_with(&r_long, &r_long, &BLOSUM62, test_gaps, 16..=16, 0, &AlignOptions::new().allocator(&allocator));
+        let capacity = a.trace().trace.capacity();
+        a.recycle();
+        assert_eq!(allocator.traces.lock().unwrap().len(), 1);
+        let a = Block::<_, true, false>::align_with(&q, &r, &BLOSUM62, test_gaps, 16..=16, 0, &AlignOptions::new().allocator(&allocator));
+        assert!(allocator.traces.lock().unwrap().is_empty());
+        assert_eq!(a.trace().trace.capacity(), capacity);
+        let res = a.res();
+        assert_eq!(res.score, 11);
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "4M");
     }
 
     #[test]
@@ -1265,4 +3358,219 @@ mod tests {
         let a = Block::<_, false, false>::align(&q, &r, &BYTES1, test_gaps, 16..=16, 0);
         assert_eq!(a.res().score, 4);
     }
+
+    #[test]
+    fn test_full_matrix() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let matrix = FullMatrix::new_simple(1, -1);
+
+        let r = PaddedBytes::from_bytes::<FullMatrix>(b"abcdefg", 16);
+        let q = PaddedBytes::from_bytes::<FullMatrix>(b"abdefg", 16);
+        let a = Block::<_, false, false>::align(&q, &r, &matrix, test_gaps, 16..=16, 0);
+        assert_eq!(a.res().score, 4);
+
+        // scores between bytes in the same group of 16
+        let matrix = FullMatrix::from_fn(|a, b| if a == b { 2 } else if a / 16 == b / 16 { 1 } else { -2 });
+        assert_eq!(matrix.get(200, 201), 1);
+        assert_eq!(matrix.get(b'\0', 201), i8::MIN);
+
+        let r = PaddedBytes::from_bytes::<FullMatrix>(&[200, 210, 220, 230, 240, 250, 255, 1], 16);
+        let q = PaddedBytes::from_bytes::<FullMatrix>(&[201, 210, 220, 230, 240, 250, 254, 2], 16);
+        let a = Block::<_, false, false>::align(&q, &r, &matrix, test_gaps, 16..=32, 0);
+        assert_eq!(a.res().score, 13);
+        let a = Block::<_, false, true>::align(&q, &r, &matrix, test_gaps, 16..=32, 4);
+        assert_eq!(a.res(), AlignResult { score: 13, query_idx: 8, reference_idx: 8 });
+    }
+
+    #[test]
+    fn test_padded_view() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"TTAAAAAAATTTTTTTTTTTT", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"TTTTTTTTAAAAAAATTTTTTTTT", 16);
+
+        // two padded references that are stored contiguously in one buffer
+        let r2 = PaddedBytes::from_bytes::<NucMatrix>(b"AAAACCCCGGGG", 16);
+        // the padding after the first reference is shared with the start of the second
+        let mut buf = r2.padded_bytes().to_owned();
+        let offset = buf.len() - 1;
+        buf.extend_from_slice(&r.padded_bytes()[1..]);
+
+        let view = PaddedBytes::view::<NucMatrix>(&buf[offset..], r.len(), 16);
+        assert_eq!(*view, r);
+        assert_eq!(view.clone(), r);
+        let a = Block::<_, true, false>::align(&q, &view, &NW1, test_gaps, 16..=16, 0);
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 7, query_idx: 24, reference_idx: 21 });
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "2M6I16M3D");
+
+        let view = PaddedBytes::view::<NucMatrix>(&buf, 12, 16);
+        assert_eq!(*view, r2);
+    }
+
+    #[test]
+    fn test_slice() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let test_gaps = Gaps { open: -11, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for _i in 0..50 {
+            let r = rand_str(300, &AMINO_ACIDS, &mut rng);
+            let q = rand_mutate(&r, 20, &AMINO_ACIDS, &mut rng);
+            let (a, b) = (rng.gen_range(0..100), rng.gen_range(200..q.len()));
+            let (c, d) = (rng.gen_range(0..100), rng.gen_range(200..=300));
+
+            let q_padded = PaddedBytes::from_bytes::<AAMatrix>(&q, 64);
+            let r_padded = PaddedBytes::from_bytes::<AAMatrix>(&r, 64);
+            let q_sub = PaddedBytes::from_bytes::<AAMatrix>(&q[a..b], 64);
+            let r_sub = PaddedBytes::from_bytes::<AAMatrix>(&r[c..d], 64);
+            let (q_slice, r_slice) = (q_padded.slice(a..b), r_padded.slice(c..d));
+            assert_eq!(*q_slice, q_sub);
+
+            let x = Block::<_, true, false>::align(&q_slice, &r_slice, &BLOSUM62, test_gaps, 16..=64, 0);
+            let y = Block::<_, true, false>::align(&q_sub, &r_sub, &BLOSUM62, test_gaps, 16..=64, 0);
+            let res = y.res();
+            assert_eq!(x.res(), res);
+            assert_eq!(x.trace().cigar(res.query_idx, res.reference_idx).to_string(), y.trace().cigar(res.query_idx, res.reference_idx).to_string());
+
+            let x = Block::<_, false, true>::align(&q_slice, &r_slice, &BLOSUM62, test_gaps, 16..=64, 50);
+            let y = Block::<_, false, true>::align(&q_sub, &r_sub, &BLOSUM62, test_gaps, 16..=64, 50);
+            assert_eq!(x.res(), y.res());
+        }
+    }
+
+    #[test]
+    fn test_block_maxes() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(&[b'A'; 100], 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(&[b'A'; 100], 16);
+        let a = Block::<_, true, false>::align(&q, &r, &NW1, test_gaps, 16..=16, 0);
+        let maxes = a.trace().block_maxes();
+        assert_eq!(maxes.len(), a.trace().blocks_iter().len());
+        let score = |i: usize, j: usize| {
+            let len = i.abs_diff(j) as i32;
+            cmp::min(i, j) as i32 + if len == 0 { 0 } else { -2 - (len - 1) }
+        };
+        for (rect, &max) in a.trace().blocks_iter().zip(&maxes) {
+            let rows = rect.row..cmp::min(rect.row + rect.height, 101);
+            let cols = rect.col..cmp::min(rect.col + rect.width, 101);
+            let expected = rows.flat_map(|i| cols.clone().map(move |j| score(i, j))).max().unwrap_or(i32::MIN);
+            assert_eq!(max, expected);
+        }
+        assert_eq!(*maxes.iter().max().unwrap(), a.res().score);
+    }
+
+    #[test]
+    fn test_align_at() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let test_gaps = Gaps { open: -11, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for _i in 0..20 {
+            let r = rand_str(300, &AMINO_ACIDS, &mut rng);
+            let q = rand_mutate(&r, 20, &AMINO_ACIDS, &mut rng);
+            let (a, c) = (rng.gen_range(0..100), rng.gen_range(0..100));
+
+            let q_padded = PaddedBytes::from_bytes::<AAMatrix>(&q, 64);
+            let r_padded = PaddedBytes::from_bytes::<AAMatrix>(&r, 64);
+            let q_sub = PaddedBytes::from_bytes::<AAMatrix>(&q[a..], 64);
+            let r_sub = PaddedBytes::from_bytes::<AAMatrix>(&r[c..], 64);
+
+            let x = Block::<_, true, true>::align_with(&q_padded, &r_padded, &BLOSUM62, test_gaps, 16..=64, 50, &AlignOptions::new().start((a, c)));
+            let y = Block::<_, true, true>::align(&q_sub, &r_sub, &BLOSUM62, test_gaps, 16..=64, 50);
+            let (x_res, y_res) = (x.res(), y.res());
+            assert_eq!(x_res, AlignResult { score: y_res.score, query_idx: y_res.query_idx + a, reference_idx: y_res.reference_idx + c });
+            assert_eq!(x.max_res(), x_res);
+            assert_eq!(x.trace().cigar(x_res.query_idx, x_res.reference_idx).to_string(), y.trace().cigar(y_res.query_idx, y_res.reference_idx).to_string());
+            let shifted = y.trace().aligned_pairs(y_res.query_idx, y_res.reference_idx).map(|(i, j)| (i.map(|i| i + a), j.map(|j| j + c)));
+            assert!(x.trace().aligned_pairs(x_res.query_idx, x_res.reference_idx).eq(shifted));
+            let clipped = x.trace().cigar_soft_clipped(x_res.query_idx, x_res.reference_idx);
+            assert_eq!(clipped.to_vec().iter().filter(|op_len| op_len.op != Operation::D).map(|op_len| op_len.len).sum::<usize>(), q.len());
+            assert_eq!(x.trace().blocks()[0], Rectangle { row: a, col: c, width: y.trace().blocks()[0].width, height: y.trace().blocks()[0].height });
+            assert_eq!(x.cigar_with_matches().1, y.cigar_with_matches().1);
+            assert_eq!(x.score_breakdown(), y.score_breakdown());
+
+            // global alignment to the end of both strings
+            let x = Block::<_, false, false>::align_with(&q_padded, &r_padded, &BLOSUM62, test_gaps, 16..=64, 0, &AlignOptions::new().start((a, c)));
+            let y = Block::<_, false, false>::align(&q_sub, &r_sub, &BLOSUM62, test_gaps, 16..=64, 0);
+            assert_eq!(x.res(), AlignResult { score: y.res().score, query_idx: q.len(), reference_idx: r.len() });
+        }
+    }
+
+    #[test]
+    fn test_masked_matrix() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAACGTTGCATGC", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAACGTAGCATGC", 16);
+
+        let matrix = MaskedMatrix::new_masked(NW1.clone(), 0);
+        let a = Block::<_, false, false>::align(&q, &r, &matrix, test_gaps, 16..=32, 0);
+        assert_eq!(a.res().score, 28);
+
+        // the poly-A run no longer contributes to the score
+        let mut matrix = MaskedMatrix::new_masked(NW1.clone(), 0);
+        matrix.mask_reference(0..20);
+        assert!(matrix.is_reference_masked(19) && !matrix.is_reference_masked(20) && !matrix.is_query_masked(0));
+        let a = Block::<_, false, false>::align(&q, &r, &matrix, test_gaps, 16..=32, 0);
+        assert_eq!(a.res().score, 8);
+
+        // mismatches within masked regions are still penalized
+        let mut matrix = MaskedMatrix::new_masked(NW1.clone(), 0);
+        matrix.mask_query(20..30);
+        let a = Block::<_, false, false>::align(&q, &r, &matrix, test_gaps, 16..=32, 0);
+        assert_eq!(a.res().score, 19);
+
+        // x drop alignment stops before entering a region with negative scores
+        let mut matrix = MaskedMatrix::new_masked(NW1.clone(), -1);
+        matrix.mask_query(20..30);
+        let a = Block::<_, false, true>::align(&q, &r, &matrix, test_gaps, 16..=32, 2);
+        assert_eq!(a.res(), AlignResult { score: 20, query_idx: 20, reference_idx: 20 });
+    }
+
+    #[test]
+    fn test_quick_score() {
+        use crate::simulate::*;
+        use rand::prelude::*;
+
+        let test_gaps = Gaps { open: -11, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        let r = rand_str(500, &AMINO_ACIDS, &mut rng);
+        let q = rand_mutate(&r, 10, &AMINO_ACIDS, &mut rng);
+        let r_padded = PaddedBytes::from_bytes::<AAMatrix>(&r, 64);
+        let q_padded = PaddedBytes::from_bytes::<AAMatrix>(&q, 64);
+        let full = Block::<_, false, false>::align(&q_padded, &r_padded, &BLOSUM62, test_gaps, 16..=64, 0);
+        let quick = Block::<_, false, false>::quick_score(&q_padded, &r_padded, &BLOSUM62, test_gaps, 16);
+        assert_eq!(quick, full.res().score);
+
+        // unrelated sequences score poorly, so the full alignment can be skipped
+        let u = rand_str(500, &AMINO_ACIDS, &mut rng);
+        let u_padded = PaddedBytes::from_bytes::<AAMatrix>(&u, 64);
+        assert!(Block::<_, false, false>::quick_score(&u_padded, &r_padded, &BLOSUM62, test_gaps, 16) < 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn test_quick_score_size() {
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"ACGT", 48);
+        Block::<_, false, false>::quick_score(&r, &r, &NW1, Gaps { open: -2, extend: -1 }, 48);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let test_gaps = Gaps { open: -2, extend: -1 };
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"GGGGGGGGACGTACGTGGGGGGGG", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGT", 16);
+        let a = Block::<_, true, false>::align_with(&q, &r, &NW1, test_gaps, 16..=16, 0, &AlignOptions::new().glocal());
+        assert_eq!(a.coverage(), Coverage { query: 1.0, reference: 8.0 / 24.0 });
+
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAACCCCCCCCCCCCCCCCCCCC", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"AAAAAAAAAAAAAAAAAAAAGGGGGGGGGGGGGGGGGGGG", 16);
+        let a = Block::<_, false, true>::align(&q, &r, &NW1, test_gaps, 16..=16, 1);
+        assert_eq!(a.coverage(), Coverage { query: 0.5, reference: 0.5 });
+    }
 }
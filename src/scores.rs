@@ -1,12 +1,10 @@
 //! Structs for representing match/mismatch scoring matrices.
 
-#[cfg(feature = "simd_avx2")]
-use crate::avx2::*;
-
-#[cfg(feature = "simd_wasm")]
-use crate::simd128::*;
+use crate::backend::*;
 
 use std::i8;
+use std::cmp;
+use std::ops::Range;
 
 pub trait Matrix {
     /// Byte to use as padding.
@@ -21,29 +19,176 @@ pub trait Matrix {
     fn get(&self, a: u8, b: u8) -> i8;
     /// Get the pointer for a specific index.
     fn as_ptr(&self, i: usize) -> *const i8;
-    /// Get the scores for a certain byte and a certain SIMD vector of bytes.
-    unsafe fn get_scores(&self, c: u8, v: HalfSimd, right: bool) -> Simd;
+    /// Get the scores for a certain byte and a certain SIMD vector of bytes, using the
+    /// SIMD backend `B`.
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, right: bool) -> B::Simd;
+    /// Get the scores for a certain byte and a certain SIMD vector of bytes, given the
+    /// positions of the byte (`c_idx`) and the first byte in the vector (`v_idx`) in
+    /// their padded strings.
+    ///
+    /// By default, the positions are ignored. Position-specific scoring schemes, like
+    /// profiles, can override this.
+    ///
+    /// # Safety
+    /// The CPU must support the SIMD instructions of the backend `B`.
+    #[inline]
+    unsafe fn get_scores_at<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, right: bool, _c_idx: usize, _v_idx: usize) -> B::Simd {
+        self.get_scores::<B>(c, v, right)
+    }
     /// Convert a byte to a better storage format that makes retrieving scores
     /// easier.
     fn convert_char(c: u8) -> u8;
+    /// Get the max score between any pair of bytes (or positions, for position-specific
+    /// matrices), ignoring padding.
+    ///
+    /// By default, this scans `get` over all pairs of uppercase letters, which covers
+    /// amino acid and nucleotide alphabets. Matrices over other alphabets should override this.
+    fn max_score(&self) -> i8 {
+        letter_scores(self).max().unwrap()
+    }
+    /// Get the min score between any pair of bytes (or positions, for position-specific
+    /// matrices), ignoring padding.
+    ///
+    /// By default, this scans `get` over all pairs of uppercase letters, like `max_score`.
+    fn min_score(&self) -> i8 {
+        letter_scores(self).min().unwrap()
+    }
+}
+
+/// Scores between all pairs of uppercase letters.
+fn letter_scores<M: Matrix + ?Sized>(matrix: &M) -> impl Iterator<Item = i8> + '_ {
+    (b'A'..=b'Z').flat_map(move |a| (b'A'..=b'Z').map(move |b| matrix.get(a, b)))
+}
+
+#[repr(C, align(32))]
+struct AlignedScores([i16; L]);
+
+/// Compute a SIMD vector of scores for position-specific matrices, given a function that
+/// scores 1-based padded query and reference positions.
+///
+/// See `Matrix::get_scores_at` for the meaning of the other parameters.
+#[inline]
+pub(crate) unsafe fn position_scores<B: SimdBackend, F: Fn(usize, usize) -> i16>(right: bool, c_idx: usize, v_idx: usize, f: F) -> B::Simd {
+    let mut scores = AlignedScores([0i16; L]);
+    for (k, s) in scores.0.iter_mut().enumerate() {
+        // when shifting right, the vector is along the query
+        *s = if right { f(v_idx + k, c_idx) } else { f(c_idx, v_idx + k) };
+    }
+    B::simd_load(scores.0.as_ptr() as _)
+}
+
+/// Apply a function to each score in a SIMD vector of scores, given the index of its lane.
+#[inline]
+pub(crate) unsafe fn map_simd_scores<B: SimdBackend, F: Fn(usize, i16) -> i16>(v: B::Simd, f: F) -> B::Simd {
+    let mut scores = AlignedScores([0i16; L]);
+    B::simd_store(scores.0.as_mut_ptr() as _, v);
+    for (k, s) in scores.0.iter_mut().enumerate() {
+        *s = f(k, *s);
+    }
+    B::simd_load(scores.0.as_ptr() as _)
 }
 
 /// Amino acid scoring matrix.
+///
+/// Besides the 26 letters, the stop codon `*` is also supported. In the built-in matrices,
+/// the rare amino acids are scored like their closest standard amino acid: `U` (selenocysteine)
+/// like `C`, `O` (pyrrolysine) like `K`, and `J` (leucine or isoleucine) like the average of
+/// `L` and `I`. `*` scores the lowest score in the matrix against every other residue and 1
+/// against itself, like in the matrices from NCBI. See `with_residue_like` and `with_stop` to
+/// change these scores. The unknown residue `X` is scored with the values from the NCBI tables,
+/// and `with_x` can be used to change how it is scored.
 #[repr(C, align(32))]
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AAMatrix {
-    scores: [i8; 27 * 32]
+    #[cfg_attr(feature = "serde", serde(with = "serde_scores"))]
+    scores: [i8; AA_ROWS * 32]
+}
+
+/// Number of rows in an `AAMatrix`: 26 letters, the padding byte, and the stop codon.
+const AA_ROWS: usize = 28;
+/// All residues that can be scored in an `AAMatrix`.
+const AA_RESIDUES: &[u8; 27] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ*";
+/// All nucleotides that can be scored in a `NucMatrix`.
+const NUC_RESIDUES: &[u8; 5] = b"ACGTN";
+/// Converted index of the stop codon `*`, right after the padding byte.
+const AA_STOP: u8 = 27;
+
+/// Index of an uppercase letter, the padding byte, or `*` in an `AAMatrix`.
+#[inline]
+const fn aa_idx(c: u8) -> usize {
+    if c == b'*' { AA_STOP as usize } else { (c - b'A') as usize }
+}
+
+/// Add the stop codon and the rare amino acids `J`, `U`, and `O` to a table of scores
+/// where these are missing.
+const fn extend_aa_table(table: [i8; 27 * 32]) -> [i8; AA_ROWS * 32] {
+    let mut scores = [i8::MIN; AA_ROWS * 32];
+    let mut i = 0;
+    while i < 27 * 32 {
+        scores[i] = table[i];
+        i += 1;
+    }
+
+    // the lowest score in the original table is used for stop codons
+    let mut min = i8::MAX;
+    i = 0;
+    while i < 27 * 32 {
+        if table[i] != i8::MIN && table[i] < min {
+            min = table[i];
+        }
+        i += 1;
+    }
+
+    let (j, u, o) = (aa_idx(b'J'), aa_idx(b'U'), aa_idx(b'O'));
+    let (i_idx, l, c, k) = (aa_idx(b'I'), aa_idx(b'L'), aa_idx(b'C'), aa_idx(b'K'));
+    let mut b = 0;
+    while b < 26 {
+        // the scores of the rare amino acids against each other are fixed below
+        if b != j && b != u && b != o {
+            let avg = ((table[i_idx * 32 + b] as i16 + table[l * 32 + b] as i16) >> 1) as i8;
+            scores[j * 32 + b] = avg;
+            scores[b * 32 + j] = avg;
+            scores[u * 32 + b] = table[c * 32 + b];
+            scores[b * 32 + u] = table[c * 32 + b];
+            scores[o * 32 + b] = table[k * 32 + b];
+            scores[b * 32 + o] = table[k * 32 + b];
+        }
+        scores[(AA_STOP as usize) * 32 + b] = min;
+        scores[b * 32 + (AA_STOP as usize)] = min;
+        b += 1;
+    }
+
+    let jj = ((table[i_idx * 32 + i_idx] as i16 + table[l * 32 + l] as i16 + 2 * (table[i_idx * 32 + l] as i16)) >> 2) as i8;
+    scores[j * 32 + j] = jj;
+    scores[u * 32 + u] = table[c * 32 + c];
+    scores[o * 32 + o] = table[k * 32 + k];
+    let pairs = [(j, u, i_idx, l, c), (j, o, i_idx, l, k)];
+    let mut p = 0;
+    while p < pairs.len() {
+        let (x, y, a1, a2, other) = pairs[p];
+        let avg = ((table[a1 * 32 + other] as i16 + table[a2 * 32 + other] as i16) >> 1) as i8;
+        scores[x * 32 + y] = avg;
+        scores[y * 32 + x] = avg;
+        p += 1;
+    }
+    scores[u * 32 + o] = table[c * 32 + k];
+    scores[o * 32 + u] = table[c * 32 + k];
+    scores[(AA_STOP as usize) * 33] = 1;
+    scores
 }
 
 impl AAMatrix {
     /// Create a simple matrix with a certain match and mismatch score.
+    ///
+    /// The stop codon `*` is treated like any other residue.
     pub const fn new_simple(match_score: i8, mismatch_score: i8) -> Self {
-        let mut scores = [i8::MIN; 27 * 32];
-        let mut i = b'A';
-        while i <= b'Z' {
-            let mut j = b'A';
-            while j <= b'Z' {
-                let idx = ((i - b'A') as usize) * 32 + ((j - b'A') as usize);
+        let mut scores = [i8::MIN; AA_ROWS * 32];
+        let mut i = 0;
+        while i < AA_RESIDUES.len() {
+            let mut j = 0;
+            while j < AA_RESIDUES.len() {
+                let idx = aa_idx(AA_RESIDUES[i]) * 32 + aa_idx(AA_RESIDUES[j]);
                 scores[idx] = if i == j { match_score } else { mismatch_score };
                 j += 1;
             }
@@ -51,64 +196,229 @@ impl AAMatrix {
         }
         Self { scores }
     }
+
+    /// Create a matrix from a table of scores without the rare amino acids and the stop codon,
+    /// which are filled in with the defaults described in `AAMatrix`.
+    const fn from_table(table: [i8; 27 * 32]) -> Self {
+        Self { scores: extend_aa_table(table) }
+    }
+
+    /// Create a new matrix where `residue` is scored exactly like the residue `like`.
+    ///
+    /// For example, `with_residue_like(b'U', b'C')` scores selenocysteine like cysteine.
+    pub fn with_residue_like(&self, residue: u8, like: u8) -> Self {
+        let mut res = self.clone();
+        for &b in AA_RESIDUES.iter() {
+            if b != residue.to_ascii_uppercase() {
+                res.set(residue, b, self.get(like, b));
+            }
+        }
+        res.set(residue, residue, self.get(like, like));
+        res
+    }
+
+    /// Create a new matrix where the unknown residue `X` is scored differently against every
+    /// residue, including `X`.
+    ///
+    /// By default, the built-in matrices score `X` with the values from their NCBI tables (for
+    /// example, -1 against everything in BLOSUM62). The score of `X` against the stop codon `*`
+    /// is not changed. Bytes that are not letters or `*` cannot be aligned with an `AAMatrix`,
+    /// so they should be replaced with `X` to be scored as unknown residues.
+    pub fn with_x(&self, x: XScore) -> Self {
+        let score = match x {
+            XScore::Zero => 0,
+            XScore::Mismatch => {
+                // use the worst score between two different residues
+                let residues = AA_RESIDUES.iter().filter(|&&c| c != b'X' && c != b'*');
+                residues.clone().flat_map(|&a| residues.clone().filter(move |&&b| b != a).map(move |&b| self.get(a, b))).min().unwrap()
+            },
+            XScore::Score(s) => s
+        };
+        let mut res = self.clone();
+        for &b in AA_RESIDUES.iter().filter(|&&c| c != b'*') {
+            res.set(b'X', b, score);
+        }
+        res
+    }
+
+    /// Create a new matrix with custom scores for the stop codon `*`.
+    pub fn with_stop(&self, stop_match: i8, stop_mismatch: i8) -> Self {
+        let mut res = self.clone();
+        for &b in AA_RESIDUES.iter() {
+            res.set(b'*', b, if b == b'*' { stop_match } else { stop_mismatch });
+        }
+        res
+    }
+
+    /// Create a matrix by calling a function (or closure) on every pair of letters.
+    ///
+    /// The function is only called when building the matrix, so the resulting lookup
+    /// table can still be used by the SIMD kernel. This is useful for prototyping new
+    /// scoring schemes. The function must be symmetric.
+    pub fn from_fn<F: Fn(u8, u8) -> i8>(f: F) -> Self {
+        let mut res = Self::new();
+        for (i, &a) in AA_RESIDUES.iter().enumerate() {
+            for &b in &AA_RESIDUES[i..] {
+                let score = f(a, b);
+                assert_eq!(score, f(b, a), "Scoring function must be symmetric!");
+                res.set(a, b, score);
+            }
+        }
+        res
+    }
+
+    /// Create a matrix from a dense, row-major array of scores, with a custom residue order.
+    ///
+    /// For example, `residues` can be `b"ARNDCQEGHILKMFPSTWYV"` and `scores` can be a flattened
+    /// 20x20 array. The array must be symmetric.
+    ///
+    /// Letters that do not appear in `residues` (like `X`, `B`, or `Z` for a 20x20 array) are
+    /// treated as unknown residues that score `unknown_score` against every other letter.
+    pub fn from_dense(residues: &[u8], scores: &[i8], unknown_score: i8) -> Self {
+        let n = residues.len();
+        assert_eq!(scores.len(), n * n, "Score array must have one score per pair of residues!");
+        let residues = residues.iter().map(|c| c.to_ascii_uppercase()).collect::<Vec<_>>();
+        assert!(residues.iter().all(|c| AA_RESIDUES.contains(c)), "Residues must be letters or '*'!");
+
+        let mut res = Self::new();
+        for &c in AA_RESIDUES.iter() {
+            if !residues.contains(&c) {
+                for &d in AA_RESIDUES.iter() {
+                    res.set(c, d, unknown_score);
+                }
+            }
+        }
+
+        for i in 0..n {
+            for j in 0..n {
+                assert_eq!(scores[i * n + j], scores[j * n + i], "Score array must be symmetric!");
+                res.set(residues[i], residues[j], scores[i * n + j]);
+            }
+        }
+        res
+    }
+
+    /// Create a new matrix by rounding scaled floating point log-odds scores.
+    ///
+    /// `residues` specifies the order of rows and columns in the dense, row-major
+    /// `log_odds` array. Each score is multiplied by `scale` and rounded.
+    pub fn from_log_odds(residues: &[u8], log_odds: &[f64], scale: f64) -> Self {
+        assert_eq!(log_odds.len(), residues.len() * residues.len(), "Log-odds array must have one score per pair of residues!");
+        let mut res = Self::new();
+        for (i, &a) in residues.iter().enumerate() {
+            for (j, &b) in residues.iter().enumerate() {
+                res.set(a, b, round_score(log_odds[i * residues.len() + j] * scale));
+            }
+        }
+        res
+    }
+
+    /// Create a new matrix with all scores multiplied by a constant factor and rounded.
+    pub fn scale(&self, factor: f64) -> Self {
+        let mut res = self.clone();
+        map_scores(&mut res.scores, |s| round_score((s as f64) * factor));
+        res
+    }
+
+    /// Create a new matrix with a constant offset added to all scores.
+    pub fn shift(&self, offset: i8) -> Self {
+        let mut res = self.clone();
+        map_scores(&mut res.scores, |s| check_score((s as i32) + (offset as i32)));
+        res
+    }
+
+    /// Create a new matrix where each score is the sum of the scores in both matrices.
+    pub fn compose(&self, other: &Self) -> Self {
+        let mut res = self.clone();
+        compose_scores(&mut res.scores, &other.scores);
+        res
+    }
+
+    /// Get the score for a pair of residues.
+    pub fn score(&self, a: u8, b: u8) -> i8 {
+        self.get(a, b)
+    }
+
+    /// Iterate over the scores of all pairs of residues (including the rare amino acids and `*`), as `(a, b, score)`.
+    pub fn pairs(&self) -> impl Iterator<Item = (u8, u8, i8)> + '_ {
+        AA_RESIDUES.iter().flat_map(move |&a| AA_RESIDUES.iter().map(move |&b| (a, b, self.get(a, b))))
+    }
+
+    /// Export the matrix to the NCBI text format, with rows and columns for all residues (including the rare amino acids and `*`).
+    pub fn to_ncbi(&self) -> String {
+        ncbi_text(self, AA_RESIDUES)
+    }
 }
 
 impl Matrix for AAMatrix {
     const NULL: u8 = b'A' + 26u8;
 
     fn new() -> Self {
-        Self { scores: [i8::MIN; 27 * 32] }
+        Self { scores: [i8::MIN; AA_ROWS * 32] }
     }
 
     fn set(&mut self, a: u8, b: u8, score: i8) {
         let a = a.to_ascii_uppercase();
         let b = b.to_ascii_uppercase();
-        assert!(b'A' <= a && a <= b'Z' + 1);
-        assert!(b'A' <= b && b <= b'Z' + 1);
-        let idx = ((a - b'A') as usize) * 32 + ((b - b'A') as usize);
+        assert!((b'A' <= a && a <= b'Z' + 1) || a == b'*');
+        assert!((b'A' <= b && b <= b'Z' + 1) || b == b'*');
+        let idx = aa_idx(a) * 32 + aa_idx(b);
         self.scores[idx] = score;
-        let idx = ((b - b'A') as usize) * 32 + ((a - b'A') as usize);
+        let idx = aa_idx(b) * 32 + aa_idx(a);
         self.scores[idx] = score;
     }
 
     fn get(&self, a: u8, b: u8) -> i8 {
         let a = a.to_ascii_uppercase();
         let b = b.to_ascii_uppercase();
-        assert!(b'A' <= a && a <= b'Z' + 1);
-        assert!(b'A' <= b && b <= b'Z' + 1);
-        let idx = ((a - b'A') as usize) * 32 + ((b - b'A') as usize);
+        assert!((b'A' <= a && a <= b'Z' + 1) || a == b'*');
+        assert!((b'A' <= b && b <= b'Z' + 1) || b == b'*');
+        let idx = aa_idx(a) * 32 + aa_idx(b);
         self.scores[idx]
     }
 
     #[inline]
     fn as_ptr(&self, i: usize) -> *const i8 {
-        debug_assert!(i < 27);
+        debug_assert!(i < AA_ROWS);
         unsafe { self.scores.as_ptr().add(i * 32) }
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[inline]
-    unsafe fn get_scores(&self, c: u8, v: HalfSimd, _right: bool) -> Simd {
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, _right: bool) -> B::Simd {
         // efficiently lookup scores for each character in v
         let matrix_ptr = self.as_ptr(c as usize);
-        let scores1 = halfsimd_load(matrix_ptr as *const HalfSimd);
-        let scores2 = halfsimd_load((matrix_ptr as *const HalfSimd).add(1));
-        halfsimd_lookup2_i16(scores1, scores2, v)
+        let scores1 = B::halfsimd_load(matrix_ptr as *const B::HalfSimd);
+        let scores2 = B::halfsimd_load((matrix_ptr as *const B::HalfSimd).add(1));
+        B::halfsimd_lookup2_i16(scores1, scores2, v)
     }
 
     #[inline]
     fn convert_char(c: u8) -> u8 {
         let c = c.to_ascii_uppercase();
+        if c == b'*' {
+            return AA_STOP;
+        }
         assert!(c >= b'A' && c <= Self::NULL);
         c - b'A'
     }
+
+    fn max_score(&self) -> i8 {
+        score_range(self.scores).1
+    }
+
+    fn min_score(&self) -> i8 {
+        score_range(self.scores).0
+    }
 }
 
 /// Nucleotide scoring matrix.
+///
+/// RNA sequences can be aligned directly, since `U` is treated as `T`.
 #[repr(C, align(32))]
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NucMatrix {
+    #[cfg_attr(feature = "serde", serde(with = "serde_scores"))]
     scores: [i8; 8 * 16]
 }
 
@@ -129,6 +439,143 @@ impl NucMatrix {
         }
         Self { scores }
     }
+
+    /// Create a matrix that penalizes transitions (A <-> G, C <-> T) and
+    /// transversions differently.
+    ///
+    /// Transversions are usually penalized more than transitions. `N` is treated
+    /// like a transversion against any other nucleotide.
+    pub const fn new_ts_tv(match_score: i8, transition_score: i8, transversion_score: i8) -> Self {
+        let mut scores = [i8::MIN; 8 * 16];
+        let alpha = *b"ATCGN";
+        let mut i = 0;
+        while i < alpha.len() {
+            let mut j = 0;
+            while j < alpha.len() {
+                let idx = ((alpha[i] & 0b111) as usize) * 16 + ((alpha[j] & 0b1111) as usize);
+                let a = alpha[i];
+                let b = alpha[j];
+                let transition = (a == b'A' && b == b'G') || (a == b'G' && b == b'A')
+                    || (a == b'C' && b == b'T') || (a == b'T' && b == b'C');
+                scores[idx] = if i == j {
+                    match_score
+                } else if transition {
+                    transition_score
+                } else {
+                    transversion_score
+                };
+                j += 1;
+            }
+            i += 1;
+        }
+        Self { scores }
+    }
+
+    /// Create a matrix by calling a function (or closure) on every pair of nucleotides
+    /// (`A`, `C`, `G`, `T`, and `N`).
+    ///
+    /// The function is only called when building the matrix, so the resulting lookup
+    /// table can still be used by the SIMD kernel. The function must be symmetric.
+    pub fn from_fn<F: Fn(u8, u8) -> i8>(f: F) -> Self {
+        let mut res = Self::new();
+        for &a in b"ACGTN" {
+            for &b in b"ACGTN" {
+                let score = f(a, b);
+                assert_eq!(score, f(b, a), "Scoring function must be symmetric!");
+                res.set(a, b, score);
+            }
+        }
+        res
+    }
+
+    /// Create a new matrix where `N` is scored differently against every nucleotide,
+    /// including `N`.
+    ///
+    /// By default, the matrices score `N` against another nucleotide as a mismatch,
+    /// and `N` against `N` as a match.
+    pub const fn with_n(&self, n: NScore) -> Self {
+        let mut scores = self.scores;
+        let alpha = *b"ATCGN";
+        let score = match n {
+            NScore::Zero => 0,
+            NScore::Mismatch => {
+                // use the worst score between two different nucleotides
+                let mut min = i8::MAX;
+                let mut i = 0;
+                while i < 4 {
+                    let mut j = 0;
+                    while j < 4 {
+                        let s = scores[((alpha[i] & 0b111) as usize) * 16 + ((alpha[j] & 0b1111) as usize)];
+                        if i != j && s < min {
+                            min = s;
+                        }
+                        j += 1;
+                    }
+                    i += 1;
+                }
+                min
+            },
+            NScore::Score(s) => s
+        };
+        let mut i = 0;
+        while i < alpha.len() {
+            scores[((alpha[i] & 0b111) as usize) * 16 + ((b'N' & 0b1111) as usize)] = score;
+            scores[((b'N' & 0b111) as usize) * 16 + ((alpha[i] & 0b1111) as usize)] = score;
+            i += 1;
+        }
+        Self { scores }
+    }
+
+    /// Create a new matrix by rounding scaled floating point log-odds scores.
+    ///
+    /// `residues` specifies the order of rows and columns in the dense, row-major
+    /// `log_odds` array. Each score is multiplied by `scale` and rounded.
+    pub fn from_log_odds(residues: &[u8], log_odds: &[f64], scale: f64) -> Self {
+        assert_eq!(log_odds.len(), residues.len() * residues.len(), "Log-odds array must have one score per pair of residues!");
+        let mut res = Self::new();
+        for (i, &a) in residues.iter().enumerate() {
+            for (j, &b) in residues.iter().enumerate() {
+                res.set(a, b, round_score(log_odds[i * residues.len() + j] * scale));
+            }
+        }
+        res
+    }
+
+    /// Create a new matrix with all scores multiplied by a constant factor and rounded.
+    pub fn scale(&self, factor: f64) -> Self {
+        let mut res = self.clone();
+        map_scores(&mut res.scores, |s| round_score((s as f64) * factor));
+        res
+    }
+
+    /// Create a new matrix with a constant offset added to all scores.
+    pub fn shift(&self, offset: i8) -> Self {
+        let mut res = self.clone();
+        map_scores(&mut res.scores, |s| check_score((s as i32) + (offset as i32)));
+        res
+    }
+
+    /// Create a new matrix where each score is the sum of the scores in both matrices.
+    pub fn compose(&self, other: &Self) -> Self {
+        let mut res = self.clone();
+        compose_scores(&mut res.scores, &other.scores);
+        res
+    }
+
+    /// Get the score for a pair of residues.
+    pub fn score(&self, a: u8, b: u8) -> i8 {
+        self.get(a, b)
+    }
+
+    /// Iterate over the scores of all pairs of nucleotides (`A`, `C`, `G`, `T`, and `N`), as `(a, b, score)`.
+    pub fn pairs(&self) -> impl Iterator<Item = (u8, u8, i8)> + '_ {
+        NUC_RESIDUES.iter().flat_map(move |&a| NUC_RESIDUES.iter().map(move |&b| (a, b, self.get(a, b))))
+    }
+
+    /// Export the matrix to the NCBI text format, with rows and columns for all nucleotides (`A`, `C`, `G`, `T`, and `N`).
+    pub fn to_ncbi(&self) -> String {
+        ncbi_text(self, NUC_RESIDUES)
+    }
 }
 
 impl Matrix for NucMatrix {
@@ -139,8 +586,8 @@ impl Matrix for NucMatrix {
     }
 
     fn set(&mut self, a: u8, b: u8, score: i8) {
-        let a = a.to_ascii_uppercase();
-        let b = b.to_ascii_uppercase();
+        let a = rna_to_dna(a.to_ascii_uppercase());
+        let b = rna_to_dna(b.to_ascii_uppercase());
         assert!(b'A' <= a && a <= b'Z');
         assert!(b'A' <= b && b <= b'Z');
         let idx = ((a & 0b111) as usize) * 16 + ((b & 0b1111) as usize);
@@ -150,8 +597,8 @@ impl Matrix for NucMatrix {
     }
 
     fn get(&self, a: u8, b: u8) -> i8 {
-        let a = a.to_ascii_uppercase();
-        let b = b.to_ascii_uppercase();
+        let a = rna_to_dna(a.to_ascii_uppercase());
+        let b = rna_to_dna(b.to_ascii_uppercase());
         assert!(b'A' <= a && a <= b'Z');
         assert!(b'A' <= b && b <= b'Z');
         let idx = ((a & 0b111) as usize) * 16 + ((b & 0b1111) as usize);
@@ -163,27 +610,265 @@ impl Matrix for NucMatrix {
         unsafe { self.scores.as_ptr().add((i & 0b111) * 16) }
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[inline]
-    unsafe fn get_scores(&self, c: u8, v: HalfSimd, _right: bool) -> Simd {
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, _right: bool) -> B::Simd {
         // efficiently lookup scores for each character in v
         let matrix_ptr = self.as_ptr(c as usize);
-        let scores = halfsimd_load(matrix_ptr as *const HalfSimd);
-        halfsimd_lookup1_i16(scores, v)
+        let scores = B::halfsimd_load(matrix_ptr as *const B::HalfSimd);
+        B::halfsimd_lookup1_i16(scores, v)
     }
 
     #[inline]
     fn convert_char(c: u8) -> u8 {
         let c = c.to_ascii_uppercase();
         assert!(c >= b'A' && c <= Self::NULL);
-        c
+        rna_to_dna(c)
+    }
+
+    fn max_score(&self) -> i8 {
+        score_range(self.scores).1
+    }
+
+    fn min_score(&self) -> i8 {
+        score_range(self.scores).0
+    }
+}
+
+/// How `N` is scored against other nucleotides in a `NucMatrix`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NScore {
+    /// `N` does not affect the score, which is common for masked assemblies.
+    Zero,
+    /// `N` is scored like the worst mismatch, which is common for raw basecalls.
+    Mismatch,
+    /// `N` has a specific score, for example a weak match against everything.
+    Score(i8)
+}
+
+/// How the unknown residue `X` is scored against other residues in an `AAMatrix`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum XScore {
+    /// `X` does not affect the score.
+    Zero,
+    /// `X` is scored like the worst mismatch, which strongly penalizes unknown residues.
+    Mismatch,
+    /// `X` has a specific score, for example a mild penalty against everything.
+    Score(i8)
+}
+
+/// Asymmetric nucleotide scoring matrix.
+///
+/// Unlike `NucMatrix`, the score of a reference byte against a query byte can differ from
+/// the score of the same query byte against the same reference byte. For `set` and `get`,
+/// the first byte is from the reference and the second byte is from the query.
+#[repr(C, align(32))]
+#[derive(Clone, PartialEq, Debug)]
+pub struct AsymNucMatrix {
+    // reference bytes are rows and query bytes are columns
+    scores: [i8; 8 * 16],
+    // query bytes are rows and reference bytes are columns
+    scores_t: [i8; 8 * 16]
+}
+
+impl AsymNucMatrix {
+    /// Create a matrix for aligning bisulfite-converted reads.
+    ///
+    /// A `C` in the reference matching a `T` in the query is not penalized, since unmethylated
+    /// cytosines are converted to thymines. If `ga` is true, then a `G` in the reference matching
+    /// an `A` in the query is also not penalized, for reads from the opposite strand.
+    pub fn new_bisulfite(match_score: i8, mismatch_score: i8, ga: bool) -> Self {
+        let mut res = Self::from_nuc(&NucMatrix::new_simple(match_score, mismatch_score));
+        res.set(b'C', b'T', match_score);
+        if ga {
+            res.set(b'G', b'A', match_score);
+        }
+        res
+    }
+
+    /// Create an asymmetric matrix from a symmetric nucleotide matrix.
+    pub fn from_nuc(matrix: &NucMatrix) -> Self {
+        Self { scores: matrix.scores, scores_t: matrix.scores }
+    }
+
+    /// Create a matrix that complements query nucleotides on the fly.
+    ///
+    /// The score of a reference byte against a query byte is the score of the reference byte
+    /// against the complement of the query byte in the original matrix. Combined with a reversed
+    /// query (see `PaddedBytes::from_bytes_rev`), this allows the reverse complement of the query
+    /// to be aligned to a large reference window without reverse complementing the reference.
+    pub fn complement_query(matrix: &NucMatrix) -> Self {
+        let mut res = Self::new();
+        for &a in b"ACGTN" {
+            for &b in b"ACGTN" {
+                res.set(a, b, matrix.get(a, complement(b)));
+            }
+        }
+        res
+    }
+}
+
+/// Treat RNA uracil (`U`) as thymine (`T`), so RNA sequences can be aligned directly.
+#[inline]
+fn rna_to_dna(c: u8) -> u8 {
+    if c == b'U' { b'T' } else { c }
+}
+
+/// Complement a nucleotide.
+///
+/// Bytes that are not `A`, `C`, `G`, `T` (or `U`) are not changed.
+#[inline]
+pub fn complement(c: u8) -> u8 {
+    match c.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' | b'U' => b'A',
+        c => c
+    }
+}
+
+impl Matrix for AsymNucMatrix {
+    const NULL: u8 = b'Z';
+
+    fn new() -> Self {
+        Self { scores: [i8::MIN; 8 * 16], scores_t: [i8::MIN; 8 * 16] }
+    }
+
+    fn set(&mut self, a: u8, b: u8, score: i8) {
+        let a = rna_to_dna(a.to_ascii_uppercase());
+        let b = rna_to_dna(b.to_ascii_uppercase());
+        assert!(a.is_ascii_uppercase());
+        assert!(b.is_ascii_uppercase());
+        let idx = ((a & 0b111) as usize) * 16 + ((b & 0b1111) as usize);
+        self.scores[idx] = score;
+        let idx = ((b & 0b111) as usize) * 16 + ((a & 0b1111) as usize);
+        self.scores_t[idx] = score;
+    }
+
+    fn get(&self, a: u8, b: u8) -> i8 {
+        let a = rna_to_dna(a.to_ascii_uppercase());
+        let b = rna_to_dna(b.to_ascii_uppercase());
+        assert!(a.is_ascii_uppercase());
+        assert!(b.is_ascii_uppercase());
+        let idx = ((a & 0b111) as usize) * 16 + ((b & 0b1111) as usize);
+        self.scores[idx]
+    }
+
+    #[inline]
+    fn as_ptr(&self, i: usize) -> *const i8 {
+        unsafe { self.scores.as_ptr().add((i & 0b111) * 16) }
+    }
+
+    #[inline]
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, right: bool) -> B::Simd {
+        // when shifting right, c is from the reference and v is from the query,
+        // and vice versa when shifting down
+        let matrix_ptr = if right {
+            self.scores.as_ptr().add(((c as usize) & 0b111) * 16)
+        } else {
+            self.scores_t.as_ptr().add(((c as usize) & 0b111) * 16)
+        };
+        let scores = B::halfsimd_load(matrix_ptr as *const B::HalfSimd);
+        B::halfsimd_lookup1_i16(scores, v)
+    }
+
+    #[inline]
+    fn convert_char(c: u8) -> u8 {
+        let c = c.to_ascii_uppercase();
+        assert!((b'A'..=Self::NULL).contains(&c));
+        rna_to_dna(c)
+    }
+
+    fn max_score(&self) -> i8 {
+        score_range(self.scores).1
+    }
+
+    fn min_score(&self) -> i8 {
+        score_range(self.scores).0
+    }
+}
+
+// i8::MIN is reserved for marking pairs of bytes that do not have a score
+
+#[inline]
+fn check_score(s: i32) -> i8 {
+    assert!(s > (i8::MIN as i32) && s <= (i8::MAX as i32), "Score {} does not fit in the scoring range!", s);
+    s as i8
+}
+
+#[inline]
+fn round_score(s: f64) -> i8 {
+    assert!(s.is_finite(), "Score must be finite!");
+    check_score(s.round() as i32)
+}
+
+/// Serialize fixed-size score tables as sequences, since serde does not support large arrays.
+#[cfg(feature = "serde")]
+mod serde_scores {
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+    use serde::de::Error;
+    use std::convert::TryInto;
+
+    pub fn serialize<S: Serializer, const N: usize>(scores: &[i8; N], serializer: S) -> Result<S::Ok, S::Error> {
+        scores[..].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[i8; N], D::Error> {
+        let scores = Vec::<i8>::deserialize(deserializer)?;
+        let len = scores.len();
+        scores.try_into().map_err(|_| D::Error::invalid_length(len, &"a table with one score for each pair of bytes"))
+    }
+}
+
+/// Format the scores between all pairs of residues as an NCBI text matrix.
+fn ncbi_text<M: Matrix>(matrix: &M, residues: &[u8]) -> String {
+    let mut res = String::from(" ");
+    for &c in residues {
+        res.push_str(&format!("{:>3}", c as char));
+    }
+    res.push('\n');
+    for &a in residues {
+        res.push(a as char);
+        for &b in residues {
+            res.push_str(&format!("{:>3}", matrix.get(a, b)));
+        }
+        res.push('\n');
+    }
+    res
+}
+
+/// Min and max scores, ignoring pairs of bytes that do not have a score.
+///
+/// Returns zeros if there are no scores.
+pub(crate) fn score_range<I: IntoIterator<Item = i8>>(scores: I) -> (i8, i8) {
+    scores
+        .into_iter()
+        .filter(|&s| s != i8::MIN)
+        .fold(None, |r, s| match r {
+            Some((min, max)) => Some((cmp::min(min, s), cmp::max(max, s))),
+            None => Some((s, s))
+        })
+        .unwrap_or((0, 0))
+}
+
+fn map_scores<F: Fn(i8) -> i8>(scores: &mut [i8], f: F) {
+    scores.iter_mut().filter(|s| **s != i8::MIN).for_each(|s| *s = f(*s));
+}
+
+fn compose_scores(scores: &mut [i8], other: &[i8]) {
+    for (s, &o) in scores.iter_mut().zip(other.iter()) {
+        if *s == i8::MIN || o == i8::MIN {
+            *s = i8::MIN;
+        } else {
+            *s = check_score((*s as i32) + (o as i32));
+        }
     }
 }
 
 /// Arbitrary bytes scoring matrix.
 #[repr(C)]
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ByteMatrix {
     match_score: i8,
     mismatch_score: i8
@@ -194,6 +879,11 @@ impl ByteMatrix {
     pub const fn new_simple(match_score: i8, mismatch_score: i8) -> Self {
         Self { match_score, mismatch_score }
     }
+
+    /// Get the score for a pair of bytes.
+    pub fn score(&self, a: u8, b: u8) -> i8 {
+        self.get(a, b)
+    }
 }
 
 impl Matrix for ByteMatrix {
@@ -220,19 +910,218 @@ impl Matrix for ByteMatrix {
         unimplemented!()
     }
 
-    #[cfg_attr(feature = "simd_avx2", target_feature(enable = "avx2"))]
-    #[cfg_attr(feature = "simd_wasm", target_feature(enable = "simd128"))]
     #[inline]
-    unsafe fn get_scores(&self, c: u8, v: HalfSimd, _right: bool) -> Simd {
-        let match_scores = halfsimd_set1_i8(self.match_score);
-        let mismatch_scores = halfsimd_set1_i8(self.mismatch_score);
-        halfsimd_lookup_bytes_i16(match_scores, mismatch_scores, halfsimd_set1_i8(c as i8), v)
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, _right: bool) -> B::Simd {
+        let match_scores = B::halfsimd_set1_i8(self.match_score);
+        let mismatch_scores = B::halfsimd_set1_i8(self.mismatch_score);
+        B::halfsimd_lookup_bytes_i16(match_scores, mismatch_scores, B::halfsimd_set1_i8(c as i8), v)
+    }
+
+    #[inline]
+    fn convert_char(c: u8) -> u8 {
+        c
+    }
+
+    fn max_score(&self) -> i8 {
+        cmp::max(self.match_score, self.mismatch_score)
+    }
+
+    fn min_score(&self) -> i8 {
+        cmp::min(self.match_score, self.mismatch_score)
+    }
+}
+
+/// Scoring matrix with a full table of scores, for alphabets of up to 255 arbitrary bytes.
+///
+/// Scores are looked up with SIMD gather instructions, so this is slower than the
+/// shuffle-based lookups in `AAMatrix` and `NucMatrix`, but it supports much larger
+/// alphabets. Unlike `ByteMatrix`, it can be used with x drop alignment.
+/// The null byte is reserved for padding.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FullMatrix {
+    // padded with extra bytes, since 32-bit words are gathered
+    scores: Vec<i8>
+}
+
+impl FullMatrix {
+    /// Create a simple matrix with a certain match and mismatch score.
+    pub fn new_simple(match_score: i8, mismatch_score: i8) -> Self {
+        Self::from_fn(|a, b| if a == b { match_score } else { mismatch_score })
+    }
+
+    /// Create a matrix by calling a function (or closure) on every pair of non-null bytes.
+    ///
+    /// The function must be symmetric.
+    pub fn from_fn<F: Fn(u8, u8) -> i8>(f: F) -> Self {
+        let mut res = Self::new();
+        for a in 1..=u8::MAX {
+            for b in a..=u8::MAX {
+                let score = f(a, b);
+                assert_eq!(score, f(b, a), "Scoring function must be symmetric!");
+                res.set(a, b, score);
+            }
+        }
+        res
+    }
+}
+
+impl Matrix for FullMatrix {
+    const NULL: u8 = b'\0';
+
+    fn new() -> Self {
+        Self { scores: vec![i8::MIN; 256 * 256 + 4] }
+    }
+
+    fn set(&mut self, a: u8, b: u8, score: i8) {
+        assert!(a != Self::NULL && b != Self::NULL, "The null byte is reserved for padding!");
+        self.scores[(a as usize) * 256 + (b as usize)] = score;
+        self.scores[(b as usize) * 256 + (a as usize)] = score;
+    }
+
+    fn get(&self, a: u8, b: u8) -> i8 {
+        self.scores[(a as usize) * 256 + (b as usize)]
+    }
+
+    #[inline]
+    fn as_ptr(&self, i: usize) -> *const i8 {
+        debug_assert!(i < 256);
+        unsafe { self.scores.as_ptr().add(i * 256) }
+    }
+
+    #[inline]
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, _right: bool) -> B::Simd {
+        B::halfsimd_gather_i16(self.as_ptr(c as usize), v)
     }
 
     #[inline]
     fn convert_char(c: u8) -> u8 {
         c
     }
+
+    fn max_score(&self) -> i8 {
+        score_range(self.scores.iter().copied()).1
+    }
+
+    fn min_score(&self) -> i8 {
+        score_range(self.scores.iter().copied()).0
+    }
+}
+
+/// Scoring matrix wrapper that caps the scores within masked query or reference regions.
+///
+/// This is useful for regions like low-complexity sequences or known adapters, where
+/// matches would otherwise produce spuriously high scores. With a cap of zero, matches
+/// within masked regions do not contribute to the score, and with a negative cap, the
+/// alignment path is discouraged from entering masked regions. Mismatches are still
+/// penalized as usual.
+///
+/// Masked regions are specified with 0-based positions in the unpadded strings.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MaskedMatrix<M: Matrix> {
+    matrix: M,
+    max_score: i8,
+    query_mask: Vec<bool>,
+    reference_mask: Vec<bool>
+}
+
+impl<M: Matrix> MaskedMatrix<M> {
+    /// Wrap a matrix so that scores within masked regions are at most `max_score`.
+    pub fn new_masked(matrix: M, max_score: i8) -> Self {
+        Self { matrix, max_score, query_mask: Vec::new(), reference_mask: Vec::new() }
+    }
+
+    /// Mask a region of the query.
+    pub fn mask_query(&mut self, range: Range<usize>) {
+        Self::mask(&mut self.query_mask, range);
+    }
+
+    /// Mask a region of the reference.
+    pub fn mask_reference(&mut self, range: Range<usize>) {
+        Self::mask(&mut self.reference_mask, range);
+    }
+
+    /// Check whether a 0-based query position is masked.
+    #[inline]
+    pub fn is_query_masked(&self, i: usize) -> bool {
+        self.query_mask.get(i).copied().unwrap_or(false)
+    }
+
+    /// Check whether a 0-based reference position is masked.
+    #[inline]
+    pub fn is_reference_masked(&self, j: usize) -> bool {
+        self.reference_mask.get(j).copied().unwrap_or(false)
+    }
+
+    fn mask(mask: &mut Vec<bool>, range: Range<usize>) {
+        assert!(range.start <= range.end, "Masked range must not be reversed!");
+        if mask.len() < range.end {
+            mask.resize(range.end, false);
+        }
+        mask[range].iter_mut().for_each(|m| *m = true);
+    }
+
+    /// Check whether a 1-based padded position pair is masked.
+    #[inline]
+    fn padded_masked(&self, i: usize, j: usize) -> bool {
+        (i > 0 && self.is_query_masked(i - 1)) || (j > 0 && self.is_reference_masked(j - 1))
+    }
+}
+
+impl<M: Matrix> Matrix for MaskedMatrix<M> {
+    const NULL: u8 = M::NULL;
+
+    fn new() -> Self {
+        Self::new_masked(M::new(), 0)
+    }
+
+    fn set(&mut self, a: u8, b: u8, score: i8) {
+        self.matrix.set(a, b, score);
+    }
+
+    /// Get the score for a pair of bytes, ignoring masked regions.
+    fn get(&self, a: u8, b: u8) -> i8 {
+        self.matrix.get(a, b)
+    }
+
+    #[inline]
+    fn as_ptr(&self, i: usize) -> *const i8 {
+        self.matrix.as_ptr(i)
+    }
+
+    #[inline]
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, right: bool) -> B::Simd {
+        self.matrix.get_scores::<B>(c, v, right)
+    }
+
+    #[inline]
+    unsafe fn get_scores_at<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, right: bool, c_idx: usize, v_idx: usize) -> B::Simd {
+        let scores = self.matrix.get_scores_at::<B>(c, v, right, c_idx, v_idx);
+        let (c_mask, v_mask) = if right { (&self.reference_mask, &self.query_mask) } else { (&self.query_mask, &self.reference_mask) };
+        // fast path when none of the positions are masked
+        if c_idx > c_mask.len() && v_idx > v_mask.len() {
+            return scores;
+        }
+
+        let mask = position_scores::<B, _>(right, c_idx, v_idx, |i, j| -(self.padded_masked(i, j) as i16));
+        let capped = B::simd_subs_i16(scores, B::simd_max_i16(B::simd_subs_i16(scores, B::simd_set1_i16(self.max_score as i16)), B::simd_set1_i16(0)));
+        B::simd_blend_i8(scores, capped, mask)
+    }
+
+    #[inline]
+    fn convert_char(c: u8) -> u8 {
+        M::convert_char(c)
+    }
+
+    fn max_score(&self) -> i8 {
+        self.matrix.max_score()
+    }
+
+    /// Get the min score, which can be lowered by the cap on scores in masked regions.
+    fn min_score(&self) -> i8 {
+        let min = self.matrix.min_score();
+        let masked = self.query_mask.iter().chain(&self.reference_mask).any(|&m| m);
+        if masked { cmp::min(min, self.max_score) } else { min }
+    }
 }
 
 /// Match = 1, mismatch = -1.
@@ -240,34 +1129,34 @@ impl Matrix for ByteMatrix {
 pub static NW1: NucMatrix = NucMatrix::new_simple(1, -1);
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static BLOSUM45: AAMatrix = AAMatrix { scores: include!("../matrices/BLOSUM45") };
+pub static BLOSUM45: AAMatrix = AAMatrix::from_table(include!("../matrices/BLOSUM45"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static BLOSUM50: AAMatrix = AAMatrix { scores: include!("../matrices/BLOSUM50") };
+pub static BLOSUM50: AAMatrix = AAMatrix::from_table(include!("../matrices/BLOSUM50"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static BLOSUM62: AAMatrix = AAMatrix { scores: include!("../matrices/BLOSUM62") };
+pub static BLOSUM62: AAMatrix = AAMatrix::from_table(include!("../matrices/BLOSUM62"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static BLOSUM80: AAMatrix = AAMatrix { scores: include!("../matrices/BLOSUM80") };
+pub static BLOSUM80: AAMatrix = AAMatrix::from_table(include!("../matrices/BLOSUM80"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static BLOSUM90: AAMatrix = AAMatrix { scores: include!("../matrices/BLOSUM90") };
+pub static BLOSUM90: AAMatrix = AAMatrix::from_table(include!("../matrices/BLOSUM90"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static PAM100: AAMatrix = AAMatrix { scores: include!("../matrices/PAM100") };
+pub static PAM100: AAMatrix = AAMatrix::from_table(include!("../matrices/PAM100"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static PAM120: AAMatrix = AAMatrix { scores: include!("../matrices/PAM120") };
+pub static PAM120: AAMatrix = AAMatrix::from_table(include!("../matrices/PAM120"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static PAM160: AAMatrix = AAMatrix { scores: include!("../matrices/PAM160") };
+pub static PAM160: AAMatrix = AAMatrix::from_table(include!("../matrices/PAM160"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static PAM200: AAMatrix = AAMatrix { scores: include!("../matrices/PAM200") };
+pub static PAM200: AAMatrix = AAMatrix::from_table(include!("../matrices/PAM200"));
 
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
-pub static PAM250: AAMatrix = AAMatrix { scores: include!("../matrices/PAM250") };
+pub static PAM250: AAMatrix = AAMatrix::from_table(include!("../matrices/PAM250"));
 
 /// Match = 1, mismatch = -1.
 #[cfg_attr(not(target_arch = "wasm32"), no_mangle)]
@@ -299,3 +1188,287 @@ pub struct Gaps {
     pub open: i8,
     pub extend: i8
 }
+
+/// Fixed-point representation for fractional scores, like log-odds scores or scores
+/// from machine-learned models.
+///
+/// Block aligner uses small integer scores, so fractional scores are multiplied by a
+/// scale factor and rounded. Using the largest scale factor where every score still fits
+/// minimizes the rounding error. Alignment scores can then be converted back with `unscale`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FixedPoint {
+    scale: f64
+}
+
+impl FixedPoint {
+    /// Create a fixed-point representation with a certain scale factor.
+    pub fn new(scale: f64) -> Self {
+        assert!(scale.is_finite() && scale > 0.0, "Scale must be positive!");
+        Self { scale }
+    }
+
+    /// Create a fixed-point representation with the largest scale factor that allows all
+    /// of the scores and gap costs to fit.
+    pub fn fit(scores: &[f64], gap_open: f64, gap_extend: f64) -> Self {
+        let max_abs = scores
+            .iter()
+            .chain([gap_open, gap_extend].iter())
+            .fold(0f64, |m, &s| m.max(s.abs()));
+        assert!(max_abs > 0.0, "Scores must not all be zero!");
+        // round down to avoid going out of bounds due to floating point error
+        Self::new(((i8::MAX as f64) / max_abs) * (1.0 - 1e-9))
+    }
+
+    /// The scale factor.
+    #[inline]
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Convert a fractional score to a fixed-point score.
+    #[inline]
+    pub fn score(&self, score: f64) -> i8 {
+        round_score(score * self.scale)
+    }
+
+    /// Convert fractional gap costs to fixed-point gap costs.
+    ///
+    /// Gap costs are rounded down to at least -1, so they are always negative.
+    pub fn gaps(&self, open: f64, extend: f64) -> Gaps {
+        let open = self.score(open).min(-1);
+        let extend = self.score(extend).min(-1);
+        Gaps { open: open.min(extend), extend }
+    }
+
+    /// Convert a fixed-point alignment score back to a fractional score.
+    #[inline]
+    pub fn unscale(&self, score: i32) -> f64 {
+        (score as f64) / self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_point() {
+        let log_odds = [0.9, -0.35, -0.35, 0.9];
+        let fp = FixedPoint::fit(&log_odds, -1.2, -0.4);
+        assert_eq!(fp.score(-1.2), -127);
+        assert_eq!(fp.gaps(-1.2, -0.4), Gaps { open: -127, extend: -42 });
+        let m = NucMatrix::from_log_odds(b"AC", &log_odds, fp.scale());
+        assert_eq!(m.get(b'A', b'A'), 95);
+        assert!((fp.unscale(95) - 0.9).abs() < 0.01);
+
+        let fp = FixedPoint::new(10.0);
+        assert_eq!(fp.score(0.26), 3);
+        assert_eq!(fp.gaps(-0.01, -0.01), Gaps { open: -1, extend: -1 });
+    }
+
+    #[test]
+    fn test_matrix_arithmetic() {
+        let m = NucMatrix::new_simple(1, -1).scale(2.0).shift(1);
+        assert_eq!(m.get(b'A', b'A'), 3);
+        assert_eq!(m.get(b'A', b'C'), -1);
+        assert_eq!(m.get(b'A', b'Z'), i8::MIN);
+
+        let m = m.compose(&NW1);
+        assert_eq!(m.get(b'A', b'A'), 4);
+        assert_eq!(m.get(b'A', b'C'), -2);
+
+        let m = AAMatrix::from_log_odds(b"AR", &[0.9, -0.4, -0.4, 1.2], 4.0);
+        assert_eq!(m.get(b'A', b'A'), 4);
+        assert_eq!(m.get(b'A', b'R'), -2);
+        assert_eq!(m.get(b'R', b'R'), 5);
+    }
+
+    #[test]
+    fn test_from_dense() {
+        let residues = b"ARNDCQEGHILKMFPSTWYV";
+        let mut scores = [0i8; 400];
+        for i in 0..20 {
+            for j in 0..20 {
+                scores[i * 20 + j] = BLOSUM62.get(residues[i], residues[j]);
+            }
+        }
+
+        let m = AAMatrix::from_dense(residues, &scores, -1);
+        for &a in residues.iter() {
+            for &b in residues.iter() {
+                assert_eq!(m.get(a, b), BLOSUM62.get(a, b));
+            }
+        }
+        assert_eq!(m.get(b'X', b'A'), -1);
+        assert_eq!(m.get(b'W', b'B'), -1);
+        assert_eq!(m.get(b'X', b'X'), -1);
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let m = AAMatrix::from_fn(|a, b| BLOSUM62.get(a, b));
+        assert_eq!(m, BLOSUM62);
+
+        let m = NucMatrix::from_fn(|a, b| if a == b { 1 } else { -1 });
+        assert_eq!(m, NW1);
+
+        let hydrophobic = |c: u8| b"AVILMFWC".contains(&c);
+        let m = AAMatrix::from_fn(|a, b| if a == b { 3 } else if hydrophobic(a) && hydrophobic(b) { 1 } else { -2 });
+        assert_eq!(m.get(b'I', b'L'), 1);
+        assert_eq!(m.get(b'K', b'L'), -2);
+        assert_eq!(m.get(b'K', b'K'), 3);
+    }
+
+    #[test]
+    fn test_matrix_export() {
+        assert_eq!(BLOSUM62.score(b'A', b'R'), -1);
+        assert_eq!(BLOSUM62.pairs().count(), 27 * 27);
+        assert!(BLOSUM62.pairs().all(|(a, b, s)| s == BLOSUM62.score(b, a)));
+
+        let text = BLOSUM62.to_ncbi();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 28);
+        assert!(lines[0].starts_with("   A  B  C  D"));
+        assert!(lines[1].starts_with("A  4 -2  0 -2"));
+        assert!(lines[27].ends_with("  1"));
+
+        let m = NucMatrix::new_ts_tv(2, -1, -3);
+        assert_eq!(m.to_ncbi(), "   A  C  G  T  N\nA  2 -3 -1 -3 -3\nC -3  2 -3 -1 -3\nG -1 -3  2 -3 -3\nT -3 -1 -3  2 -3\nN -3 -3 -3 -3  2\n");
+        let pairs = m.pairs().collect::<Vec<_>>();
+        assert_eq!(pairs[1], (b'A', b'C', -3));
+        assert_eq!(BYTES1.score(b'x', b'y'), -1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let m = BLOSUM62.with_stop(2, -8);
+        let json = serde_json::to_string(&m).unwrap();
+        assert_eq!(serde_json::from_str::<AAMatrix>(&json).unwrap(), m);
+
+        let m = NucMatrix::new_ts_tv(2, -1, -3);
+        assert_eq!(serde_json::from_str::<NucMatrix>(&serde_json::to_string(&m).unwrap()).unwrap(), m);
+        assert!(serde_json::from_str::<NucMatrix>("{\"scores\":[1,2,3]}").is_err());
+
+        let json = serde_json::to_string(&BYTES1).unwrap();
+        assert_eq!(json, "{\"match_score\":1,\"mismatch_score\":-1}");
+        assert_eq!(serde_json::from_str::<ByteMatrix>(&json).unwrap(), BYTES1);
+    }
+
+    #[test]
+    fn test_extended_aa() {
+        use crate::scan_block::*;
+
+        assert_eq!((BLOSUM62.get(b'U', b'C'), BLOSUM62.get(b'U', b'U')), (9, 9));
+        assert_eq!((BLOSUM62.get(b'O', b'K'), BLOSUM62.get(b'O', b'A')), (5, -1));
+        assert_eq!((BLOSUM62.get(b'J', b'I'), BLOSUM62.get(b'J', b'L'), BLOSUM62.get(b'J', b'J')), (3, 3, 3));
+        assert_eq!((BLOSUM62.get(b'*', b'A'), BLOSUM62.get(b'*', b'*')), (-4, 1));
+        assert_eq!((PAM250.get(b'*', b'W'), PAM250.get(b'J', b'*')), (-8, -8));
+
+        let m = BLOSUM62.with_stop(0, -10).with_residue_like(b'U', b'S');
+        assert_eq!((m.get(b'*', b'*'), m.get(b'*', b'U')), (0, -10));
+        assert_eq!((m.get(b'U', b'S'), m.get(b'U', b'U'), m.get(b'U', b'C')), (4, 4, -1));
+
+        let gaps = Gaps { open: -11, extend: -1 };
+        let q = PaddedBytes::from_bytes::<AAMatrix>(b"MKU*", 16);
+        let r = PaddedBytes::from_bytes::<AAMatrix>(b"MKC*", 16);
+        let a = Block::<_, false, false>::align(&q, &r, &BLOSUM62, gaps, 16..=16, 0);
+        assert_eq!(a.res().score, 5 + 5 + 9 + 1);
+    }
+
+    #[test]
+    fn test_ts_tv() {
+        let m = NucMatrix::new_ts_tv(2, -1, -3);
+        assert_eq!(m.get(b'A', b'A'), 2);
+        assert_eq!(m.get(b'A', b'G'), -1);
+        assert_eq!(m.get(b'T', b'C'), -1);
+        assert_eq!(m.get(b'A', b'C'), -3);
+        assert_eq!(m.get(b'G', b'T'), -3);
+        assert_eq!(m.get(b'N', b'A'), -3);
+    }
+
+    #[test]
+    fn test_n_score() {
+        assert_eq!(NW1.get(b'N', b'N'), 1);
+        assert_eq!(NW1.get(b'N', b'A'), -1);
+
+        let m = NW1.with_n(NScore::Zero);
+        assert_eq!(m.get(b'N', b'N'), 0);
+        assert_eq!(m.get(b'G', b'N'), 0);
+        assert_eq!(m.get(b'G', b'G'), 1);
+
+        let m = NucMatrix::new_ts_tv(2, -1, -3).with_n(NScore::Mismatch);
+        assert_eq!(m.get(b'N', b'N'), -3);
+        assert_eq!(m.get(b'A', b'N'), -3);
+
+        let m = NucMatrix::new_simple(2, -4).with_n(NScore::Score(1));
+        assert_eq!(m.get(b'N', b'T'), 1);
+        assert_eq!(m.get(b'T', b'N'), 1);
+        assert_eq!(m.get(b'T', b'A'), -4);
+    }
+
+    #[test]
+    fn test_x_score() {
+        assert_eq!(BLOSUM62.get(b'X', b'X'), -1);
+        assert_eq!(BLOSUM62.get(b'X', b'W'), -2);
+
+        let m = BLOSUM62.with_x(XScore::Zero);
+        assert_eq!(m.get(b'X', b'X'), 0);
+        assert_eq!(m.get(b'W', b'x'), 0);
+        assert_eq!(m.get(b'X', b'*'), BLOSUM62.get(b'X', b'*'));
+        assert_eq!(m.get(b'W', b'W'), 11);
+
+        let m = BLOSUM62.with_x(XScore::Mismatch);
+        assert_eq!(m.get(b'X', b'A'), -4);
+        let m = BLOSUM62.with_x(XScore::Score(-2));
+        assert_eq!(m.get(b'C', b'X'), -2);
+        assert_eq!(m.min_score(), -4);
+    }
+
+    #[test]
+    fn test_rna() {
+        assert_eq!(NW1.get(b'U', b'T'), 1);
+        assert_eq!(NW1.get(b'u', b'A'), -1);
+        assert_eq!(NucMatrix::convert_char(b'u'), b'T');
+        assert_eq!(AsymNucMatrix::complement_query(&NW1).get(b'A', b'U'), 1);
+    }
+
+    #[test]
+    fn test_bisulfite() {
+        let m = AsymNucMatrix::new_bisulfite(1, -1, false);
+        assert_eq!(m.get(b'C', b'T'), 1);
+        assert_eq!(m.get(b'T', b'C'), -1);
+        assert_eq!(m.get(b'G', b'A'), -1);
+        assert_eq!(m.get(b'A', b'A'), 1);
+    }
+
+    #[test]
+    fn test_complement_query() {
+        let m = AsymNucMatrix::complement_query(&NW1);
+        assert_eq!(m.get(b'A', b'T'), 1);
+        assert_eq!(m.get(b'C', b'G'), 1);
+        assert_eq!(m.get(b'A', b'A'), -1);
+        assert_eq!(m.get(b'N', b'N'), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_matrix_out_of_range() {
+        BLOSUM62.scale(100.0);
+    }
+
+    #[test]
+    fn test_score_range() {
+        assert_eq!((BLOSUM62.min_score(), BLOSUM62.max_score()), (-4, 11));
+        assert_eq!((NW1.min_score(), NW1.max_score()), (-1, 1));
+        assert_eq!((BYTES1.min_score(), BYTES1.max_score()), (-1, 1));
+        let m = NucMatrix::new_ts_tv(2, -1, -3);
+        assert_eq!((m.min_score(), m.max_score()), (-3, 2));
+        assert_eq!(FullMatrix::new_simple(3, -2).max_score(), 3);
+
+        let mut m = MaskedMatrix::new_masked(NW1.clone(), -5);
+        assert_eq!(m.min_score(), -1);
+        m.mask_query(0..1);
+        assert_eq!(m.min_score(), -5);
+    }
+}
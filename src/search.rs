@@ -0,0 +1,175 @@
+//! Searching queries against a database of reference sequences.
+//!
+//! Each query is first aligned against every reference with X-drop alignment that is
+//! limited to the minimum block size, which is cheap and acts as a prefilter. Only the
+//! best candidates are then realigned with the full range of block sizes.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::multi::*;
+use crate::fasta::*;
+
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Database of padded reference sequences and their IDs.
+pub struct Database {
+    ids: Vec<String>,
+    references: Vec<PaddedBytes>,
+    total_len: usize
+}
+
+impl Database {
+    /// Create a database from FASTA or FASTQ records.
+    ///
+    /// `block_size` is used for padding, so it should be the max block size used for searching.
+    pub fn from_records<M: Matrix>(records: &[Record], block_size: usize) -> Self {
+        let ids = records.iter().map(|r| r.id.clone()).collect();
+        let references = records.iter().map(|r| PaddedBytes::from_bytes::<M>(&r.seq, block_size)).collect();
+        let total_len = records.iter().map(|r| r.seq.len()).sum();
+        Self { ids, references, total_len }
+    }
+
+    /// Load a database from a FASTA or FASTQ file.
+    pub fn from_file<M: Matrix, P: AsRef<Path>>(path: P, block_size: usize) -> io::Result<Self> {
+        Ok(Self::from_records::<M>(&read_file(path)?, block_size))
+    }
+
+    /// Number of references in the database.
+    pub fn len(&self) -> usize {
+        self.references.len()
+    }
+
+    /// Whether the database has no references.
+    pub fn is_empty(&self) -> bool {
+        self.references.is_empty()
+    }
+
+    /// Total length of all references, which can be used for computing e-values.
+    pub fn total_len(&self) -> usize {
+        self.total_len
+    }
+
+    /// Get the ID of a reference.
+    pub fn id(&self, i: usize) -> &str {
+        &self.ids[i]
+    }
+
+    /// Get the padded references.
+    pub fn references(&self) -> &[PaddedBytes] {
+        &self.references
+    }
+}
+
+/// Data structure storing the settings for searching queries against a database.
+pub struct Searcher<'a, M: 'static + Matrix + Sync> {
+    matrix: &'a M,
+    gaps: Gaps,
+    size: RangeInclusive<usize>,
+    x_drop: i32,
+    prefilter: Option<(usize, i32)>,
+    top_k: usize,
+    threads: Option<usize>
+}
+
+impl<'a, M: 'static + Matrix + Sync> Searcher<'a, M> {
+    /// Create a new searcher that aligns with X-drop alignment.
+    ///
+    /// By default, there is no prefilter, only the single best hit is kept for each
+    /// query, and all available threads are used.
+    pub fn new(matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>, x_drop: i32) -> Self {
+        Self { matrix, gaps, size, x_drop, prefilter: None, top_k: 1, threads: None }
+    }
+
+    /// Set the number of hits to keep for each query.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Set the number of worker threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "Number of threads must be positive!");
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Enable the prefilter, which aligns with only the minimum block size and keeps at most
+    /// `candidates` references with a score of at least `min_score` for each query.
+    pub fn prefilter(mut self, candidates: usize, min_score: i32) -> Self {
+        assert!(candidates > 0, "Number of prefilter candidates must be positive!");
+        self.prefilter = Some((candidates, min_score));
+        self
+    }
+
+    /// Search every query against the database.
+    ///
+    /// Returns the top hits for each query, sorted by decreasing score.
+    pub fn search(&self, queries: &[PaddedBytes], db: &Database) -> Vec<Vec<Hit>> {
+        let aligner = self.aligner(self.size.clone()).top_k(self.top_k);
+
+        match self.prefilter {
+            Some((candidates, min_score)) => {
+                let min_size = *self.size.start();
+                let candidates = self
+                    .aligner(min_size..=min_size)
+                    .top_k(candidates)
+                    .min_score(min_score)
+                    .align_all(queries, db.references());
+                let pairs = candidates
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(q, hits)| hits.iter().map(move |h| (q, h.reference)))
+                    .collect::<Vec<_>>();
+                aligner.align_pairs(queries, db.references(), &pairs)
+            },
+            None => aligner.align_all(queries, db.references())
+        }
+    }
+
+    fn aligner(&self, size: RangeInclusive<usize>) -> MultiAligner<'a, M> {
+        let aligner = MultiAligner::new(self.matrix, self.gaps, size, self.x_drop);
+        match self.threads {
+            Some(threads) => aligner.threads(threads),
+            None => aligner
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_search() {
+        let gaps = Gaps { open: -11, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        let records = (0..20)
+            .map(|i| Record { id: format!("r{}", i), seq: rand_str(200, &AMINO_ACIDS, &mut rng), qual: None })
+            .collect::<Vec<_>>();
+        let db = Database::from_records::<AAMatrix>(&records, 64);
+        assert_eq!(db.len(), 20);
+        assert_eq!(db.total_len(), 20 * 200);
+        assert_eq!(db.id(3), "r3");
+
+        let queries = [7, 13]
+            .iter()
+            .map(|&i| PaddedBytes::from_bytes::<AAMatrix>(&rand_mutate(&records[i].seq, 10, &AMINO_ACIDS, &mut rng), 64))
+            .collect::<Vec<_>>();
+
+        let searcher = Searcher::new(&BLOSUM62, gaps, 16..=64, 50).top_k(3).threads(2);
+        let res = searcher.search(&queries, &db);
+        assert_eq!(res[0][0].reference, 7);
+        assert_eq!(res[1][0].reference, 13);
+        assert_eq!(res[0].len(), 3);
+
+        // the prefilter keeps the true hits, but drops the random ones
+        let res_prefilter = searcher.prefilter(2, 100).search(&queries, &db);
+        assert_eq!(res_prefilter[0], res[0][..1].to_vec());
+        assert_eq!(res_prefilter[1], res[1][..1].to_vec());
+    }
+}
@@ -1,5 +1,7 @@
 use std::arch::wasm32::*;
 
+use crate::backend::SimdBackend;
+
 pub type Simd = v128;
 // no v64 type, so HalfSimd is just v128 with upper half ignored
 pub type HalfSimd = v128;
@@ -11,6 +13,13 @@ pub const HALFSIMD_MUL: usize = 2;
 pub const ZERO: i16 = 1 << 14;
 pub const MIN: i16 = 0;
 
+/// Name of the instruction set.
+pub const NAME: &str = "simd128";
+
+/// Check whether the CPU that is running the code supports the instruction set.
+#[inline]
+pub fn is_supported() -> bool { cfg!(target_feature = "simd128") }
+
 // Note: SIMD vectors treated as little-endian
 
 // No non-temporal store in WASM
@@ -290,6 +299,16 @@ pub unsafe fn halfsimd_lookup_bytes_i16(match_scores: HalfSimd, mismatch_scores:
     i16x8_extend_low_i8x16(c)
 }
 
+#[target_feature(enable = "simd128")]
+#[inline]
+pub unsafe fn halfsimd_gather_i16(table: *const i8, v: HalfSimd) -> Simd {
+    // no gather instruction in WASM
+    let mut idx = [0u8; 16];
+    v128_store(idx.as_mut_ptr() as _, v);
+    let t = |i: usize| *table.add(idx[i] as usize) as i16;
+    i16x8(t(0), t(1), t(2), t(3), t(4), t(5), t(6), t(7))
+}
+
 #[target_feature(enable = "simd128")]
 #[inline]
 pub unsafe fn halfsimd_load(ptr: *const HalfSimd) -> HalfSimd { v128_load(ptr) }
@@ -337,16 +356,23 @@ macro_rules! halfsimd_sr_i8 {
 #[target_feature(enable = "simd128")]
 #[allow(dead_code)]
 pub unsafe fn simd_dbg_i16(v: Simd) {
+    println!("{}", simd_fmt_i16(v));
+}
+
+#[target_feature(enable = "simd128")]
+#[allow(dead_code)]
+pub unsafe fn simd_fmt_i16(v: Simd) -> String {
     #[repr(align(16))]
     struct A([i16; L]);
 
     let mut a = A([0i16; L]);
     simd_store(a.0.as_mut_ptr() as *mut Simd, v);
 
+    let mut res = String::new();
     for i in (0..a.0.len()).rev() {
-        print!("{:6} ", a.0[i]);
+        res.push_str(&format!("{:6} ", a.0[i]));
     }
-    println!();
+    res
 }
 
 #[target_feature(enable = "simd128")]
@@ -386,6 +412,85 @@ pub unsafe fn halfsimd_assert_vec_eq(a: HalfSimd, b: [i8; L]) {
     assert_eq!(&arr.0[..L], b);
 }
 
+/// Marker type for the WASM SIMD backend.
+#[derive(Copy, Clone, Debug)]
+pub struct Simd128;
+
+// shuffles need literal lane indexes, so const generic arguments are matched against
+// every possible value, and only the matching arm is compiled
+macro_rules! match_imm {
+    ($n:expr, $imm:ident in [$($v:literal),*] => $body:expr) => {
+        match $n {
+            $($v => { const $imm: usize = $v; $body })*
+            _ => unreachable!()
+        }
+    };
+}
+
+unsafe impl SimdBackend for Simd128 {
+    type Simd = Simd;
+    type HalfSimd = HalfSimd;
+    type PrefixScanConsts = PrefixScanConsts;
+
+    const NAME: &'static str = NAME;
+
+    #[inline]
+    fn is_supported() -> bool { is_supported() }
+
+    #[target_feature(enable = "simd128")]
+    #[inline]
+    unsafe fn with_features<R, F: FnOnce() -> R>(f: F) -> R { f() }
+
+    #[inline] unsafe fn store_trace(ptr: *mut TraceType, trace: TraceType) { store_trace(ptr, trace) }
+    #[inline] unsafe fn simd_adds_i16(a: Simd, b: Simd) -> Simd { simd_adds_i16(a, b) }
+    #[inline] unsafe fn simd_subs_i16(a: Simd, b: Simd) -> Simd { simd_subs_i16(a, b) }
+    #[inline] unsafe fn simd_max_i16(a: Simd, b: Simd) -> Simd { simd_max_i16(a, b) }
+    #[inline] unsafe fn simd_cmpeq_i16(a: Simd, b: Simd) -> Simd { simd_cmpeq_i16(a, b) }
+    #[inline] unsafe fn simd_blend_i8(a: Simd, b: Simd, mask: Simd) -> Simd { simd_blend_i8(a, b, mask) }
+    #[inline] unsafe fn simd_load(ptr: *const Simd) -> Simd { simd_load(ptr) }
+    #[inline] unsafe fn simd_store(ptr: *mut Simd, a: Simd) { simd_store(ptr, a) }
+    #[inline] unsafe fn simd_set1_i16(v: i16) -> Simd { simd_set1_i16(v) }
+
+    #[inline]
+    unsafe fn simd_extract_i16<const I: usize>(a: Simd) -> i16 {
+        match_imm!(I, J in [0, 1, 2, 3, 4, 5, 6, 7] => simd_extract_i16!(a, J))
+    }
+
+    #[inline]
+    unsafe fn simd_insert_i16<const I: usize>(a: Simd, v: i16) -> Simd {
+        match_imm!(I, J in [0, 1, 2, 3, 4, 5, 6, 7] => simd_insert_i16!(a, v, J))
+    }
+
+    #[inline] unsafe fn simd_movemask_i8(a: Simd) -> u32 { simd_movemask_i8(a) as u32 }
+
+    #[inline]
+    unsafe fn simd_sl_i16<const N: usize>(a: Simd, b: Simd) -> Simd {
+        match_imm!(N, M in [1, 2, 3, 4, 5, 6, 7, 8] => simd_sl_i16!(a, b, M))
+    }
+
+    #[inline]
+    unsafe fn simd_sr_i16<const N: usize>(a: Simd, b: Simd) -> Simd {
+        match_imm!(N, M in [1, 2, 3, 4, 5, 6, 7, 8] => simd_sr_i16!(a, b, M))
+    }
+
+    #[inline] unsafe fn simd_broadcasthi_i16(v: Simd) -> Simd { simd_broadcasthi_i16(v) }
+    #[inline] unsafe fn simd_slow_extract_i16(v: Simd, i: usize) -> i16 { simd_slow_extract_i16(v, i) }
+    #[inline] unsafe fn simd_hmax_i16(v: Simd) -> i16 { simd_hmax_i16(v) }
+    #[inline] unsafe fn simd_prefix_hadd_i16<const N: usize>(a: Simd) -> i16 { simd_prefix_hadd_i16!(a, N) }
+    #[inline] unsafe fn simd_hargmax_i16(v: Simd, max: i16) -> usize { simd_hargmax_i16(v, max) }
+    #[inline] unsafe fn get_gap_extend_all(gap: i16) -> Simd { get_gap_extend_all(gap) }
+    #[inline] unsafe fn get_prefix_scan_consts(gap: i16) -> PrefixScanConsts { get_prefix_scan_consts(gap) }
+    #[inline] unsafe fn simd_prefix_scan_i16(r_max: Simd, consts: PrefixScanConsts) -> Simd { simd_prefix_scan_i16(r_max, consts) }
+    #[inline] unsafe fn halfsimd_lookup2_i16(lut1: HalfSimd, lut2: HalfSimd, v: HalfSimd) -> Simd { halfsimd_lookup2_i16(lut1, lut2, v) }
+    #[inline] unsafe fn halfsimd_lookup1_i16(lut: HalfSimd, v: HalfSimd) -> Simd { halfsimd_lookup1_i16(lut, v) }
+    #[inline] unsafe fn halfsimd_lookup_bytes_i16(match_scores: HalfSimd, mismatch_scores: HalfSimd, a: HalfSimd, b: HalfSimd) -> Simd { halfsimd_lookup_bytes_i16(match_scores, mismatch_scores, a, b) }
+    #[inline] unsafe fn halfsimd_gather_i16(table: *const i8, v: HalfSimd) -> Simd { halfsimd_gather_i16(table, v) }
+    #[inline] unsafe fn halfsimd_load(ptr: *const HalfSimd) -> HalfSimd { halfsimd_load(ptr) }
+    #[inline] unsafe fn halfsimd_loadu(ptr: *const HalfSimd) -> HalfSimd { halfsimd_loadu(ptr) }
+    #[inline] unsafe fn halfsimd_set1_i8(v: i8) -> HalfSimd { halfsimd_set1_i8(v) }
+    #[inline] unsafe fn simd_fmt_i16(v: Simd) -> String { simd_fmt_i16(v) }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
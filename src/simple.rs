@@ -0,0 +1,45 @@
+//! Convenience functions for simple use cases.
+//!
+//! These take unpadded strings and handle padding, const generics, and traceback
+//! internally. For more control (for example, X-drop alignment or reusing padded
+//! strings), use `Block` directly.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::ops::RangeInclusive;
+
+/// Globally align two strings and return the score.
+///
+/// `size` is the range of block sizes, like in `Block::align`.
+pub fn align_score<M: 'static + Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>) -> i32 {
+    let q = PaddedBytes::from_bytes::<M>(query, *size.end());
+    let r = PaddedBytes::from_bytes::<M>(reference, *size.end());
+    Block::<_, false, false>::align(&q, &r, matrix, gaps, size, 0).res().score
+}
+
+/// Globally align two strings and return the score and the CIGAR string.
+///
+/// `size` is the range of block sizes, like in `Block::align`.
+pub fn align_with_cigar<M: 'static + Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>) -> (i32, Cigar) {
+    let q = PaddedBytes::from_bytes::<M>(query, *size.end());
+    let r = PaddedBytes::from_bytes::<M>(reference, *size.end());
+    let a = Block::<_, true, false>::align(&q, &r, matrix, gaps, size, 0);
+    let res = a.res();
+    (res.score, a.trace().cigar(res.query_idx, res.reference_idx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        assert_eq!(align_score(b"TTTTTTTTAAAAAAATTTTTTTTT", b"TTAAAAAAATTTTTTTTTTTT", &NW1, gaps, 16..=16), 7);
+        let (score, cigar) = align_with_cigar(b"TTTTTTTTAAAAAAATTTTTTTTT", b"TTAAAAAAATTTTTTTTTTTT", &NW1, gaps, 16..=16);
+        assert_eq!(score, 7);
+        assert_eq!(cigar.to_string(), "2M6I16M3D");
+    }
+}
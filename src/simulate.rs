@@ -1,6 +1,10 @@
-//! Utility functions for simulating random sequences.
+//! Utility functions for simulating random sequences and evaluating alignments against
+//! the ground truth.
+
+use crate::cigar::*;
 
 use rand::prelude::*;
+use rand::distributions::WeightedIndex;
 
 /// All 20 amino acids.
 pub static AMINO_ACIDS: [u8; 20] = [
@@ -40,6 +44,30 @@ pub fn rand_mutate_insert<R: Rng>(a: &[u8], k: usize, alpha: &[u8], insert_len:
 
 /// Given an input byte string, craete a randomly mutated copy.
 pub fn rand_mutate<R: Rng>(a: &[u8], k: usize, alpha: &[u8], rng: &mut R) -> Vec<u8> {
+    mutate(a, k, alpha, rng, None)
+}
+
+/// Given an input byte string, create a randomly mutated copy, along with the
+/// ground-truth alignment of the mutated copy (query) against the input (reference).
+///
+/// This uses the same random choices as `rand_mutate`, so the mutated copy is the same.
+pub fn rand_mutate_with_cigar<R: Rng>(a: &[u8], k: usize, alpha: &[u8], rng: &mut R) -> (Vec<u8>, Cigar) {
+    let mut ops = Vec::with_capacity(a.len() + k);
+    let b = mutate(a, k, alpha, rng, Some(&mut ops));
+    (b, ops_to_cigar(&ops))
+}
+
+/// Convert a list of operations in order into a CIGAR string.
+pub(crate) fn ops_to_cigar(ops: &[Operation]) -> Cigar {
+    let mut cigar = unsafe { Cigar::new(ops.len()) };
+    // operations are added in reverse order
+    for &op in ops.iter().rev() {
+        unsafe { cigar.add(op); }
+    }
+    cigar
+}
+
+fn mutate<R: Rng>(a: &[u8], k: usize, alpha: &[u8], rng: &mut R, ops: Option<&mut Vec<Operation>>) -> Vec<u8> {
     let mut edits = vec![0u8; a.len()];
     let curr_k: usize = rng.gen_range(k * 3 / 4..k + 1);
     let mut idx: Vec<usize> = (0usize..a.len()).collect();
@@ -50,24 +78,74 @@ pub fn rand_mutate<R: Rng>(a: &[u8], k: usize, alpha: &[u8], rng: &mut R) -> Vec
         edits[idx[i]] = rng.gen_range(1u8..4u8);
     }
 
+    apply_edits(a, &edits, alpha, rng, ops)
+}
+
+/// Given an input byte string, generate a mutated copy with a target percent identity
+/// and fraction of edits that are indels, along with the ground-truth alignment of the
+/// mutated copy (query) against the input (reference).
+///
+/// The identity is the number of matches over the number of alignment columns in the
+/// ground-truth alignment. Indels are split evenly between insertions and deletions,
+/// and they all have length 1.
+pub fn rand_mutate_identity<R: Rng>(a: &[u8], identity: f32, indel_frac: f32, alpha: &[u8], rng: &mut R) -> (Vec<u8>, Cigar) {
+    assert!((0.0..=1.0).contains(&identity), "Identity must be between 0 and 1!");
+    assert!((0.0..=1.0).contains(&indel_frac), "Indel fraction must be between 0 and 1!");
+
+    // with k edits where k * indel_frac / 2 are insertions, there are
+    // len + k * indel_frac / 2 alignment columns and k of them are not matches
+    let diverge = 1.0 - (identity as f64);
+    let k = (diverge * (a.len() as f64) / (1.0 - diverge * (indel_frac as f64) / 2.0)).round() as usize;
+    let k = k.min(a.len());
+    let indels = ((k as f64) * (indel_frac as f64)).round() as usize;
+    let ins = indels / 2 + (indels % 2 == 1 && rng.gen::<bool>()) as usize;
+
+    let mut edits = vec![0u8; a.len()];
+    let mut idx: Vec<usize> = (0usize..a.len()).collect();
+    idx.shuffle(rng);
+    for (i, &j) in idx[..k].iter().enumerate() {
+        edits[j] = if i < ins { 2u8 } else if i < indels { 3u8 } else { 1u8 };
+    }
+
+    let mut ops = Vec::with_capacity(a.len() + ins);
+    let b = apply_edits(a, &edits, alpha, rng, Some(&mut ops));
+    (b, ops_to_cigar(&ops))
+}
+
+/// Generate a random reference string and a mutated query string with a target percent
+/// identity and indel fraction, along with the ground-truth alignment.
+///
+/// Returns the query, reference, and the CIGAR string. See `rand_mutate_identity`.
+pub fn rand_pair_identity<R: Rng>(len: usize, identity: f32, indel_frac: f32, alpha: &[u8], rng: &mut R) -> (Vec<u8>, Vec<u8>, Cigar) {
+    let r = rand_str(len, alpha, rng);
+    let (q, cigar) = rand_mutate_identity(&r, identity, indel_frac, alpha, rng);
+    (q, r, cigar)
+}
+
+fn apply_edits<R: Rng>(a: &[u8], edits: &[u8], alpha: &[u8], rng: &mut R, mut ops: Option<&mut Vec<Operation>>) -> Vec<u8> {
     let mut b = vec![];
+    let mut push_op = |op: Operation| if let Some(ops) = ops.as_mut() { ops.push(op); };
 
     for i in 0..a.len() {
         match edits[i] {
             0u8 => { // same
                 b.push(a[i]);
+                push_op(Operation::M);
             },
             1u8 => { // diff
                 let mut iter = alpha.choose_multiple(rng, 2);
                 let first = *iter.next().unwrap();
                 let second = *iter.next().unwrap();
                 b.push(if first == a[i] { second } else { first });
+                push_op(Operation::M);
             },
             2u8 => { // insert
                 b.push(*alpha.choose(rng).unwrap());
                 b.push(a[i]);
+                push_op(Operation::I);
+                push_op(Operation::M);
             },
-            3u8 => (), // delete
+            3u8 => push_op(Operation::D), // delete
             _ => panic!("This should not have been reached!")
         }
     }
@@ -86,3 +164,115 @@ pub fn rand_str<R: Rng>(length: usize, alpha: &[u8], rng: &mut R) -> Vec<u8> {
 
     res
 }
+
+/// Generate a random string of a certain length, where each character in the alphabet
+/// is sampled in proportion to its weight in `weights`.
+///
+/// This is useful for simulating a realistic composition, like a certain GC content
+/// (see `gc_weights`) or the amino acid frequencies of a proteome.
+pub fn rand_str_weighted<R: Rng>(length: usize, alpha: &[u8], weights: &[f32], rng: &mut R) -> Vec<u8> {
+    assert_eq!(alpha.len(), weights.len(), "Alphabet and weights must have the same length!");
+    let dist = WeightedIndex::new(weights).expect("Weights must be nonnegative and not all zero!");
+    (0..length).map(|_| alpha[dist.sample(rng)]).collect()
+}
+
+/// Weights for sampling the `NUC` alphabet with a certain GC content, without any `N`s.
+pub fn gc_weights(gc: f32) -> [f32; 5] {
+    assert!((0.0..=1.0).contains(&gc), "GC content must be between 0 and 1!");
+    let (at, gc) = ((1.0 - gc) / 2.0, gc / 2.0);
+    [at, gc, gc, 0.0, at]
+}
+
+/// Accuracy of a predicted alignment compared to the ground-truth alignment.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AlignmentAccuracy {
+    /// Fraction of query and reference bases that are aligned to the same position (or to a gap)
+    /// as in the ground truth.
+    pub base_accuracy: f64,
+    /// Fraction of ground-truth indels that are predicted at exactly the same position.
+    pub indel_accuracy: f64,
+    /// Fraction of ground-truth indels that are predicted at the same position or at an
+    /// equivalent position along the same diagonal, like a different placement in a repeat.
+    pub indel_equiv_accuracy: f64
+}
+
+/// Evaluate a predicted global alignment against the ground-truth alignment, for example from
+/// `rand_mutate_with_cigar` or `rand_pair_identity`.
+///
+/// If there are no ground-truth indels, then both indel accuracies are 1.
+pub fn alignment_accuracy(predicted: &Cigar, truth: &Cigar) -> AlignmentAccuracy {
+    let c = truth.compare(predicted);
+    let indel_frac = |n: usize| if c.indels == 0 { 1.0 } else { (n as f64) / (c.indels as f64) };
+    AlignmentAccuracy {
+        base_accuracy: c.frac_identical(),
+        indel_accuracy: indel_frac(c.identical_indels),
+        indel_equiv_accuracy: indel_frac(c.identical_indels + c.shifted_indels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rand_mutate_with_cigar() {
+        let r = rand_str(200, &NUC, &mut StdRng::seed_from_u64(1234));
+        let q = rand_mutate(&r, 20, &NUC, &mut StdRng::seed_from_u64(5678));
+        let (q_truth, cigar) = rand_mutate_with_cigar(&r, 20, &NUC, &mut StdRng::seed_from_u64(5678));
+        assert_eq!(q, q_truth);
+
+        let (q_gapped, r_gapped) = cigar.format(&q, &r);
+        assert_eq!(q_gapped.replace('-', "").as_bytes(), &q[..]);
+        assert_eq!(r_gapped.replace('-', "").as_bytes(), &r[..]);
+    }
+
+    #[test]
+    fn test_rand_pair_identity() {
+        let mut rng = StdRng::seed_from_u64(1234);
+        for &(identity, indel_frac) in &[(1.0, 0.5), (0.9, 0.0), (0.8, 0.25), (0.7, 1.0)] {
+            let (q, r, cigar) = rand_pair_identity(1000, identity, indel_frac, &AMINO_ACIDS, &mut rng);
+            let (mut matches, mut cols, mut indels) = (0, 0, 0);
+            for pair in cigar.aligned_pairs(0, 0) {
+                cols += 1;
+                match pair {
+                    (Some(i), Some(j)) => matches += (q[i] == r[j]) as usize,
+                    _ => indels += 1
+                }
+            }
+            let edits = cols - matches;
+            assert!(((matches as f32) / (cols as f32) - identity).abs() < 0.002);
+            assert!(edits == 0 || ((indels as f32) / (edits as f32) - indel_frac).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_rand_str_weighted() {
+        let mut rng = StdRng::seed_from_u64(1234);
+        let s = rand_str_weighted(10000, &NUC, &gc_weights(0.7), &mut rng);
+        let gc = s.iter().filter(|&&c| c == b'G' || c == b'C').count();
+        assert!(((gc as f32) / 10000.0 - 0.7).abs() < 0.02);
+        assert!(!s.contains(&b'N'));
+
+        let s = rand_str_weighted(100, b"AC", &[0.0, 1.0], &mut rng);
+        assert_eq!(s, vec![b'C'; 100]);
+    }
+
+    #[test]
+    fn test_alignment_accuracy() {
+        use crate::scan_block::*;
+        use crate::scores::*;
+
+        let mut rng = StdRng::seed_from_u64(1234);
+        let (q, r, truth) = rand_pair_identity(1000, 0.9, 0.5, &NUC, &mut rng);
+        let acc = alignment_accuracy(&truth, &truth);
+        assert_eq!(acc, AlignmentAccuracy { base_accuracy: 1.0, indel_accuracy: 1.0, indel_equiv_accuracy: 1.0 });
+
+        let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 64);
+        let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 64);
+        let a = Block::<_, true, false>::align(&q_padded, &r_padded, &NW1, Gaps { open: -2, extend: -1 }, 32..=64, 0);
+        let acc = alignment_accuracy(&a.trace().cigar(q.len(), r.len()), &truth);
+        assert!(acc.base_accuracy > 0.9 && acc.base_accuracy < 1.0);
+        assert!(acc.indel_accuracy <= acc.indel_equiv_accuracy);
+        assert!(acc.indel_equiv_accuracy > 0.5);
+    }
+}
@@ -0,0 +1,121 @@
+//! Fast approximate alignment scores for clustering and triage.
+//!
+//! Aligning every pair of sequences can be too expensive when only a rough similarity score
+//! is needed. Instead, the sequences are split into segments along the expected diagonal,
+//! and only every k-th segment is globally aligned. The total score is extrapolated from the
+//! scores of the sampled segments.
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+use std::cmp;
+use std::ops::RangeInclusive;
+
+/// Data structure storing the settings for estimating alignment scores.
+pub struct SketchAligner<'a, M: 'static + Matrix> {
+    matrix: &'a M,
+    gaps: Gaps,
+    size: RangeInclusive<usize>,
+    segment_len: usize,
+    stride: usize
+}
+
+/// Result of `SketchAligner::estimate`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SketchEstimate {
+    /// Estimated global alignment score, extrapolated from the sampled segments.
+    pub score: i32,
+    /// Total number of segments along the diagonal.
+    pub segments: usize,
+    /// Number of segments that were aligned.
+    pub sampled: usize
+}
+
+impl<'a, M: 'static + Matrix> SketchAligner<'a, M> {
+    /// Create a new sketch aligner.
+    ///
+    /// By default, segments span 256 query bases and every 4th segment is aligned.
+    pub fn new(matrix: &'a M, gaps: Gaps, size: RangeInclusive<usize>) -> Self {
+        Self { matrix, gaps, size, segment_len: 256, stride: 4 }
+    }
+
+    /// Set the number of query bases in each segment.
+    pub fn segment_len(mut self, segment_len: usize) -> Self {
+        assert!(segment_len > 0, "Segment length must be positive!");
+        self.segment_len = segment_len;
+        self
+    }
+
+    /// Set the stride `k`, so only every k-th segment is aligned.
+    ///
+    /// A stride of 1 aligns every segment, which is still faster than full alignment with
+    /// large blocks, but the segment boundaries are fixed on the diagonal.
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0, "Stride must be positive!");
+        self.stride = stride;
+        self
+    }
+
+    /// Estimate the global alignment score of the query and the reference.
+    ///
+    /// `query` and `reference` are the unpadded strings.
+    pub fn estimate(&self, query: &[u8], reference: &[u8]) -> SketchEstimate {
+        let segments = cmp::max(div_ceil(query.len(), self.segment_len), 1);
+        // slope of the expected diagonal
+        let slope = (reference.len() as f64) / (cmp::max(query.len(), 1) as f64);
+        let ref_idx = |i: usize| cmp::min(((i as f64) * slope).round() as usize, reference.len());
+
+        let (mut total, mut sampled) = (0i64, 0usize);
+        for t in (0..segments).step_by(self.stride) {
+            let (i_start, i_end) = (t * self.segment_len, cmp::min((t + 1) * self.segment_len, query.len()));
+            let (j_start, j_end) = if t == segments - 1 {
+                (ref_idx(i_start), reference.len())
+            } else {
+                (ref_idx(i_start), ref_idx(i_end))
+            };
+
+            let q = PaddedBytes::from_bytes::<M>(&query[i_start..i_end], *self.size.end());
+            let r = PaddedBytes::from_bytes::<M>(&reference[j_start..j_end], *self.size.end());
+            let a = Block::<_, false, false>::align(&q, &r, self.matrix, self.gaps, self.size.clone(), 0);
+            total += a.res().score as i64;
+            sampled += 1;
+        }
+
+        let score = ((total as f64) * (segments as f64) / (sampled as f64)).round() as i32;
+        SketchEstimate { score, segments, sampled }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_sketch_aligner() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let mut rng = StdRng::seed_from_u64(1234);
+        let r = rand_str(4096, &NUC, &mut rng);
+
+        let e = SketchAligner::new(&NW1, gaps, 32..=64).estimate(&r, &r);
+        assert_eq!(e, SketchEstimate { score: 4096, segments: 16, sampled: 4 });
+
+        let q = rand_mutate(&r, 200, &NUC, &mut rng);
+        let q_padded = PaddedBytes::from_bytes::<NucMatrix>(&q, 256);
+        let r_padded = PaddedBytes::from_bytes::<NucMatrix>(&r, 256);
+        let score = Block::<_, false, false>::align(&q_padded, &r_padded, &NW1, gaps, 32..=256, 0).res().score;
+
+        let e = SketchAligner::new(&NW1, gaps, 32..=64).stride(1).estimate(&q, &r);
+        assert_eq!(e.sampled, e.segments);
+        // drift away from the diagonal adds gaps at segment boundaries
+        assert!(e.score <= score && e.score >= score * 4 / 5);
+        let e = SketchAligner::new(&NW1, gaps, 32..=64).segment_len(500).stride(3).estimate(&q, &r);
+        assert_eq!(e.sampled, 3);
+        assert!((e.score - score).abs() < score / 5);
+
+        let u = rand_str(4096, &NUC, &mut rng);
+        assert!(SketchAligner::new(&NW1, gaps, 32..=64).estimate(&u, &r).score < 0);
+        assert_eq!(SketchAligner::new(&NW1, gaps, 32..=64).estimate(b"", b"").score, 0);
+    }
+}
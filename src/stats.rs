@@ -0,0 +1,264 @@
+//! Alignment statistics for converting raw scores into bit scores and e-values.
+//!
+//! This follows the Karlin-Altschul statistics used by BLAST.
+
+use crate::scan_block::AlignResult;
+use crate::scores::*;
+
+use std::time::Duration;
+
+/// Background amino acid frequencies from Robinson and Robinson (1991), as used by BLAST.
+pub static AA_FREQS: [(u8, f64); 20] = [
+    (b'A', 0.07805), (b'R', 0.05129), (b'N', 0.04487), (b'D', 0.05364), (b'C', 0.01925),
+    (b'Q', 0.04264), (b'E', 0.06295), (b'G', 0.07377), (b'H', 0.02199), (b'I', 0.05142),
+    (b'L', 0.09019), (b'K', 0.05744), (b'M', 0.02243), (b'F', 0.03856), (b'P', 0.05203),
+    (b'S', 0.07120), (b'T', 0.05841), (b'W', 0.01330), (b'Y', 0.03216), (b'V', 0.06441)
+];
+
+/// Uniform background nucleotide frequencies.
+pub static NUC_FREQS: [(u8, f64); 4] = [
+    (b'A', 0.25), (b'C', 0.25), (b'G', 0.25), (b'T', 0.25)
+];
+
+/// Precomputed gapped Karlin-Altschul parameters for BLOSUM62 from BLAST, indexed by
+/// BLAST-style gap existence and extension costs.
+///
+/// Note that BLAST's existence cost does not include the extension cost.
+static BLOSUM62_GAPPED: [(i8, i8, KarlinAltschul); 11] = [
+    (11, 2, KarlinAltschul::new(0.297, 0.082, 0.27)),
+    (10, 2, KarlinAltschul::new(0.291, 0.075, 0.23)),
+    (9, 2, KarlinAltschul::new(0.279, 0.058, 0.19)),
+    (8, 2, KarlinAltschul::new(0.264, 0.045, 0.15)),
+    (7, 2, KarlinAltschul::new(0.239, 0.027, 0.10)),
+    (6, 2, KarlinAltschul::new(0.201, 0.012, 0.061)),
+    (13, 1, KarlinAltschul::new(0.292, 0.071, 0.23)),
+    (12, 1, KarlinAltschul::new(0.283, 0.059, 0.19)),
+    (11, 1, KarlinAltschul::new(0.267, 0.041, 0.14)),
+    (10, 1, KarlinAltschul::new(0.243, 0.024, 0.10)),
+    (9, 1, KarlinAltschul::new(0.206, 0.010, 0.052))
+];
+
+/// Karlin-Altschul parameters for a certain scoring scheme.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct KarlinAltschul {
+    /// Scale of the scoring scheme.
+    pub lambda: f64,
+    /// Search space correction factor.
+    pub k: f64,
+    /// Relative entropy (nats per aligned pair).
+    pub h: f64
+}
+
+impl KarlinAltschul {
+    /// Create a new set of Karlin-Altschul parameters.
+    pub const fn new(lambda: f64, k: f64, h: f64) -> Self {
+        Self { lambda, k, h }
+    }
+
+    /// Compute the ungapped Karlin-Altschul parameters for a scoring matrix, given
+    /// the background frequencies of each residue.
+    ///
+    /// Returns `None` if the expected score is not negative or if there are no
+    /// positive scores, since the statistics are undefined in those cases.
+    pub fn ungapped<M: Matrix>(matrix: &M, freqs: &[(u8, f64)]) -> Option<Self> {
+        // probability distribution of scores, offset by the min score
+        let mut min = i32::MAX;
+        let mut max = i32::MIN;
+        for &(a, _) in freqs {
+            for &(b, _) in freqs {
+                let s = matrix.get(a, b) as i32;
+                min = min.min(s);
+                max = max.max(s);
+            }
+        }
+        if max <= 0 || min >= 0 {
+            return None;
+        }
+
+        let mut probs = vec![0f64; (max - min + 1) as usize];
+        for &(a, p_a) in freqs {
+            for &(b, p_b) in freqs {
+                probs[(matrix.get(a, b) as i32 - min) as usize] += p_a * p_b;
+            }
+        }
+        let total = probs.iter().sum::<f64>();
+        probs.iter_mut().for_each(|p| *p /= total);
+
+        let expected = probs.iter().enumerate().map(|(s, p)| ((s as i32 + min) as f64) * p).sum::<f64>();
+        if expected >= 0.0 {
+            return None;
+        }
+
+        let d = probs
+            .iter()
+            .enumerate()
+            .filter(|&(_, &p)| p > 0.0)
+            .fold(0, |g, (s, _)| gcd(g, (s as i32 + min).abs()));
+
+        // solve sum(p(s) * e^(lambda * s)) = 1 for lambda with bisection
+        let f = |lambda: f64| probs.iter().enumerate().map(|(s, p)| p * (lambda * ((s as i32 + min) as f64)).exp()).sum::<f64>() - 1.0;
+        let mut lo = 0f64;
+        let mut hi = 1f64;
+        while f(hi) < 0.0 {
+            hi *= 2.0;
+        }
+        for _i in 0..100 {
+            let mid = (lo + hi) / 2.0;
+            if f(mid) > 0.0 { hi = mid; } else { lo = mid; }
+        }
+        let lambda = (lo + hi) / 2.0;
+
+        let h = lambda * probs
+            .iter()
+            .enumerate()
+            .map(|(s, p)| {
+                let s = (s as i32 + min) as f64;
+                s * p * (lambda * s).exp()
+            })
+            .sum::<f64>();
+
+        // sigma = sum over k of (E[e^(lambda * S_k); S_k < 0] + P(S_k >= 0)) / k,
+        // where S_k is the sum of k random scores
+        let mut sigma = 0f64;
+        let mut sum_probs = vec![1f64];
+        let mut sum_min = 0i32;
+        for k in 1..=200 {
+            let mut next = vec![0f64; sum_probs.len() + probs.len() - 1];
+            for (s1, &p1) in sum_probs.iter().enumerate() {
+                for (s2, &p2) in probs.iter().enumerate() {
+                    next[s1 + s2] += p1 * p2;
+                }
+            }
+            sum_probs = next;
+            sum_min += min;
+
+            let term = sum_probs
+                .iter()
+                .enumerate()
+                .map(|(s, &p)| {
+                    let s = s as i32 + sum_min;
+                    if s < 0 { p * (lambda * (s as f64)).exp() } else { p }
+                })
+                .sum::<f64>() / (k as f64);
+            sigma += term;
+
+            if term < 1e-12 {
+                break;
+            }
+        }
+
+        let d = d as f64;
+        let k = d * lambda * (-2.0 * sigma).exp() / (h * (1.0 - (-d * lambda).exp()));
+        Some(Self { lambda, k, h })
+    }
+
+    /// Get the gapped Karlin-Altschul parameters for an amino acid scoring matrix
+    /// and gap costs.
+    ///
+    /// Precomputed parameters from BLAST are used for BLOSUM62 with the standard gap costs.
+    /// Otherwise, the ungapped parameters are used as an approximation, which will
+    /// overestimate the significance of gapped alignments.
+    pub fn gapped_aa(matrix: &AAMatrix, gaps: Gaps) -> Option<Self> {
+        if *matrix == BLOSUM62 {
+            // convert to BLAST-style costs where the existence cost does not include extension
+            let extend = -(gaps.extend as i32);
+            let existence = -(gaps.open as i32) - extend;
+            let found = BLOSUM62_GAPPED
+                .iter()
+                .find(|&&(o, e, _)| o as i32 == existence && e as i32 == extend);
+            if let Some(&(_, _, params)) = found {
+                return Some(params);
+            }
+        }
+
+        Self::ungapped(matrix, &AA_FREQS)
+    }
+
+    /// Convert a raw score into a normalized bit score.
+    #[inline]
+    pub fn bit_score(&self, score: i32) -> f64 {
+        (self.lambda * (score as f64) - self.k.ln()) / std::f64::consts::LN_2
+    }
+
+    /// Compute the expected number of hits with at least a certain raw score
+    /// when searching a query against a database.
+    ///
+    /// The search space is `query_len * db_len`, where `db_len` is the
+    /// total length of all references in the database.
+    #[inline]
+    pub fn e_value(&self, score: i32, query_len: usize, db_len: usize) -> f64 {
+        self.k * (query_len as f64) * (db_len as f64) * (-self.lambda * (score as f64)).exp()
+    }
+
+    /// Convert the score of an alignment result into a bit score.
+    #[inline]
+    pub fn res_bit_score(&self, res: &AlignResult) -> f64 {
+        self.bit_score(res.score)
+    }
+
+    /// Compute the e-value of an alignment result.
+    #[inline]
+    pub fn res_e_value(&self, res: &AlignResult, query_len: usize, db_len: usize) -> f64 {
+        self.e_value(res.score, query_len, db_len)
+    }
+}
+
+/// Compute the throughput in billions of cell updates per second (GCUPS).
+///
+/// With the `stats` feature, the number of cells computed by block aligner can be obtained
+/// from `Counters`. Note that block aligner computes much fewer cells than full DP, so
+/// GCUPS based on `query_len * reference_len` cells can also be useful for comparisons.
+#[inline]
+pub fn gcups(cells: u64, elapsed: Duration) -> f64 {
+    (cells as f64) / elapsed.as_secs_f64() / 1e9
+}
+
+#[inline]
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Compute the e-value from a bit score, given the size of the search space.
+#[inline]
+pub fn bit_score_e_value(bit_score: f64, query_len: usize, db_len: usize) -> f64 {
+    (query_len as f64) * (db_len as f64) * (-bit_score).exp2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_score_e_value() {
+        let params = KarlinAltschul::new(0.267, 0.041, 0.14);
+        let bits = params.bit_score(100);
+        assert!((bits - 43.13).abs() < 0.01);
+
+        let e = params.e_value(100, 300, 1_000_000);
+        let e_bits = bit_score_e_value(bits, 300, 1_000_000);
+        assert!((e - e_bits).abs() / e < 1e-9);
+
+        assert!(params.e_value(200, 300, 1_000_000) < e);
+    }
+
+    #[test]
+    fn test_gcups() {
+        assert!((gcups(2_000_000_000, Duration::from_millis(500)) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let params = KarlinAltschul::ungapped(&NucMatrix::new_simple(1, -3), &NUC_FREQS).unwrap();
+        assert!((params.lambda - 1.374).abs() < 0.001);
+        assert!((params.k - 0.711).abs() < 0.001);
+        assert!((params.h - 1.307).abs() < 0.001);
+
+        let params = KarlinAltschul::ungapped(&BLOSUM62, &AA_FREQS).unwrap();
+        assert!((params.lambda - 0.3176).abs() < 0.002);
+
+        let params = KarlinAltschul::gapped_aa(&BLOSUM62, Gaps { open: -12, extend: -1 }).unwrap();
+        assert_eq!(params, KarlinAltschul::new(0.267, 0.041, 0.14));
+
+        assert!(KarlinAltschul::ungapped(&NucMatrix::new_simple(1, 1), &NUC_FREQS).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+//! Aligning both orientations of a nucleotide query to a reference.
+//!
+//! The reverse complement of the query is never materialized. Instead, the query is reversed
+//! and a matrix that complements query nucleotides on the fly (see
+//! `AsymNucMatrix::complement_query`) is used, so the same padded reference is shared by both
+//! orientations.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::ops::RangeInclusive;
+
+/// Orientation of the query relative to the reference.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Strand {
+    /// The query as given.
+    Forward,
+    /// The reverse complement of the query.
+    Reverse
+}
+
+/// Result of `align_both_strands`.
+pub struct StrandAlignment {
+    /// Result of the alignment in the better orientation.
+    ///
+    /// For the reverse strand, `query_idx` is an index into the reverse complement of the query.
+    pub res: AlignResult,
+    /// Orientation with the higher score. Ties are broken in favor of the forward strand.
+    pub strand: Strand,
+    /// CIGAR string of the alignment in the better orientation, if traceback is enabled.
+    ///
+    /// For the reverse strand, this aligns the reverse complement of the query to the reference.
+    pub cigar: Option<Cigar>
+}
+
+/// Align the forward and reverse complement orientations of the query to the reference
+/// and return the result for the orientation with the higher score.
+///
+/// `query` is the unpadded string, while `reference` is padded with `NucMatrix` so it can
+/// be prepared once and reused for many queries. The other parameters are the same as in
+/// `Block::align`.
+pub fn align_both_strands<const TRACE: bool, const X_DROP: bool>(query: &[u8], reference: &PaddedBytes, matrix: &NucMatrix, gaps: Gaps, size: RangeInclusive<usize>, x_drop: i32) -> StrandAlignment {
+    let q = PaddedBytes::from_bytes::<NucMatrix>(query, *size.end());
+    let forward = Block::<_, TRACE, X_DROP>::align(&q, reference, matrix, gaps, size.clone(), x_drop);
+
+    let rev_matrix = AsymNucMatrix::complement_query(matrix);
+    let q_rev = PaddedBytes::from_bytes_rev::<AsymNucMatrix>(query, *size.end());
+    let reverse = Block::<_, TRACE, X_DROP>::align(&q_rev, reference, &rev_matrix, gaps, size, x_drop);
+
+    let (f_res, r_res) = (forward.res(), reverse.res());
+    if r_res.score > f_res.score {
+        let cigar = if TRACE { Some(reverse.trace().cigar(r_res.query_idx, r_res.reference_idx)) } else { None };
+        StrandAlignment { res: r_res, strand: Strand::Reverse, cigar }
+    } else {
+        let cigar = if TRACE { Some(forward.trace().cigar(f_res.query_idx, f_res.reference_idx)) } else { None };
+        StrandAlignment { res: f_res, strand: Strand::Forward, cigar }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align_both_strands() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTCCGATCGATCGGACTCGATCAGCTACGACTGCATCGACTCGCATCAGCATCGAC", 32);
+
+        let a = align_both_strands::<true, false>(b"ACGTCCGATCGATCGGACTCGATCAGCTACGACTGCATCGACTCGCATCAGCATCGAC", &r, &NW1, gaps, 16..=32, 0);
+        assert_eq!(a.strand, Strand::Forward);
+        assert_eq!(a.res, AlignResult { score: 58, query_idx: 58, reference_idx: 58 });
+        assert_eq!(a.cigar.unwrap().to_string(), "58M");
+
+        // reverse complement of the reference, with a mismatch
+        let a = align_both_strands::<true, false>(b"GTCGATGCTGATGCGAGTCGATGCAGTCGTAGCTGATCGAGTCCGATCGATCGGACTT", &r, &NW1, gaps, 16..=32, 0);
+        assert_eq!(a.strand, Strand::Reverse);
+        assert_eq!(a.res, AlignResult { score: 56, query_idx: 58, reference_idx: 58 });
+        assert_eq!(a.cigar.unwrap().to_string(), "58M");
+
+        let a = align_both_strands::<false, false>(b"GTCGATGCTGATGCGAGTCGATGCAGTCGTAGCTGATCGAGTCCGATCGATCGGACGT", &r, &NW1, gaps, 16..=32, 0);
+        assert_eq!(a.strand, Strand::Reverse);
+        assert_eq!(a.res.score, 58);
+        assert!(a.cigar.is_none());
+    }
+}
@@ -0,0 +1,155 @@
+//! Global alignment that tolerates a single long insertion or deletion.
+//!
+//! A structural variant, like a deletion of hundreds of bases, destroys the score of a normal
+//! affine gap alignment, and block aligner would need an enormous max block size to even span
+//! the gap. Instead, the two sequences are X-drop extended from both ends. If the extensions
+//! end up on diagonals that are far apart, then the sequences are split at a breakpoint between
+//! the extensions, the two halves are globally aligned separately, and they are joined by a
+//! single long gap with its own (usually much cheaper) gap cost.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::ops::RangeInclusive;
+
+/// Cost of the single long gap.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LongGapCost {
+    /// Minimum length of a gap to be treated as a long gap.
+    pub min_len: usize,
+    /// Score for opening the long gap (negative or zero).
+    pub open: i32,
+    /// Score for each base of the long gap after the first (negative or zero).
+    pub extend: i32
+}
+
+/// Location of the long gap in an alignment.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LongGap {
+    /// Either `Operation::I` (bases only in the query) or `Operation::D` (bases only in the reference).
+    pub op: Operation,
+    /// Position in the query where the gap starts.
+    pub query_pos: usize,
+    /// Position in the reference where the gap starts.
+    pub reference_pos: usize,
+    /// Length of the gap.
+    pub len: usize
+}
+
+/// Result of `align_with_long_gap`.
+pub struct LongGapAlignment {
+    /// Score of the global alignment, including the cost of the long gap.
+    pub score: i32,
+    /// CIGAR string of the global alignment.
+    pub cigar: Cigar,
+    /// The long gap, if the alignment with a long gap scores higher than the normal alignment.
+    pub long_gap: Option<LongGap>
+}
+
+/// Globally align two strings, allowing one long gap of at least `long_gap.min_len` bases that is
+/// scored with `long_gap` instead of `gaps`.
+///
+/// `query` and `reference` are the unpadded strings. `x_drop` is used for extending from both
+/// ends to find the diagonals before and after the long gap. The exact breakpoint is ambiguous
+/// within the region where both extensions agree, so the middle of that region is used. The
+/// normal global alignment is returned if it has a higher score.
+pub fn align_with_long_gap<M: 'static + Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>, long_gap: LongGapCost, x_drop: i32) -> LongGapAlignment {
+    assert!(long_gap.min_len > 0, "Long gap min length must be positive!");
+    let (score, ops) = global(query, reference, matrix, gaps, size.clone());
+    let normal = LongGapAlignment { score, cigar: Cigar::from_ops(ops), long_gap: None };
+
+    // extend from the start
+    let q = PaddedBytes::from_bytes::<M>(query, *size.end());
+    let r = PaddedBytes::from_bytes::<M>(reference, *size.end());
+    let fwd = Block::<_, false, true>::align(&q, &r, matrix, gaps, size.clone(), x_drop).res();
+    // extend from the end
+    let q = PaddedBytes::from_bytes_rev::<M>(query, *size.end());
+    let r = PaddedBytes::from_bytes_rev::<M>(reference, *size.end());
+    let rev = Block::<_, false, true>::align(&q, &r, matrix, gaps, size.clone(), x_drop).res();
+
+    let (fwd_i, rev_i) = (fwd.query_idx as isize, (query.len() - rev.query_idx) as isize);
+    let fwd_diag = (fwd.reference_idx as isize) - fwd_i;
+    let rev_diag = ((reference.len() - rev.reference_idx) as isize) - rev_i;
+    let len = (rev_diag - fwd_diag).unsigned_abs();
+    if len < long_gap.min_len {
+        return normal;
+    }
+
+    // breakpoint in the middle of the region where both extensions agree, so the prefix
+    // ends before the forward extension ends and the suffix starts after the reverse extension
+    // starts (in the query, an insertion spans from the breakpoint to the start of the suffix)
+    let (prefix_end, suffix_start) = if rev_diag > fwd_diag {
+        let b = (fwd_i + rev_i) / 2;
+        ((b, b + fwd_diag), (b, b + rev_diag))
+    } else {
+        let b = (fwd_i + rev_i - (len as isize)) / 2;
+        ((b, b + fwd_diag), (b + (len as isize), b + fwd_diag))
+    };
+    let valid = |(i, j): (isize, isize)| i >= 0 && j >= 0 && i <= query.len() as isize && j <= reference.len() as isize;
+    if !valid(prefix_end) || !valid(suffix_start) {
+        return normal;
+    }
+
+    let (prefix_end, suffix_start) = ((prefix_end.0 as usize, prefix_end.1 as usize), (suffix_start.0 as usize, suffix_start.1 as usize));
+    let (prefix_score, mut ops) = global(&query[..prefix_end.0], &reference[..prefix_end.1], matrix, gaps, size.clone());
+    let (suffix_score, suffix_ops) = global(&query[suffix_start.0..], &reference[suffix_start.1..], matrix, gaps, size);
+    let op = if rev_diag > fwd_diag { Operation::D } else { Operation::I };
+    let score = prefix_score + suffix_score + long_gap.open + long_gap.extend * ((len as i32) - 1);
+    if score <= normal.score {
+        return normal;
+    }
+
+    ops.push(OpLen { op, len });
+    ops.extend(suffix_ops);
+    LongGapAlignment {
+        score,
+        cigar: Cigar::from_ops(ops),
+        long_gap: Some(LongGap { op, query_pos: prefix_end.0, reference_pos: prefix_end.1, len })
+    }
+}
+
+/// Global alignment score and operations.
+fn global<M: 'static + Matrix>(query: &[u8], reference: &[u8], matrix: &M, gaps: Gaps, size: RangeInclusive<usize>) -> (i32, Vec<OpLen>) {
+    let q = PaddedBytes::from_bytes::<M>(query, *size.end());
+    let r = PaddedBytes::from_bytes::<M>(reference, *size.end());
+    let a = Block::<_, true, false>::align(&q, &r, matrix, gaps, size, 0);
+    let res = a.res();
+    (res.score, a.trace().cigar(res.query_idx, res.reference_idx).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_align_with_long_gap() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let cost = LongGapCost { min_len: 50, open: -10, extend: 0 };
+        let mut rng = StdRng::seed_from_u64(1234);
+        let r = rand_str(400, &NUC, &mut rng);
+        let q = [&r[..200], &r[300..]].concat();
+
+        let a = align_with_long_gap(&q, &r, &NW1, gaps, 32..=64, cost, 20);
+        assert_eq!(a.score, 300 - 10);
+        let gap = a.long_gap.unwrap();
+        assert_eq!((gap.op, gap.len), (Operation::D, 100));
+        assert_eq!(gap.reference_pos - gap.query_pos, 0);
+        let (q_gapped, r_gapped) = a.cigar.format(&q, &r);
+        assert_eq!(q_gapped.replace('-', "").as_bytes(), &q[..]);
+        assert_eq!(r_gapped.replace('-', "").as_bytes(), &r[..]);
+
+        // the same gap in the other direction is an insertion
+        let a = align_with_long_gap(&r, &q, &NW1, gaps, 32..=64, cost, 20);
+        assert_eq!(a.score, 300 - 10);
+        assert_eq!(a.long_gap.map(|g| (g.op, g.len)), Some((Operation::I, 100)));
+
+        // short gaps are aligned normally
+        let q = [&r[..200], &r[210..]].concat();
+        let a = align_with_long_gap(&q, &r, &NW1, gaps, 32..=64, cost, 20);
+        assert!(a.long_gap.is_none());
+        assert_eq!(a.score, 390 - 2 - 9);
+    }
+}
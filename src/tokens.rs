@@ -0,0 +1,185 @@
+//! Alignment of sequences of integer tokens that are larger than a byte.
+//!
+//! This is useful for alphabets like syntenic gene IDs, k-mer ranks, or minimizer sketches.
+
+use crate::backend::SimdBackend;
+
+use crate::scan_block::*;
+use crate::scores::*;
+
+use std::fmt;
+
+/// Token types that can be aligned with `TokenMatrix`.
+///
+/// Tokens can be created from a byte, so a pair of bytes can also be scored as tokens.
+pub trait Token: Copy + PartialEq + From<u8> + 'static {}
+
+impl Token for u8 {}
+impl Token for u16 {}
+impl Token for u32 {}
+impl Token for u64 {}
+
+/// Position-specific "matrix" that holds a query and a reference sequence of tokens.
+///
+/// Tokens are scored with either a simple match/mismatch score or a user-supplied rule.
+/// Use `query_bytes` and `reference_bytes` to get the placeholder strings to align with `Block`.
+#[derive(Clone)]
+pub struct TokenMatrix<T: Token, F: Fn(T, T) -> i8 = fn(T, T) -> i8> {
+    query: Vec<T>,
+    reference: Vec<T>,
+    match_score: i8,
+    mismatch_score: i8,
+    rule: Option<F>
+}
+
+impl<T: Token> TokenMatrix<T> {
+    /// Create a matrix for a pair of token sequences with a certain match and mismatch score.
+    pub fn new_simple(query: &[T], reference: &[T], match_score: i8, mismatch_score: i8) -> Self {
+        Self { query: query.to_owned(), reference: reference.to_owned(), match_score, mismatch_score, rule: None }
+    }
+}
+
+impl<T: Token, F: Fn(T, T) -> i8> TokenMatrix<T, F> {
+    /// Create a matrix for a pair of token sequences with a custom scoring rule.
+    ///
+    /// The rule is called with a query token and a reference token, and it must not
+    /// return `i8::MIN`.
+    pub fn with_rule(query: &[T], reference: &[T], rule: F) -> Self {
+        Self { query: query.to_owned(), reference: reference.to_owned(), match_score: 0, mismatch_score: 0, rule: Some(rule) }
+    }
+
+    /// Get the score between query token `i` and reference token `j`.
+    #[inline]
+    pub fn score(&self, i: usize, j: usize) -> i8 {
+        self.score_tokens(self.query[i], self.reference[j])
+    }
+
+    /// Get the score between a query token and a reference token.
+    #[inline]
+    pub fn score_tokens(&self, a: T, b: T) -> i8 {
+        match &self.rule {
+            Some(rule) => rule(a, b),
+            None => if a == b { self.match_score } else { self.mismatch_score }
+        }
+    }
+
+    /// Create the placeholder query string.
+    pub fn query_bytes(&self, block_size: usize) -> PaddedBytes {
+        PaddedBytes::from_len::<Self>(self.query.len(), block_size)
+    }
+
+    /// Create the placeholder reference string.
+    pub fn reference_bytes(&self, block_size: usize) -> PaddedBytes {
+        PaddedBytes::from_len::<Self>(self.reference.len(), block_size)
+    }
+
+    /// Min and max scores over all pairs of positions.
+    fn score_range(&self) -> (i8, i8) {
+        score_range((0..self.query.len()).flat_map(|i| (0..self.reference.len()).map(move |j| self.score(i, j))))
+    }
+
+    /// Score for a 1-based padded position pair, where out of bounds positions are padding.
+    #[inline]
+    fn padded_score(&self, i: usize, j: usize) -> i16 {
+        if i == 0 || j == 0 || i > self.query.len() || j > self.reference.len() {
+            i8::MIN as i16
+        } else {
+            self.score(i - 1, j - 1) as i16
+        }
+    }
+}
+
+impl<T: Token + fmt::Debug, F: Fn(T, T) -> i8> fmt::Debug for TokenMatrix<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenMatrix")
+            .field("query", &self.query)
+            .field("reference", &self.reference)
+            .field("match_score", &self.match_score)
+            .field("mismatch_score", &self.mismatch_score)
+            .field("rule", &self.rule.as_ref().map(|_| "Fn(T, T) -> i8"))
+            .finish()
+    }
+}
+
+impl<T: Token, F: Fn(T, T) -> i8> Matrix for TokenMatrix<T, F> {
+    const NULL: u8 = b'\0';
+
+    fn new() -> Self {
+        Self { query: Vec::new(), reference: Vec::new(), match_score: i8::MIN, mismatch_score: i8::MIN, rule: None }
+    }
+
+    fn set(&mut self, _a: u8, _b: u8, _score: i8) {
+        unimplemented!();
+    }
+
+    /// Get the score for a pair of bytes, which are converted to a pair of tokens.
+    fn get(&self, a: u8, b: u8) -> i8 {
+        self.score_tokens(T::from(a), T::from(b))
+    }
+
+    #[inline]
+    fn as_ptr(&self, _i: usize) -> *const i8 {
+        unimplemented!()
+    }
+
+    unsafe fn get_scores<B: SimdBackend>(&self, _c: u8, _v: B::HalfSimd, _right: bool) -> B::Simd {
+        unimplemented!()
+    }
+
+    #[inline]
+    unsafe fn get_scores_at<B: SimdBackend>(&self, _c: u8, _v: B::HalfSimd, right: bool, c_idx: usize, v_idx: usize) -> B::Simd {
+        position_scores::<B, _>(right, c_idx, v_idx, |i, j| self.padded_score(i, j))
+    }
+
+    #[inline]
+    fn convert_char(c: u8) -> u8 {
+        c
+    }
+
+    /// Get the max score over all pairs of positions, which is slow for long sequences.
+    fn max_score(&self) -> i8 {
+        self.score_range().1
+    }
+
+    /// Get the min score over all pairs of positions, which is slow for long sequences.
+    fn min_score(&self) -> i8 {
+        self.score_range().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        let gaps = Gaps { open: -2, extend: -1 };
+        let q = [1000u32, 2000, 3000, 4000, 5000, 6000];
+        let r = [1000u32, 2000, 70000, 3000, 4000, 5000, 6000];
+
+        let m = TokenMatrix::new_simple(&q, &r, 1, -1);
+        let (q_padded, r_padded) = (m.query_bytes(16), m.reference_bytes(16));
+        let a = Block::<_, true, false>::align(&q_padded, &r_padded, &m, gaps, 16..=16, 0);
+        let res = a.res();
+        assert_eq!(res, AlignResult { score: 4, query_idx: 6, reference_idx: 7 });
+        assert_eq!(a.trace().cigar(res.query_idx, res.reference_idx).to_string(), "2M1D4M");
+
+        // tokens within 100 of each other are a weak match
+        let q = [100u16, 200, 300];
+        let r = [150u16, 200, 390];
+        let m = TokenMatrix::with_rule(&q, &r, |a, b| if a == b { 2 } else if a.abs_diff(b) < 100 { 1 } else { -2 });
+        assert_eq!(m.score(0, 0), 1);
+        assert_eq!(m.get(100, 150), 1);
+        assert_eq!(m.get(0, 200), -2);
+        let (q_padded, r_padded) = (m.query_bytes(16), m.reference_bytes(16));
+        let a = Block::<_, false, false>::align(&q_padded, &r_padded, &m, gaps, 16..=16, 0);
+        assert_eq!(a.res().score, 4);
+
+        // the rule can capture state
+        let threshold = 30u16;
+        let m = TokenMatrix::with_rule(&q, &r, move |a, b| if a.abs_diff(b) <= threshold { 1 } else { -2 });
+        assert_eq!(m.get(10, 40), 1);
+        let a = Block::<_, false, false>::align(&q_padded, &r_padded, &m, gaps, 16..=16, 0);
+        assert_eq!(a.res().score, -2 + 1 - 2);
+    }
+}
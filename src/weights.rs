@@ -0,0 +1,144 @@
+//! Per-position weights on substitution scores.
+//!
+//! Some regions of a query are more trustworthy than others, for example because of base
+//! quality, mappability, or confidence from an upstream tool. Scaling the substitution scores
+//! at each query position by a weight lets the trusted regions dominate the alignment, while
+//! matches and mismatches in untrusted regions barely matter.
+
+use crate::backend::SimdBackend;
+
+use crate::scores::*;
+
+/// Matrix that scales the substitution scores of another matrix by per-query-position weights.
+///
+/// The weights are stored with a precision of 1/256 and scaled scores are rounded. Gap costs
+/// are not affected. Query positions without a weight (past the end of the weights) and padding
+/// are scored by the inner matrix as usual.
+#[derive(Clone, PartialEq, Debug)]
+pub struct WeightedMatrix<M: Matrix> {
+    matrix: M,
+    weights: Vec<i16>
+}
+
+/// Fixed point precision of the weights.
+const WEIGHT_SHIFT: u32 = 8;
+
+impl<M: Matrix> WeightedMatrix<M> {
+    /// Create a weighted matrix, with one weight between 0 and 1 for each query position.
+    pub fn new(matrix: M, weights: &[f32]) -> Self {
+        assert!(weights.iter().all(|w| (0.0..=1.0).contains(w)), "Weights must be between 0 and 1!");
+        let weights = weights.iter().map(|&w| (w * ((1 << WEIGHT_SHIFT) as f32)).round() as i16).collect();
+        Self { matrix, weights }
+    }
+
+    /// Get the inner matrix.
+    pub fn matrix(&self) -> &M {
+        &self.matrix
+    }
+
+    /// Get the score for a pair of bytes at a query position (0-based).
+    pub fn get_at(&self, a: u8, b: u8, i: usize) -> i8 {
+        self.scale(self.matrix.get(a, b) as i16, i + 1) as i8
+    }
+
+    /// Scale a score at a 1-based padded query position.
+    #[inline]
+    fn scale(&self, score: i16, i: usize) -> i16 {
+        if i == 0 || i > self.weights.len() {
+            return score;
+        }
+        scale_score(score, self.weights[i - 1])
+    }
+
+    /// Scores of the inner matrix at the min and max weights.
+    fn scaled_extremes(&self) -> [i16; 4] {
+        let (min_w, max_w) = match (self.weights.iter().min(), self.weights.iter().max()) {
+            (Some(&min_w), Some(&max_w)) => (min_w, max_w),
+            _ => (1 << WEIGHT_SHIFT, 1 << WEIGHT_SHIFT)
+        };
+        let (min, max) = (self.matrix.min_score() as i16, self.matrix.max_score() as i16);
+        [scale_score(min, min_w), scale_score(min, max_w), scale_score(max, min_w), scale_score(max, max_w)]
+    }
+}
+
+/// Multiply a score by a fixed point weight, with rounding.
+#[inline]
+fn scale_score(score: i16, w: i16) -> i16 {
+    (((score as i32) * (w as i32) + (1 << (WEIGHT_SHIFT - 1))) >> WEIGHT_SHIFT) as i16
+}
+
+impl<M: Matrix> Matrix for WeightedMatrix<M> {
+    const NULL: u8 = M::NULL;
+
+    fn new() -> Self {
+        Self { matrix: M::new(), weights: Vec::new() }
+    }
+
+    fn set(&mut self, a: u8, b: u8, score: i8) {
+        self.matrix.set(a, b, score);
+    }
+
+    /// Get the unweighted score for a pair of bytes.
+    fn get(&self, a: u8, b: u8) -> i8 {
+        self.matrix.get(a, b)
+    }
+
+    #[inline]
+    fn as_ptr(&self, i: usize) -> *const i8 {
+        self.matrix.as_ptr(i)
+    }
+
+    #[inline]
+    unsafe fn get_scores<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, right: bool) -> B::Simd {
+        self.matrix.get_scores::<B>(c, v, right)
+    }
+
+    #[inline]
+    unsafe fn get_scores_at<B: SimdBackend>(&self, c: u8, v: B::HalfSimd, right: bool, c_idx: usize, v_idx: usize) -> B::Simd {
+        let scores = self.matrix.get_scores_at::<B>(c, v, right, c_idx, v_idx);
+        // when shifting right, the vector is along the query
+        map_simd_scores::<B, _>(scores, |k, s| self.scale(s, if right { v_idx + k } else { c_idx }))
+    }
+
+    #[inline]
+    fn convert_char(c: u8) -> u8 {
+        M::convert_char(c)
+    }
+
+    fn max_score(&self) -> i8 {
+        *self.scaled_extremes().iter().max().unwrap() as i8
+    }
+
+    fn min_score(&self) -> i8 {
+        *self.scaled_extremes().iter().min().unwrap() as i8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan_block::*;
+
+    #[test]
+    fn test_weighted_matrix() {
+        let gaps = Gaps { open: -10, extend: -10 };
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGT", 16);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGAACGT", 16);
+        let matrix = NucMatrix::new_simple(2, -4);
+
+        let m = WeightedMatrix::new(matrix.clone(), &[1.0; 8]);
+        let a = Block::<_, false, false>::align(&q, &r, &m, gaps, 16..=16, 0);
+        assert_eq!(a.res().score, Block::<_, false, false>::align(&q, &r, &matrix, gaps, 16..=16, 0).res().score);
+
+        let m = WeightedMatrix::new(matrix.clone(), &[0.5, 0.5, 0.5, 0.5, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(m.get_at(b'A', b'T', 3), -2);
+        assert_eq!((m.min_score(), m.max_score()), (-4, 2));
+        let a = Block::<_, false, false>::align(&q, &r, &m, gaps, 16..=16, 0);
+        assert_eq!(a.res().score, 1 + 1 + 1 - 2 + 8);
+
+        // the mismatch does not matter at all
+        let m = WeightedMatrix::new(matrix, &[1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0]);
+        let a = Block::<_, false, false>::align(&q, &r, &m, gaps, 16..=16, 0);
+        assert_eq!(a.res().score, 14);
+    }
+}
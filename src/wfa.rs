@@ -0,0 +1,297 @@
+//! Gap-affine wavefront alignment (WFA) for near-identical sequences.
+//!
+//! WFA computes an exact global alignment in time that depends on the alignment score
+//! instead of the lengths of the sequences, so it is much faster than any DP for long,
+//! highly similar sequences. When the sequences turn out to be too divergent, alignment
+//! falls back to block aligner.
+
+use crate::scan_block::*;
+use crate::scores::*;
+use crate::cigar::*;
+
+use std::cmp;
+use std::ops::RangeInclusive;
+
+const NONE: i32 = i32::MIN / 2;
+
+/// Data structure storing the settings for WFA with fallback to block aligner.
+///
+/// WFA minimizes penalties instead of maximizing scores, so it only supports a uniform
+/// match and mismatch score. The scores are converted to penalties such that the resulting
+/// alignment score is the same as the score from block aligner. Bytes are compared after
+/// they are converted by the matrix, so the matrix must not be position-specific.
+pub struct WfaAligner<'a, M: 'static + Matrix> {
+    matrix: &'a M,
+    match_score: i8,
+    mismatch_score: i8,
+    gaps: Gaps,
+    size: RangeInclusive<usize>,
+    max_divergence: f32
+}
+
+/// Result of aligning with `WfaAligner`.
+pub struct WfaAlignment {
+    /// Result of the global alignment.
+    pub res: AlignResult,
+    /// CIGAR string of the global alignment.
+    pub cigar: Cigar,
+    /// Whether WFA was used, or whether alignment fell back to block aligner.
+    pub used_wfa: bool
+}
+
+/// Penalties after converting scores, doubled to keep them integers.
+#[derive(Copy, Clone)]
+struct Penalties {
+    mismatch: i32,
+    open: i32,
+    extend: i32
+}
+
+/// Wavefront for a single score, covering the diagonals `lo..=hi`.
+#[derive(Clone)]
+struct Wavefront {
+    lo: i32,
+    hi: i32,
+    offsets: Vec<i32>
+}
+
+impl Wavefront {
+    fn new(lo: i32, hi: i32) -> Self {
+        Self { lo, hi, offsets: vec![NONE; (hi - lo + 1) as usize] }
+    }
+
+    #[inline]
+    fn get(&self, k: i32) -> i32 {
+        if k < self.lo || k > self.hi { NONE } else { self.offsets[(k - self.lo) as usize] }
+    }
+}
+
+/// Get the wavefront for a score, which is empty if the score is negative.
+#[inline]
+fn wavefront_at<'b>(wf: &'b [Wavefront], s: i32, empty: &'b Wavefront) -> &'b Wavefront {
+    if s >= 0 { &wf[s as usize] } else { empty }
+}
+
+impl<'a, M: 'static + Matrix> WfaAligner<'a, M> {
+    /// Create a new aligner.
+    ///
+    /// `match_score` and `mismatch_score` must match the scores in `matrix`, which is used
+    /// for the fallback. `size` is the range of block sizes for the fallback. By default,
+    /// WFA gives up when the number of differences exceeds roughly 5% of the sequence length.
+    pub fn new(matrix: &'a M, match_score: i8, mismatch_score: i8, gaps: Gaps, size: RangeInclusive<usize>) -> Self {
+        assert!(match_score >= 0 && mismatch_score < match_score, "Match score must be nonnegative and greater than the mismatch score!");
+        assert!(gaps.open < 0 && gaps.extend < 0, "Gap costs must be negative!");
+        assert!(gaps.open <= gaps.extend, "Gap open must not cost less than gap extend!");
+        Self { matrix, match_score, mismatch_score, gaps, size, max_divergence: 0.05 }
+    }
+
+    /// Set the max fraction of differences before falling back to block aligner.
+    pub fn max_divergence(mut self, max_divergence: f32) -> Self {
+        assert!(max_divergence >= 0.0, "Max divergence must be nonnegative!");
+        self.max_divergence = max_divergence;
+        self
+    }
+
+    /// Globally align two strings with WFA, or with block aligner if they are too divergent.
+    pub fn align(&self, query: &PaddedBytes, reference: &PaddedBytes) -> WfaAlignment {
+        if let Some(a) = self.align_wfa(query, reference) {
+            return a;
+        }
+
+        let a = Block::<_, true, false>::align(query, reference, self.matrix, self.gaps, self.size.clone(), 0);
+        let res = a.res();
+        WfaAlignment { res, cigar: a.trace().cigar(res.query_idx, res.reference_idx), used_wfa: false }
+    }
+
+    fn penalties(&self) -> Penalties {
+        let a = self.match_score as i32;
+        Penalties {
+            mismatch: 2 * (a - self.mismatch_score as i32),
+            open: 2 * ((self.gaps.extend as i32) - (self.gaps.open as i32)),
+            extend: a - 2 * (self.gaps.extend as i32)
+        }
+    }
+
+    /// Run WFA, returning `None` if the max penalty is exceeded.
+    fn align_wfa(&self, query: &PaddedBytes, reference: &PaddedBytes) -> Option<WfaAlignment> {
+        let p = self.penalties();
+        let (n, m) = (query.len() as i32, reference.len() as i32);
+        let max_diffs = ((cmp::max(n, m) as f32) * self.max_divergence).ceil() as i32 + (n - m).abs();
+        let max_penalty = max_diffs * cmp::max(p.mismatch, p.open + p.extend);
+        let k_end = m - n;
+
+        // offsets are positions in the reference, and diagonals are reference - query positions
+        let extend = |k: i32, mut h: i32| {
+            let mut v = h - k;
+            while v < n && h < m && unsafe { query.get((v + 1) as usize) == reference.get((h + 1) as usize) } {
+                v += 1;
+                h += 1;
+            }
+            h
+        };
+        let valid = |k: i32, h: i32| if h >= 0 && h <= m && h - k >= 0 && h - k <= n { h } else { NONE };
+
+        let empty = Wavefront::new(0, -1);
+        let mut wf_m: Vec<Wavefront> = Vec::new();
+        let mut wf_i: Vec<Wavefront> = Vec::new();
+        let mut wf_d: Vec<Wavefront> = Vec::new();
+
+        let mut s = 0;
+        loop {
+            let (m_x, m_oe) = (wavefront_at(&wf_m, s - p.mismatch, &empty), wavefront_at(&wf_m, s - p.open - p.extend, &empty));
+            let (i_e, d_e) = (wavefront_at(&wf_i, s - p.extend, &empty), wavefront_at(&wf_d, s - p.extend, &empty));
+
+            // range of diagonals that can be reached from the previous wavefronts
+            let ranges = [(m_x, 0), (m_oe, -1), (m_oe, 1), (i_e, -1), (d_e, 1)];
+            let lo = ranges.iter().filter(|(w, _)| w.lo <= w.hi).map(|(w, d)| w.lo + d).min();
+            let hi = ranges.iter().filter(|(w, _)| w.lo <= w.hi).map(|(w, d)| w.hi + d).max();
+
+            let (mut next_m, next_i, next_d) = match (lo, hi) {
+                _ if s == 0 => {
+                    let mut w = Wavefront::new(0, 0);
+                    w.offsets[0] = 0;
+                    (w, empty.clone(), empty.clone())
+                },
+                (Some(lo), Some(hi)) => {
+                    let (mut wm, mut wi, mut wd) = (Wavefront::new(lo, hi), Wavefront::new(lo, hi), Wavefront::new(lo, hi));
+                    for k in lo..=hi {
+                        let idx = (k - lo) as usize;
+                        let i = valid(k, cmp::max(m_oe.get(k + 1), i_e.get(k + 1)));
+                        let d = valid(k, cmp::max(m_oe.get(k - 1), d_e.get(k - 1)) + 1);
+                        let x = valid(k, m_x.get(k) + 1);
+                        wi.offsets[idx] = i;
+                        wd.offsets[idx] = d;
+                        wm.offsets[idx] = cmp::max(x, cmp::max(i, d));
+                    }
+                    (wm, wi, wd)
+                },
+                _ => (empty.clone(), empty.clone(), empty.clone())
+            };
+
+            for k in next_m.lo..=next_m.hi {
+                let idx = (k - next_m.lo) as usize;
+                if next_m.offsets[idx] > NONE {
+                    next_m.offsets[idx] = extend(k, next_m.offsets[idx]);
+                }
+            }
+
+            let done = next_m.get(k_end) >= m;
+            wf_m.push(next_m);
+            wf_i.push(next_i);
+            wf_d.push(next_d);
+
+            if done {
+                break;
+            }
+            s += 1;
+            if s > max_penalty {
+                return None;
+            }
+        }
+
+        let cigar = Self::traceback(&wf_m, &wf_i, &wf_d, p, s, k_end, m, n, valid);
+        let score = ((self.match_score as i32) * (n + m) - s) / 2;
+        let res = AlignResult { score, query_idx: n as usize, reference_idx: m as usize };
+        Some(WfaAlignment { res, cigar, used_wfa: true })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn traceback(wf_m: &[Wavefront], wf_i: &[Wavefront], wf_d: &[Wavefront], p: Penalties, mut s: i32, mut k: i32, mut h: i32, n: i32, valid: impl Fn(i32, i32) -> i32) -> Cigar {
+        #[derive(PartialEq)]
+        enum State { M, I, D }
+
+        let get = |wf: &[Wavefront], s: i32, k: i32| if s >= 0 { wf[s as usize].get(k) } else { NONE };
+        let mut cigar = unsafe { Cigar::new((n as usize) + (h as usize)) };
+        let mut state = State::M;
+
+        loop {
+            match state {
+                State::M => {
+                    let x = if s > 0 { valid(k, get(wf_m, s - p.mismatch, k) + 1) } else { NONE };
+                    let (i, d) = (get(wf_i, s, k), get(wf_d, s, k));
+                    let start = if s == 0 { 0 } else { cmp::max(x, cmp::max(i, d)) };
+                    for _ in start..h {
+                        unsafe { cigar.add(Operation::M); }
+                    }
+                    h = start;
+                    if s == 0 {
+                        break;
+                    }
+                    if start == i {
+                        state = State::I;
+                    } else if start == d {
+                        state = State::D;
+                    } else {
+                        unsafe { cigar.add(Operation::M); }
+                        s -= p.mismatch;
+                        h -= 1;
+                    }
+                },
+                State::I => {
+                    unsafe { cigar.add(Operation::I); }
+                    state = if get(wf_m, s - p.open - p.extend, k + 1) == h { State::M } else { State::I };
+                    s -= if state == State::M { p.open + p.extend } else { p.extend };
+                    k += 1;
+                },
+                State::D => {
+                    unsafe { cigar.add(Operation::D); }
+                    state = if get(wf_m, s - p.open - p.extend, k - 1) == h - 1 { State::M } else { State::D };
+                    s -= if state == State::M { p.open + p.extend } else { p.extend };
+                    k -= 1;
+                    h -= 1;
+                }
+            }
+        }
+
+        cigar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_wfa() {
+        let gaps = Gaps { open: -3, extend: -1 };
+        let matrix = NucMatrix::new_simple(2, -3);
+        let aligner = WfaAligner::new(&matrix, 2, -3, gaps, 16..=256);
+
+        let q = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGTACGTTTTACGTACGTAAGT", 256);
+        let r = PaddedBytes::from_bytes::<NucMatrix>(b"ACGTACGTACGTACGTACGTACGTACGT", 256);
+        let a = WfaAligner::new(&matrix, 2, -3, gaps, 16..=256).max_divergence(0.3).align(&q, &r);
+        let b = Block::<_, true, false, true>::align(&q, &r, &matrix, gaps, 16..=256, 0);
+        assert!(a.used_wfa);
+        assert_eq!(a.res, b.res());
+        assert_eq!(a.cigar.to_string(), b.trace().cigar(b.res().query_idx, b.res().reference_idx).to_string());
+
+        let mut rng = StdRng::seed_from_u64(1234);
+        for _i in 0..20 {
+            let r = rand_str(1000, &NUC, &mut rng);
+            let q = rand_mutate(&r, 20, &NUC, &mut rng);
+            let r = PaddedBytes::from_bytes::<NucMatrix>(&r, 256);
+            let q = PaddedBytes::from_bytes::<NucMatrix>(&q, 256);
+            let a = aligner.align(&q, &r);
+            let b = Block::<_, true, false>::align(&q, &r, &matrix, gaps, 16..=256, 0);
+            assert!(a.used_wfa);
+            // WFA is exact, so it is at least as good as block aligner
+            assert!(a.res.score >= b.res().score);
+            let (q_len, r_len) = a.cigar.to_vec().iter().fold((0, 0), |(i, j), op_len| match op_len.op {
+                Operation::M => (i + op_len.len, j + op_len.len),
+                Operation::I => (i + op_len.len, j),
+                _ => (i, j + op_len.len)
+            });
+            assert_eq!((q_len, r_len), (q.len(), r.len()));
+        }
+
+        // unrelated sequences fall back to block aligner
+        let r = PaddedBytes::from_bytes::<NucMatrix>(&rand_str(500, &NUC, &mut rng), 256);
+        let q = PaddedBytes::from_bytes::<NucMatrix>(&rand_str(500, &NUC, &mut rng), 256);
+        let a = aligner.align(&q, &r);
+        let b = Block::<_, true, false>::align(&q, &r, &matrix, gaps, 16..=256, 0);
+        assert!(!a.used_wfa);
+        assert_eq!(a.res, b.res());
+    }
+}